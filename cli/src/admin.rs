@@ -0,0 +1,506 @@
+use crate::cli::{AdminCommands, AuditCommands, ClaimCommands, NameCommands};
+use crate::endpoint;
+use crate::logger::Logger;
+use anyhow::{Context, Result, anyhow};
+use comfy_table::Table;
+use inquire::Confirm;
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// The admin token is its own secret, not a user login. It never touches the keyring
+/// or auth.toml—operators keep it in their shell or a password manager.
+const TOKEN_ENV: &str = "MOSAIC_ADMIN_TOKEN";
+
+/// Entry point for `mosaic admin`.
+pub async fn run(command: &AdminCommands) -> Result<()> {
+    let token = std::env::var(TOKEN_ENV)
+        .ok()
+        .filter(|t| !t.is_empty())
+        .with_context(|| format!("{} is not set. Admin commands need the registry's admin token.", TOKEN_ENV))?;
+    let registry_url = endpoint::registry_url();
+    let client = AdminClient {
+        http: reqwest::Client::new(),
+        base: format!("{}/admin", registry_url),
+        token,
+    };
+
+    match command {
+        AdminCommands::Takedown {
+            package,
+            reason,
+            undo,
+        } => {
+            let path = format!("/packages/{}/takedown", package);
+            let res = if *undo {
+                client.send(client.http.delete(client.url(&path))).await?
+            } else {
+                client
+                    .send(client.http.post(client.url(&path)).json(&json!({"reason": reason})))
+                    .await?
+            };
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::BanUser {
+            username,
+            reason,
+            undo,
+        } => {
+            let path = format!("/users/{}/ban", username);
+            let res = if *undo {
+                client.send(client.http.delete(client.url(&path))).await?
+            } else {
+                client
+                    .send(client.http.post(client.url(&path)).json(&json!({"reason": reason})))
+                    .await?
+            };
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::Feature {
+            package,
+            reason,
+            undo,
+        } => {
+            let path = format!("/packages/{}/feature", package);
+            let res = if *undo {
+                client.send(client.http.delete(client.url(&path))).await?
+            } else {
+                client
+                    .send(client.http.post(client.url(&path)).json(&json!({"reason": reason})))
+                    .await?
+            };
+            Logger::success(message(&res));
+        }
+        AdminCommands::VerifyUser {
+            username,
+            reason,
+            undo,
+        } => {
+            let path = format!("/users/{}/verify", username);
+            let res = if *undo {
+                client.send(client.http.delete(client.url(&path))).await?
+            } else {
+                client
+                    .send(client.http.post(client.url(&path)).json(&json!({"reason": reason})))
+                    .await?
+            };
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::ReleaseName {
+            package,
+            reason,
+            yes,
+        } => {
+            Logger::warn(format!(
+                "This permanently deletes {} and every version of it from {}.",
+                Logger::highlight(package),
+                registry_url
+            ));
+            if !*yes
+                && !Confirm::new(&format!("Release the name '{}'?", package))
+                    .with_default(false)
+                    .prompt()?
+            {
+                Logger::info("Cancelled.");
+                return Ok(());
+            }
+
+            let res = client
+                .send(
+                    client
+                        .http
+                        .delete(client.url(&format!("/packages/{}", package)))
+                        .json(&json!({"reason": reason})),
+                )
+                .await?;
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::Stats => {
+            let res = client.send(client.http.get(client.url("/stats"))).await?;
+
+            Logger::header(format!("Registry stats for {}", registry_url));
+            let mut table = Table::new();
+            table.set_header(vec!["Metric", "Count"]);
+            for (label, key) in [
+                ("Users", "users"),
+                ("Banned users", "banned_users"),
+                ("Packages", "packages"),
+                ("Taken down", "taken_down_packages"),
+                ("Versions", "versions"),
+                ("Downloads", "downloads"),
+            ] {
+                table.add_row(vec![label.to_string(), res[key].to_string()]);
+            }
+            println!("{table}");
+        }
+
+        AdminCommands::Clients { days } => {
+            let res = client
+                .send(client.http.get(client.url("/clients")).query(&[("days", days)]))
+                .await?;
+
+            Logger::header(format!(
+                "CLI versions over the last {} days ({} opted-in downloads)",
+                days, res["total"]
+            ));
+            let versions = res["versions"].as_array().cloned().unwrap_or_default();
+            if versions.is_empty() {
+                Logger::info("Nothing yet. Only downloads from users who ran 'mosaic telemetry on' count.");
+                return Ok(());
+            }
+
+            let percent = |v: &serde_json::Value| format!("{:.1}%", v.as_f64().unwrap_or(0.0) * 100.0);
+            let mut table = Table::new();
+            table.set_header(vec!["Version", "Downloads", "Share", "OS"]);
+            for v in &versions {
+                let os = v["os"]
+                    .as_object()
+                    .map(|os| {
+                        let mut os: Vec<_> = os.iter().collect();
+                        os.sort_by_key(|(_, n)| std::cmp::Reverse(n.as_i64()));
+                        os.iter()
+                            .map(|(name, n)| format!("{} {}", name, n))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .unwrap_or_default();
+                table.add_row(vec![
+                    v["version"].as_str().unwrap_or("?").to_string(),
+                    v["downloads"].to_string(),
+                    percent(&v["share"]),
+                    os,
+                ]);
+            }
+            println!("{table}");
+        }
+
+        AdminCommands::Check { repair } => {
+            Logger::info(format!(
+                "Checking {}{}...",
+                Logger::highlight(&registry_url),
+                if *repair { " (with repair)" } else { "" }
+            ));
+            let res = client
+                .send(
+                    client
+                        .http
+                        .post(client.url("/jobs/integrity"))
+                        .query(&[("repair", repair.to_string())]),
+                )
+                .await?;
+
+            let list = |key: &str| -> Vec<String> {
+                res[key]
+                    .as_array()
+                    .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                    .unwrap_or_default()
+            };
+            let drift = res["download_count_drift"].as_array().cloned().unwrap_or_default();
+            let incomplete = list("incomplete_versions");
+            let missing = list("missing_blobs");
+            let unchecked = list("unchecked");
+
+            Logger::info(format!("{} versions checked", res["versions_checked"]));
+
+            if !drift.is_empty() {
+                let mut table = Table::new();
+                table.set_header(vec!["Package", "Stored", "From series"]);
+                for row in &drift {
+                    table.add_row(vec![
+                        row["package"].as_str().unwrap_or("?").to_string(),
+                        row["stored"].to_string(),
+                        row["actual"].to_string(),
+                    ]);
+                }
+                Logger::warn(format!("{} download counters drifted", drift.len()));
+                println!("{table}");
+            }
+            for (label, items) in [
+                ("Incomplete (never uploaded)", &incomplete),
+                ("Missing blob", &missing),
+                ("Couldn't check", &unchecked),
+            ] {
+                if !items.is_empty() {
+                    Logger::warn(format!("{}: {}", label, items.join(", ")));
+                }
+            }
+
+            if drift.is_empty() && incomplete.is_empty() && missing.is_empty() && unchecked.is_empty() {
+                Logger::success("Everything checks out.");
+            } else if *repair {
+                Logger::success(format!(
+                    "Repaired: counters rewritten, {} incomplete versions removed. Missing blobs need a human.",
+                    res["incomplete_versions_removed"]
+                ));
+            } else {
+                Logger::info("Run again with --repair to fix counters and stale incomplete versions.");
+            }
+        }
+
+        AdminCommands::Export {
+            output,
+            blobs,
+            password_hashes,
+        } => {
+            Logger::info(format!("Exporting {}...", Logger::highlight(&registry_url)));
+
+            let mut res = client
+                .http
+                .get(client.url("/export"))
+                .query(&[
+                    ("blobs", blobs.to_string()),
+                    ("password_hashes", password_hashes.to_string()),
+                ])
+                .header("Authorization", format!("Bearer {}", client.token))
+                .send()
+                .await?;
+
+            if !res.status().is_success() {
+                return Err(anyhow!("Export failed ({}): {}", res.status(), res.text().await?));
+            }
+
+            // Stream straight to disk. With --blobs this can be gigabytes.
+            let mut file = tokio::fs::File::create(output).await?;
+            let mut written: u64 = 0;
+            while let Some(chunk) = res.chunk().await? {
+                file.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+            }
+            file.flush().await?;
+
+            Logger::success(format!(
+                "Wrote {} ({} KB)",
+                Logger::highlight(output.to_string_lossy()),
+                written / 1024
+            ));
+            if !*password_hashes {
+                Logger::warn("Password hashes were not included. Imported users won't be able to log in.");
+            }
+        }
+
+        AdminCommands::Import { input } => {
+            let file = tokio::fs::File::open(input)
+                .await
+                .with_context(|| format!("Could not open {}", input.display()))?;
+            let len = file.metadata().await?.len();
+
+            Logger::info(format!(
+                "Importing {} into {}...",
+                Logger::highlight(input.to_string_lossy()),
+                Logger::highlight(&registry_url)
+            ));
+
+            let res = client
+                .send(
+                    client
+                        .http
+                        .post(client.url("/import"))
+                        .header("Content-Type", "application/x-ndjson")
+                        .header("Content-Length", len)
+                        .body(file),
+                )
+                .await?;
+
+            Logger::success(message(&res));
+            if let Some(counts) = res["imported"].as_object() {
+                let mut table = Table::new();
+                table.set_header(vec!["Record", "Count"]);
+                for (kind, count) in counts {
+                    table.add_row(vec![kind.clone(), count.to_string()]);
+                }
+                println!("{table}");
+            }
+        }
+
+        AdminCommands::Audit {
+            command: AuditCommands::Tail { lines, follow },
+        } => {
+            let mut last_id = 0;
+            let mut limit = *lines;
+
+            loop {
+                let res = client
+                    .send(client.http.get(client.url("/audit")).query(&[
+                        ("limit", limit.to_string()),
+                        ("after", last_id.to_string()),
+                    ]))
+                    .await?;
+
+                for entry in res.as_array().map(|a| a.as_slice()).unwrap_or_default() {
+                    print_audit_entry(entry);
+                    last_id = entry["id"].as_i64().unwrap_or(last_id);
+                }
+
+                if !*follow {
+                    break;
+                }
+                // After the first page, grab everything new (up to the server cap).
+                limit = 200;
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+
+        AdminCommands::Claims {
+            command: ClaimCommands::List { status },
+        } => {
+            let res = client
+                .send(client.http.get(client.url("/claims")).query(&[("status", status)]))
+                .await?;
+            let claims = res.as_array().cloned().unwrap_or_default();
+            if claims.is_empty() {
+                Logger::info(format!("No {} claims.", status));
+                return Ok(());
+            }
+
+            for claim in &claims {
+                println!(
+                    "{} {} {} {}",
+                    Logger::dim(format!("#{} @{}", claim["id"], claim["created_at"])),
+                    Logger::highlight(claim["name"].as_str().unwrap_or("?")),
+                    format_args!(
+                        "by {} (owner: {})",
+                        claim["claimant"].as_str().unwrap_or("?"),
+                        claim["owner"].as_str().unwrap_or("none")
+                    ),
+                    Logger::dim(format!("[{}]", claim["status"].as_str().unwrap_or("?")))
+                );
+                println!("    {}", claim["reason"].as_str().unwrap_or(""));
+            }
+        }
+
+        AdminCommands::Claims {
+            command: ClaimCommands::Approve { id, reason, force },
+        } => {
+            let res = client
+                .send(
+                    client
+                        .http
+                        .post(client.url(&format!("/claims/{}/approve", id)))
+                        .json(&json!({"reason": reason, "force": force})),
+                )
+                .await?;
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::Claims {
+            command: ClaimCommands::Reject { id, reason },
+        } => {
+            let res = client
+                .send(
+                    client
+                        .http
+                        .post(client.url(&format!("/claims/{}/reject", id)))
+                        .json(&json!({"reason": reason})),
+                )
+                .await?;
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::Names { command: NameCommands::List } => {
+            let res = client.send(client.http.get(client.url("/names"))).await?;
+            let rules = res.as_array().cloned().unwrap_or_default();
+            if rules.is_empty() {
+                Logger::info("No name rules.");
+                return Ok(());
+            }
+
+            let mut table = Table::new();
+            table.set_header(vec!["Kind", "Pattern", "User", "Reason"]);
+            for rule in &rules {
+                table.add_row(vec![
+                    rule["kind"].as_str().unwrap_or("?").replace('_', "-"),
+                    rule["pattern"].as_str().unwrap_or("?").to_string(),
+                    rule["username"].as_str().unwrap_or("-").to_string(),
+                    rule["reason"].as_str().unwrap_or("-").to_string(),
+                ]);
+            }
+            println!("{table}");
+        }
+
+        AdminCommands::Names {
+            command: NameCommands::Add { kind, pattern, user, reason },
+        } => {
+            let res = client
+                .send(client.http.post(client.url("/names")).json(&json!({
+                    "kind": kind,
+                    "pattern": pattern,
+                    "username": user,
+                    "reason": reason
+                })))
+                .await?;
+            Logger::success(message(&res));
+        }
+
+        AdminCommands::Names {
+            command: NameCommands::Remove { kind, pattern, reason },
+        } => {
+            let res = client
+                .send(
+                    client
+                        .http
+                        .delete(client.url(&format!("/names/{}/{}", kind, pattern)))
+                        .json(&json!({"reason": reason})),
+                )
+                .await?;
+            Logger::success(message(&res));
+        }
+    }
+
+    Ok(())
+}
+
+struct AdminClient {
+    http: reqwest::Client,
+    base: String,
+    token: String,
+}
+
+impl AdminClient {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    /// Adds the admin token, sends, and turns anything but a 2xx into an error with the server's message.
+    async fn send(&self, req: reqwest::RequestBuilder) -> Result<serde_json::Value> {
+        let res = req
+            .header("Authorization", format!("Bearer {}", self.token))
+            .send()
+            .await?;
+
+        let status = res.status();
+        let text = res.text().await?;
+
+        if status == reqwest::StatusCode::NOT_FOUND && text == "Not found" {
+            return Err(anyhow!("This registry doesn't have the admin API enabled (ADMIN_TOKEN unset)"));
+        }
+
+        if !status.is_success() {
+            let msg = match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(json) => json["error"].as_str().unwrap_or(&text).to_string(),
+                Err(_) => text,
+            };
+            return Err(anyhow!("Admin request failed ({}): {}", status, msg));
+        }
+
+        Ok(serde_json::from_str(&text).unwrap_or(serde_json::Value::Null))
+    }
+}
+
+fn message(res: &serde_json::Value) -> String {
+    res["message"].as_str().unwrap_or("Done").to_string()
+}
+
+fn print_audit_entry(entry: &serde_json::Value) {
+    let reason = entry["reason"].as_str().unwrap_or("-");
+    println!(
+        "{} {} {} {}",
+        Logger::dim(format!("#{} @{}", entry["id"], entry["created_at"])),
+        Logger::highlight(entry["action"].as_str().unwrap_or("?")),
+        entry["target"].as_str().unwrap_or("?"),
+        Logger::dim(format!("({})", reason))
+    );
+}
@@ -0,0 +1,178 @@
+use crate::attest;
+use crate::config::Config;
+use crate::installer;
+use crate::logger::Logger;
+use crate::metadata;
+use crate::mode;
+use crate::progress;
+use crate::registry;
+use crate::xml_handler;
+use anyhow::{Context, Result, anyhow};
+use comfy_table::Table;
+use inquire::Confirm;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// How many published versions we'll download to find the one a module came from.
+/// Newest first, so this only runs out on a module that's years behind (or was edited).
+const MAX_VERSIONS_TO_HASH: usize = 15;
+
+/// A ModuleScript in the .poly whose name is a package on the registry.
+struct Candidate {
+    name: String,
+    /// The published version whose source is byte-for-byte what's in the .poly, if any.
+    matched: Option<String>,
+    /// What we'd adopt it at if nothing matched: the newest stable release.
+    latest: String,
+}
+
+/// `mosaic init --from-poly`: starts a project from a place file that already has packages
+/// pasted into it by hand.
+///
+/// Every ModuleScript in ScriptService named like a package is looked up on the registry and
+/// its source hashed against the published versions. An exact match is adopted at that
+/// version, so nothing in the .poly changes. A module that matches no version was probably
+/// edited; adopting it means the next install replaces it, so it's only offered, never the
+/// default, and `--yes` leaves it alone. The adopted set is then installed like any other
+/// mosaic.toml, which fills in mosaic.lock and whatever dependencies they need.
+pub async fn from_poly(project_name: &str, poly: Option<&Path>, yes: bool) -> Result<()> {
+    if Path::new("mosaic.toml").exists() {
+        return Err(anyhow!(
+            "There's already a mosaic.toml here. Use 'mosaic install <package>' to add packages to it."
+        ));
+    }
+    if !yes && !mode::is_interactive() {
+        return Err(anyhow!(
+            "Not adopting packages without confirmation. Run it in a terminal, or pass --yes to adopt exact matches only."
+        ));
+    }
+
+    let poly_path = attest::find_poly(poly)?;
+    let poly_content = fs::read_to_string(&poly_path)
+        .with_context(|| format!("Could not read {}", poly_path.display()))?;
+    let modules = xml_handler::module_sources(&poly_content)?;
+    let candidates = find_candidates(&modules).await?;
+
+    let mut config = Config::default(project_name);
+    if candidates.is_empty() {
+        Logger::info(format!(
+            "None of the {} ModuleScripts in {} are registry packages.",
+            modules.len(),
+            Logger::highlight(poly_path.to_string_lossy())
+        ));
+        config.save()?;
+        Logger::success("Created mosaic.toml");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Module", "Matches", "Latest"]);
+    for c in &candidates {
+        table.add_row(vec![
+            c.name.clone(),
+            c.matched.clone().unwrap_or_else(|| "no published version (edited?)".to_string()),
+            c.latest.clone(),
+        ]);
+    }
+    println!("{}", table);
+
+    for c in &candidates {
+        let adopt = match (&c.matched, yes) {
+            (Some(_), true) => true,
+            (None, true) => false,
+            (Some(version), false) => Confirm::new(&format!("Adopt {} as {}@{}?", c.name, c.name, version))
+                .with_default(true)
+                .prompt()?,
+            (None, false) => Confirm::new(&format!(
+                "Adopt {} at {}? The next install replaces your copy with the published one.",
+                c.name, c.latest
+            ))
+            .with_default(false)
+            .prompt()?,
+        };
+        if adopt {
+            config.add_dependency(&c.name, c.matched.as_deref().unwrap_or(&c.latest));
+        } else {
+            Logger::info(format!("Leaving {} as it is.", c.name));
+        }
+    }
+
+    config.save()?;
+    Logger::success(format!(
+        "Created mosaic.toml with {} adopted package{}",
+        config.dependencies.len(),
+        if config.dependencies.len() == 1 { "" } else { "s" }
+    ));
+
+    if !config.dependencies.is_empty() {
+        installer::install_all().await?;
+    }
+    Ok(())
+}
+
+/// Looks up every plausible module name and works out which version it is.
+async fn find_candidates(modules: &BTreeMap<String, String>) -> Result<Vec<Candidate>> {
+    let names: Vec<&String> = modules.keys().filter(|n| could_be_package(n)).collect();
+    let pb = progress::spinner("cyan", format!("Looking up {} modules on the registry...", names.len()));
+
+    let mut candidates = Vec::new();
+    for name in names {
+        let versions = match metadata::get_json(&format!("/packages/{}/versions", name)).await {
+            Ok(Some(body)) => serde_json::from_value::<Vec<serde_json::Value>>(body).unwrap_or_default(),
+            Ok(None) => continue,
+            Err(e) => {
+                pb.suspend(|| Logger::warn(format!("Skipping {}: {}", name, e)));
+                continue;
+            }
+        };
+        let mut versions: Vec<semver::Version> = versions
+            .iter()
+            .filter_map(|v| v["version"].as_str())
+            .filter_map(|v| semver::Version::parse(v).ok())
+            .collect();
+        versions.sort_by(|a, b| b.cmp(a));
+        let Some(latest) = versions.iter().find(|v| v.pre.is_empty()).or(versions.first()) else {
+            continue;
+        };
+
+        pb.set_message(format!("Matching {} against its releases...", Logger::highlight(name)));
+        let wanted = attest::sha256_hex(modules[name].as_bytes());
+        let mut matched = None;
+        for version in versions.iter().take(MAX_VERSIONS_TO_HASH) {
+            let version = version.to_string();
+            match source_hash(name, &version).await {
+                Ok(hash) if hash == wanted => {
+                    matched = Some(version);
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => pb.suspend(|| Logger::warn(format!("Couldn't check {}@{}: {}", name, version, e))),
+            }
+        }
+
+        candidates.push(Candidate {
+            name: name.clone(),
+            matched,
+            latest: latest.to_string(),
+        });
+    }
+    pb.finish_and_clear();
+    Ok(candidates)
+}
+
+/// SHA256 of the source a version would inject, the same thing `mosaic attest` hashes.
+async fn source_hash(name: &str, version: &str) -> Result<String> {
+    let (blob, _) = registry::download_from_registry(name, version).await?;
+    let source = registry::extract_lua_from_archive(blob.file)?;
+    Ok(attest::sha256_hex(source.as_bytes()))
+}
+
+/// Names the registry could have published. Saves a request for every `MainModule` and `Utils`.
+pub fn could_be_package(name: &str) -> bool {
+    (2..=64).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && !name.contains("--")
+}
@@ -0,0 +1,268 @@
+use crate::config::Config;
+use crate::lockfile::Lockfile;
+use crate::logger::Logger;
+use crate::xml_handler;
+use anyhow::{Context, Result, anyhow};
+use comfy_table::Table;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where `mosaic attest` writes to (and `--verify` reads from) unless told otherwise.
+pub const DEFAULT_ATTESTATION_FILE: &str = "mosaic.attest.json";
+
+/// The signing key comes from the environment so it can live in CI secrets, not the repo.
+/// Anyone with the key can forge an attestation, so treat it like a deploy key.
+const KEY_ENV: &str = "MOSAIC_ATTEST_KEY";
+
+/// A snapshot of what a build contained, signed so it can't be quietly edited afterwards.
+///
+/// Everything uses BTreeMap so the JSON (and therefore the signature) is byte-for-byte stable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attestation {
+    pub project: String,
+    pub project_version: String,
+    pub cli_version: String,
+    pub created_at: u64,
+    /// SHA256 of mosaic.lock exactly as it was on disk.
+    pub lockfile_sha256: String,
+    pub packages: BTreeMap<String, AttestedPackage>,
+    /// `hmac-sha256:<hex>` over the JSON of every field above. Empty while signing.
+    #[serde(default)]
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestedPackage {
+    pub version: String,
+    /// Blob hash from the lockfile.
+    pub integrity: String,
+    /// SHA256 of the ModuleScript source embedded in the .poly.
+    /// None if the package is locked but wasn't in the .poly—which is itself worth knowing.
+    pub source_sha256: Option<String>,
+}
+
+/// `mosaic attest`: records the lockfile and the .poly contents and signs the result.
+pub fn create(poly: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    let key = signing_key()?;
+    let config = Config::load().context("Could not find mosaic.toml in current directory.")?;
+    let poly_path = find_poly(poly)?;
+
+    Logger::header(format!(
+        "Attesting {}@{}",
+        config.package.name, config.package.version
+    ));
+
+    let mut attestation = Attestation {
+        project: config.package.name.clone(),
+        project_version: config.package.version.clone(),
+        cli_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+        lockfile_sha256: lockfile_hash()?,
+        packages: snapshot(&poly_path)?,
+        signature: String::new(),
+    };
+
+    // Refuse to sign a build that's already drifted. An attestation of a broken state is worse than none.
+    let missing: Vec<&String> = attestation
+        .packages
+        .iter()
+        .filter(|(_, p)| p.source_sha256.is_none())
+        .map(|(name, _)| name)
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "These locked packages aren't in {}: {}. Run 'mosaic install' first.",
+            poly_path.display(),
+            missing
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    attestation.signature = sign(&key, &attestation)?;
+
+    let out = output.unwrap_or(Path::new(DEFAULT_ATTESTATION_FILE));
+    fs::write(out, serde_json::to_string_pretty(&attestation)?)?;
+
+    Logger::success(format!(
+        "Attested {} packages from {} into {}",
+        attestation.packages.len(),
+        Logger::highlight(poly_path.to_string_lossy()),
+        Logger::highlight(out.to_string_lossy())
+    ));
+    Ok(())
+}
+
+/// `mosaic attest --verify`: checks the signature, then checks the lockfile and .poly still match.
+pub fn verify(poly: Option<&Path>, input: Option<&Path>) -> Result<()> {
+    let key = signing_key()?;
+    let path = input.unwrap_or(Path::new(DEFAULT_ATTESTATION_FILE));
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let attestation: Attestation = serde_json::from_str(&content)
+        .with_context(|| format!("{} is not a valid attestation", path.display()))?;
+
+    Logger::header(format!(
+        "Verifying {}@{}",
+        attestation.project, attestation.project_version
+    ));
+
+    // 1. Signature first. If it's wrong, nothing else in the file can be trusted.
+    if !signature_matches(&key, &attestation)? {
+        return Err(anyhow!(
+            "Signature check failed for {}. The file was modified or signed with a different key.",
+            path.display()
+        ));
+    }
+    Logger::success("Signature is valid");
+
+    let mut problems = Vec::new();
+
+    // 2. The lockfile. A mismatch here isn't fatal on its own (the .poly is what ships),
+    //    but it means the repo moved on since the build.
+    if lockfile_hash()? != attestation.lockfile_sha256 {
+        problems.push((
+            "mosaic.lock".to_string(),
+            "changed since attestation".to_string(),
+        ));
+    }
+
+    // 3. What's actually in the .poly right now vs what was attested.
+    let poly_path = find_poly(poly)?;
+    let sources = xml_handler::module_sources(&fs::read_to_string(&poly_path)?)?;
+
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Version", "Status"]);
+
+    for (name, attested) in &attestation.packages {
+        let status = match (sources.get(name), &attested.source_sha256) {
+            (Some(source), Some(expected)) if &sha256_hex(source.as_bytes()) == expected => "ok",
+            (Some(_), _) => "modified",
+            (None, _) => "missing",
+        };
+        if status != "ok" {
+            problems.push((name.clone(), format!("{} in {}", status, poly_path.display())));
+        }
+        table.add_row(vec![name.as_str(), attested.version.as_str(), status]);
+    }
+
+    println!("{table}");
+
+    if problems.is_empty() {
+        Logger::success(format!(
+            "{} matches the attestation from CLI {}",
+            Logger::highlight(poly_path.to_string_lossy()),
+            attestation.cli_version
+        ));
+        return Ok(());
+    }
+
+    for (what, why) in &problems {
+        Logger::error(format!("{}: {}", what, why));
+    }
+    Err(anyhow!("Verification failed ({} problems)", problems.len()))
+}
+
+/// Lockfile entries plus the hash of whatever's embedded in the .poly for each of them.
+fn snapshot(poly_path: &Path) -> Result<BTreeMap<String, AttestedPackage>> {
+    let lockfile = Lockfile::load()?;
+    let sources = xml_handler::module_sources(&fs::read_to_string(poly_path)?)?;
+
+    Ok(lockfile
+        .packages
+        .iter()
+        .map(|(name, locked)| {
+            (
+                name.clone(),
+                AttestedPackage {
+                    version: locked.version.clone(),
+                    integrity: locked.integrity.clone(),
+                    source_sha256: sources.get(name).map(|s| sha256_hex(s.as_bytes())),
+                },
+            )
+        })
+        .collect())
+}
+
+/// HMAC over the attestation with the signature field blanked out.
+fn mac(key: &[u8], attestation: &Attestation) -> Result<Hmac<Sha256>> {
+    let mut unsigned = attestation.clone();
+    unsigned.signature.clear();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|e| anyhow!("Invalid signing key: {}", e))?;
+    mac.update(&serde_json::to_vec(&unsigned)?);
+    Ok(mac)
+}
+
+fn sign(key: &[u8], attestation: &Attestation) -> Result<String> {
+    let hex: String = mac(key, attestation)?
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(format!("hmac-sha256:{}", hex))
+}
+
+/// Checks the attestation's signature in constant time, so how long a forgery takes to fail
+/// says nothing about how close it was. A signature that isn't even hex is just wrong.
+fn signature_matches(key: &[u8], attestation: &Attestation) -> Result<bool> {
+    let Some(hex) = attestation.signature.strip_prefix("hmac-sha256:") else {
+        return Ok(false);
+    };
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Ok(false);
+    }
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect();
+    let Some(bytes) = bytes else {
+        return Ok(false);
+    };
+    Ok(mac(key, attestation)?.verify_slice(&bytes).is_ok())
+}
+
+fn signing_key() -> Result<Vec<u8>> {
+    match std::env::var(KEY_ENV) {
+        Ok(key) if !key.is_empty() => Ok(key.into_bytes()),
+        _ => Err(anyhow!(
+            "{} is not set. Attestations are signed with a shared key—set it to your team's secret.",
+            KEY_ENV
+        )),
+    }
+}
+
+fn lockfile_hash() -> Result<String> {
+    let bytes = fs::read("mosaic.lock").context("No mosaic.lock found. Run 'mosaic install' first.")?;
+    Ok(sha256_hex(&bytes))
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Uses the given .poly, or the first one in the current directory (same rule as the installer).
+pub fn find_poly(explicit: Option<&Path>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return Ok(path.to_path_buf());
+    }
+
+    for entry in fs::read_dir(".")? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("poly") {
+            return Ok(path);
+        }
+    }
+
+    Err(anyhow!("No .poly file found in the current directory"))
+}
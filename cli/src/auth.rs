@@ -1,5 +1,5 @@
-use anyhow::{Context, Result};
-use directories::ProjectDirs;
+use crate::paths;
+use anyhow::Result;
 use keyring::Entry;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -8,23 +8,52 @@ use std::path::PathBuf;
 /// Auth config split across two storage systems because I didn't want tokens in plaintext files.
 /// username + registry_url live in TOML on disk. Token lives in the system keyring (if you're lucky).
 /// #[serde(skip)] makes sure the token never gets serialized—learned that the hard way.
+///
+/// Portable mode (see `paths`) is the exception: school and shared machines often have no usable
+/// keyring, and a keyring entry wouldn't follow the USB stick anyway. There the token goes in
+/// its own `credentials.toml` inside the portable folder.
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct AuthConfig {
     #[serde(skip)]
     pub token: Option<String>,
     pub username: Option<String>,
     pub registry_url: Option<String>,
+    /// Which release channel `mosaic upgrade` follows. Lives here because this is the only
+    /// per-user config file we have; auth.toml is a bit of a misnomer at this point.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_channel: Option<String>,
+    /// Whether downloads carry the anonymous client header (see `telemetry`). Unset means off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<bool>,
+    /// Whether to look for a new mosaic release after commands (see `updater`). Unset means off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_check: Option<bool>,
+    /// Output colors: "dark", "light", "plain" or "auto" (see `theme`). Unset means auto.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
 }
 
 impl AuthConfig {
-    /// Gets the config directory using ProjectDirs to respect OS conventions.
-    /// Creates the directory if it doesn't exist. Will panic if your OS is from the 90s.
+    /// Gets the config file path. OS config dir normally, the portable folder in portable mode.
+    /// Creates the directory if it doesn't exist.
     pub fn get_path() -> Result<PathBuf> {
-        let proj_dirs = ProjectDirs::from("com", "mosaic", "mosaic")
-            .context("Could not determine config directory")?;
-        let config_dir = proj_dirs.config_dir();
-        fs::create_dir_all(config_dir)?;
-        Ok(config_dir.join("auth.toml"))
+        Ok(paths::config_dir()?.join("auth.toml"))
+    }
+
+    /// Where the token lives in portable mode.
+    fn credentials_path() -> Result<PathBuf> {
+        Ok(paths::config_dir()?.join("credentials.toml"))
+    }
+
+    /// Just auth.toml, no token. For settings read on every run (like the theme), where a
+    /// keyring lookup would be a slow way to find out which colors to use.
+    pub fn load_settings() -> Result<Self> {
+        let path = Self::get_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
     }
 
     /// Loads config from disk + tries to pull the token from the system keyring.
@@ -32,14 +61,29 @@ impl AuthConfig {
     /// If the keyring is locked/broken/whatever, we just continue without a token.
     /// The user will get a proper "not authenticated" error later if they actually need it.
     /// This is intentional—don't make startup fail because some system daemon is being weird.
+    ///
+    /// MOSAIC_TOKEN (an API token from `mosaic token create`) beats both, and is taken to be
+    /// for whichever registry this run uses. That's how CI logs in.
     pub fn load() -> Result<Self> {
-        let path = Self::get_path()?;
-        let mut config: AuthConfig = if path.exists() {
-            let content = fs::read_to_string(path)?;
-            toml::from_str(&content)?
-        } else {
-            Self::default()
-        };
+        let mut config = Self::load_settings()?;
+
+        if let Some(token) = std::env::var("MOSAIC_TOKEN").ok().filter(|t| !t.trim().is_empty()) {
+            let token = token.trim().to_string();
+            config.username = token_username(&token).or(config.username);
+            config.registry_url = Some(crate::endpoint::registry_url());
+            config.token = Some(token);
+            return Ok(config);
+        }
+
+        if paths::is_portable() {
+            let creds = Self::credentials_path()?;
+            if creds.exists() {
+                let content = fs::read_to_string(creds)?;
+                let stored: PortableCredentials = toml::from_str(&content)?;
+                config.token = stored.token;
+            }
+            return Ok(config);
+        }
 
         if let Some(raw_username) = &config.username {
             let username = raw_username.trim();
@@ -65,6 +109,21 @@ impl AuthConfig {
         let content = toml::to_string_pretty(self)?;
         fs::write(path, content)?;
 
+        if paths::is_portable() {
+            let creds = Self::credentials_path()?;
+            match &self.token {
+                Some(token) => write_private(
+                    &creds,
+                    &toml::to_string_pretty(&PortableCredentials {
+                        token: Some(token.clone()),
+                    })?,
+                )?,
+                None if creds.exists() => fs::remove_file(creds)?,
+                None => {}
+            }
+            return Ok(());
+        }
+
         if let Some(raw_username) = &self.username {
             let username = raw_username.trim();
             if let Ok(entry) =
@@ -83,6 +142,44 @@ impl AuthConfig {
         Ok(())
     }
 
+    /// Switches stored credentials over to a new username after `mosaic account rename`.
+    /// The keyring entry is keyed on the username, so the old one is deleted rather than orphaned.
+    pub fn rename(&mut self, new_username: &str, token: String) -> Result<()> {
+        if !paths::is_portable()
+            && let Some(old) = self.username.as_deref().map(str::trim)
+            && let Ok(entry) =
+                Entry::new_with_target("mosaic-package-manager", "mosaic-package-manager", old)
+        {
+            let _ = entry.delete_credential();
+        }
+
+        self.username = Some(new_username.to_string());
+        self.token = Some(token);
+        self.save()
+    }
+
+    /// Returns the stored token, but only if it was issued by `registry_url`.
+    ///
+    /// Credentials are bound to the registry you logged in to. Sending a token from
+    /// one registry to another would leak it to whoever runs the second one, so we refuse.
+    pub fn token_for(&self, registry_url: &str) -> Result<&String> {
+        let Some(token) = self.token.as_ref() else {
+            return Err(AuthRequired::new(registry_url, AuthReason::NotLoggedIn).into());
+        };
+
+        let bound_to = self
+            .registry_url
+            .as_deref()
+            .map(crate::endpoint::normalize)
+            .unwrap_or_else(|| crate::endpoint::DEFAULT_REGISTRY.to_string());
+
+        if bound_to != crate::endpoint::normalize(registry_url) {
+            return Err(AuthRequired::new(registry_url, AuthReason::OtherRegistry(bound_to)).into());
+        }
+
+        Ok(token)
+    }
+
     /// Nukes all auth data everywhere: config file + keyring.
     ///
     /// Has to load the config first just to get the username for keyring cleanup.
@@ -91,10 +188,23 @@ impl AuthConfig {
     pub fn logout() -> Result<()> {
         let path = Self::get_path()?;
 
+        if paths::is_portable() {
+            let creds = Self::credentials_path()?;
+            if creds.exists() {
+                fs::remove_file(creds)?;
+            }
+        }
+
         if path.exists() {
             let content = fs::read_to_string(&path)?;
+            let mut update_channel = None;
+            let mut telemetry = None;
+            let mut theme = None;
             if let Ok(config) = toml::from_str::<AuthConfig>(&content) {
-                if let Some(raw_username) = config.username {
+                update_channel = config.update_channel;
+                telemetry = config.telemetry;
+                theme = config.theme;
+                if let Some(raw_username) = config.username.filter(|_| !paths::is_portable()) {
                     let username = raw_username.trim();
                     if let Ok(entry) = Entry::new_with_target(
                         "mosaic-package-manager",
@@ -105,9 +215,134 @@ impl AuthConfig {
                     }
                 }
             }
-            fs::remove_file(path)?;
+            fs::remove_file(&path)?;
+
+            // Logging out shouldn't quietly move you back to the stable channel,
+            // flip your telemetry choice, or change your colors.
+            if update_channel.is_some() || telemetry.is_some() || theme.is_some() {
+                let keep = AuthConfig {
+                    update_channel,
+                    telemetry,
+                    theme,
+                    ..Default::default()
+                };
+                fs::write(path, toml::to_string_pretty(&keep)?)?;
+            }
         }
 
         Ok(())
     }
 }
+
+/// Why a command couldn't authenticate.
+#[derive(Debug)]
+pub enum AuthReason {
+    /// No token stored at all.
+    NotLoggedIn,
+    /// There's a token, but it belongs to this other registry.
+    OtherRegistry(String),
+    /// We sent a token and the registry said 401—expired, revoked, or the server's secret changed.
+    Rejected,
+}
+
+/// Every "you need to log in" failure, whichever way it happened.
+///
+/// It's a real type instead of a context string so main can spot it with `downcast_ref`:
+/// interactive terminals get offered a login and the command runs again, `--json` gets
+/// `{"error": {"code": "AUTH_REQUIRED", ...}}`.
+#[derive(Debug)]
+pub struct AuthRequired {
+    pub registry: String,
+    pub reason: AuthReason,
+}
+
+impl AuthRequired {
+    /// Machine-readable error code for `--json` output.
+    pub const CODE: &'static str = "AUTH_REQUIRED";
+
+    pub fn new(registry: &str, reason: AuthReason) -> Self {
+        Self {
+            registry: crate::endpoint::normalize(registry),
+            reason,
+        }
+    }
+
+    /// What to run to fix it. Only mentions --registry when it's not the public one.
+    pub fn login_command(&self) -> String {
+        if self.registry == crate::endpoint::DEFAULT_REGISTRY {
+            "mosaic login".to_string()
+        } else {
+            format!("mosaic login --registry {}", self.registry)
+        }
+    }
+}
+
+impl std::fmt::Display for AuthRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.reason {
+            AuthReason::NotLoggedIn => write!(f, "Not logged in. Run '{}' first.", self.login_command()),
+            AuthReason::OtherRegistry(bound_to) => write!(
+                f,
+                "You're logged in to {}, not {}. Run '{}' first.",
+                bound_to,
+                self.registry,
+                self.login_command()
+            ),
+            AuthReason::Rejected => write!(
+                f,
+                "{} rejected your credentials (expired or revoked). Run '{}' again.",
+                self.registry,
+                self.login_command()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AuthRequired {}
+
+/// Turns a 401 from an authenticated request into `AuthRequired`, so it gets the same
+/// treatment as not being logged in at all. Anything else passes through for the caller to handle.
+pub fn ensure_authorized(status: reqwest::StatusCode, registry_url: &str) -> Result<()> {
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(AuthRequired::new(registry_url, AuthReason::Rejected).into());
+    }
+    Ok(())
+}
+
+/// The token file used in portable mode instead of the keyring.
+#[derive(Serialize, Deserialize)]
+struct PortableCredentials {
+    token: Option<String>,
+}
+
+/// The username inside a registry token (a JWT), for display. The registry checks the rest.
+fn token_username(token: &str) -> Option<String> {
+    use base64::Engine;
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    claims["username"].as_str().map(str::to_string)
+}
+
+/// Writes a file only the current user can read, where the OS lets us say so.
+/// On Windows the folder's ACLs are all we get.
+fn write_private(path: &std::path::Path, content: &str) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
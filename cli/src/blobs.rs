@@ -0,0 +1,162 @@
+use crate::logger::Logger;
+use crate::metadata;
+use crate::paths;
+use anyhow::Result;
+use comfy_table::Table;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Which packages a cached zip was downloaded as. Only for `mosaic cache ls`: the zip is
+/// found by its hash, and two versions that ship the same bytes share one file.
+#[derive(Serialize, Deserialize, Default)]
+struct Label {
+    packages: Vec<String>,
+}
+
+/// Where downloaded zips are kept: `<cache dir>/blobs/<sha256>.zip`, shared by every project.
+pub fn dir() -> Result<PathBuf> {
+    let dir = paths::cache_dir()?.join("blobs");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// The cached zip with this SHA-256, rewound and ready to read. None with `--no-cache`, if
+/// it isn't cached, or if `hash` isn't a SHA-256 at all. The file is hashed again on the way
+/// out, so a corrupted one is thrown away and downloaded fresh rather than installed.
+pub fn get(hash: &str) -> Option<File> {
+    if metadata::no_cache() || !crate::registry::is_sha256(hash) {
+        return None;
+    }
+    let path = dir().ok()?.join(format!("{}.zip", hash));
+    let mut file = File::open(&path).ok()?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    if format!("{:x}", hasher.finalize()) != hash {
+        Logger::debug(format!("Cached {} doesn't match its hash, dropping it", path.display()));
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    file.seek(SeekFrom::Start(0)).ok()?;
+    // Last used, for `mosaic cache ls`.
+    let _ = File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now()));
+    Some(file)
+}
+
+/// Keeps a downloaded zip that's passed every check, as `name@version`. Errors are only
+/// logged: a full disk shouldn't fail an install that already has its bytes.
+pub fn put(hash: &str, file: &mut File, package: &str) {
+    if let Err(e) = store(hash, file, package) {
+        Logger::debug(format!("Couldn't cache {}: {}", package, e));
+    }
+}
+
+fn store(hash: &str, file: &mut File, package: &str) -> Result<()> {
+    if !crate::registry::is_sha256(hash) {
+        return Err(anyhow::anyhow!("{:?} isn't a sha256", hash));
+    }
+    let dir = dir()?;
+    let path = dir.join(format!("{}.zip", hash));
+    if !path.exists() {
+        // Written next to it and renamed into place, so a half-written zip is never found.
+        let mut tmp = tempfile::NamedTempFile::new_in(&dir)?;
+        file.seek(SeekFrom::Start(0))?;
+        std::io::copy(file, &mut tmp)?;
+        file.seek(SeekFrom::Start(0))?;
+        tmp.persist(&path)?;
+    }
+
+    let label_path = dir.join(format!("{}.json", hash));
+    let mut label: Label = fs::read_to_string(&label_path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    if !label.packages.iter().any(|p| p == package) {
+        label.packages.push(package.to_string());
+        label.packages.sort();
+        fs::write(&label_path, serde_json::to_string(&label)?)?;
+    }
+    Ok(())
+}
+
+/// `mosaic cache ls`: every cached zip, with what it was downloaded as.
+pub fn list() -> Result<()> {
+    let dir = dir()?;
+    let mut rows = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "zip") {
+            continue;
+        }
+        let Some(hash) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let meta = fs::metadata(&path)?;
+        let label: Label = fs::read_to_string(dir.join(format!("{}.json", hash)))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let used = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        rows.push((label.packages.join(", "), meta.len(), used, hash.to_string()));
+    }
+
+    if rows.is_empty() {
+        Logger::info(format!("No cached packages in {}", Logger::dim(dir.display())));
+        return Ok(());
+    }
+
+    rows.sort();
+    let total: u64 = rows.iter().map(|(_, size, _, _)| size).sum();
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Size", "Last used", "SHA-256"]);
+    for (packages, size, used, hash) in &rows {
+        table.add_row(vec![
+            if packages.is_empty() { "-".to_string() } else { packages.clone() },
+            bytes(*size),
+            crate::registry::time_ago(*used),
+            hash[..12.min(hash.len())].to_string(),
+        ]);
+    }
+    println!("{}", table);
+    Logger::info(format!(
+        "{} cached zip{}, {} in {}",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" },
+        bytes(total),
+        Logger::dim(dir.display())
+    ));
+    Ok(())
+}
+
+/// Deletes every cached zip. Returns how many went.
+pub fn clean() -> Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir()?)? {
+        let path = entry?.path();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("zip") => {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+            Some("json") => fs::remove_file(path)?,
+            _ => {}
+        }
+    }
+    Ok(removed)
+}
+
+fn bytes(n: u64) -> String {
+    match n {
+        n if n >= 1024 * 1024 => format!("{:.1} MB", n as f64 / (1024.0 * 1024.0)),
+        n if n >= 1024 => format!("{:.1} KB", n as f64 / 1024.0),
+        n => format!("{} B", n),
+    }
+}
@@ -11,14 +11,31 @@ pub struct Cli {
 
     /// Let users override the registry URL if they're running their own instance.
     /// Useful for testing or if someone wants to self-host the registry.
-    /// `global = true` means it works with any subcommand.
-    #[arg(long, global = true)]
-    pub api_url: Option<String>,
+    /// `global = true` means it works with any subcommand, so `mosaic login --registry ...` just works.
+    /// `--api-url` is the old name, kept so existing scripts don't break.
+    #[arg(long, global = true, alias = "api-url")]
+    pub registry: Option<String>,
 
     /// Enable verbose logging for debugging.
     /// Prints detailed error messages and other internal info.
     #[arg(long, short, global = true)]
     pub verbose: bool,
+
+    /// Keep config, cache and credentials in a `mosaic-home` folder next to the binary
+    /// instead of OS config dirs and the keyring. For USB sticks and locked-down machines.
+    /// `MOSAIC_HOME=<dir>` does the same with a folder of your choosing.
+    #[arg(long, global = true)]
+    pub portable: bool,
+
+    /// Skip the cached registry metadata and package zips, and ask the registry directly.
+    /// The fresh answers still get cached for next time.
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+
+    /// Machine-readable mode for scripts and CI. Never prompts, and a failure prints one
+    /// `{"error": {"code": ..., "message": ...}}` object on stdout instead of prose.
+    #[arg(long, global = true)]
+    pub json: bool,
 }
 
 /// Every command the CLI supports. Pretty much what you'd expect from a package manager.
@@ -26,14 +43,57 @@ pub struct Cli {
 pub enum Commands {
     /// Sets up mosaic.toml in the current directory.
     /// Nothing fancy—just scaffolds the config file.
-    Init,
+    Init {
+        /// Adopt packages already pasted into the .poly: ModuleScripts named like registry
+        /// packages are matched to a version by their source and written to mosaic.toml.
+        #[arg(long)]
+        from_poly: bool,
+
+        /// The .poly to adopt from. Defaults to the one in the current directory. Implies --from-poly.
+        #[arg(long)]
+        poly: Option<std::path::PathBuf>,
+
+        /// Also scaffold src/init.lua, a README and a .mosaicignore from a built-in template.
+        /// Files that already exist are kept.
+        #[arg(long, value_parser = crate::init::TEMPLATES, conflicts_with_all = ["from_poly", "poly"])]
+        template: Option<String>,
+
+        /// Don't ask anything: write just the name and version. With --from-poly, adopt every
+        /// exact match without asking; modules that match no version are left alone.
+        #[arg(long)]
+        yes: bool,
+    },
 
     /// Install a package. Can handle:
     /// - Registry packages: `logger@1.0.0`
-    /// - GitHub repos: `github:username/repo` (might add this someday)
+    /// - Dist-tags: `router@beta`
+    /// - GitHub repos: `github:username/repo@tag`
     Install {
-        /// Package name (e.g. logger@1.0.0 or github:user/repo)
+        /// Package name (e.g. logger@1.0.0, router@beta or github:user/repo)
         package: Option<String>,
+
+        /// Record the package under [dev-dependencies] instead of [dependencies].
+        #[arg(long, requires = "package", conflicts_with = "no_save")]
+        save_dev: bool,
+
+        /// Install without touching mosaic.toml. Good for trying a package out.
+        /// The lockfile and .poly still get updated.
+        #[arg(long, requires = "package")]
+        no_save: bool,
+
+        /// Install exactly what mosaic.lock has, without asking the registry which versions to
+        /// use. Fails if mosaic.lock doesn't match mosaic.toml instead of updating it. For CI.
+        #[arg(long, visible_alias = "locked", conflicts_with = "package")]
+        frozen: bool,
+
+        /// Don't run the [hooks] post-install command from mosaic.toml.
+        #[arg(long)]
+        no_hooks: bool,
+
+        /// Print how long resolution, downloads, verification and .poly injection took.
+        /// `--timings=json` or `--timings=html` also writes mosaic-timings.json / .html.
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+        timings: Option<String>,
     },
 
     /// Removes a package from mosaic.toml and from your .poly file.
@@ -45,11 +105,122 @@ pub enum Commands {
 
     /// Lists everything installed. Reads from mosaic.toml.
     /// Useful if you forget what you added.
-    List,
+    List {
+        /// Show what's actually installed (mosaic.lock + .poly) and flag drift
+        #[arg(long)]
+        installed: bool,
 
-    /// Updates all packages to their latest versions.
-    /// Respects version constraints (if we implement those someday).
-    Update,
+        /// Also show each dependency's resolved version, license, status and description.
+        /// Descriptions come from the registry (cached like everything else).
+        #[arg(long, conflicts_with = "installed")]
+        long: bool,
+    },
+
+    /// Updates packages within the ranges declared in mosaic.toml.
+    /// With no names it updates everything; otherwise only the packages you list.
+    Update {
+        /// Packages to update (defaults to all of them)
+        packages: Vec<String>,
+
+        /// Ignore the declared ranges and jump to the newest release, majors included.
+        /// Rewrites mosaic.toml to match.
+        #[arg(long)]
+        latest: bool,
+
+        /// Show what would change without installing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Shows dependencies that have newer versions: in range (Wanted) and overall (Latest).
+    Outdated {
+        /// Exit with status 1 if anything is out of date, or couldn't be checked. For CI.
+        #[arg(long)]
+        exit_code: bool,
+    },
+
+    /// Shows past installs, updates and removes in this project: when, what changed, how
+    /// long it took and whether it failed. Kept in .mosaic/history.json.
+    History {
+        /// Only runs that changed this package or named it.
+        package: Option<String>,
+
+        /// How many runs to show, newest first.
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Manages what mosaic keeps in its cache directory.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Collapses duplicate versions of the same package in mosaic.lock.
+    /// Picks the highest version every dependent is happy with and rewrites the lockfile and .poly.
+    Dedupe,
+
+    /// Checks every version in mosaic.lock is still on the registry: not unpublished,
+    /// not taken down. Fails with the closest safe version for anything that isn't.
+    /// CI installs run the same check automatically.
+    Verify,
+
+    /// Links a package folder on this computer into a project, for working on both at once.
+    /// In the package: registers it. In a project: installs it from that folder, and keeps
+    /// doing so on every install until it's unlinked.
+    Link {
+        /// Package to use from its registered folder. Leave out to register this folder.
+        package: Option<String>,
+    },
+
+    /// Undoes `mosaic link`. In a project: back to the registry version. In the package:
+    /// unregisters it.
+    Unlink {
+        /// Package to stop using from its folder. Leave out to unregister this folder.
+        package: Option<String>,
+    },
+
+    /// Checks the .poly for problems mosaic can spot, like two ModuleScripts with the same name.
+    Doctor {
+        /// Keep a single copy of each duplicated package, reinstalled from mosaic.lock.
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Rebuilds mosaic.lock from mosaic.toml and shows what changed.
+    /// Handy after a merge conflict in the lockfile. Dry run unless you pass --repair.
+    Lock {
+        /// Actually write the regenerated lockfile
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Asks the registry admins for a reserved name or an abandoned package.
+    Claim {
+        /// The package name you want
+        #[arg(required_unless_present = "list")]
+        name: Option<String>,
+
+        /// Who you are and why the name should be yours. Prompted for if left out.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Show your claims and where they stand instead.
+        #[arg(long, conflicts_with_all = ["name", "reason"])]
+        list: bool,
+    },
+
+    /// Account settings.
+    Account {
+        #[command(subcommand)]
+        command: AccountCommands,
+    },
+
+    /// API tokens for CI. Set one as MOSAIC_TOKEN and `mosaic publish` uses it instead of your login.
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
 
     /// Logs you in. Stores credentials securely (hopefully).
     /// Prompts for username/password and stashes the token in the system keyring.
@@ -69,6 +240,36 @@ pub enum Commands {
         /// Optional version string (defaults to mosaic.toml version)
         /// Useful if you want to bump the version from the CLI instead of editing the file.
         version: Option<String>,
+
+        /// Dist-tag to point at this version (e.g. `beta`). Defaults to `latest` on the registry.
+        /// Handy for pre-releases you don't want `mosaic install name` to pick up yet.
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Include symlinked files, as long as they point somewhere inside the project.
+        /// Off by default so a stray link can't smuggle ~/.ssh into a package.
+        #[arg(long)]
+        follow_symlinks: bool,
+
+        /// Publish even if some .lua files don't parse. You almost certainly don't want this.
+        #[arg(long)]
+        allow_syntax_errors: bool,
+
+        /// Skip the summary and confirmation prompt. For CI and scripts.
+        #[arg(long, short)]
+        yes: bool,
+    },
+
+    /// Zips the package exactly as `publish` would, without publishing it.
+    /// Same source, same bytes, same SHA-256, on any machine.
+    Pack {
+        /// Where to write the zip. Defaults to .mosaic/<name>-<version>.zip.
+        #[arg(long, short)]
+        out: Option<std::path::PathBuf>,
+
+        /// Include symlinked files that point somewhere inside the project, like `publish`.
+        #[arg(long)]
+        follow_symlinks: bool,
     },
 
     /// Searches the registry for packages.
@@ -76,6 +277,10 @@ pub enum Commands {
     Search {
         /// Search query
         query: String,
+
+        /// Order results by: relevance (default), downloads, trending (last 7 days), newest, updated
+        #[arg(long, value_parser = ["relevance", "downloads", "trending", "newest", "updated"])]
+        sort: Option<String>,
     },
 
     /// Shows details about a package without installing it.
@@ -86,15 +291,465 @@ pub enum Commands {
         package: String,
     },
 
+    /// Prints a file from a published package without installing it.
+    /// Good for reading the source before you trust it with your game.
+    View {
+        /// Package name, optionally with a version or tag (e.g. logger@1.2.0)
+        package: String,
+
+        /// Path of the file inside the package (e.g. init.lua)
+        file: String,
+    },
+
+    /// Shows the module a package would inject, highlighted, without installing it.
+    /// Never runs anything: it's for reading the code before you trust it.
+    Exec {
+        /// Package name, optionally with a version or tag (e.g. logger@1.2.0)
+        package: String,
+
+        /// Write the module to this file instead of printing it.
+        #[arg(long)]
+        save: Option<std::path::PathBuf>,
+    },
+
+    /// Shows a file-by-file diff between two versions of a package.
+    /// Defaults to your installed version vs. the latest.
+    Diff {
+        /// Package name to diff
+        package: String,
+
+        /// Version to diff from (defaults to the version in mosaic.lock)
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Version or tag to diff to (defaults to latest)
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// Shows changelog entries between your installed version and the latest.
+    /// Read this before `mosaic update --latest`. Future you will thank you.
+    Changelog {
+        /// Package name to look up
+        package: String,
+    },
+
+    /// Publishes every package folder inside a directory, dependencies first.
+    /// For seeding a self-hosted registry. Safe to re-run: already-published versions are skipped.
+    MigratePublish {
+        /// Directory containing one folder per package, each with its own mosaic.toml.
+        dir: std::path::PathBuf,
+
+        /// Print the publish order without publishing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Writes a signed manifest of everything embedded in your .poly, or checks one.
+    /// Signed with MOSAIC_ATTEST_KEY, so only people with the key can produce a valid one.
+    Attest {
+        /// Check an existing attestation instead of writing a new one.
+        #[arg(long)]
+        verify: bool,
+
+        /// The .poly to attest or verify. Defaults to the one in the current directory.
+        #[arg(long)]
+        poly: Option<std::path::PathBuf>,
+
+        /// Attestation file to write or read. Defaults to mosaic.attest.json.
+        #[arg(long)]
+        file: Option<std::path::PathBuf>,
+    },
+
     /// Removes a version from the registry.
     /// Only works within 24 hours of publishing and if no one else depends on it.
     /// Use this if you accidentally uploaded your cat's photos instead of code.
     Unpublish {
         /// Package name and version (e.g. logger@1.0.0)
         package: String,
+
+        /// Skip the confirmation prompt. For scripts.
+        #[arg(long, short)]
+        yes: bool,
+    },
+
+    /// Makes new versions of your package wait for your approval before anyone can install them.
+    /// For packages a lot of people depend on: a stolen login alone can't ship a release.
+    Protect {
+        /// Package name
+        package: String,
+
+        /// Turn protection off. Takes effect after the registry's delay (an hour by default).
+        #[arg(long)]
+        off: bool,
+    },
+
+    /// Makes your package public or private.
+    /// A private package only exists for you and its members: for studios sharing internal libraries.
+    Visibility {
+        /// Package name
+        package: String,
+
+        /// public or private
+        #[arg(value_parser = ["public", "private"])]
+        visibility: String,
+    },
+
+    /// Lists who can see a private package. The owner can add and remove people.
+    Members {
+        /// Package name
+        package: String,
+
+        /// Let this user see and install the package. Can be repeated.
+        #[arg(long, value_name = "USERNAME")]
+        add: Vec<String>,
+
+        /// Take this user off the list. Can be repeated.
+        #[arg(long, value_name = "USERNAME")]
+        remove: Vec<String>,
+    },
+
+    /// Publishes a version of a protected package that's waiting for approval.
+    Approve {
+        /// Package name and version (e.g. logger@1.0.0)
+        package: String,
+
+        /// The token from the confirmation link the registry sent your webhooks.
+        #[arg(long)]
+        token: String,
+    },
+
+    /// Throws away a version of a protected package that's waiting for approval.
+    Reject {
+        /// Package name and version (e.g. logger@1.0.0)
+        package: String,
+    },
+
+    /// Prints a shell completion script.
+    /// bash, zsh and fish also complete package names for `install` and `info`, straight from the registry.
+    Completions {
+        /// Shell to generate for (bash, zsh, fish, powershell, elvish)
+        shell: clap_complete::Shell,
+    },
+
+    /// Used by the completion scripts. Prints package names starting with a prefix.
+    #[command(name = "__complete-packages", hide = true)]
+    CompletePackages {
+        prefix: Option<String>,
+    },
+
+    /// Picks output colors: dark, light, plain (no color at all) or auto (guess from the terminal).
+    /// NO_COLOR always wins; MOSAIC_THEME overrides the saved choice for one run.
+    Theme {
+        /// "auto", "dark", "light" or "plain". Leave it out to see what's in effect.
+        #[arg(value_parser = crate::theme::NAMES)]
+        theme: Option<String>,
+    },
+
+    /// Opt in (or out) of sending your CLI version and OS with package downloads.
+    /// Off by default. Nothing else is sent, and the registry only keeps daily totals.
+    Telemetry {
+        /// "on", "off", or "status" (the default).
+        #[arg(value_parser = ["on", "off", "status"])]
+        action: Option<String>,
+    },
+
+    /// Checks GitHub for a newer mosaic, or turns the once-a-day automatic check on or off.
+    /// Off by default. The automatic check never runs on CI.
+    CheckUpdate {
+        /// "on" or "off". Leave it out to check right now.
+        #[arg(value_parser = ["on", "off"])]
+        action: Option<String>,
     },
 
     /// Upgrades the Mosaic CLI to the latest version.
-    /// Fetches the latest binary from GitHub Releases and replaces the current executable.
-    Upgrade,
+    /// Fetches the latest binary from GitHub Releases, verifies its signed checksum,
+    /// and replaces the current executable.
+    Upgrade {
+        /// Release channel to follow: "stable" or "beta". Remembered for next time.
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// Install exactly this version instead of the newest one (e.g. 0.5.2).
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Install the latest release even if it's older than what you're running.
+        #[arg(long)]
+        allow_downgrade: bool,
+    },
+
+    /// Moderation tools for registry operators. Needs MOSAIC_ADMIN_TOKEN.
+    /// Regular users can ignore this—the registry rejects everyone else anyway.
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommands,
+    },
+}
+
+/// `mosaic account ...` subcommands.
+#[derive(Subcommand)]
+pub enum AccountCommands {
+    /// Changes your username. Your packages move with you, and the old name keeps redirecting to you.
+    Rename {
+        /// The new username
+        username: String,
+    },
+}
+
+/// `mosaic token ...` subcommands.
+#[derive(Subcommand)]
+pub enum TokenCommands {
+    /// Creates a token. It's printed once, so put it straight into your CI secrets.
+    Create {
+        /// What it's for, e.g. "my-ui-lib CI"
+        name: String,
+
+        /// Only allow publishing and managing this package. Repeat for more.
+        /// Without it the token can do everything your login can.
+        #[arg(long = "package", value_name = "NAME")]
+        packages: Vec<String>,
+
+        /// Days until it expires (90 by default, at most 365)
+        #[arg(long)]
+        days: Option<i64>,
+    },
+
+    /// Lists your tokens that haven't expired or been revoked.
+    List,
+
+    /// Revokes a token. Anything still using it gets a 401.
+    Revoke {
+        /// The token's id, from `mosaic token list`
+        id: String,
+    },
+}
+
+/// `mosaic cache ...` subcommands.
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Lists the package zips kept from earlier installs, in any project.
+    Ls,
+
+    /// Deletes cached data. Everything by default.
+    Clean {
+        /// Only drop cached registry metadata (package info, version lists, search results)
+        #[arg(long)]
+        metadata: bool,
+    },
+}
+
+/// `mosaic admin ...` subcommands. Each one maps to a single /admin endpoint.
+#[derive(Subcommand)]
+pub enum AdminCommands {
+    /// Hides a package and stops it from downloading. Reversible with --undo.
+    Takedown {
+        /// Package name
+        package: String,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Restore a taken-down package instead.
+        #[arg(long, conflicts_with = "reason")]
+        undo: bool,
+    },
+
+    /// Features a package: a badge in search results and a bump in search relevance.
+    Feature {
+        /// Package name
+        package: String,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Take it off the featured list instead.
+        #[arg(long, conflicts_with = "reason")]
+        undo: bool,
+    },
+
+    /// Blocks a user from logging in and kills their existing sessions.
+    BanUser {
+        /// Username
+        username: String,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Lift the ban instead.
+        #[arg(long, conflicts_with = "reason")]
+        undo: bool,
+    },
+
+    /// Marks a user as verified, which gives them a bigger per-package publish quota and a ✓
+    /// next to their name.
+    VerifyUser {
+        /// Username
+        username: String,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Remove verification instead.
+        #[arg(long, conflicts_with = "reason")]
+        undo: bool,
+    },
+
+    /// Deletes a package and all its versions so the name can be claimed again. Permanent.
+    ReleaseName {
+        /// Package name
+        package: String,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Skip the confirmation prompt.
+        #[arg(long, short)]
+        yes: bool,
+    },
+
+    /// Registry-wide counts: users, packages, versions, downloads.
+    Stats,
+
+    /// Downloads per CLI version and OS, from users who opted in to telemetry.
+    Clients {
+        /// How many days back to look, today included.
+        #[arg(long, default_value_t = 30)]
+        days: u32,
+    },
+
+    /// Runs the integrity job: download counters, half-finished versions, missing blobs.
+    Check {
+        /// Fix what can be fixed safely (counters, stale half-uploaded versions).
+        /// Missing blobs are only ever reported.
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Downloads a full dump of the registry (NDJSON) for moving to another host.
+    Export {
+        /// Where to write the dump.
+        output: std::path::PathBuf,
+
+        /// Include package zips. Without them the dump is metadata only.
+        #[arg(long)]
+        blobs: bool,
+
+        /// Include password hashes so users can still log in after the move.
+        #[arg(long)]
+        password_hashes: bool,
+    },
+
+    /// Loads a dump from `mosaic admin export` into an empty registry.
+    Import {
+        /// The dump file.
+        input: std::path::PathBuf,
+    },
+
+    /// Reads the admin audit log.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+
+    /// Reviews name claims.
+    Claims {
+        #[command(subcommand)]
+        command: ClaimCommands,
+    },
+
+    /// Manages the package/username blocklist, allowlist and reserved prefixes.
+    Names {
+        #[command(subcommand)]
+        command: NameCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ClaimCommands {
+    /// Shows claims waiting for review, oldest first.
+    List {
+        /// pending, approved, rejected or all.
+        #[arg(long, default_value = "pending")]
+        status: String,
+    },
+
+    /// Approves a claim: transfers the package, or lets the claimant publish the reserved name.
+    Approve {
+        /// Claim id, from `mosaic admin claims list`.
+        id: i64,
+
+        /// Why. Goes into the audit log and to the claimant.
+        #[arg(long)]
+        reason: Option<String>,
+
+        /// Transfer even if the package isn't abandoned or the owner's notice period isn't over.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Rejects a claim.
+    Reject {
+        /// Claim id, from `mosaic admin claims list`.
+        id: i64,
+
+        /// Why. Goes into the audit log and to the claimant.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NameCommands {
+    /// Shows every name rule.
+    List,
+
+    /// Adds a rule. Takes effect on the next signup or publish, no redeploy needed.
+    Add {
+        /// block, allow, reserved, reserved-prefix or approved
+        kind: String,
+
+        /// The word, prefix or (for approved) exact package name.
+        pattern: String,
+
+        /// Who may claim the name. Required for approved.
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+
+    /// Removes a rule.
+    Remove {
+        /// block, allow, reserved, reserved-prefix or approved
+        kind: String,
+
+        /// The pattern to remove.
+        pattern: String,
+
+        /// Why. Goes into the audit log.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
+    /// Shows the most recent admin actions.
+    Tail {
+        /// How many entries to show.
+        #[arg(long, short = 'n', default_value_t = 20)]
+        lines: u32,
+
+        /// Keep polling for new entries until Ctrl+C.
+        #[arg(long, short)]
+        follow: bool,
+    },
 }
@@ -0,0 +1,189 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::endpoint;
+use crate::lockfile::Lockfile;
+use crate::paths;
+use anyhow::Result;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long we wait on the registry before giving up and completing from the cache.
+/// Anything longer and you feel it every time you hit tab.
+const LOOKUP_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// How long a prefix we already asked about counts as fresh. Package names don't change much.
+const CACHE_TTL_SECS: u64 = 60 * 60;
+
+/// Every package name we've ever seen while completing, plus when we last asked about each prefix.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CompletionCache {
+    #[serde(default)]
+    names: BTreeSet<String>,
+    #[serde(default)]
+    queried: HashMap<String, u64>,
+}
+
+impl CompletionCache {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::cache_dir()?.join("completions.json"))
+    }
+
+    fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(Self::path()?, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Whether we asked the registry about this prefix (or a shorter one) recently.
+    /// Asking about "lo" an hour ago covers "log" too—the search is a substring match.
+    fn is_fresh(&self, prefix: &str, now: u64) -> bool {
+        self.queried.iter().any(|(q, at)| {
+            prefix.starts_with(q.as_str()) && now.saturating_sub(*at) < CACHE_TTL_SECS
+        })
+    }
+}
+
+/// Forgets every package name we've cached for completion. Used by `mosaic cache clean`.
+pub fn clear_cache() -> Result<()> {
+    match fs::remove_file(CompletionCache::path()?) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Prints the completion script for `shell`.
+///
+/// clap generates the static part (commands, flags). On top of that, bash, zsh and fish get a
+/// small hook that asks `mosaic __complete-packages` for package names after `install` and `info`.
+pub fn generate(shell: Shell) {
+    let mut cmd = Cli::command();
+    let mut out = std::io::stdout();
+    clap_complete::generate(shell, &mut cmd, "mosaic", &mut out);
+
+    if let Some(hook) = package_hook(shell) {
+        println!("{}", hook);
+    }
+}
+
+/// The dynamic package-name bit for each shell. PowerShell and elvish only get the static script.
+fn package_hook(shell: Shell) -> Option<&'static str> {
+    match shell {
+        Shell::Bash => Some(
+            r#"
+_mosaic_with_packages() {
+    if [[ ${COMP_CWORD} -eq 2 && ( "${COMP_WORDS[1]}" == "install" || "${COMP_WORDS[1]}" == "info" ) ]]; then
+        local cur="${COMP_WORDS[COMP_CWORD]}"
+        COMPREPLY=( $(mosaic __complete-packages "${cur}" 2>/dev/null) )
+        return 0
+    fi
+    _mosaic "$@"
+}
+complete -F _mosaic_with_packages -o bashdefault -o default mosaic"#,
+        ),
+        Shell::Zsh => Some(
+            r#"
+_mosaic_with_packages() {
+    if (( CURRENT == 3 )) && [[ "${words[2]}" == (install|info) ]]; then
+        compadd -- ${(f)"$(mosaic __complete-packages "${words[3]}" 2>/dev/null)"}
+        return
+    fi
+    _mosaic "$@"
+}
+compdef _mosaic_with_packages mosaic"#,
+        ),
+        Shell::Fish => Some(
+            r#"
+complete -c mosaic -n "__fish_seen_subcommand_from install info" -f -a "(mosaic __complete-packages (commandline -ct) 2>/dev/null)""#,
+        ),
+        _ => None,
+    }
+}
+
+/// Prints package names starting with `prefix`, one per line. What the shell hooks call.
+///
+/// 1. Names from the current project (mosaic.toml, mosaic.lock)—always available, even offline.
+/// 2. Names from the local cache.
+/// 3. If the cache doesn't cover this prefix yet, a quick registry search, merged into the cache.
+///
+/// Never errors: a completion helper that prints a stack trace into your prompt is worse than none.
+pub async fn complete_packages(prefix: &str) {
+    let prefix = prefix.trim();
+    // "logger@1.2" completes the name part only; the version is the user's business.
+    let name_prefix = prefix.split('@').next().unwrap_or("").to_ascii_lowercase();
+
+    let mut names: BTreeSet<String> = BTreeSet::new();
+
+    // 1. Local project
+    if let Ok(config) = Config::load() {
+        names.extend(config.dependencies.into_keys());
+        names.extend(config.dev_dependencies.into_keys());
+    }
+    if let Ok(lock) = Lockfile::load() {
+        names.extend(lock.packages.into_keys());
+    }
+
+    // 2. Cache
+    let mut cache = CompletionCache::load();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    // 3. Registry. Skipped for an empty prefix—listing the whole registry on a bare tab isn't helpful.
+    if !name_prefix.is_empty()
+        && !cache.is_fresh(&name_prefix, now)
+        && let Some(found) = search_names(&name_prefix).await
+    {
+        cache.names.extend(found);
+        cache.queried.insert(name_prefix.clone(), now);
+        cache.queried.retain(|_, at| now.saturating_sub(*at) < CACHE_TTL_SECS);
+        let _ = cache.save();
+    }
+    names.extend(cache.names);
+
+    for name in names
+        .iter()
+        .filter(|n| n.to_ascii_lowercase().starts_with(&name_prefix))
+    {
+        println!("{}", name);
+    }
+}
+
+/// Asks the registry for names matching `query`. None if it's slow, down, or says no.
+async fn search_names(query: &str) -> Option<Vec<String>> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(LOOKUP_TIMEOUT)
+        .timeout(LOOKUP_TIMEOUT)
+        .build()
+        .ok()?;
+
+    let registry_url = endpoint::registry_url();
+    let res = endpoint::with_auth(client.get(format!("{}/packages/search", registry_url)), &registry_url)
+        .query(&[("q", query)])
+        .send()
+        .await
+        .ok()?;
+    if !res.status().is_success() {
+        return None;
+    }
+
+    let packages: Vec<serde_json::Value> = res.json().await.ok()?;
+    Some(
+        packages
+            .iter()
+            .filter_map(|p| p["name"].as_str().map(|s| s.to_string()))
+            .collect(),
+    )
+}
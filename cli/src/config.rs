@@ -1,12 +1,64 @@
+use crate::logger::Logger;
+use crate::spec::GithubSource;
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+
+/// The mosaic.toml format this build reads and writes (`schema = 1` at the top of the file).
+///
+/// Bump it when a change would make an older mosaic misread the file, and add a step to
+/// [`MIGRATIONS`]. Files from a newer mosaic are refused outright: guessing at fields we've
+/// never heard of is how dependencies get silently dropped.
+pub const SCHEMA: i64 = 1;
+
+/// `MIGRATIONS[n]` turns a schema-n file into schema n+1. Files from before `schema` existed
+/// count as 0.
+const MIGRATIONS: &[fn(&mut toml::Table)] = &[
+    // 0 -> 1: nothing moved. The field just didn't exist yet.
+    |_| {},
+];
 
 /// The main config struct that mirrors mosaic.toml.
 /// Split into package metadata and dependencies because it's cleaner that way.
-#[derive(Serialize, Deserialize, Debug)]
+///
+/// Dependencies are kept as plain `name -> query` maps no matter how they were written,
+/// with any `sha256` pins pulled out into `integrity`. Saving puts them back together.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "RawConfig", into = "RawConfig")]
 pub struct Config {
     pub package: PackageConfig,
     pub dependencies: HashMap<String, String>,
+    /// Stuff you need while developing but that isn't part of the package contract
+    /// (test helpers, debug tooling). Installed locally, never sent to the registry on publish.
+    #[serde(
+        default,
+        rename = "dev-dependencies",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub dev_dependencies: HashMap<String, String>,
+    /// Integrity pins from the manifest: `name -> sha256` of the zip blob.
+    /// Written as `logger = { version = "1.2.0", sha256 = "..." }`. Unlike the lockfile,
+    /// these survive `mosaic lock --repair` and a deleted mosaic.lock, so a reviewed hash stays reviewed.
+    pub integrity: HashMap<String, String>,
+    /// Dependencies that come from a GitHub repo instead of the registry, written as
+    /// `cool-lib = { github = "alice/cool-lib", rev = "v1.2.0" }`. Their query in
+    /// `dependencies` is just "*": the repo's own mosaic.toml decides the version.
+    pub github: HashMap<String, GithubSource>,
+    /// Dependencies installed under a name of your own: `local name -> registry package`,
+    /// written as `log = { package = "super-logger", version = "^2" }`. The local name is what
+    /// goes in the .poly (so it's what you `require`) and in mosaic.lock. The registry only
+    /// ever hears the package's real name.
+    pub aliases: HashMap<String, String>,
+    /// Commands to run at points in the install. Only ever read from *your* mosaic.toml—a
+    /// package you download can't bring hooks along. See `hooks`.
+    pub hooks: HooksConfig,
+    /// `[patch]`: packages to take from a local folder or a git repo instead of the registry,
+    /// wherever they show up in the tree. Like hooks, only *your* mosaic.toml counts.
+    pub patch: HashMap<String, PatchSource>,
+    /// The schema and original text of a file that [`MIGRATIONS`] actually changed, for `save`
+    /// to back up. None if the file was current or only its `schema` number was behind.
+    migrated_from: Option<(i64, String)>,
 }
 
 impl Config {
@@ -17,47 +69,435 @@ impl Config {
             package: PackageConfig {
                 name: name.to_string(),
                 version: "0.1.0".to_string(),
+                description: None,
+                author: None,
+                license: None,
+                repository: None,
+                entry: None,
+                registry: None,
+                fallback_registries: Vec::new(),
+                private: false,
             },
             dependencies: HashMap::new(),
+            dev_dependencies: HashMap::new(),
+            integrity: HashMap::new(),
+            github: HashMap::new(),
+            aliases: HashMap::new(),
+            hooks: HooksConfig::default(),
+            patch: HashMap::new(),
+            migrated_from: None,
         }
     }
 
     /// Reads mosaic.toml from disk and parses it.
     /// Assumes you're running from the project root. Will fail if you're not.
+    ///
+    /// An older schema is migrated in memory. It only reaches the disk if the command saves
+    /// mosaic.toml anyway, so read-only commands leave the file alone.
     pub fn load() -> anyhow::Result<Self> {
-        let content = std::fs::read_to_string("mosaic.toml")?;
-        let config: Config = toml::from_str(&content)?;
+        Self::read(Path::new("."))
+    }
+
+    /// Same as load(), but for a project somewhere other than the current directory.
+    pub fn load_from(dir: &Path) -> anyhow::Result<Self> {
+        Self::read(dir)
+    }
+
+    fn read(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join("mosaic.toml");
+        let content = std::fs::read_to_string(&path)?;
+        let mut table: toml::Table = toml::from_str(&content)?;
+
+        // Checked before the real parse, so a newer file gets "upgrade" and not serde's
+        // opinion of a field it doesn't know.
+        let schema = match table.get("schema") {
+            None => 0,
+            Some(toml::Value::Integer(n)) if *n >= 0 => *n,
+            Some(other) => return Err(anyhow!("`schema` in {} has to be a whole number, not {}", path.display(), other)),
+        };
+        if schema > SCHEMA {
+            return Err(anyhow!(
+                "{} uses schema {}, but this mosaic ({}) only understands up to {}. Run `mosaic upgrade`.",
+                path.display(),
+                schema,
+                env!("CARGO_PKG_VERSION"),
+                SCHEMA
+            ));
+        }
+
+        let mut migrated_from = None;
+        if schema < SCHEMA {
+            let mut before = table.clone();
+            before.remove("schema");
+            for step in &MIGRATIONS[schema as usize..] {
+                step(&mut table);
+            }
+            // A bumped number alone isn't worth a backup.
+            if table != before {
+                migrated_from = Some((schema, content));
+            }
+            table.insert("schema".to_string(), toml::Value::Integer(SCHEMA));
+        }
+
+        let mut config: Self = toml::Value::Table(table).try_into()?;
+        config.migrated_from = migrated_from;
         Ok(config)
     }
 
     /// Adds or updates a dependency in memory.
     /// Doesn't write to disk—call save() when you're ready.
     /// The query is usually something like "1.0.0" or "^1.2.0" but we don't validate it here.
+    /// If the package was a dev-dependency, it gets promoted—a package lives in one table, not both.
+    ///
+    /// A sha256 pin only covers the exact version it was written for, so moving the version drops it.
+    /// Re-pin once you've looked at the new release.
+    ///
+    /// A `github:owner/repo@ref` query is recorded as a GitHub dependency (see `github`).
     pub fn add_dependency(&mut self, name: &str, query: &str) {
-        self.dependencies
-            .insert(name.to_string(), query.to_string());
+        self.drop_stale_pin(name, query);
+        self.dev_dependencies.remove(name);
+        let query = self.record_github(name, query);
+        self.dependencies.insert(name.to_string(), query);
     }
 
-    /// Removes a dependency from the config.
+    /// Same as add_dependency, but for the [dev-dependencies] table.
+    pub fn add_dev_dependency(&mut self, name: &str, query: &str) {
+        self.drop_stale_pin(name, query);
+        self.dependencies.remove(name);
+        let query = self.record_github(name, query);
+        self.dev_dependencies.insert(name.to_string(), query);
+    }
+
+    /// Files a `github:` query under `github` and returns the query to keep in the table.
+    fn record_github(&mut self, name: &str, query: &str) -> String {
+        match GithubSource::parse(query).and_then(Result::ok) {
+            Some(source) => {
+                self.github.insert(name.to_string(), source);
+                "*".to_string()
+            }
+            None => {
+                self.github.remove(name);
+                query.to_string()
+            }
+        }
+    }
+
+    /// Returns true if the package is listed in either table.
+    pub fn has_dependency(&self, name: &str) -> bool {
+        self.dependencies.contains_key(name) || self.dev_dependencies.contains_key(name)
+    }
+
+    /// Everything the project needs installed locally: regular deps plus dev-deps.
+    pub fn all_dependencies(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.dependencies.iter().chain(self.dev_dependencies.iter())
+    }
+
+    /// Removes a dependency from the config (from whichever table it's in).
     /// Again, in-memory only. You have to save() to persist it.
     pub fn remove_dependency(&mut self, name: &str) {
         self.dependencies.remove(name);
+        self.dev_dependencies.remove(name);
+        self.integrity.remove(name);
+        self.github.remove(name);
+        self.aliases.remove(name);
+    }
+
+    /// The manifest's sha256 pin for `name`, if it has one and it's for `version`.
+    pub fn pinned_integrity(&self, name: &str, version: &str) -> Option<&str> {
+        let query = self
+            .dependencies
+            .get(name)
+            .or_else(|| self.dev_dependencies.get(name))?;
+        if query.trim_start_matches('=') != version {
+            return None;
+        }
+        self.integrity.get(name).map(String::as_str)
+    }
+
+    fn drop_stale_pin(&mut self, name: &str, query: &str) {
+        let current = self
+            .dependencies
+            .get(name)
+            .or_else(|| self.dev_dependencies.get(name));
+        if current.map(String::as_str) != Some(query) {
+            self.integrity.remove(name);
+        }
     }
 
     /// Writes the config back to mosaic.toml.
     /// Uses pretty TOML formatting so it's actually readable (learned that lesson early).
+    /// If a migration changed the file on the way in, the original is kept as
+    /// `mosaic.toml.schema<N>.bak` the first time.
     pub fn save(&self) -> anyhow::Result<()> {
         let toml = toml::to_string_pretty(self)?;
+        if let Some((schema, original)) = &self.migrated_from {
+            let backup = format!("mosaic.toml.schema{}.bak", schema);
+            if !Path::new(&backup).exists() {
+                std::fs::write(&backup, original)?;
+                Logger::info(format!(
+                    "Updated mosaic.toml from schema {} to {}. The old file is in {}.",
+                    schema, SCHEMA, backup
+                ));
+            }
+        }
         std::fs::write("mosaic.toml", toml)?;
         Ok(())
     }
 }
 
-/// Package metadata—just name and version.
-/// Could expand this later if we need more fields (author, license, etc).
-/// Right now it's kept simple because YAGNI.
-#[derive(Serialize, Deserialize, Debug)]
+/// One entry in [dependencies], the way it's actually written in mosaic.toml.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum DependencySpec {
+    /// `logger = "^1.2.0"`
+    Query(String),
+    /// `log = { package = "super-logger", version = "^2" }`, optionally with a `sha256` pin.
+    /// Before `Pinned`, which would otherwise take it and drop the `package`.
+    Aliased {
+        package: String,
+        version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// `logger = { version = "1.2.0", sha256 = "..." }`
+    Pinned { version: String, sha256: String },
+    /// `cool-lib = { github = "alice/cool-lib", rev = "v1.2.0" }`
+    Github {
+        github: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rev: Option<String>,
+    },
+}
+
+/// mosaic.toml as it sits on disk. Config is the flattened version everyone else uses.
+#[derive(Serialize, Deserialize)]
+struct RawConfig {
+    /// Always [`SCHEMA`] by the time it gets here: `Config::read` migrates first.
+    #[serde(default)]
+    schema: i64,
+    package: PackageConfig,
+    dependencies: HashMap<String, DependencySpec>,
+    #[serde(
+        default,
+        rename = "dev-dependencies",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    dev_dependencies: HashMap<String, DependencySpec>,
+    #[serde(default, skip_serializing_if = "HooksConfig::is_empty")]
+    hooks: HooksConfig,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    patch: HashMap<String, PatchSource>,
+}
+
+/// Splits a raw table into queries, pins, GitHub sources and aliases. A pin has to sit on an exact
+/// version—"^1.2.0" plus one hash would fail the moment 1.2.1 comes out, which isn't a pin, it's a trap.
+fn flatten(
+    raw: HashMap<String, DependencySpec>,
+    integrity: &mut HashMap<String, String>,
+    github: &mut HashMap<String, GithubSource>,
+    aliases: &mut HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut queries = HashMap::new();
+    for (name, spec) in raw {
+        let query = match spec {
+            DependencySpec::Query(query) => query,
+            DependencySpec::Aliased { package, version, sha256 } => {
+                if package.trim().is_empty() {
+                    return Err(format!("{}'s package can't be empty", name));
+                }
+                if let Some(sha256) = sha256 {
+                    integrity.insert(name.clone(), check_pin(&name, &version, &sha256)?);
+                }
+                aliases.insert(name.clone(), package);
+                version
+            }
+            DependencySpec::Pinned { version, sha256 } => {
+                integrity.insert(name.clone(), check_pin(&name, &version, &sha256)?);
+                version
+            }
+            DependencySpec::Github { github: repo, rev } => {
+                let query = match &rev {
+                    Some(rev) => format!("github:{}@{}", repo, rev),
+                    None => format!("github:{}", repo),
+                };
+                let source = GithubSource::parse(&query)
+                    .and_then(Result::ok)
+                    .ok_or_else(|| {
+                        format!(
+                            "{}'s github has to be \"owner/repo\" and its rev a branch, tag or commit (got '{}')",
+                            name, query
+                        )
+                    })?;
+                github.insert(name.clone(), source);
+                "*".to_string()
+            }
+        };
+        queries.insert(name, query);
+    }
+    Ok(queries)
+}
+
+/// A pin's version has to be exact and its hash a hash. Returns the hash, lowercased.
+fn check_pin(name: &str, version: &str, sha256: &str) -> Result<String, String> {
+    if semver::Version::parse(version.trim_start_matches('=')).is_err() {
+        return Err(format!(
+            "{} has a sha256 pin, so its version has to be exact (got '{}')",
+            name, version
+        ));
+    }
+    let sha256 = sha256.trim().to_ascii_lowercase();
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "{}'s sha256 isn't a sha256 (expected 64 hex characters)",
+            name
+        ));
+    }
+    Ok(sha256)
+}
+
+fn unflatten(
+    queries: HashMap<String, String>,
+    integrity: &HashMap<String, String>,
+    github: &HashMap<String, GithubSource>,
+    aliases: &HashMap<String, String>,
+) -> HashMap<String, DependencySpec> {
+    queries
+        .into_iter()
+        .map(|(name, version)| {
+            let spec = match (github.get(&name), integrity.get(&name)) {
+                (Some(source), _) => DependencySpec::Github {
+                    github: source.repo.clone(),
+                    rev: source.rev.clone(),
+                },
+                (None, sha256) if aliases.contains_key(&name) => DependencySpec::Aliased {
+                    package: aliases[&name].clone(),
+                    version,
+                    sha256: sha256.cloned(),
+                },
+                (None, Some(sha256)) => DependencySpec::Pinned {
+                    version,
+                    sha256: sha256.clone(),
+                },
+                (None, None) => DependencySpec::Query(version),
+            };
+            (name, spec)
+        })
+        .collect()
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = String;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let mut integrity = HashMap::new();
+        let mut github = HashMap::new();
+        let mut aliases = HashMap::new();
+        let dependencies = flatten(raw.dependencies, &mut integrity, &mut github, &mut aliases)?;
+        let dev_dependencies = flatten(raw.dev_dependencies, &mut integrity, &mut github, &mut aliases)?;
+        Ok(Self {
+            package: raw.package,
+            dependencies,
+            dev_dependencies,
+            integrity,
+            github,
+            aliases,
+            hooks: raw.hooks,
+            patch: raw.patch,
+            migrated_from: None,
+        })
+    }
+}
+
+impl From<Config> for RawConfig {
+    fn from(config: Config) -> Self {
+        Self {
+            schema: SCHEMA,
+            package: config.package,
+            dependencies: unflatten(config.dependencies, &config.integrity, &config.github, &config.aliases),
+            dev_dependencies: unflatten(config.dev_dependencies, &config.integrity, &config.github, &config.aliases),
+            hooks: config.hooks,
+            patch: config.patch,
+        }
+    }
+}
+
+/// Package metadata. Name and version are all a project needs; the rest is for publishing,
+/// and `mosaic init` asks for it in a terminal.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PackageConfig {
     pub name: String,
     pub version: String,
+    /// One line for search results and `mosaic info`. Sent when the package is first published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Who wrote it, for people reading the manifest. The registry goes by the account
+    /// that publishes, whatever this says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// SPDX license expression, e.g. "MIT" or "MIT OR Apache-2.0".
+    /// The registry validates it and warns if the LICENSE file says something else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Where the source lives. Sent when the package is first published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// The .lua file that gets injected, as a path in the package. Publish puts it first in
+    /// the zip, which is where installs look. Unset means whichever .lua file sorts first.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    /// Pins the project to a specific registry (self-hosted, staging, whatever).
+    /// Beats your login profile but loses to `--registry` and `MOSAIC_REGISTRY_URL`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// Mirrors to try, in order, when the main registry is down or erroring.
+    /// Only used for installs. Downloads are hash-checked, so a mirror can't swap the bytes.
+    #[serde(
+        default,
+        rename = "fallback-registries",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub fallback_registries: Vec<String>,
+    /// Publish the package as private: only you and the members you add can see it.
+    /// Only read on the first publish; after that it's `mosaic visibility`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+}
+
+/// One entry in `[patch]`.
+///
+/// ```toml
+/// [patch]
+/// logger = { path = "../logger" }
+/// ui = { git = "https://github.com/me/ui", rev = "fix-layout" }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PatchSource {
+    /// A folder with its own mosaic.toml. Relative paths are from the project root.
+    Path { path: String },
+    /// A git repo, at `rev` (branch, tag or commit) or the default branch.
+    Git {
+        git: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rev: Option<String>,
+    },
+}
+
+/// The [hooks] table: shell commands the project wants run around installs.
+///
+/// ```toml
+/// [hooks]
+/// post-install = "npm run build-assets"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HooksConfig {
+    /// Runs after a successful `mosaic install`. Skipped on CI unless `MOSAIC_ALLOW_HOOKS=1`.
+    #[serde(default, rename = "post-install", skip_serializing_if = "Option::is_none")]
+    pub post_install: Option<String>,
+}
+
+impl HooksConfig {
+    fn is_empty(&self) -> bool {
+        self.post_install.is_none()
+    }
 }
@@ -0,0 +1,153 @@
+use crate::attest;
+use crate::installer;
+use crate::lockfile::Lockfile;
+use crate::logger::Logger;
+use crate::xml_handler;
+use anyhow::{Context, Result, anyhow};
+use comfy_table::Table;
+use std::fs;
+
+/// A package whose stamp in the .poly disagrees with mosaic.lock, or with its own Source.
+pub struct Drift {
+    pub name: String,
+    /// The version the stamp says is embedded.
+    pub embedded: String,
+    pub locked: Option<String>,
+    /// The Source no longer hashes to what was installed.
+    pub edited: bool,
+}
+
+impl Drift {
+    fn problem(&self) -> &'static str {
+        match (&self.locked, self.edited) {
+            (None, _) => "not in mosaic.lock",
+            (Some(locked), _) if *locked != self.embedded => "different version than mosaic.lock",
+            _ => "edited since install",
+        }
+    }
+}
+
+/// `mosaic doctor`: looks for things in the .poly that will bite later.
+///
+/// Two kinds for now. ModuleScripts sharing a name: `--fix` takes every copy of a locked
+/// package out and reinstalls it from mosaic.lock, so exactly one (the published one) is left.
+/// Copies of anything mosaic doesn't manage are somebody's own code, so those are only
+/// reported. And packages whose stamp says they aren't what mosaic.lock expects: `--fix`
+/// reinstalls the locked version over them. One that isn't locked at all came from somebody
+/// else's install, so that's only reported too.
+pub async fn doctor(fix: bool) -> Result<()> {
+    let poly_path = attest::find_poly(None)?;
+    let poly_content = fs::read_to_string(&poly_path)
+        .with_context(|| format!("Could not read {}", poly_path.display()))?;
+    let lockfile = Lockfile::load()?;
+    let duplicates = xml_handler::duplicate_modules(&poly_content)?;
+    let drifted = drift(&poly_content, &lockfile)?;
+    if duplicates.is_empty() && drifted.is_empty() {
+        Logger::success(format!("No problems found in {}.", poly_path.display()));
+        return Ok(());
+    }
+
+    if !duplicates.is_empty() {
+        let mut table = Table::new();
+        table.set_header(vec!["Module", "Copies", "Managed by mosaic"]);
+        for (name, copies) in &duplicates {
+            let managed = lockfile.get(name).map(|l| l.version.as_str()).unwrap_or("no");
+            table.add_row(vec![name.clone(), copies.to_string(), managed.to_string()]);
+        }
+        println!("{}", table);
+    }
+    if !drifted.is_empty() {
+        print_drift(&drifted);
+    }
+
+    let (managed, unmanaged): (Vec<&String>, Vec<&String>) =
+        duplicates.keys().partition(|name| lockfile.get(name).is_some());
+    let (fixable, unlocked): (Vec<&Drift>, Vec<&Drift>) = drifted.iter().partition(|d| d.locked.is_some());
+
+    if !fix {
+        let count = duplicates.len() + drifted.len();
+        return Err(anyhow!(
+            "Found {} problem{} in {}.{}",
+            count,
+            if count == 1 { "" } else { "s" },
+            poly_path.display(),
+            if managed.is_empty() && fixable.is_empty() {
+                ""
+            } else {
+                " Run 'mosaic doctor --fix' to reinstall the locked packages."
+            }
+        ));
+    }
+
+    if !managed.is_empty() || !fixable.is_empty() {
+        let mut content = poly_content;
+        for name in &managed {
+            content = xml_handler::remove_module_script(&content, name)?;
+        }
+        fs::write(&poly_path, content)?;
+        let names: Vec<&String> = managed.iter().copied().chain(fixable.iter().map(|d| &d.name)).collect();
+        Logger::info(format!("Reinstalling {} from mosaic.lock...", join(&names)));
+        installer::install_all().await?;
+    }
+
+    if !unmanaged.is_empty() {
+        return Err(anyhow!(
+            "{} {} not from the registry, so mosaic won't pick a copy to keep. Merge them by hand in Polytoria.",
+            join(&unmanaged),
+            if unmanaged.len() == 1 { "is" } else { "are" }
+        ));
+    }
+    if !unlocked.is_empty() {
+        let names: Vec<&String> = unlocked.iter().map(|d| &d.name).collect();
+        return Err(anyhow!(
+            "{} {} installed but not in mosaic.lock. Get the mosaic.lock that goes with this place, \
+             or run 'mosaic install <name>@<version>' to lock what's there.",
+            join(&names),
+            if names.len() == 1 { "is" } else { "are" }
+        ));
+    }
+    Ok(())
+}
+
+/// Compares the stamp on every package mosaic installed into the .poly with mosaic.lock.
+///
+/// The stamp is written at install time, so it still knows what's embedded when the lockfile
+/// is out of date or never got committed. Modules without a stamp aren't mosaic's to judge.
+pub fn drift(poly_content: &str, lockfile: &Lockfile) -> Result<Vec<Drift>> {
+    let sources = xml_handler::module_sources(poly_content)?;
+    let mut drifted = Vec::new();
+    for (name, stamp) in xml_handler::module_stamps(poly_content)? {
+        let edited = sources
+            .get(&name)
+            .is_some_and(|source| attest::sha256_hex(source.as_bytes()) != stamp.sha256);
+        let locked = lockfile.get(&name).map(|l| l.version.clone());
+        if locked.as_ref() == Some(&stamp.version) && !edited {
+            continue;
+        }
+        drifted.push(Drift {
+            name,
+            embedded: stamp.version,
+            locked,
+            edited,
+        });
+    }
+    Ok(drifted)
+}
+
+pub fn print_drift(drifted: &[Drift]) {
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "In the .poly", "mosaic.lock", "Problem"]);
+    for d in drifted {
+        table.add_row(vec![
+            d.name.clone(),
+            d.embedded.clone(),
+            d.locked.clone().unwrap_or_else(|| "-".to_string()),
+            d.problem().to_string(),
+        ]);
+    }
+    println!("{}", table);
+}
+
+fn join(names: &[&String]) -> String {
+    names.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(", ")
+}
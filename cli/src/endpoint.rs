@@ -0,0 +1,217 @@
+use crate::auth::AuthConfig;
+use crate::config::Config;
+use crate::logger::Logger;
+use anyhow::{Result, anyhow};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The public registry. Used when nothing else says otherwise.
+pub const DEFAULT_REGISTRY: &str = "https://api.getmosaic.run";
+
+/// Whatever was passed to `--registry`. Set once in main, read everywhere else.
+/// This used to be smuggled through `MOSAIC_REGISTRY_URL` with `set_var`, which was unsafe
+/// and meant half the code read the env var and the other half read auth.toml.
+static FLAG_OVERRIDE: OnceLock<Option<String>> = OnceLock::new();
+
+/// Records the `--registry` flag. Only the first call counts.
+pub fn set_override(url: Option<String>) {
+    let _ = FLAG_OVERRIDE.set(url);
+}
+
+/// Works out which registry this command talks to. First match wins:
+/// 1. `--registry` flag
+/// 2. `MOSAIC_REGISTRY_URL` env var
+/// 3. `registry` in mosaic.toml's [package] table (if we're in a project)
+/// 4. The registry you last logged in to (auth.toml)
+/// 5. The public registry
+///
+/// Always returned without a trailing slash so `format!("{}/packages", url)` stays sane.
+pub fn registry_url() -> String {
+    let from_flag = FLAG_OVERRIDE.get().cloned().flatten();
+    let from_env = || std::env::var("MOSAIC_REGISTRY_URL").ok();
+    let from_project = || Config::load().ok().and_then(|c| c.package.registry);
+    let from_profile = || AuthConfig::load().ok().and_then(|a| a.registry_url);
+
+    let url = from_flag
+        .or_else(from_env)
+        .or_else(from_project)
+        .or_else(from_profile)
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_REGISTRY.to_string());
+
+    normalize(&url)
+}
+
+/// Trims whitespace and trailing slashes so `https://x/` and `https://x` are the same registry.
+pub fn normalize(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+/// The primary registry followed by any fallbacks, in the order we should try them.
+///
+/// Fallbacks come from `MOSAIC_FALLBACK_REGISTRIES` (comma separated) if set,
+/// otherwise from `fallback-registries` in mosaic.toml. Duplicates are dropped.
+pub fn registry_urls() -> Vec<String> {
+    let fallbacks: Vec<String> = match std::env::var("MOSAIC_FALLBACK_REGISTRIES") {
+        Ok(raw) => raw.split(',').map(|s| s.to_string()).collect(),
+        Err(_) => Config::load()
+            .map(|c| c.package.fallback_registries)
+            .unwrap_or_default(),
+    };
+
+    let mut urls = vec![registry_url()];
+    for url in fallbacks {
+        let url = normalize(&url);
+        if !url.is_empty() && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+/// GETs `path` from the primary registry, falling back to the next one on failure.
+///
+/// "Failure" means we couldn't connect, it timed out, or the server returned a 5xx.
+/// A 4xx is a real answer (the package doesn't exist, etc.) so it comes straight back—
+/// asking a mirror the same question would just waste time.
+///
+/// A mirror's answer is taken as-is, hashes included. What keeps it honest is the lockfile:
+/// a locked package's blob has to match the locked hash (see `installer`), whoever served it.
+/// For a package that isn't locked yet, the mirror is trusted as much as the primary.
+pub async fn get(path: &str) -> Result<reqwest::Response> {
+    get_conditional(path, None).await
+}
+
+/// Same as `get`, but sends `If-None-Match` when we have an ETag, so an unchanged
+/// document comes back as an empty 304 instead of the whole thing again.
+pub async fn get_conditional(path: &str, etag: Option<&str>) -> Result<reqwest::Response> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(etag) = etag {
+        headers.insert(reqwest::header::IF_NONE_MATCH, etag.parse()?);
+    }
+    get_with_headers(path, headers).await
+}
+
+/// Same as `get`, with extra request headers (If-None-Match, Range, ...) sent to every registry tried.
+#[tracing::instrument(level = "debug", skip_all, fields(path = %path))]
+pub async fn get_with_headers(
+    path: &str,
+    headers: reqwest::header::HeaderMap,
+) -> Result<reqwest::Response> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(60))
+        .build()?;
+
+    let urls = registry_urls();
+    let mut last_error = None;
+
+    for (i, base) in urls.iter().enumerate() {
+        let url = format!("{}{}", base, path);
+        match with_auth(client.get(&url).headers(headers.clone()), base).send().await {
+            Ok(res) if !res.status().is_server_error() => {
+                tracing::debug!(%url, status = %res.status(), "GET");
+                if i > 0 {
+                    Logger::debug(format!("Served by fallback registry {}", base));
+                }
+                return Ok(res);
+            }
+            Ok(res) => {
+                Logger::debug(format!("{} returned {}", url, res.status()));
+                last_error = Some(anyhow!("{} returned {}", base, res.status()));
+            }
+            Err(e) => {
+                Logger::debug(format!("{} failed: {}", url, e));
+                last_error = Some(anyhow!("{} is unreachable: {}", base, e));
+            }
+        }
+
+        if let Some(next) = urls.get(i + 1) {
+            Logger::warn(format!("Registry {} failed, trying {}...", base, next));
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow!("No registry configured")))
+}
+
+/// The login for `base` as an Authorization header, if we have one for that registry.
+///
+/// Reads send it too, so private packages (see `mosaic visibility`) resolve and download like
+/// any other for their team. Only the registry the token came from gets it (`token_for`); a
+/// fallback mirror asks anonymously. Loaded once per run: it can mean a keyring lookup.
+pub fn bearer(base: &str) -> Option<String> {
+    static AUTH: OnceLock<Option<AuthConfig>> = OnceLock::new();
+    let auth = AUTH.get_or_init(|| AuthConfig::load().ok()).as_ref()?;
+    auth.token_for(base).ok().map(|token| format!("Bearer {}", token))
+}
+
+/// Adds `bearer(base)` to a request, for the ones that don't go through `get`.
+pub fn with_auth(req: reqwest::RequestBuilder, base: &str) -> reqwest::RequestBuilder {
+    match bearer(base) {
+        Some(value) => req.header(reqwest::header::AUTHORIZATION, value),
+        None => req,
+    }
+}
+
+/// Reads a successful response as JSON. A body that doesn't parse (a captive portal, a proxy's
+/// HTML page, a registry mid-deploy) says so instead of surfacing serde's column numbers.
+pub async fn json(res: reqwest::Response) -> Result<serde_json::Value> {
+    let status = res.status();
+    let text = res.text().await?;
+    serde_json::from_str(&text).map_err(|_| {
+        anyhow!(
+            "The registry sent back something that isn't JSON ({}): {}",
+            status,
+            snippet(&text)
+        )
+    })
+}
+
+/// What a failed request says went wrong. Bodies that aren't JSON (tower_governor's plain-text
+/// 429, a proxy's error page) come back as `{"error": "<text>"}`, so `["error"]` always works.
+pub async fn error_body(res: reqwest::Response) -> serde_json::Value {
+    let text = res.text().await.unwrap_or_default();
+    match serde_json::from_str::<serde_json::Value>(&text) {
+        Ok(body) if body["error"].is_string() => body,
+        _ => serde_json::json!({ "error": snippet(&text) }),
+    }
+}
+
+/// Turns a failed response into an error worth printing.
+///
+/// 429s say when to try again: the per-package publish quota explains itself in the body,
+/// the per-IP limits only send `Retry-After`.
+pub async fn error(res: reqwest::Response) -> anyhow::Error {
+    let status = res.status();
+    let retry_after = res
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let body = error_body(res).await;
+    let msg = body["error"].as_str().unwrap_or_default();
+
+    if status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return anyhow!("Registry error ({}): {}", status, msg);
+    }
+    if body["code"].is_string() {
+        return anyhow!("{}", msg);
+    }
+    let wait = match retry_after.or_else(|| body["retry_after"].as_u64()) {
+        Some(secs) if secs >= 120 => format!("{} minutes", secs.div_ceil(60)),
+        Some(secs) if secs > 1 => format!("{} seconds", secs),
+        Some(_) => "a second".to_string(),
+        None => "a few minutes".to_string(),
+    };
+    anyhow!("The registry is rate limiting you. Try again in {}.", wait)
+}
+
+/// The start of a response body, on one line, for error messages.
+fn snippet(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(120) {
+        Some((i, _)) => format!("{}...", &line[..i]),
+        None if line.is_empty() => "(empty response)".to_string(),
+        None => line,
+    }
+}
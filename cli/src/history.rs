@@ -0,0 +1,199 @@
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::logger::Logger;
+use crate::registry;
+use anyhow::Result;
+use comfy_table::Table;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const HISTORY_FILE: &str = ".mosaic/history.json";
+
+/// Oldest entries are dropped past this, so the file can't grow forever.
+const MAX_ENTRIES: usize = 500;
+
+/// One install, update or remove in this project.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix seconds, when the command started.
+    pub at: i64,
+    /// What was run, without the `mosaic`: `install logger@^1.2`, `update`, `remove ui`.
+    pub command: String,
+    pub duration_ms: u64,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What it did to mosaic.lock. A failed run can still have changed some packages.
+    #[serde(default)]
+    pub changes: Vec<Change>,
+}
+
+/// A package whose locked version (or hash) changed. `from` is None when it was added,
+/// `to` is None when it was removed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Appends a run to `.mosaic/history.json`, diffing mosaic.lock against `before`.
+///
+/// Only in a project (a folder with mosaic.toml). Never fails the command: the history is a
+/// debugging aid, and a read-only folder shouldn't turn a good install into an error.
+pub fn record<T>(command: &str, before: &Lockfile, started: Instant, result: &Result<T>) {
+    let after = Lockfile::load().unwrap_or_default();
+    write(command, diff(before, &after), started, result);
+}
+
+/// Same as `record`, for `mosaic remove`. That leaves mosaic.lock alone (something else may
+/// still depend on the package), so the removal is noted from mosaic.toml instead.
+pub fn record_removal<T>(name: &str, was_declared: bool, before: &Lockfile, started: Instant, result: &Result<T>) {
+    let removed = result.is_ok() && was_declared;
+    let changes = removed
+        .then(|| Change {
+            name: name.to_string(),
+            from: before.get(name).map(|p| p.version.clone()),
+            to: None,
+        })
+        .into_iter()
+        .collect();
+    write(&format!("remove {}", name), changes, started, result);
+}
+
+/// `mosaic history`: the last `limit` runs, newest first. With a package, only the runs that
+/// changed it or named it on the command line.
+pub fn show(package: Option<&str>, limit: usize) -> Result<()> {
+    let entries: Vec<Entry> = load()?
+        .into_iter()
+        .rev()
+        .filter(|e| package.is_none_or(|p| mentions(e, p)))
+        .take(limit)
+        .collect();
+
+    if entries.is_empty() {
+        Logger::info(match package {
+            Some(p) => format!("No history for {} in this project", Logger::brand_text(p)),
+            None => "No install history in this project yet".to_string(),
+        });
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["When", "Command", "Took", "Result", "Changes"]);
+    for entry in &entries {
+        let result = match &entry.error {
+            _ if entry.ok => "ok".to_string(),
+            Some(e) => format!("failed: {}", e.lines().next().unwrap_or_default()),
+            None => "failed".to_string(),
+        };
+        let changes: Vec<String> = entry
+            .changes
+            .iter()
+            .filter(|c| package.is_none_or(|p| c.name == p))
+            .map(describe)
+            .collect();
+        table.add_row(vec![
+            registry::time_ago(entry.at),
+            entry.command.clone(),
+            took(entry.duration_ms),
+            result,
+            if changes.is_empty() { "-".to_string() } else { changes.join("\n") },
+        ]);
+    }
+    println!("{}", table);
+    Ok(())
+}
+
+/// Everything recorded so far, oldest first. No file means no history yet.
+fn load() -> Result<Vec<Entry>> {
+    let path = Path::new(HISTORY_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn write<T>(command: &str, changes: Vec<Change>, started: Instant, result: &Result<T>) {
+    if !Path::new("mosaic.toml").exists() {
+        return;
+    }
+    let entry = Entry {
+        at: SystemTime::now()
+            .checked_sub(started.elapsed())
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0),
+        command: command.to_string(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        changes,
+    };
+    if let Err(e) = append(entry) {
+        Logger::debug(format!("Couldn't write {}: {}", HISTORY_FILE, e));
+    }
+}
+
+fn append(entry: Entry) -> Result<()> {
+    // A history file from somewhere else (or hand-edited into junk) just starts over.
+    let mut entries = load().unwrap_or_default();
+    entries.push(entry);
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    entries.drain(..excess);
+
+    fs::create_dir_all(".mosaic")?;
+    fs::write(HISTORY_FILE, serde_json::to_string_pretty(&entries)?)?;
+    Ok(())
+}
+
+/// A package changed if its version or its hash did. Two versions can ship identical zips.
+fn diff(before: &Lockfile, after: &Lockfile) -> Vec<Change> {
+    let names: BTreeSet<&String> = before.packages.keys().chain(after.packages.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (old, new) = (before.get(name), after.get(name));
+            let key = |p: &LockedPackage| (p.version.clone(), p.integrity.clone());
+            if old.map(key) == new.map(key) {
+                return None;
+            }
+            Some(Change {
+                name: name.clone(),
+                from: old.map(|p| p.version.clone()),
+                to: new.map(|p| p.version.clone()),
+            })
+        })
+        .collect()
+}
+
+fn mentions(entry: &Entry, package: &str) -> bool {
+    entry.changes.iter().any(|c| c.name == package)
+        || entry
+            .command
+            .split_whitespace()
+            .skip(1)
+            .any(|arg| arg.split('@').next() == Some(package))
+}
+
+/// `+ui 0.3.1`, `-old 1.0.0`, `logger 1.0.0 -> 1.2.0`.
+fn describe(change: &Change) -> String {
+    match (&change.from, &change.to) {
+        (None, Some(to)) => format!("+{} {}", change.name, to),
+        (Some(from), None) => format!("-{} {}", change.name, from),
+        (None, None) => format!("-{}", change.name),
+        (Some(from), Some(to)) if from == to => format!("{} {} (new hash)", change.name, to),
+        (Some(from), Some(to)) => format!("{} {} -> {}", change.name, from, to),
+    }
+}
+
+fn took(ms: u64) -> String {
+    if ms >= 1000 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}ms", ms)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::config::Config;
+use crate::lockfile::Lockfile;
+use crate::logger::Logger;
+use crate::mode;
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use std::process::{Command, Stdio};
+
+/// Runs the project's `[hooks] post-install` command, if it has one.
+///
+/// Hooks come from the mosaic.toml in the current directory and nowhere else. Packages are
+/// just Lua in a zip; nothing we download gets to run anything on your machine. The command
+/// you wrote yourself is a different matter, but even that stays off on CI unless the pipeline
+/// opts in with `MOSAIC_ALLOW_HOOKS=1`—a hook that rebuilds assets on your laptop has no
+/// business running on a shared runner just because somebody merged it.
+///
+/// What changed is passed through the environment, diffed from the lockfile:
+/// - `MOSAIC_HOOK`: `post-install`
+/// - `MOSAIC_INSTALLED`: the new or changed packages, space-separated: `logger@1.2.0 ui@0.3.1`
+/// - `MOSAIC_INSTALL_SUMMARY`: the same as JSON, with each package's previous version
+///
+/// 1. Nothing configured, `--no-hooks`, or CI without the opt-in: skip
+/// 2. Work out what the install changed
+/// 3. Run it through the shell, and fail the command if the hook fails
+pub fn post_install(before: &Lockfile, no_hooks: bool) -> Result<()> {
+    // 1. Whether to run at all
+    let Some(command) = Config::load().ok().and_then(|c| c.hooks.post_install) else {
+        return Ok(());
+    };
+    if no_hooks {
+        Logger::info("Skipped post-install hook (--no-hooks)");
+        return Ok(());
+    }
+    if mode::is_ci() && !allowed_on_ci() {
+        Logger::info("Skipped post-install hook on CI. Set MOSAIC_ALLOW_HOOKS=1 to run it.");
+        return Ok(());
+    }
+
+    // 2. The diff
+    let after = Lockfile::load().unwrap_or_default();
+    let mut changed: Vec<_> = after
+        .packages
+        .iter()
+        .filter(|(name, pkg)| before.get(name).is_none_or(|old| old.integrity != pkg.integrity))
+        .map(|(name, pkg)| {
+            let previous = before.get(name).map(|old| old.version.clone());
+            (name.clone(), pkg.version.clone(), previous)
+        })
+        .collect();
+    changed.sort();
+
+    let installed: Vec<String> = changed
+        .iter()
+        .map(|(name, version, _)| format!("{}@{}", name, version))
+        .collect();
+    let summary = json!({
+        "installed": changed
+            .iter()
+            .map(|(name, version, previous)| json!({"name": name, "version": version, "previous": previous}))
+            .collect::<Vec<_>>(),
+        "total": after.packages.len()
+    });
+
+    // 3. Run it
+    Logger::command("hook", &command);
+    let mut shell = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    shell
+        .arg(&command)
+        .env("MOSAIC_HOOK", "post-install")
+        .env("MOSAIC_INSTALLED", installed.join(" "))
+        .env("MOSAIC_INSTALL_SUMMARY", summary.to_string());
+    // --json promises one JSON document on stdout. Whatever the hook prints goes to stderr.
+    if mode::is_json() {
+        shell.stdout(Stdio::from(std::io::stderr()));
+    }
+
+    let status = shell
+        .status()
+        .map_err(|e| anyhow!("Couldn't run the post-install hook: {}", e))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "post-install hook failed ({}). The packages themselves are installed.",
+            status
+                .code()
+                .map_or_else(|| "killed by a signal".to_string(), |c| format!("exit code {}", c))
+        ));
+    }
+    Ok(())
+}
+
+fn allowed_on_ci() -> bool {
+    std::env::var("MOSAIC_ALLOW_HOOKS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
@@ -0,0 +1,226 @@
+use crate::adopt;
+use crate::auth::AuthConfig;
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::mode;
+use anyhow::{Result, anyhow};
+use inquire::Text;
+use inquire::validator::Validation;
+use std::path::Path;
+use std::process::Command;
+
+/// Templates `mosaic init --template` knows. They're built into the binary.
+pub const TEMPLATES: [&str; 2] = ["library", "game-module"];
+
+/// Where both templates put the entry point.
+const TEMPLATE_ENTRY: &str = "src/init.lua";
+
+/// `mosaic init`: writes a mosaic.toml for a new package.
+///
+/// In a terminal it asks for the package metadata, pre-filled where there's a sensible guess:
+/// your registry username as the author, the `origin` remote as the repository, an existing
+/// .lua file as the entry. An empty answer leaves the field out of mosaic.toml.
+/// Without a terminal, or with `--yes`, it takes the defaults: just a name and a version.
+///
+/// With a template, the entry defaults to src/init.lua and the template's files are written
+/// next to mosaic.toml.
+pub fn new_project(project_name: &str, yes: bool, template: Option<&str>) -> Result<()> {
+    let mut config = Config::default(project_name);
+    if template.is_some() {
+        config.package.entry = Some(TEMPLATE_ENTRY.to_string());
+    }
+    if !yes && mode::is_interactive() {
+        prompt_metadata(&mut config)?;
+    }
+    config.save()?;
+    Logger::success("Created mosaic.toml");
+
+    if let Some(template) = template {
+        scaffold(template, &config)?;
+    }
+    Ok(())
+}
+
+fn prompt_metadata(config: &mut Config) -> Result<()> {
+    let package = &mut config.package;
+
+    package.name = Text::new("Package name:")
+        .with_default(&package.name)
+        .with_validator(|name: &str| {
+            Ok(if adopt::could_be_package(name) {
+                Validation::Valid
+            } else {
+                Validation::Invalid("2-64 lowercase letters, digits and single dashes".into())
+            })
+        })
+        .prompt()?;
+    package.version = Text::new("Version:")
+        .with_default(&package.version)
+        .with_validator(|version: &str| {
+            Ok(match semver::Version::parse(version) {
+                Ok(_) => Validation::Valid,
+                Err(e) => Validation::Invalid(format!("Not a semver version: {}", e).into()),
+            })
+        })
+        .prompt()?;
+
+    package.description = optional(Text::new("Description:"), None)?;
+    let username = AuthConfig::load_settings().ok().and_then(|a| a.username);
+    package.author = optional(Text::new("Author:"), username.as_deref())?;
+    package.license = optional(Text::new("License (SPDX, e.g. MIT):"), None)?;
+    package.repository = optional(Text::new("Repository URL:"), git_origin().as_deref())?;
+    package.entry = optional(
+        Text::new("Entry file:").with_help_message("The module that gets injected. Published first in the zip."),
+        Some(&package.entry.clone().unwrap_or_else(|| default_entry(Path::new(".")))),
+    )?;
+    Ok(())
+}
+
+/// Asks, and treats an empty answer as "leave it out". The guess is editable text rather than
+/// a default, so clearing it really does leave the field out.
+fn optional<'a>(prompt: Text<'a, '_>, guess: Option<&'a str>) -> Result<Option<String>> {
+    let prompt = match guess {
+        Some(guess) => prompt.with_initial_value(guess),
+        None => prompt,
+    };
+    let answer = prompt.prompt()?;
+    let answer = answer.trim();
+    Ok((!answer.is_empty()).then(|| answer.to_string()))
+}
+
+/// The `origin` remote, if this is a git checkout that has one.
+fn git_origin() -> Option<String> {
+    let output = Command::new("git").args(["remote", "get-url", "origin"]).output().ok()?;
+    let url = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !url.is_empty()).then_some(url)
+}
+
+/// init.lua if it's there, else the first .lua file in the folder, else init.lua anyway.
+fn default_entry(dir: &Path) -> String {
+    if dir.join("init.lua").is_file() {
+        return "init.lua".to_string();
+    }
+    let mut lua: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".lua"))
+        .collect();
+    lua.sort();
+    lua.into_iter().next().unwrap_or_else(|| "init.lua".to_string())
+}
+
+/// Writes a template's files, filled in from the manifest. Files that are already there are
+/// kept: `--template` on an existing folder adds what's missing and nothing else.
+fn scaffold(template: &str, config: &Config) -> Result<()> {
+    let (entry, usage) = match template {
+        "library" => (LIBRARY_LUA, LIBRARY_USAGE),
+        "game-module" => (GAME_MODULE_LUA, GAME_MODULE_USAGE),
+        other => return Err(anyhow!("Unknown template '{}'. Pick one of: {}", other, TEMPLATES.join(", "))),
+    };
+    let package = &config.package;
+    let fill = |text: &str| {
+        text.replace("{{usage}}", usage)
+            .replace("{{name}}", &package.name)
+            .replace("{{ident}}", &lua_identifier(&package.name))
+            .replace(
+                "{{description}}",
+                package.description.as_deref().unwrap_or("What it does, in a sentence or two."),
+            )
+    };
+
+    let mut written = Vec::new();
+    for (path, contents) in [(TEMPLATE_ENTRY, entry), ("README.md", README), (".mosaicignore", MOSAICIGNORE)] {
+        let path = Path::new(path);
+        if path.exists() {
+            Logger::info(format!("Kept the existing {}", path.display()));
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, fill(contents))?;
+        written.push(path.display().to_string());
+    }
+    if !written.is_empty() {
+        Logger::success(format!("Scaffolded the {} template: {}", template, written.join(", ")));
+    }
+    Ok(())
+}
+
+/// A package name as a Lua local: dashes become underscores, and a leading digit gets one too.
+fn lua_identifier(name: &str) -> String {
+    let ident = name.replace('-', "_");
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", ident)
+    } else {
+        ident
+    }
+}
+
+const LIBRARY_LUA: &str = r#"-- {{name}}: everything the package exports goes in this table.
+local {{ident}} = {}
+
+function {{ident}}.greet(name)
+	return "Hello, " .. name .. "!"
+end
+
+return {{ident}}
+"#;
+
+const LIBRARY_USAGE: &str = r#"local {{ident}} = require(game.ScriptService["{{name}}"])
+
+print({{ident}}.greet("world"))"#;
+
+const GAME_MODULE_LUA: &str = r#"-- {{name}}: game logic that runs once it's started.
+-- Require it from one server script and call start(); stop() undoes it.
+local {{ident}} = {}
+
+local running = false
+
+function {{ident}}.start()
+	if running then
+		return
+	end
+	running = true
+	-- Connect events and set up state here.
+end
+
+function {{ident}}.stop()
+	if not running then
+		return
+	end
+	running = false
+	-- Disconnect events and clean up here.
+end
+
+return {{ident}}
+"#;
+
+const GAME_MODULE_USAGE: &str = r#"local {{ident}} = require(game.ScriptService["{{name}}"])
+
+{{ident}}.start()"#;
+
+const README: &str = r#"# {{name}}
+
+{{description}}
+
+## Install
+
+```bash
+mosaic install {{name}}
+```
+
+## Usage
+
+```lua
+{{usage}}
+```
+"#;
+
+const MOSAICIGNORE: &str = "# Left out of the published zip. Same syntax as .gitignore.
+# Hidden files and mosaic.toml are always left out.
+*.poly
+tests/
+";
@@ -1,24 +1,59 @@
 use crate::lockfile::{LockedPackage, Lockfile};
 use crate::logger::Logger;
+use crate::metadata;
+use crate::patch::registry_name;
+use crate::progress;
 use crate::registry;
+use crate::spec::{GithubSource, PackageSpec, VersionSelector};
+use crate::timings::{self, Phase};
 use crate::xml_handler;
 use anyhow::{Result, anyhow};
 use comfy_table::Table;
-use indicatif::{ProgressBar, ProgressStyle};
-use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use semver::{Version, VersionReq};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::{debug, instrument};
+
+/// Whether `--frozen` was passed. Set once in main, like `--no-cache`.
+static FROZEN: OnceLock<bool> = OnceLock::new();
+
+/// Records the `--frozen` flag. Only the first call counts.
+pub fn set_frozen(frozen: bool) {
+    let _ = FROZEN.set(frozen);
+}
+
+fn frozen() -> bool {
+    FROZEN.get().copied().unwrap_or(false)
+}
+
+/// What `--frozen` says when mosaic.lock doesn't already have the answer.
+fn out_of_sync(detail: String) -> anyhow::Error {
+    anyhow!(
+        "mosaic.lock is out of sync with mosaic.toml: {}. Run 'mosaic install' without --frozen to update it, then commit mosaic.lock.",
+        detail
+    )
+}
 
 /// Installs a package.
 ///
-/// Handles `name` (latest) or `name@version`.
-/// Updates `mosaic.toml` if we succeed, because manually editing TOML is for robots.
+/// Handles `name` (latest), `name@version`, `name@range` or `name@tag`.
+/// Returns the package name and what should go into `mosaic.toml` for it:
+/// the range if the user gave one, otherwise the pinned version. The lockfile always gets the pin.
+#[instrument(level = "debug")]
 pub async fn install_package(package_query: &str) -> Result<(String, String)> {
+    // A GitHub package goes into mosaic.toml as the repo and ref, not a version.
+    let github = GithubSource::parse(package_query).transpose()?;
+    let selector = match &github {
+        Some(_) => None,
+        None => Some(PackageSpec::parse(package_query)?.selector),
+    };
     let mut visited = HashSet::new();
     let mut recursion_stack = Vec::new();
-    let mut lockfile = Lockfile::load()?;
+    let mut lockfile = load_lockfile().await?;
 
-    let result = resolve_and_install(
+    let (name, resolved_version) = resolve_and_install(
         package_query,
         &mut visited,
         &mut recursion_stack,
@@ -27,7 +62,72 @@ pub async fn install_package(package_query: &str) -> Result<(String, String)> {
     .await?;
 
     lockfile.save()?;
-    Ok(result)
+
+    let requirement = match (github, selector) {
+        (Some(source), _) => source.to_string(),
+        (None, Some(VersionSelector::Range(range))) => range,
+        _ => resolved_version,
+    };
+    Ok((name, requirement))
+}
+
+/// Fetches every published version of a package (newest first) from the registry.
+/// Goes through the metadata cache, so resolving the same package twice in one run is free.
+async fn fetch_versions(name: &str) -> Result<Vec<serde_json::Value>> {
+    let body = metadata::get_json(&format!("/packages/{}/versions", name))
+        .await?
+        .ok_or_else(|| anyhow!("Package not found in registry: {}", name))?;
+
+    let versions: Vec<serde_json::Value> = serde_json::from_value(body)?;
+    debug!(package = name, count = versions.len(), "fetched versions");
+    Ok(versions)
+}
+
+/// Picks the highest published version that satisfies a semver range.
+///
+/// If the lockfile already pins something inside the range we keep it, otherwise every
+/// `mosaic install` would quietly bump you to the newest match. That's what `update` is for.
+async fn resolve_range(name: &str, range: &str, lockfile: &Lockfile) -> Result<String> {
+    let req = VersionReq::parse(range)
+        .map_err(|e| anyhow!("Invalid version range '{}': {}", range, e))?;
+
+    if let Some(locked) = lockfile.get(name)
+        && let Ok(v) = Version::parse(&locked.version)
+    {
+        if req.matches(&v) {
+            debug!(package = name, range, locked = %v, "lockfile pin satisfies range, keeping it");
+            return Ok(locked.version.clone());
+        }
+        if frozen() {
+            return Err(out_of_sync(format!("it has {}@{}, outside {}", name, v, range)));
+        }
+        debug!(package = name, range, locked = %v, "lockfile pin is outside the range, re-resolving");
+    }
+    if frozen() {
+        return Err(out_of_sync(format!("{} isn't in it", name)));
+    }
+
+    newest_matching(registry_name(name), &req)
+        .await?
+        .ok_or_else(|| anyhow!("No published version of {} matches {}", name, range))
+}
+
+/// Highest published version of a package that satisfies `req`, ignoring the lockfile.
+///
+/// Pre-releases only match a range that names one (`^2.0.0-beta` does, `^2` doesn't). That's
+/// semver's rule, not ours, and it's exactly what we want, so we keep the full list here: it's the
+/// same cached response the rest of the install reads version metadata out of.
+async fn newest_matching(name: &str, req: &VersionReq) -> Result<Option<String>> {
+    let versions = fetch_versions(name).await?;
+    let newest = versions
+        .iter()
+        .filter_map(|v| v["version"].as_str())
+        .filter_map(|v| Version::parse(v).ok())
+        .filter(|v| req.matches(v))
+        .max()
+        .map(|v| v.to_string());
+    debug!(package = name, %req, picked = ?newest, "newest matching version");
+    Ok(newest)
 }
 
 /// The brain of the operation.
@@ -36,75 +136,129 @@ pub async fn install_package(package_query: &str) -> Result<(String, String)> {
 /// 2. Checks for circular dependencies (because infinite recursion is only fun in theory).
 /// 3. Installs dependencies first (bottom-up).
 /// 4. Downloads, verifies hash, and injects the package.
+#[instrument(level = "debug", skip(visited, recursion_stack, lockfile))]
 async fn resolve_and_install(
     package_query: &str,
     visited: &mut HashSet<String>,
     recursion_stack: &mut Vec<String>,
     lockfile: &mut Lockfile,
 ) -> Result<(String, String)> {
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.cyan} {msg}")
-            .unwrap(),
-    );
-    pb.set_message(format!("Resolving {}", Logger::highlight(package_query)));
-    pb.enable_steady_tick(std::time::Duration::from_millis(120));
+    let pb = progress::spinner("cyan", format!("Resolving {}", Logger::highlight(package_query)));
+    let resolve_started = Instant::now();
 
     // 1. Resolve Name & Version
-    let (name, version) = if package_query.contains('@') {
-        let parts: Vec<&str> = package_query.split('@').collect();
-        if parts.len() != 2 {
+    // Exact versions go straight through. "latest" and dist-tags need a trip to the registry.
+    // [patch] entries and GitHub packages are packed locally and bring their own version.
+    let (spec, patched) = match local_source(package_query, lockfile) {
+        Ok(found) => found,
+        Err(e) => {
             pb.finish_and_clear();
-            return Err(anyhow!(
-                "Invalid package format. Expected: name or name@version"
+            return Err(e);
+        }
+    };
+
+    let (name, version) = match (patched.as_ref().map(|p| p.version.clone()), spec.selector) {
+        (Some(version), selector) => {
+            // Allowed, since trying a fix is the whole point, but worth a heads-up.
+            if let VersionSelector::Range(range) = &selector
+                && let Ok(req) = VersionReq::parse(range)
+                && Version::parse(&version).is_ok_and(|v| !req.matches(&v))
+            {
+                pb.suspend(|| {
+                    Logger::warn(format!(
+                        "Patched {}@{} doesn't satisfy {} where it's required. Using it anyway.",
+                        spec.name, version, range
+                    ))
+                });
+            }
+            (spec.name, version)
+        }
+        (None, VersionSelector::Exact(version)) => (spec.name, version),
+        (None, VersionSelector::Range(range)) => {
+            pb.set_message(format!(
+                "Resolving {} for {}...",
+                range,
+                Logger::highlight(&spec.name)
             ));
+            match resolve_range(&spec.name, &range, lockfile).await {
+                Ok(version) => (spec.name, version),
+                Err(e) => {
+                    pb.finish_and_clear();
+                    return Err(e);
+                }
+            }
         }
-        (parts[0].to_string(), parts[1].to_string())
-    } else {
-        pb.set_message(format!(
-            "Fetching latest version for {}...",
-            Logger::highlight(package_query)
-        ));
-        let registry_url = std::env::var("MOSAIC_REGISTRY_URL")
-            .unwrap_or_else(|_| "https://api.getmosaic.run".to_string());
+        // Whatever the tag pointed at when mosaic.lock was written. Asking again is resolving.
+        (None, VersionSelector::Latest | VersionSelector::Tag(_)) if frozen() => match lockfile.get(&spec.name) {
+            Some(locked) => (spec.name, locked.version.clone()),
+            None => {
+                pb.finish_and_clear();
+                return Err(out_of_sync(format!("{} isn't in it", spec.name)));
+            }
+        },
+        (None, selector @ (VersionSelector::Latest | VersionSelector::Tag(_))) => {
+            pb.set_message(format!(
+                "Fetching {} version for {}...",
+                selector,
+                Logger::highlight(&spec.name)
+            ));
+            let tag = match &selector {
+                VersionSelector::Tag(tag) => tag.as_str(),
+                _ => "latest",
+            };
+            // Same request fetch_tag starts with, so the metadata cache answers the second one.
+            let pkg = match fetch_package(registry_name(&spec.name), tag == "latest").await {
+                Ok(pkg) => pkg,
+                Err(e) => {
+                    pb.finish_and_clear();
+                    return Err(e);
+                }
+            };
 
-        let client = reqwest::Client::new();
-        let res = client
-            .get(format!("{}/packages/{}", registry_url, package_query))
-            .send()
-            .await?;
+            // Check for deprecation
+            if pkg["deprecated"].as_bool().unwrap_or(false) {
+                let reason = pkg["deprecation_reason"]
+                    .as_str()
+                    .unwrap_or("No reason provided.");
 
-        if !res.status().is_success() {
-            pb.finish_and_clear();
-            return Err(anyhow!("Package not found in registry: {}", package_query));
-        }
-
-        let pkg: serde_json::Value = res.json().await?;
-
-        // Check for deprecation
-        if pkg["deprecated"].as_bool().unwrap_or(false) {
-            let reason = pkg["deprecation_reason"]
-                .as_str()
-                .unwrap_or("No reason provided.");
-            
-            // Suspend spinner to print warning clearly
-            pb.suspend(|| {
-                Logger::warn(format!(
-                    "Package {} is deprecated: {}",
-                    Logger::highlight(package_query),
-                    reason
-                ));
-            });
-        }
+                // Suspend spinner to print warning clearly
+                pb.suspend(|| {
+                    Logger::warn(format!(
+                        "Package {} is deprecated: {}",
+                        Logger::highlight(&spec.name),
+                        reason
+                    ));
+                });
+            }
 
-        let latest_version = pkg["version"]
-            .as_str()
-            .ok_or_else(|| anyhow!("Could not determine latest version"))?
-            .to_string();
+            let resolved = match fetch_tag(registry_name(&spec.name), tag).await {
+                Ok(v) => v,
+                Err(e) => {
+                    pb.finish_and_clear();
+                    return Err(e);
+                }
+            };
 
-        (package_query.to_string(), latest_version)
+            (spec.name, resolved)
+        }
     };
+    debug!(package = %name, %version, "resolved");
+    timings::record(&name, Phase::Resolve, resolve_started);
+
+    // Exact pins and patches don't ask the registry either, but they still have to be what's locked.
+    if frozen() {
+        let detail = match lockfile.get(&name) {
+            None => Some(format!("{} isn't in it", name)),
+            Some(locked) if patched.is_none() && locked.version != version => {
+                Some(format!("it has {}@{}, mosaic.toml wants {}", name, locked.version, version))
+            }
+            Some(_) => None,
+        };
+        if let Some(detail) = detail {
+            pb.finish_and_clear();
+            return Err(out_of_sync(detail));
+        }
+    }
 
     // 2. Circular Dependency Check
     // If we see the same package twice in one branch, we bail.
@@ -113,12 +267,14 @@ async fn resolve_and_install(
         pb.finish_and_clear();
         let mut cycle = recursion_stack.join(" -> ");
         cycle.push_str(&format!(" -> {}", name));
+        debug!(%cycle, "dependency cycle");
         return Err(anyhow!("Circular dependency detected: {}", cycle));
     }
 
     // 3. Skip if already visited
     // Efficiency win: don't install the same logger library 5 times.
     if visited.contains(&name) {
+        debug!(package = %name, "already installed in this run, skipping");
         pb.finish_and_clear();
         return Ok((name, version));
     }
@@ -128,40 +284,35 @@ async fn resolve_and_install(
 
     // 4. Fetch Metadata & Dependencies
     // We need to know what this package depends on BEFORE we download the blob.
-    let registry_url = std::env::var("MOSAIC_REGISTRY_URL")
-        .unwrap_or_else(|_| "https://api.getmosaic.run".to_string());
-    
-    let client = reqwest::Client::new();
-    let res = client
-        .get(format!("{}/packages/{}/versions", registry_url, name))
-        .send()
-        .await?;
-
-    let versions: Vec<serde_json::Value> = res.json().await?;
-    let version_meta = versions
-        .into_iter()
-        .find(|v| v["version"].as_str() == Some(&version))
-        .ok_or_else(|| anyhow!("Version {} not found for {}", version, name))?;
+    // A patch brings its own (from its mosaic.toml), so there's nothing to ask the registry.
+    let meta_started = Instant::now();
+    let (deps, license) = match &patched {
+        Some(patched) => (patched.dependencies.clone(), patched.license.clone()),
+        None => {
+            let meta = registry::fetch_version(registry_name(&name), &version).await?;
+            (declared_dependencies(&meta), meta["license"].as_str().map(str::to_string))
+        }
+    };
+    timings::record(&name, Phase::Resolve, meta_started);
 
     let mut dependencies_map = HashMap::new();
 
     // Extract dependencies if any
-    if let Some(deps) = version_meta["dependencies"].as_object() {
-        if !deps.is_empty() {
-            pb.set_message(format!("Installing dependencies for {}...", name));
-            for (dep_name, dep_version) in deps {
-                let dep_query = format!("{}@{}", dep_name, dep_version.as_str().unwrap_or("*"));
-                // Recursion happens here.
-                // We pass the lockfile down so everything gets locked in one go.
-                let (_, resolved_dep_version) = Box::pin(resolve_and_install(
-                    &dep_query,
-                    visited,
-                    recursion_stack,
-                    lockfile,
-                ))
-                .await?;
-                dependencies_map.insert(dep_name.clone(), resolved_dep_version);
-            }
+    if !deps.is_empty() {
+        debug!(package = %name, dependencies = ?deps.keys().collect::<Vec<_>>(), "installing dependencies first");
+        pb.set_message(format!("Installing dependencies for {}...", name));
+        for (dep_name, dep_version) in &deps {
+            let dep_query = format!("{}@{}", dep_name, dep_version);
+            // Recursion happens here.
+            // We pass the lockfile down so everything gets locked in one go.
+            let (_, resolved_dep_version) = Box::pin(resolve_and_install(
+                &dep_query,
+                visited,
+                recursion_stack,
+                lockfile,
+            ))
+            .await?;
+            dependencies_map.insert(dep_name.clone(), resolved_dep_version);
         }
     }
 
@@ -172,18 +323,32 @@ async fn resolve_and_install(
         Logger::brand_text(&version)
     ));
 
-    // Get the raw bytes so we can hash them
-    let (bytes, resolved_version) = registry::download_from_registry(&name, &version).await?;
+    // The blob is streamed to a tempfile and hashed on the way in.
+    // A patch was already packed locally in step 1.
+    let download_started = Instant::now();
+    let (blob, resolved_version, source) = match patched {
+        Some(patched) => (patched.blob, version.clone(), Some(patched.source)),
+        None => {
+            let (blob, resolved_version) = registry::download_from_registry(registry_name(&name), &version).await?;
+            (blob, resolved_version, None)
+        }
+    };
+    timings::record(&name, Phase::Download, download_started);
 
     // 5a. Verify Hash
-    let mut hasher = Sha256::new();
-    hasher.update(&bytes);
-    let hash = format!("{:x}", hasher.finalize());
+    // Patches are local code that changes whenever you edit it, so there's nothing to hold
+    // them to—and a registry copy locked over a patch (or the other way round) isn't a mismatch.
+    let verify_started = Instant::now();
+    let hash = blob.integrity.clone();
 
-    if let Some(locked) = lockfile.get(&name) {
+    if let Some(locked) = lockfile.get(&name)
+        && source.is_none()
+        && locked.source.is_none()
+    {
         // Security check: if the lockfile says hash X, and we got hash Y,
         // someone is trying to mess with us (or the registry is broken).
         if locked.version == resolved_version {
+            debug!(package = %name, locked = %locked.integrity, downloaded = %hash, "checking against lockfile");
             if locked.integrity != hash {
                 pb.finish_and_clear();
                 return Err(anyhow!(
@@ -196,6 +361,15 @@ async fn resolve_and_install(
         }
     }
 
+    if source.is_none()
+        && let Err(e) = check_manifest_pin(&name, &resolved_version, &hash)
+    {
+        pb.finish_and_clear();
+        return Err(e);
+    }
+
+    timings::record(&name, Phase::Verify, verify_started);
+
     // Update lockfile with the new/verified package
     lockfile.insert(
         name.clone(),
@@ -203,11 +377,16 @@ async fn resolve_and_install(
             version: resolved_version.clone(),
             integrity: hash,
             dependencies: dependencies_map,
+            license,
+            package: aliased(&name, &source),
+            source: source.clone(),
         },
     );
 
-    // Extract Lua code from the verified bytes
-    let lua_code = registry::extract_lua_from_bytes(&bytes)?;
+    // Extract Lua code from the verified archive
+    let extract_started = Instant::now();
+    let lua_code = registry::extract_lua_from_archive(blob.file)?;
+    timings::record(&name, Phase::Extract, extract_started);
 
     // Find the .poly file.
     let entries = fs::read_dir(".")?;
@@ -229,11 +408,24 @@ async fn resolve_and_install(
         }
     };
 
+    debug!(poly = %poly_path.display(), "injecting into .poly");
     pb.set_message(format!("Injecting {} into project...", name));
-    let poly_content = fs::read_to_string(&poly_path)?;
-    let new_content = xml_handler::inject_module_script(&poly_content, &name, &lua_code)?;
+    let inject_started = Instant::now();
+    let mut poly_content = fs::read_to_string(&poly_path)?;
+    if let Some(&copies) = xml_handler::duplicate_modules(&poly_content)?.get(&name)
+        && pb.suspend(|| consolidate_duplicates(&poly_path, &name, copies))?
+    {
+        // Take them all out, so the one that goes back in is the only one.
+        poly_content = xml_handler::remove_module_script(&poly_content, &name)?;
+    }
+    let stamp = xml_handler::Stamp {
+        version: resolved_version.clone(),
+        sha256: crate::attest::sha256_hex(lua_code.as_bytes()),
+    };
+    let new_content = xml_handler::inject_module_script(&poly_content, &name, &lua_code, &stamp)?;
 
     fs::write(&poly_path, new_content)?;
+    timings::record(&name, Phase::Inject, inject_started);
     
     // Done with this branch
     visited.insert(name.clone());
@@ -241,17 +433,109 @@ async fn resolve_and_install(
     
     pb.finish_and_clear();
     Logger::success(format!(
-        "Installed {}@{} into {}",
+        "Installed {}@{} into {}{}",
         Logger::brand_text(&name),
         Logger::brand_text(&resolved_version),
-        Logger::highlight(poly_path.to_string_lossy())
+        Logger::highlight(poly_path.to_string_lossy()),
+        source.map(|s| format!(" (patched: {})", s)).unwrap_or_default()
     ));
 
     Ok((name, resolved_version))
 }
 
+/// Parses an install query and, for a package that doesn't come from the registry, packs it.
+///
+/// - `mosaic link` beats everything, however deep in the tree the package is. It's what
+///   you're working on right now.
+/// - Then `[patch]` in mosaic.toml.
+/// - `github:owner/repo@ref` from the command line is fetched fresh and named by the repo's
+///   own mosaic.toml.
+/// - A GitHub dependency, listed in mosaic.toml or as `name@github:owner/repo@ref` by another
+///   GitHub package, is fetched at the commit mosaic.lock has for it.
+fn local_source(
+    query: &str,
+    lockfile: &Lockfile,
+) -> Result<(PackageSpec, Option<crate::patch::Patched>)> {
+    let (named, github) = match query.split_once("@github:") {
+        Some((name, rest)) => (Some(name), GithubSource::parse(&format!("github:{}", rest))),
+        None => (None, GithubSource::parse(query)),
+    };
+    let spec = match (named, github) {
+        (None, Some(source)) => {
+            let patched = progress::suspend(|| crate::patch::load_github(&source?, None, None))?;
+            let spec = PackageSpec {
+                name: patched.name.clone(),
+                selector: VersionSelector::Latest,
+            };
+            return Ok((spec, Some(patched)));
+        }
+        (Some(name), Some(source)) => {
+            let source = source?;
+            if crate::patch::lookup(name).is_none() && crate::link::lookup(name).is_none() {
+                let locked = lockfile.get(name).and_then(|l| l.source.as_deref());
+                let patched =
+                    progress::suspend(|| crate::patch::load_github(&source, Some(name), locked))?;
+                return Ok((PackageSpec::parse(name)?, Some(patched)));
+            }
+            PackageSpec::parse(name)?
+        }
+        (_, None) => PackageSpec::parse(query)?,
+    };
+
+    if let Some(dir) = crate::link::lookup(&spec.name) {
+        let patched = progress::suspend(|| crate::patch::load_linked(&spec.name, dir))?;
+        return Ok((spec, Some(patched)));
+    }
+    if let Some(source) = crate::patch::lookup(&spec.name) {
+        let patched = progress::suspend(|| crate::patch::load(&spec.name, source))?;
+        return Ok((spec, Some(patched)));
+    }
+    if let Some(source) = crate::patch::github(&spec.name) {
+        let locked = lockfile.get(&spec.name).and_then(|l| l.source.as_deref());
+        let patched =
+            progress::suspend(|| crate::patch::load_github(source, Some(&spec.name), locked))?;
+        return Ok((spec, Some(patched)));
+    }
+    Ok((spec, None))
+}
+
+/// Warns about `copies` ModuleScripts called `name` and asks whether to swap them for the one
+/// being installed. Without a terminal there's nobody to ask, so they're left for `mosaic doctor --fix`.
+fn consolidate_duplicates(poly_path: &std::path::Path, name: &str, copies: usize) -> Result<bool> {
+    Logger::warn(format!(
+        "{} has {} ModuleScripts named {}. Only one of them is what 'require' finds.",
+        poly_path.display(),
+        copies,
+        name
+    ));
+    if !crate::mode::is_interactive() {
+        Logger::info("Updating all of them. Run 'mosaic doctor --fix' to keep just one.");
+        return Ok(false);
+    }
+    Ok(inquire::Confirm::new(&format!("Replace all {} with a single copy managed by mosaic?", copies))
+        .with_default(true)
+        .prompt()?)
+}
+
+/// A version's declared dependencies (`name -> range`) from the registry's version metadata.
+/// Sorted, so installs walk the tree in the same order every time.
+fn declared_dependencies(meta: &serde_json::Value) -> BTreeMap<String, String> {
+    meta["dependencies"]
+        .as_object()
+        .map(|deps| {
+            deps.iter()
+                .map(|(name, query)| (name.clone(), query.as_str().unwrap_or("*").to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Installs everything listed in mosaic.toml.
 /// Useful for CI or when you just cloned a repo and nothing works.
+///
+/// With `--frozen`, every version comes out of mosaic.lock: no version lists, no dist-tags,
+/// just the locked versions' own metadata and blobs. Anything a normal install would have to
+/// resolve (or write back to mosaic.lock) is an error instead, and mosaic.lock is never saved.
 pub async fn install_all() -> Result<()> {
     let config = crate::config::Config::load()?;
     Logger::header(format!(
@@ -259,29 +543,205 @@ pub async fn install_all() -> Result<()> {
         config.package.name
     ));
 
-    if config.dependencies.is_empty() {
+    if config.dependencies.is_empty() && config.dev_dependencies.is_empty() {
         Logger::info("No dependencies to install.");
         return Ok(());
     }
 
     let mut visited = HashSet::new();
     let mut recursion_stack = Vec::new();
-    let mut lockfile = Lockfile::load()?;
+    // --frozen doesn't get to fix merge conflicts either: Lockfile::load refuses them outright.
+    let mut lockfile = if frozen() {
+        if !std::path::Path::new("mosaic.lock").exists() {
+            return Err(out_of_sync("there's no mosaic.lock".to_string()));
+        }
+        let mut lockfile = Lockfile::load()?;
+        forget_retargeted(&mut lockfile);
+        lockfile
+    } else {
+        load_lockfile().await?
+    };
+    let before: HashMap<String, LockedPackage> = lockfile.packages.clone();
+
+    // On CI, find out about pulled versions up front instead of through a 404 halfway through.
+    if crate::mode::is_ci() {
+        check_lockfile(&lockfile, false).await?;
+    }
 
-    for (name, query) in &config.dependencies {
+    // Dev-dependencies get installed too. They only matter for what we publish, not what's local.
+    for (name, query) in config.all_dependencies() {
         Logger::command("mosaic", format!("Processing {} ({})", name, query));
         let dep_query = format!("{}@{}", name, query);
         resolve_and_install(&dep_query, &mut visited, &mut recursion_stack, &mut lockfile).await?;
     }
+    // Linked packages that aren't dependencies (yet) go back in too. Not with --frozen: they're
+    // in neither file.
+    if !frozen() {
+        for name in crate::link::extra(&config) {
+            Logger::command("mosaic", format!("Processing {} (linked)", name));
+            resolve_and_install(&name, &mut visited, &mut recursion_stack, &mut lockfile).await?;
+        }
+    }
+
+    if frozen() {
+        // Everything came from the lock, so this only trips on what the checks above can't see,
+        // like a GitHub dependency whose locked commit points somewhere new.
+        let mut changed: Vec<&String> = lockfile
+            .packages
+            .iter()
+            .filter(|(name, pkg)| {
+                before.get(*name).is_none_or(|old| {
+                    (&old.version, &old.integrity, &old.source) != (&pkg.version, &pkg.integrity, &pkg.source)
+                })
+            })
+            .map(|(name, _)| name)
+            .collect();
+        if !changed.is_empty() {
+            changed.sort();
+            return Err(out_of_sync(format!(
+                "installing would change {}",
+                changed.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+        Logger::success("Installed exactly what mosaic.lock has.");
+        return Ok(());
+    }
 
     lockfile.save()?;
     Logger::success("All dependencies are up to date!");
     Ok(())
 }
 
+/// `mosaic verify`: checks every registry pin in mosaic.lock is still safe to install, and
+/// that the packages stamped into the .poly are the ones mosaic.lock has.
+pub async fn verify() -> Result<()> {
+    let lockfile = Lockfile::load()?;
+    let drifted = match crate::attest::find_poly(None) {
+        Ok(path) => crate::doctor::drift(&fs::read_to_string(path)?, &lockfile)?,
+        Err(_) => Vec::new(),
+    };
+    if lockfile.packages.is_empty() && drifted.is_empty() {
+        Logger::info("Lockfile is empty. Nothing to verify.");
+        return Ok(());
+    }
+    check_lockfile(&lockfile, true).await?;
+
+    if drifted.is_empty() {
+        return Ok(());
+    }
+    crate::doctor::print_drift(&drifted);
+    Err(anyhow!(
+        "{} package{} in the .poly {} match mosaic.lock. Run 'mosaic doctor' for the fix.",
+        drifted.len(),
+        if drifted.len() == 1 { "" } else { "s" },
+        if drifted.len() == 1 { "doesn't" } else { "don't" }
+    ))
+}
+
+/// Cross-checks the lockfile against what the registry has right now.
+///
+/// A lockfile promises the same bytes every time, but it was written in the past: since then a
+/// version may have been unpublished, or the whole package taken down for malware. Installing
+/// from it would either 404 or, worse, keep shipping the bad version from somebody's cache.
+/// Every problem gets the closest version that's still up, so fixing it is one command.
+///
+/// Patched packages are skipped—they never came from the registry. `strict` decides what a
+/// registry that can't answer means: an error for `mosaic verify`, a warning for CI installs
+/// (older registries don't have the endpoint, and that shouldn't break anybody's build).
+async fn check_lockfile(lockfile: &Lockfile, strict: bool) -> Result<()> {
+    let mut pinned: Vec<(&String, &LockedPackage)> = lockfile
+        .packages
+        .iter()
+        .filter(|(_, pkg)| pkg.source.is_none())
+        .collect();
+    if pinned.is_empty() {
+        return Ok(());
+    }
+    pinned.sort_by(|a, b| a.0.cmp(b.0));
+
+    Logger::info(format!("Checking {} locked packages against the registry...", pinned.len()));
+    // Asked about by their registry names: an alias means nothing to the registry.
+    let versions: HashMap<&str, &String> =
+        pinned.iter().map(|(name, pkg)| (registry_name(name), &pkg.version)).collect();
+    let registry_url = crate::endpoint::registry_url();
+    let res = crate::endpoint::with_auth(
+        reqwest::Client::new().post(format!("{}/packages/lock-status", registry_url)),
+        &registry_url,
+    )
+    .json(&serde_json::json!({ "packages": versions }))
+    .send()
+    .await;
+    let res = match res {
+        Ok(res) if res.status().is_success() => Ok(res),
+        Ok(res) => Err(format!("the registry answered {}", res.status())),
+        Err(e) => Err(e.to_string()),
+    };
+    let res = match res {
+        Ok(res) => res,
+        Err(why) => {
+            let msg = format!("Couldn't check mosaic.lock against the registry: {}", why);
+            if strict {
+                return Err(anyhow!(msg));
+            }
+            Logger::warn(msg);
+            return Ok(());
+        }
+    };
+    let body: serde_json::Value = res.json().await?;
+
+    let mut problems = Table::new();
+    problems.set_header(vec!["Package", "Locked", "Problem", "Closest safe version"]);
+    let mut count = 0;
+    for (name, pkg) in &pinned {
+        let status = &body["packages"][registry_name(name)];
+        let problem = match status["status"].as_str() {
+            Some("ok") => {
+                if status["deprecated"] == true {
+                    Logger::warn(format!("{} is deprecated", Logger::highlight(name)));
+                }
+                match status["sha256"].as_str() {
+                    Some(sha) if sha != pkg.integrity => {
+                        "the registry's hash doesn't match mosaic.lock".to_string()
+                    }
+                    _ => continue,
+                }
+            }
+            Some("unpublished") => "unpublished".to_string(),
+            Some("taken_down") => format!(
+                "package taken down: {}",
+                status["reason"].as_str().unwrap_or("no reason given")
+            ),
+            Some("missing") => "package no longer exists".to_string(),
+            _ => continue,
+        };
+        count += 1;
+        problems.add_row(vec![
+            name.to_string(),
+            pkg.version.clone(),
+            problem,
+            status["suggestion"].as_str().unwrap_or("-").to_string(),
+        ]);
+    }
+
+    if count == 0 {
+        Logger::success(format!("All {} locked packages are still available.", pinned.len()));
+        return Ok(());
+    }
+    println!("{}", problems);
+    Logger::info(
+        "Move to a safe version with 'mosaic install <name>@<version>' (or update whatever depends on it), \
+         drop taken-down packages with 'mosaic remove <name>', then commit the new mosaic.lock.",
+    );
+    Err(anyhow!(
+        "{} locked package{} can't be installed safely anymore",
+        count,
+        if count == 1 { "" } else { "s" }
+    ))
+}
+
 /// Lists installed packages.
 /// Mostly for humans. Robots should parse the lockfile.
-pub async fn list_packages() -> Result<()> {
+pub async fn list_packages(long: bool) -> Result<()> {
     let config = crate::config::Config::load()?;
 
     Logger::header("Project Environment");
@@ -292,71 +752,1016 @@ pub async fn list_packages() -> Result<()> {
         config.package.version
     );
 
-    if config.dependencies.is_empty() {
+    if config.dependencies.is_empty() && config.dev_dependencies.is_empty() {
+        Logger::info("No dependencies installed.");
+        return Ok(());
+    }
+
+    // --long: what the lockfile resolved, plus a line about each package from the registry.
+    // Fetched side by side and through the metadata cache, so a second run is instant.
+    let lockfile = if long { Lockfile::load().unwrap_or_default() } else { Lockfile::default() };
+    let mut registry_info = HashMap::new();
+    if long {
+        let _pb = progress::spinner("cyan", "Looking up packages on the registry...");
+        let mut lookups = tokio::task::JoinSet::new();
+        for (name, _) in config.all_dependencies() {
+            let name = name.clone();
+            lookups.spawn(async move {
+                let pkg = metadata::get_json(&format!("/packages/{}", registry_name(&name))).await;
+                (name, pkg)
+            });
+        }
+        while let Some(joined) = lookups.join_next().await {
+            let (name, pkg) = joined?;
+            match pkg {
+                Ok(Some(pkg)) => {
+                    registry_info.insert(name, pkg);
+                }
+                Ok(None) => {}
+                Err(e) => debug!(package = %name, error = %e, "couldn't fetch package info"),
+            }
+        }
+    }
+
+    for (title, deps) in [
+        ("Dependencies", &config.dependencies),
+        ("Dev Dependencies", &config.dev_dependencies),
+    ] {
+        if deps.is_empty() {
+            continue;
+        }
+        Logger::header(title);
+        let mut names: Vec<&String> = deps.keys().collect();
+        names.sort();
+
+        let mut table = Table::new();
+        if long {
+            table.set_header(vec!["Package", "Source/Query", "Resolved", "License", "Status", "Description"]);
+        } else {
+            table.set_header(vec!["Package", "Source/Query"]);
+        }
+
+        for name in names {
+            let query = match config.aliases.get(name) {
+                Some(package) => format!("{}@{}", package, deps[name]),
+                None => deps[name].to_string(),
+            };
+            if !long {
+                table.add_row(vec![name.to_string(), query]);
+                continue;
+            }
+            let locked = lockfile.get(name);
+            let pkg = registry_info.get(name.as_str());
+            table.add_row(vec![
+                name.to_string(),
+                query,
+                locked.map_or_else(|| "-".to_string(), |l| l.version.clone()),
+                locked
+                    .and_then(|l| l.license.clone())
+                    .or_else(|| pkg.and_then(|p| p["license"].as_str()).map(str::to_string))
+                    .unwrap_or_else(|| "-".to_string()),
+                package_status(locked, pkg),
+                pkg.and_then(|p| p["description"].as_str())
+                    .map(one_line)
+                    .unwrap_or_else(|| "-".to_string()),
+            ]);
+        }
+
+        println!("{}", table);
+    }
+
+    if long && registry_info.len() < config.all_dependencies().count() {
+        Logger::info(Logger::dim("Some packages couldn't be looked up on the registry (shown as '-')."));
+    }
+
+    Ok(())
+}
+
+/// The Status column of `list --long`: anything worth knowing before you ship with it.
+fn package_status(locked: Option<&LockedPackage>, pkg: Option<&serde_json::Value>) -> String {
+    let mut status = Vec::new();
+    if locked.is_none() {
+        status.push("not installed".to_string());
+    }
+    match locked.and_then(|l| l.source.as_deref()) {
+        Some(source) if source.starts_with("github+") => status.push("github".to_string()),
+        Some(source) if source.starts_with("link+") => status.push("linked".to_string()),
+        Some(_) => status.push("patched".to_string()),
+        None => {}
+    }
+    if pkg.is_some_and(|p| p["deprecated"] == true) {
+        status.push("deprecated".to_string());
+    }
+    if status.is_empty() { "ok".to_string() } else { status.join(", ") }
+}
+
+/// First line of a description, cut short enough to keep the table on one screen.
+fn one_line(text: &str) -> String {
+    const MAX: usize = 60;
+    let line = text.lines().next().unwrap_or("").trim();
+    if line.chars().count() <= MAX {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(MAX - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// `mosaic list --installed`: what's actually installed, not just what mosaic.toml asks for.
+///
+/// Lines up three sources—mosaic.toml (declared), mosaic.lock (resolved) and the .poly
+/// (what the game actually runs)—and flags wherever they disagree. Offline; no registry calls.
+pub async fn list_installed() -> Result<()> {
+    let config = crate::config::Config::load()?;
+    let lockfile = Lockfile::load()?;
+
+    // Same rule as install: first .poly in the current directory.
+    let mut poly_path = None;
+    for entry in fs::read_dir(".")? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("poly") {
+            poly_path = Some(path);
+            break;
+        }
+    }
+    let modules = match &poly_path {
+        Some(path) => xml_handler::module_sources(&fs::read_to_string(path)?)?,
+        None => Default::default(),
+    };
+
+    let declared: HashMap<&String, &String> = config.all_dependencies().collect();
+    // Packages something else in the lockfile depends on. Locked-but-undeclared is fine for these.
+    let transitive: HashSet<&String> = lockfile
+        .packages
+        .values()
+        .flat_map(|p| p.dependencies.keys())
+        .collect();
+
+    let mut names: Vec<&String> = declared.keys().copied().chain(lockfile.packages.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    if names.is_empty() {
         Logger::info("No dependencies installed.");
         return Ok(());
     }
 
-    Logger::header("Dependencies");
+    Logger::header(format!(
+        "Installed in {}",
+        poly_path
+            .as_ref()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "(no .poly file)".to_string())
+    ));
+
     let mut table = Table::new();
-    table.set_header(vec!["Package", "Source/Query"]);
+    table.set_header(vec!["Package", "Declared", "Locked", "Integrity", "In .poly", "Status"]);
+    let mut drifted = 0;
+
+    for name in names {
+        let wanted = declared.get(name).copied();
+        let locked = lockfile.get(name);
+        let in_poly = modules.contains_key(name.as_str());
 
-    for (name, query) in &config.dependencies {
-        table.add_row(vec![name.to_string(), query.to_string()]);
+        // Worst problem wins; one label per row keeps the table readable.
+        let status = match (wanted, locked) {
+            (Some(_), None) => "not installed",
+            (None, Some(_)) if !transitive.contains(name) => "orphaned",
+            (Some(query), Some(lock))
+                if requirement_for(query).is_some_and(|req| {
+                    Version::parse(&lock.version).is_ok_and(|v| !req.matches(&v))
+                }) =>
+            {
+                "out of range"
+            }
+            (_, Some(_)) if !in_poly => "missing from .poly",
+            (None, Some(_)) => "transitive",
+            _ => "ok",
+        };
+        if status != "ok" && status != "transitive" {
+            drifted += 1;
+        }
+
+        table.add_row(vec![
+            name.to_string(),
+            wanted.cloned().unwrap_or_else(|| "-".to_string()),
+            locked.map(|l| l.version.clone()).unwrap_or_else(|| "-".to_string()),
+            locked
+                .map(|l| l.integrity.chars().take(12).collect())
+                .unwrap_or_else(|| "-".to_string()),
+            if in_poly { "yes" } else { "no" }.to_string(),
+            status.to_string(),
+        ]);
     }
 
     println!("{}", table);
+
+    if drifted == 0 {
+        Logger::success("mosaic.toml, mosaic.lock and the .poly all agree.");
+    } else {
+        Logger::warn(format!(
+            "{} package{} out of sync. Run 'mosaic install' to fix.",
+            drifted,
+            if drifted == 1 { "" } else { "s" }
+        ));
+    }
+
     Ok(())
 }
 
-/// Reinstalls everything to their latest versions.
-/// A glorified `install_all` that ignores your current lockfile versions.
-pub async fn update_all() -> Result<()> {
-    Logger::info("Updating all project dependencies to latest versions...");
-    
+/// `mosaic outdated`: which dependencies have something newer, in range or not.
+///
+/// Read-only. Version lists go through the metadata cache and are fetched in parallel,
+/// so a second run (or a run on hotel wifi) mostly doesn't wait on the network.
+///
+/// With `exit_code`, anything behind Latest is an error, and so is a package we couldn't
+/// look up: a CI check that passes because the registry was down isn't much of a check.
+pub async fn outdated(exit_code: bool) -> Result<()> {
+    let config = crate::config::Config::load()?;
+    let lockfile = Lockfile::load()?;
+
+    // GitHub packages have no releases to compare against, just whatever the ref points at.
+    let mut deps: Vec<(String, String)> = config
+        .all_dependencies()
+        .filter(|(name, _)| !config.github.contains_key(*name))
+        .map(|(name, query)| (name.clone(), query.clone()))
+        .collect();
+    deps.sort();
+
+    if deps.is_empty() {
+        Logger::info("No dependencies to check.");
+        return Ok(());
+    }
+
+    let pb = progress::spinner("cyan", format!("Checking {} packages for updates...", deps.len()));
+    let mut lookups = tokio::task::JoinSet::new();
+    for (name, _) in &deps {
+        let name = name.clone();
+        lookups.spawn(async move {
+            let versions = fetch_versions(registry_name(&name)).await;
+            (name, versions)
+        });
+    }
+    let mut published = HashMap::new();
+    let mut skipped = Vec::new();
+    while let Some(joined) = lookups.join_next().await {
+        let (name, versions) = joined?;
+        match versions {
+            Ok(versions) => {
+                let parsed: Vec<Version> = versions
+                    .iter()
+                    .filter_map(|v| v["version"].as_str())
+                    .filter_map(|v| Version::parse(v).ok())
+                    .collect();
+                published.insert(name, parsed);
+            }
+            Err(e) => {
+                Logger::warn(format!("Skipping {}: {}", name, e));
+                skipped.push(name);
+            }
+        }
+    }
+    pb.finish_and_clear();
+    skipped.sort();
+
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Declared", "Current", "Wanted", "Latest"]);
+    let mut behind = 0;
+
+    for (name, query) in &deps {
+        let Some(versions) = published.get(name) else {
+            continue;
+        };
+        let current = lockfile.get(name).map(|l| l.version.clone());
+        // Latest skips prereleases unless that's all there is—same as what a bare install picks.
+        let latest = versions
+            .iter()
+            .filter(|v| v.pre.is_empty())
+            .max()
+            .or_else(|| versions.iter().max());
+        let wanted = requirement_for(query)
+            .and_then(|req| versions.iter().filter(|v| req.matches(v)).max());
+
+        let current_version = current.as_deref().and_then(|c| Version::parse(c).ok());
+        let up_to_date = match (&current_version, latest) {
+            (Some(c), Some(l)) => c >= l,
+            _ => false,
+        };
+        if up_to_date {
+            continue;
+        }
+        behind += 1;
+
+        table.add_row(vec![
+            name.clone(),
+            query.clone(),
+            current.unwrap_or_else(|| "-".to_string()),
+            wanted.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string()),
+            latest.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string()),
+        ]);
+    }
+
+    if behind == 0 && skipped.is_empty() {
+        Logger::success("Everything is up to date.");
+        return Ok(());
+    }
+
+    if behind > 0 {
+        println!("{}", table);
+        Logger::info("'mosaic update' moves to Wanted. 'mosaic update --latest' moves to Latest.");
+    } else {
+        Logger::info("Everything else is up to date.");
+    }
+
+    if exit_code {
+        if behind > 0 {
+            return Err(anyhow!(
+                "{} dependenc{} out of date",
+                behind,
+                if behind == 1 { "y is" } else { "ies are" }
+            ));
+        }
+        return Err(anyhow!("Couldn't check {}", skipped.join(", ")));
+    }
+    Ok(())
+}
+
+/// Package metadata from the registry. `stable_only` asks it to leave pre-releases out of
+/// `version` and `dist_tags`—what resolving "latest" wants.
+async fn fetch_package(name: &str, stable_only: bool) -> Result<serde_json::Value> {
+    let path = if stable_only {
+        format!("/packages/{}?include_prereleases=false", name)
+    } else {
+        format!("/packages/{}", name)
+    };
+    metadata::get_json(&path)
+        .await?
+        .ok_or_else(|| anyhow!("Package not found in registry: {}", name))
+}
+
+/// Where `tag` points in a package's metadata. "latest" falls back to the newest version
+/// for packages published before tags existed.
+fn tag_in(pkg: &serde_json::Value, tag: &str) -> Option<String> {
+    match pkg["dist_tags"][tag].as_str() {
+        Some(v) => Some(v.to_string()),
+        None if tag == "latest" => pkg["version"].as_str().map(|v| v.to_string()),
+        None => None,
+    }
+}
+
+/// Asks the registry which version a dist-tag points at.
+///
+/// "latest" never means a pre-release: `mosaic install foo` shouldn't quietly grab
+/// `2.0.0-alpha.1` because somebody published it without `--tag next`. Ask for that by
+/// name (`foo@next`, `foo@2.0.0-alpha.1`) and you get it.
+///
+/// 1. Ask for the stable view of the package
+/// 2. Registries that predate `include_prereleases` ignore it, so fall back to the newest stable
+///    version in the full list (a bare `*` never matches pre-releases)
+/// 3. Nothing but pre-releases published? Then that's what latest is
+async fn fetch_tag(name: &str, tag: &str) -> Result<String> {
+    let not_found = || anyhow!("Tag '{}' does not exist for {}", tag, name);
+    if tag != "latest" {
+        return tag_in(&fetch_package(name, false).await?, tag).ok_or_else(not_found);
+    }
+
+    // 1. Stable view. "0.0.0" is what the registry says when nothing matched.
+    let stable = tag_in(&fetch_package(name, true).await?, tag).filter(|v| {
+        Version::parse(v).is_ok_and(|v| v.pre.is_empty() && v != Version::new(0, 0, 0))
+    });
+    if let Some(version) = stable {
+        return Ok(version);
+    }
+
+    // 2. Old registry, or a latest tag that moved onto a pre-release anyway
+    if let Some(version) = newest_matching(name, &VersionReq::STAR).await? {
+        return Ok(version);
+    }
+
+    // 3. Pre-releases only
+    debug!(package = name, "no stable release, latest is a pre-release");
+    tag_in(&fetch_package(name, false).await?, tag).ok_or_else(not_found)
+}
+
+/// Resolves a spec to a concrete version without installing anything or looking at the lockfile.
+/// Used by read-only commands like `mosaic view` that just need to know which version to ask for.
+pub async fn resolve_version(spec: &PackageSpec) -> Result<String> {
+    match &spec.selector {
+        VersionSelector::Exact(v) => Ok(v.clone()),
+        VersionSelector::Latest => fetch_tag(&spec.name, "latest").await,
+        VersionSelector::Tag(tag) => fetch_tag(&spec.name, tag).await,
+        VersionSelector::Range(range) => {
+            let req = VersionReq::parse(range)?;
+            newest_matching(&spec.name, &req)
+                .await?
+                .ok_or_else(|| anyhow!("No published version of {} matches {}", spec.name, range))
+        }
+    }
+}
+
+/// Updates dependencies.
+///
+/// By default this respects mosaic.toml: a package declared as `^1.2` moves to the newest 1.x,
+/// and an exact pin stays put. `latest` ignores the declared ranges, jumps to the newest release
+/// (majors included) and rewrites mosaic.toml to match, so the manifest never lies about what's installed.
+///
+/// With no names, everything in mosaic.toml gets updated. With names, only those packages
+/// (and whatever they pull in) are touched—every other lock entry stays exactly as it was.
+/// `dry_run` prints the version delta and stops before changing anything.
+pub async fn update(packages: &[String], latest: bool, dry_run: bool) -> Result<()> {
     let mut config = crate::config::Config::load()?;
-    let dependencies: Vec<String> = config.dependencies.keys().cloned().collect();
 
-    if dependencies.is_empty() {
+    let targets: Vec<(String, String)> = if packages.is_empty() {
+        config
+            .all_dependencies()
+            .map(|(name, query)| (name.clone(), query.clone()))
+            .collect()
+    } else {
+        let mut targets = Vec::new();
+        for name in packages {
+            let query = config
+                .dependencies
+                .get(name)
+                .or_else(|| config.dev_dependencies.get(name))
+                .ok_or_else(|| anyhow!("Package {} not found in mosaic.toml", name))?;
+            targets.push((name.clone(), query.clone()));
+        }
+        targets
+    };
+
+    if targets.is_empty() {
         Logger::info("No dependencies to update.");
         return Ok(());
     }
 
+    let mut lockfile = load_lockfile().await?;
+
+    // Work out the delta first so --dry-run can show it without touching anything.
+    Logger::info("Checking for newer versions...");
+    let mut plan = Vec::new();
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Requirement", "Current", "New"]);
+
+    for (name, query) in &targets {
+        if let Some(source) = config.github.get(name) {
+            Logger::info(format!(
+                "{} comes from {}. Run 'mosaic install {}' to fetch it again.",
+                name, source, source
+            ));
+            continue;
+        }
+        let selector = VersionSelector::parse(query)?;
+        let target = match (&selector, latest) {
+            (_, true) | (VersionSelector::Latest, false) => fetch_tag(registry_name(name), "latest").await?,
+            (VersionSelector::Tag(tag), false) => fetch_tag(registry_name(name), tag).await?,
+            (VersionSelector::Exact(v), false) => v.clone(),
+            (VersionSelector::Range(range), false) => {
+                let req = VersionReq::parse(range)?;
+                match newest_matching(registry_name(name), &req).await? {
+                    Some(v) => v,
+                    None => {
+                        Logger::warn(format!(
+                            "No published version of {} matches {}. Skipping.",
+                            name, range
+                        ));
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let current = lockfile
+            .get(name)
+            .map(|p| p.version.clone())
+            .unwrap_or_else(|| "-".to_string());
+
+        if current != target {
+            table.add_row(vec![name.clone(), query.clone(), current, target.clone()]);
+            plan.push((name.clone(), selector, target));
+        }
+    }
+
+    if plan.is_empty() {
+        Logger::success("Everything is already up to date!");
+        if !latest {
+            Logger::info(format!(
+                "Run {} to move past the ranges in mosaic.toml.",
+                Logger::brand_text("mosaic update --latest")
+            ));
+        }
+        return Ok(());
+    }
+
+    println!("{}", table);
+
+    if dry_run {
+        Logger::info("Dry run: nothing was changed.");
+        return Ok(());
+    }
+
     let mut visited = HashSet::new();
     let mut recursion_stack = Vec::new();
-    let mut lockfile = Lockfile::load()?;
 
-    for name in dependencies {
+    for (name, selector, target) in plan {
         Logger::command("mosaic", format!("Updating {}...", name));
-        
-        // Passing &name without @version forces resolution to latest
-        let (_, new_version) = resolve_and_install(&name, &mut visited, &mut recursion_stack, &mut lockfile).await?;
-        
-        // Update manifest
-        config.add_dependency(&name, &new_version);
+
+        let query = format!("{}@{}", name, target);
+        let (_, new_version) =
+            resolve_and_install(&query, &mut visited, &mut recursion_stack, &mut lockfile).await?;
+
+        // Within-range updates leave mosaic.toml alone—the range still describes what's installed.
+        // --latest may have left the range behind, so rewrite it. Ranges stay ranges (caret on the
+        // new version), pins stay pins.
+        if latest {
+            let requirement = match selector {
+                VersionSelector::Range(_) => format!("^{}", new_version),
+                _ => new_version,
+            };
+            Logger::info(format!(
+                "Rewrote {} to {} in mosaic.toml",
+                Logger::brand_text(&name),
+                requirement
+            ));
+            if config.dev_dependencies.contains_key(&name) {
+                config.add_dev_dependency(&name, &requirement);
+            } else {
+                config.add_dependency(&name, &requirement);
+            }
+        }
     }
 
     config.save()?;
     lockfile.save()?;
-    
-    Logger::success("All dependencies updated to latest versions!");
+
+    Logger::success("Dependencies updated!");
+    Ok(())
+}
+
+/// Turns a mosaic.toml / registry dependency string into a semver requirement.
+/// Exact versions become `=x.y.z`. Tags and junk return None—there's nothing to unify against.
+fn requirement_for(query: &str) -> Option<VersionReq> {
+    match VersionSelector::parse(query).ok()? {
+        VersionSelector::Exact(v) => VersionReq::parse(&format!("={}", v)).ok(),
+        VersionSelector::Range(r) => VersionReq::parse(&r).ok(),
+        VersionSelector::Latest | VersionSelector::Tag(_) => None,
+    }
+}
+
+/// `mosaic lock`: rebuilds mosaic.lock from mosaic.toml and reports what would change.
+/// With `repair` it writes the result; otherwise it's a dry run.
+///
+/// Meant for after a merge conflict mangled the lockfile. Everything is re-resolved from the
+/// declared ranges (keeping old pins where they still fit) and every hash is re-fetched by
+/// actually downloading the blob. The .poly isn't touched—run `mosaic install` afterwards
+/// if versions moved.
+pub async fn lock(repair: bool) -> Result<()> {
+    let config = crate::config::Config::load()?;
+
+    // The old lockfile is only used for preferred pins and the report. If it doesn't even
+    // parse (conflict markers, usually), start from nothing.
+    let old = match Lockfile::load() {
+        Ok(lockfile) => lockfile,
+        // Mid-merge: the entries both sides agree on are still good pins.
+        Err(_) if matches!(Lockfile::load_conflicted(), Ok(Some(_))) => {
+            Logger::warn("mosaic.lock has merge conflicts. Keeping only the entries both sides agree on.");
+            Lockfile::load_conflicted()?.map(|c| c.agreed).unwrap_or_default()
+        }
+        Err(e) => {
+            Logger::warn(format!(
+                "mosaic.lock couldn't be parsed ({}). Rebuilding it from scratch.",
+                e
+            ));
+            Lockfile::default()
+        }
+    };
+
+    Logger::header(format!("Re-resolving dependencies for {}", config.package.name));
+
+    let mut fresh = Lockfile::default();
+    for (name, query) in config.all_dependencies() {
+        lock_one(name, query, &old, &mut fresh).await?;
+    }
+
+    // The report: every name in either lockfile.
+    let mut names: Vec<&String> = old.packages.keys().chain(fresh.packages.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Before", "After", "Change"]);
+    let mut changed = 0;
+    let mut hash_changed = Vec::new();
+
+    for name in names {
+        let (before, after) = (old.get(name), fresh.get(name));
+        let change = match (before, after) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(b), Some(a)) if b.version != a.version => "version changed",
+            (Some(b), Some(a)) if b.integrity != a.integrity => {
+                hash_changed.push(name.clone());
+                "hash changed"
+            }
+            (Some(b), Some(a)) if b.dependencies != a.dependencies => "dependencies changed",
+            _ => "unchanged",
+        };
+        if change != "unchanged" {
+            changed += 1;
+        }
+        table.add_row(vec![
+            name.to_string(),
+            before.map(|p| p.version.clone()).unwrap_or_else(|| "-".to_string()),
+            after.map(|p| p.version.clone()).unwrap_or_else(|| "-".to_string()),
+            change.to_string(),
+        ]);
+    }
+
+    println!("{}", table);
+
+    // Same version, different bytes. After a merge that's usually one side's stale entry,
+    // but it's also exactly what a swapped blob would look like. Say so loudly.
+    for name in &hash_changed {
+        Logger::warn(format!(
+            "{}: the registry's hash differs from the old lockfile for the same version. Double-check before committing.",
+            Logger::highlight(name)
+        ));
+    }
+
+    if changed == 0 {
+        Logger::success("mosaic.lock is already correct.");
+        return Ok(());
+    }
+
+    if !repair {
+        Logger::info(format!(
+            "{} entries would change. Run 'mosaic lock --repair' to write them.",
+            changed
+        ));
+        return Ok(());
+    }
+
+    fresh.save()?;
+    Logger::success(format!(
+        "Rewrote mosaic.lock ({} entries changed). Run 'mosaic install' to sync your .poly.",
+        changed
+    ));
+    Ok(())
+}
+
+/// Loads mosaic.lock, fixing up git merge conflicts on the way if there are any.
+///
+/// Like cargo: instead of dying on a TOML parse error, keep every entry both sides agree on
+/// and re-resolve only the conflicted packages from mosaic.toml. Where both sides pinned a
+/// version that still fits the range, the newer pin wins. The result is saved straight away
+/// so the conflict markers are gone even if the rest of the command fails.
+#[instrument(level = "debug")]
+async fn load_lockfile() -> Result<Lockfile> {
+    let Some(conflict) = Lockfile::load_conflicted()? else {
+        let mut lockfile = Lockfile::load()?;
+        forget_retargeted(&mut lockfile);
+        return Ok(lockfile);
+    };
+
+    Logger::warn(format!(
+        "mosaic.lock has merge conflicts in: {}. Re-resolving those.",
+        conflict.conflicted.join(", ")
+    ));
+
+    // Preferred pins for the conflicted packages: whichever side's version is higher.
+    let mut preferred = Lockfile::default();
+    for name in &conflict.conflicted {
+        let pick = match (conflict.ours.get(name), conflict.theirs.get(name)) {
+            (Some(a), Some(b)) => {
+                let newer = Version::parse(&b.version)
+                    .ok()
+                    .zip(Version::parse(&a.version).ok())
+                    .is_some_and(|(b, a)| b > a);
+                Some(if newer { b } else { a })
+            }
+            (a, b) => a.or(b),
+        };
+        if let Some(pkg) = pick {
+            debug!(package = %name, preferred = %pkg.version, "conflicted lockfile entry");
+            preferred.insert(name.clone(), pkg.clone());
+        }
+    }
+
+    let config = crate::config::Config::load()?;
+    let mut fresh = conflict.agreed;
+    for (name, query) in config.all_dependencies() {
+        lock_one(name, query, &preferred, &mut fresh).await?;
+    }
+
+    // Conflicted packages only reachable through an agreed-on package: lock_one stopped at the
+    // agreed parent, so pick them up using the exact version that parent recorded.
+    for name in &conflict.conflicted {
+        if fresh.get(name).is_some() {
+            continue;
+        }
+        let wanted = fresh
+            .packages
+            .values()
+            .find_map(|p| p.dependencies.get(name).cloned());
+        if let Some(version) = wanted {
+            lock_one(name, &version, &preferred, &mut fresh).await?;
+        }
+    }
+
+    fresh.save()?;
+    Logger::success(format!(
+        "Resolved {} conflicted entr{} in mosaic.lock",
+        conflict.conflicted.len(),
+        if conflict.conflicted.len() == 1 { "y" } else { "ies" }
+    ));
+    Ok(fresh)
+}
+
+/// What goes in a lock entry's `package`: the registry package when `name` is an alias of one.
+/// Patches and GitHub packages never asked the registry, so they don't get one.
+fn aliased(name: &str, source: &Option<String>) -> Option<String> {
+    let package = registry_name(name);
+    (source.is_none() && package != name).then(|| package.to_string())
+}
+
+/// Drops lock entries that were locked as a different registry package than mosaic.toml now
+/// says (an alias pointed somewhere else, or removed). Their version and hash belong to the old
+/// package, so they get resolved again rather than tripping the hash check.
+fn forget_retargeted(lockfile: &mut Lockfile) {
+    lockfile.packages.retain(|name, pkg| {
+        let keep = pkg.source.is_some() || pkg.package.as_deref().unwrap_or(name) == registry_name(name);
+        if !keep {
+            debug!(package = %name, "locked as another package, re-resolving");
+        }
+        keep
+    });
+}
+
+/// Checks a downloaded blob against a `sha256` pin in mosaic.toml, if there is one.
+///
+/// The lockfile check only works while the lockfile exists. This one lives in the manifest,
+/// so deleting mosaic.lock or running `lock --repair` doesn't quietly re-trust whatever the registry serves.
+/// No mosaic.toml (installing from somewhere odd) means no pins, not an error.
+fn check_manifest_pin(name: &str, version: &str, hash: &str) -> Result<()> {
+    let Ok(config) = crate::config::Config::load() else {
+        return Ok(());
+    };
+    let Some(pinned) = config.pinned_integrity(name, version) else {
+        return Ok(());
+    };
+
+    debug!(package = %name, pinned = %pinned, downloaded = %hash, "checking against mosaic.toml pin");
+    if pinned != hash {
+        return Err(anyhow!(
+            "Security Alert: {}@{} doesn't match the sha256 pinned in mosaic.toml! Pinned: {}, Downloaded: {}. Refusing to install it.",
+            name,
+            version,
+            pinned,
+            hash
+        ));
+    }
+    Ok(())
+}
+
+/// Resolves one package (and its dependencies) into `fresh` without installing anything.
+#[instrument(level = "debug", skip(old, fresh))]
+async fn lock_one(name: &str, query: &str, old: &Lockfile, fresh: &mut Lockfile) -> Result<()> {
+    // Already locked in this run. Also what stops a dependency cycle from looping forever.
+    if fresh.get(name).is_some() {
+        return Ok(());
+    }
+
+    // [patch] and GitHub packages here too: pack them locally instead of resolving and downloading.
+    let local = local_source(&format!("{}@{}", name, query), old)?.1;
+    let (version, integrity, deps, license, source) = match local {
+        Some(patched) => (
+            patched.version,
+            patched.blob.integrity,
+            patched.dependencies,
+            patched.license,
+            Some(patched.source),
+        ),
+        None => {
+            let version = match VersionSelector::parse(query)? {
+                VersionSelector::Exact(v) => v,
+                VersionSelector::Range(range) => resolve_range(name, &range, old).await?,
+                VersionSelector::Latest => fetch_tag(registry_name(name), "latest").await?,
+                VersionSelector::Tag(tag) => fetch_tag(registry_name(name), &tag).await?,
+            };
+
+            let meta = registry::fetch_version(registry_name(name), &version).await?;
+
+            // Download just to hash it. The tempfile is dropped right after.
+            Logger::info(format!("Hashing {}@{}...", Logger::highlight(name), version));
+            let (blob, _) = registry::download_from_registry(registry_name(name), &version).await?;
+            // The old lockfile is being thrown away here, but the manifest's pins aren't.
+            check_manifest_pin(name, &version, &blob.integrity)?;
+
+            let license = meta["license"].as_str().map(str::to_string);
+            (version, blob.integrity, declared_dependencies(&meta), license, None)
+        }
+    };
+
+    // Placeholder first so cycles see this package as done.
+    fresh.insert(
+        name.to_string(),
+        LockedPackage {
+            version: version.clone(),
+            integrity: integrity.clone(),
+            dependencies: HashMap::new(),
+            license: None,
+            package: aliased(name, &source),
+            source: source.clone(),
+        },
+    );
+
+    let mut dependencies = HashMap::new();
+    for (dep_name, dep_query) in &deps {
+        Box::pin(lock_one(dep_name, dep_query, old, fresh)).await?;
+        if let Some(locked) = fresh.get(dep_name) {
+            dependencies.insert(dep_name.clone(), locked.version.clone());
+        }
+    }
+
+    fresh.insert(
+        name.to_string(),
+        LockedPackage {
+            version,
+            integrity,
+            dependencies,
+            license,
+            package: aliased(name, &source),
+            source,
+        },
+    );
+    Ok(())
+}
+
+/// Collapses duplicate versions of the same package in the lockfile.
+///
+/// Because the installer only injects one ModuleScript per name, a lockfile can end up with
+/// package A pinned to logger 1.0.0 while package B's entry says it wants logger 1.2.0.
+/// This recomputes the graph from the *declared* ranges (mosaic.toml + the registry's
+/// dependency metadata) and, for each package referenced at more than one version, picks a
+/// version that satisfies everyone: the highest one already referenced if any does, otherwise
+/// the highest published one. It then reinstalls anything that moved and points every lock
+/// entry at the survivor. Packages with a single version are left exactly as they are.
+pub async fn dedupe() -> Result<()> {
+    let config = crate::config::Config::load()?;
+    let mut lockfile = load_lockfile().await?;
+
+    if lockfile.packages.is_empty() {
+        Logger::info("Lockfile is empty. Nothing to dedupe.");
+        return Ok(());
+    }
+
+    Logger::info("Recomputing dependency graph...");
+
+    // 1. Gather every requirement anyone has on each package.
+    let mut requirements: HashMap<String, Vec<VersionReq>> = HashMap::new();
+    for (name, query) in config.all_dependencies() {
+        if let Some(req) = requirement_for(query) {
+            requirements.entry(name.clone()).or_default().push(req);
+        }
+    }
+
+    let mut published: HashMap<String, Vec<Version>> = HashMap::new();
+    // Patched packages aren't ours to move: [patch] decides what they are, not the registry.
+    let locked_names: Vec<String> = lockfile
+        .packages
+        .iter()
+        .filter(|(_, pkg)| pkg.source.is_none())
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in &locked_names {
+        let versions = fetch_versions(registry_name(name)).await?;
+        let locked_version = &lockfile.packages[name].version;
+
+        // The lockfile only has resolved pins, so the declared ranges come from the registry.
+        if let Some(meta) = versions
+            .iter()
+            .find(|v| v["version"].as_str() == Some(locked_version.as_str()))
+            && let Some(deps) = meta["dependencies"].as_object()
+        {
+            for (dep_name, dep_query) in deps {
+                if let Some(req) = requirement_for(dep_query.as_str().unwrap_or("*")) {
+                    requirements.entry(dep_name.clone()).or_default().push(req);
+                }
+            }
+        }
+
+        published.insert(
+            name.clone(),
+            versions
+                .iter()
+                .filter_map(|v| v["version"].as_str())
+                .filter_map(|v| Version::parse(v).ok())
+                .collect(),
+        );
+    }
+
+    // 2. For each package, see how many versions the lockfile currently references and, if
+    // there's more than one, pick a single one that keeps every requirement happy.
+    let mut chosen: HashMap<String, String> = HashMap::new();
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Before", "After"]);
+
+    for name in &locked_names {
+        let mut referenced: Vec<String> = vec![lockfile.packages[name].version.clone()];
+        for pkg in lockfile.packages.values() {
+            if let Some(v) = pkg.dependencies.get(name)
+                && !referenced.contains(v)
+            {
+                referenced.push(v.clone());
+            }
+        }
+
+        // Nothing to collapse. Moving it anyway would be `update`'s job, not ours.
+        if referenced.len() < 2 {
+            continue;
+        }
+
+        // A version that's already locked is already installed and vetted, so it beats a newer
+        // one. Pre-releases are only in the running if one is referenced already: with no ranges
+        // at all, every version "satisfies" them.
+        let reqs = requirements.get(name).cloned().unwrap_or_default();
+        let satisfies = |v: &Version| reqs.iter().all(|r| r.matches(v));
+        let locked: Vec<Version> = referenced.iter().filter_map(|v| Version::parse(v).ok()).collect();
+        let allow_pre = locked.iter().any(|v| !v.pre.is_empty());
+        let best = locked
+            .iter()
+            .filter(|v| satisfies(v))
+            .max()
+            .or_else(|| {
+                published
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|v| allow_pre || v.pre.is_empty())
+                    .filter(|v| satisfies(v))
+                    .max()
+            })
+            .map(|v| v.to_string());
+
+        let Some(best) = best else {
+            Logger::warn(format!(
+                "No single version of {} satisfies every dependent. Leaving it alone.",
+                Logger::highlight(name)
+            ));
+            continue;
+        };
+
+        referenced.sort();
+        table.add_row(vec![name.clone(), referenced.join(", "), best.clone()]);
+        chosen.insert(name.clone(), best);
+    }
+
+    let changed: Vec<(String, String)> = chosen
+        .iter()
+        .filter(|(name, version)| lockfile.packages[name.as_str()].version != **version)
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect();
+
+    let rewires = lockfile.packages.values().any(|pkg| {
+        pkg.dependencies
+            .iter()
+            .any(|(dep, v)| chosen.get(dep).is_some_and(|c| c != v))
+    });
+
+    if changed.is_empty() && !rewires {
+        Logger::success("Lockfile is already deduplicated.");
+        return Ok(());
+    }
+
+    println!("{}", table);
+
+    // 3. Reinstall everything whose pin moved so the .poly matches the lockfile.
+    let mut visited = HashSet::new();
+    let mut recursion_stack = Vec::new();
+    for (name, version) in &changed {
+        let query = format!("{}@{}", name, version);
+        resolve_and_install(&query, &mut visited, &mut recursion_stack, &mut lockfile).await?;
+    }
+
+    // 4. Point every dependency edge at the survivor.
+    for pkg in lockfile.packages.values_mut() {
+        for (dep, v) in pkg.dependencies.iter_mut() {
+            if let Some(c) = chosen.get(dep) {
+                *v = c.clone();
+            }
+        }
+    }
+
+    lockfile.save()?;
+    Logger::success("Deduplicated mosaic.lock.");
     Ok(())
 }
 
 /// Nukes a package from mosaic.toml and the .poly file.
 /// We do this in both places so your config doesn't lie to you.
 pub async fn remove_package(name: &str) -> Result<()> {
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.red} {msg}")
-            .unwrap(),
-    );
-    pb.enable_steady_tick(std::time::Duration::from_millis(120));
-    pb.set_message(format!("Removing {}...", name));
+    let pb = progress::spinner("red", format!("Removing {}...", name));
 
     let mut config = crate::config::Config::load()?;
-    if !config.dependencies.contains_key(name) {
+    if !config.has_dependency(name) {
         pb.finish_and_clear();
         Logger::error(format!("Package {} not found in mosaic.toml", name));
         return Ok(());
@@ -0,0 +1,185 @@
+use crate::config::Config;
+use crate::installer;
+use crate::lockfile::Lockfile;
+use crate::logger::Logger;
+use crate::paths;
+use crate::xml_handler;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// links.toml in the config dir. Machine-wide, never committed: a link points at a folder on
+/// this computer, which means nothing anywhere else.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Links {
+    /// Package name -> the folder `mosaic link` was run in.
+    #[serde(default)]
+    packages: BTreeMap<String, PathBuf>,
+    /// Project folder -> the linked packages it uses instead of the registry's.
+    #[serde(default)]
+    projects: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl Links {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::config_dir()?.join("links.toml"))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).with_context(|| format!("Could not read {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(Self::path()?, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// The project we're in, spelled the same way however you got here.
+fn project_dir() -> Result<PathBuf> {
+    Ok(fs::canonicalize(".")?)
+}
+
+/// This project's links, as name -> folder. Read once per run, like `[patch]`.
+fn linked() -> &'static BTreeMap<String, PathBuf> {
+    static LINKED: OnceLock<BTreeMap<String, PathBuf>> = OnceLock::new();
+    LINKED.get_or_init(|| {
+        let (Ok(links), Ok(project)) = (Links::load(), project_dir()) else {
+            return BTreeMap::new();
+        };
+        links
+            .projects
+            .get(&project)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| Some((name.clone(), links.packages.get(name)?.clone())))
+            .collect()
+    })
+}
+
+/// The folder this project takes `name` from, if it's linked.
+pub fn lookup(name: &str) -> Option<&'static PathBuf> {
+    linked().get(name)
+}
+
+/// Linked packages this project doesn't list in mosaic.toml. `mosaic install` puts those in too,
+/// or the link would vanish from the place the first time you reinstall.
+pub fn extra(config: &Config) -> Vec<String> {
+    linked().keys().filter(|name| !config.has_dependency(name)).cloned().collect()
+}
+
+/// `mosaic link`: with no name, offers the package in this folder for linking. With a name,
+/// uses that registered package in this project instead of the registry's, and installs it.
+///
+/// Every install after that packs the folder again, so edits show up without publishing.
+pub async fn link(name: Option<&str>) -> Result<()> {
+    let mut links = Links::load()?;
+    let Some(name) = name else {
+        let config = Config::load().context("Run 'mosaic link' in the package you want to link")?;
+        let dir = project_dir()?;
+        Logger::success(format!(
+            "Registered {} from {}. Run 'mosaic link {}' in a project to use it.",
+            Logger::highlight(&config.package.name),
+            dir.display(),
+            config.package.name
+        ));
+        links.packages.insert(config.package.name, dir);
+        return links.save();
+    };
+
+    let Some(dir) = links.packages.get(name).cloned() else {
+        return Err(anyhow!(
+            "No package called {} is registered. Run 'mosaic link' in its folder first.",
+            name
+        ));
+    };
+    let project = project_dir()?;
+    if project == dir {
+        return Err(anyhow!("{} can't be linked into itself", name));
+    }
+    Config::load()?;
+    links.projects.entry(project).or_default().insert(name.to_string());
+    links.save()?;
+
+    installer::install_package(name).await?;
+    Logger::success(format!(
+        "Linked {} to {}. Installs use it until you run 'mosaic unlink {}'.",
+        Logger::highlight(name),
+        dir.display(),
+        name
+    ));
+    Ok(())
+}
+
+/// `mosaic unlink`: with no name, withdraws the package in this folder from every project.
+/// With a name, goes back to the registry's version of it in this project.
+pub async fn unlink(name: Option<&str>) -> Result<()> {
+    let mut links = Links::load()?;
+    let Some(name) = name else {
+        let config = Config::load().context("Run 'mosaic unlink' in the package you linked")?;
+        let name = config.package.name;
+        if links.packages.remove(&name).is_none() {
+            return Err(anyhow!("{} isn't registered for linking", name));
+        }
+        let users: Vec<String> = links
+            .projects
+            .iter_mut()
+            .filter_map(|(project, names)| names.remove(&name).then(|| project.display().to_string()))
+            .collect();
+        links.projects.retain(|_, names| !names.is_empty());
+        links.save()?;
+        Logger::success(format!("Unregistered {}", Logger::highlight(&name)));
+        if !users.is_empty() {
+            Logger::info(format!(
+                "Still installed from your folder in: {}. Run 'mosaic install' there to go back to the registry.",
+                users.join(", ")
+            ));
+        }
+        return Ok(());
+    };
+
+    let project = project_dir()?;
+    let removed = links.projects.get_mut(&project).is_some_and(|names| names.remove(name));
+    if !removed {
+        return Err(anyhow!("{} isn't linked in this project", name));
+    }
+    links.projects.retain(|_, names| !names.is_empty());
+    links.save()?;
+
+    // The lockfile has the linked version, which may never have been published. Forget it.
+    let mut lockfile = Lockfile::load()?;
+    lockfile.packages.remove(name);
+    lockfile.save()?;
+
+    let config = Config::load()?;
+    match config.all_dependencies().find(|(dep, _)| dep.as_str() == name) {
+        Some((_, query)) => {
+            installer::install_package(&format!("{}@{}", name, query)).await?;
+            Logger::success(format!("Unlinked {}. It's from the registry again.", Logger::highlight(name)));
+        }
+        None => {
+            let poly_path = crate::attest::find_poly(None)?;
+            let content = xml_handler::remove_module_script(&fs::read_to_string(&poly_path)?, name)?;
+            fs::write(&poly_path, content)?;
+            Logger::success(format!(
+                "Unlinked {} and removed it from {}",
+                Logger::highlight(name),
+                poly_path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// What mosaic.lock records for a linked package: `link+<folder>`.
+pub fn source(dir: &Path) -> String {
+    format!("link+{}", dir.display())
+}
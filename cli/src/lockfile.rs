@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
 /// Represents the mosaic.lock file.
 /// This file ensures reproducible builds by locking dependencies to specific versions and hashes.
@@ -13,12 +13,24 @@ pub struct Lockfile {
     pub packages: HashMap<String, LockedPackage>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct LockedPackage {
     pub version: String,
     pub integrity: String, // SHA256 hash of the zip blob
     #[serde(default)]
     pub dependencies: HashMap<String, String>,
+    /// The license the registry listed when this was installed. Lets `mosaic info` say something
+    /// useful offline. Lockfiles from before this field just don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Set when `[patch]` swapped the registry out for this package: `path+../logger` or
+    /// `git+https://github.com/me/logger#<commit>`. None means it came from the registry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The registry package, when mosaic.toml installs it under another name. The key stays
+    /// the local name, since that's the module in the .poly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
 }
 
 impl Lockfile {
@@ -26,6 +38,11 @@ impl Lockfile {
         let path = Path::new("mosaic.lock");
         if path.exists() {
             let content = fs::read_to_string(path)?;
+            if has_conflict_markers(&content) {
+                return Err(anyhow!(
+                    "mosaic.lock has merge conflict markers. Run 'mosaic install' to resolve them, or 'mosaic lock --repair' to rebuild it."
+                ));
+            }
             let lockfile: Lockfile = toml::from_str(&content)?;
             Ok(lockfile)
         } else {
@@ -46,4 +63,113 @@ impl Lockfile {
     pub fn insert(&mut self, name: String, pkg: LockedPackage) {
         self.packages.insert(name, pkg);
     }
+
+    /// Reads a mosaic.lock that has git conflict markers in it.
+    /// Ok(None) if there's no lockfile or it isn't conflicted—use `load()` then.
+    pub fn load_conflicted() -> Result<Option<ConflictedLockfile>> {
+        let path = Path::new("mosaic.lock");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        if !has_conflict_markers(&content) {
+            return Ok(None);
+        }
+
+        // Rebuild each side of the merge as a full file and parse it on its own.
+        let (ours, theirs) = split_conflict(&content);
+        let parse = |side: &str, text: &str| -> Result<Lockfile> {
+            toml::from_str(text).map_err(|e| {
+                anyhow!(
+                    "Couldn't parse the {} side of the mosaic.lock conflict ({}). Run 'mosaic lock --repair' to rebuild it.",
+                    side,
+                    e
+                )
+            })
+        };
+        let ours = parse("local", &ours)?;
+        let theirs = parse("incoming", &theirs)?;
+
+        // Entries both sides agree on are kept as-is. Everything else is "conflicted".
+        let mut agreed = Lockfile::default();
+        let mut conflicted = Vec::new();
+        for name in ours.packages.keys().chain(theirs.packages.keys()) {
+            match (ours.get(name), theirs.get(name)) {
+                (Some(a), Some(b)) if a == b => agreed.insert(name.clone(), a.clone()),
+                _ if !conflicted.contains(name) => conflicted.push(name.clone()),
+                _ => {}
+            }
+        }
+        conflicted.sort();
+
+        Ok(Some(ConflictedLockfile {
+            agreed,
+            conflicted,
+            ours,
+            theirs,
+        }))
+    }
+}
+
+/// Both sides of a conflicted lockfile, split into what they agree on and what they don't.
+pub struct ConflictedLockfile {
+    pub agreed: Lockfile,
+    /// Packages that differ between the sides or only exist on one. Sorted.
+    pub conflicted: Vec<String>,
+    pub ours: Lockfile,
+    pub theirs: Lockfile,
+}
+
+fn has_conflict_markers(content: &str) -> bool {
+    content.lines().any(|l| l.starts_with("<<<<<<<"))
+}
+
+/// Splits git conflict hunks into an "ours" file and a "theirs" file.
+/// Handles diff3 style too (the `|||||||` base section is dropped from both).
+fn split_conflict(content: &str) -> (String, String) {
+    enum Section {
+        Shared,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let (mut ours, mut theirs) = (String::new(), String::new());
+    let mut section = Section::Shared;
+
+    for line in content.lines() {
+        if line.starts_with("<<<<<<<") {
+            section = Section::Ours;
+            continue;
+        }
+        if line.starts_with("|||||||") {
+            section = Section::Base;
+            continue;
+        }
+        if line.starts_with("=======") {
+            section = Section::Theirs;
+            continue;
+        }
+        if line.starts_with(">>>>>>>") {
+            section = Section::Shared;
+            continue;
+        }
+
+        match section {
+            Section::Shared => {
+                push_line(&mut ours, line);
+                push_line(&mut theirs, line);
+            }
+            Section::Ours => push_line(&mut ours, line),
+            Section::Base => {}
+            Section::Theirs => push_line(&mut theirs, line),
+        }
+    }
+
+    (ours, theirs)
+}
+
+fn push_line(buf: &mut String, line: &str) {
+    buf.push_str(line);
+    buf.push('\n');
 }
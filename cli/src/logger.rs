@@ -1,11 +1,14 @@
+use crate::progress;
+use crate::theme;
 use colored::*;
 use console::Term;
 use std::fmt::Display;
 
 /// Logging utilities with branded colors and emoji.
 /// All methods are static because we never instantiate this—it's just a namespace for logging functions.
-/// Color scheme: blue (14, 173, 221) for info, purple (125, 59, 155) for brand.
-/// Yes, I hardcoded the RGB values instead of using constants. Don't @ me.
+/// Everything prints through `progress::suspend`, so messages never collide with a spinner.
+/// Colors come from the theme (see `theme`): accent blue for info, brand purple for labels,
+/// darker versions of both on light terminals, and nothing at all in plain mode.
 pub struct Logger;
 
 impl Logger {
@@ -31,9 +34,9 @@ impl Logger {
             // Color slashes/pipes purple to match the brand, everything else blue.
             // This is petty and unnecessary but I like how it looks.
             let colored_line = if line.contains('/') || line.contains('|') {
-                line.truecolor(125, 59, 155).bold().to_string()
+                theme::brand(line).bold().to_string()
             } else {
-                line.truecolor(14, 173, 221).bold().to_string()
+                theme::accent(line).bold().to_string()
             };
             println!("{:^width$}", colored_line, width = width);
         }
@@ -43,58 +46,53 @@ impl Logger {
     /// Prints an info message with a blue bullet point.
     /// Use this for general information that doesn't fit the other categories.
     pub fn info<T: Display>(msg: T) {
-        println!("{} {}", "*".truecolor(14, 173, 221).bold(), msg);
+        progress::suspend(|| println!("{} {}", theme::accent("*").bold(), msg));
     }
 
     /// Prints a success message with a green indicator.
     /// Feels good when operations complete.
     pub fn success<T: Display>(msg: T) {
-        println!("{} {}", "[v]".green().bold(), msg);
+        progress::suspend(|| println!("{} {}", "[v]".green().bold(), msg));
     }
 
     /// Prints an error message.
     /// Something went wrong and the user needs to know.
     pub fn error<T: Display>(msg: T) {
-        println!("{} {}", "[x]".red().bold(), msg);
+        progress::suspend(|| println!("{} {}", "[x]".red().bold(), msg));
     }
 
     /// Prints a warning.
     /// Use sparingly—overuse makes people ignore warnings.
     pub fn warn<T: Display>(msg: T) {
-        println!("{} {}", "[!]".yellow().bold(), msg);
+        progress::suspend(|| println!("{} {}", "[!]".yellow().bold(), msg));
     }
 
     /// Prints a section header in purple with underline.
     /// Breaks up the output so users can follow along.
     /// The leading newline prevents it from running into previous output.
     pub fn header<T: Display>(msg: T) {
-        println!(
-            "\n{}",
-            msg.to_string().truecolor(125, 59, 155).bold().underline()
-        );
+        let line = theme::brand(&msg.to_string()).bold().underline();
+        progress::suspend(|| println!("\n{}", line));
     }
 
     /// Prints a command being executed (like a label for what's happening).
     /// Format: "command description" where command is purple and description is dimmed.
     /// Looks nice in output logs.
     pub fn command<T: Display>(cmd: &str, msg: T) {
-        println!(
-            "{} {}",
-            cmd.truecolor(125, 59, 155).bold(),
-            msg.to_string().dimmed()
-        );
+        let line = format!("{} {}", theme::brand(cmd).bold(), msg.to_string().dimmed());
+        progress::suspend(|| println!("{}", line));
     }
 
     /// Returns a string colored in brand blue (for inline use).
     /// Used in formatted strings where you need to highlight something.
     pub fn highlight<T: Display>(msg: T) -> String {
-        msg.to_string().truecolor(14, 173, 221).bold().to_string()
+        theme::accent(&msg.to_string()).bold().to_string()
     }
 
     /// Returns a string colored in brand purple (for inline use).
     /// Used for package names, versions, and other important metadata.
     pub fn brand_text<T: Display>(msg: T) -> String {
-        msg.to_string().truecolor(125, 59, 155).bold().to_string()
+        theme::brand(&msg.to_string()).bold().to_string()
     }
 
     /// Returns a dimmed string (less important text).
@@ -103,8 +101,40 @@ impl Logger {
         msg.to_string().dimmed().to_string()
     }
 
-    /// Prints a debug message. Use sparingly.
+    /// Logs a debug message. Only shows up with `--verbose` (or a RUST_LOG that asks for it).
+    /// Goes through tracing like everything else now, so it lands inside whatever span is active.
     pub fn debug<T: Display>(msg: T) {
-        println!("{} {}", "[DEBUG]".dimmed(), msg);
+        tracing::debug!("{}", msg);
+    }
+
+    /// Sets up tracing output. Call once, first thing in main.
+    ///
+    /// Quiet by default—only warnings from our own code. `--verbose` turns on debug for mosaic
+    /// and shows spans, so you can see which package a request or decision belonged to and how
+    /// long it took. `RUST_LOG` wins over both if it's set (e.g. `RUST_LOG=mosaic=trace,reqwest=debug`).
+    ///
+    /// Everything goes to stderr so `--verbose` never corrupts output that's meant to be piped,
+    /// and around any spinners rather than through them.
+    pub fn init_tracing(verbose: bool) {
+        use tracing_subscriber::EnvFilter;
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        let default = if verbose { "mosaic=debug" } else { "mosaic=warn" };
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default));
+
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(|| progress::Stderr)
+            .without_time();
+
+        if verbose {
+            builder
+                .with_target(false)
+                .with_span_events(FmtSpan::CLOSE)
+                .pretty()
+                .init();
+        } else {
+            builder.compact().init();
+        }
     }
 }
@@ -1,43 +1,113 @@
+pub mod admin;
+pub mod adopt;
+pub mod attest;
 pub mod auth;
+pub mod blobs;
 pub mod cli;
+pub mod completions;
 pub mod config;
+pub mod doctor;
+pub mod endpoint;
+pub mod history;
+pub mod hooks;
+pub mod init;
 pub mod installer;
+pub mod link;
 pub mod lockfile;
 pub mod logger;
+pub mod metadata;
+pub mod migrate;
+pub mod mode;
+pub mod patch;
+pub mod paths;
+pub mod progress;
+pub mod project_lock;
 pub mod registry;
+pub mod spec;
+pub mod syntax;
+pub mod telemetry;
+pub mod theme;
+pub mod timings;
 pub mod updater;
 pub mod xml_handler;
 
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{AccountCommands, CacheCommands, Cli, Commands, TokenCommands};
 use logger::Logger;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    // Allow users to override the registry URL via CLI flag.
-    // We use unsafe here because set_var mutates global state, but it's fine since we're doing it once at startup.
-    // If you're uncomfortable with this, feel free to refactor to pass the URL through the call stack instead.
-    if let Some(url) = &cli.api_url {
-        unsafe {
-            std::env::set_var("MOSAIC_REGISTRY_URL", url);
-        }
+    // Remember --registry for endpoint::registry_url(). Resolution order lives there.
+    endpoint::set_override(cli.registry.clone());
+    // Same for --portable, read by paths::portable_root().
+    paths::set_portable(cli.portable);
+    // And --no-cache, read by metadata::get_json().
+    metadata::set_no_cache(cli.no_cache);
+    // And --json, read by mode::is_json().
+    mode::set_json(cli.json);
+
+    // Colors, before anything gets printed. Needs --portable to know where the config lives.
+    theme::init();
+
+    // Debug output (request URLs, resolution decisions, .poly edits) only shows with --verbose.
+    Logger::init_tracing(cli.verbose);
+    Logger::debug("Verbose logging enabled");
+
+    // The opt-in update check (`mosaic check-update on`) runs alongside the command, so it
+    // costs nothing unless GitHub is slower than the command itself.
+    // Not for completions though—their stdout is parsed by the shell.
+    let update_check = (!matches!(
+        cli.command,
+        Commands::Completions { .. } | Commands::CompletePackages { .. } | Commands::Upgrade { .. } | Commands::CheckUpdate { .. }
+    ) && updater::auto_check_due())
+    .then(|| tokio::spawn(updater::check_for_updates()));
+
+    let mut result = run(&cli).await;
+
+    // Needed credentials we don't have? In a terminal, offer to log in right here and then
+    // run the command again, instead of making people retype it.
+    if let Err(e) = &result
+        && let Some(needed) = e.downcast_ref::<auth::AuthRequired>()
+        && mode::is_interactive()
+        && login_inline(needed).await?
+    {
+        result = run(&cli).await;
     }
 
-    // Enable verbose logging if requested
-    if cli.verbose {
-        if std::env::var("RUST_LOG").is_err() {
-            unsafe {
-                std::env::set_var("RUST_LOG", "debug");
-            }
+    if let Err(e) = result {
+        return fail(e);
+    }
+
+    if let Some(check) = update_check {
+        match check.await {
+            Ok(Ok(Some(latest))) => updater::announce(&latest),
+            Ok(Ok(None)) => {}
+            // Don't crash if update check fails, just log debug
+            Ok(Err(e)) => Logger::debug(format!("Update check failed: {}", e)),
+            Err(e) => Logger::debug(format!("Update check failed: {}", e)),
         }
-        env_logger::init();
-        Logger::debug("Verbose logging enabled");
     }
 
+    Ok(())
+}
+
+/// Runs the chosen command. Split out of main so a command can be retried after an inline login.
+async fn run(cli: &Cli) -> anyhow::Result<()> {
+    // Commands that write mosaic.lock, mosaic.toml or the .poly take turns.
+    let _project_lock = match writes_project(&cli.command) {
+        Some(command) => project_lock::acquire(command)?,
+        None => None,
+    };
+
     match &cli.command {
-        Commands::Init => {
+        Commands::Init {
+            from_poly,
+            poly,
+            template,
+            yes,
+        } => {
             Logger::banner();
             // Get the directory name as a fallback project name.
             // If the user is in /home/alice/my-project, we use "my-project".
@@ -51,46 +121,185 @@ async fn main() -> anyhow::Result<()> {
                 "Initializing project: {}...",
                 Logger::highlight(project_name)
             ));
-            let config = config::Config::default(project_name);
-            config.save()?;
-            Logger::success("Created mosaic.toml");
+            if *from_poly || poly.is_some() {
+                adopt::from_poly(project_name, poly.as_deref(), *yes).await?;
+            } else {
+                init::new_project(project_name, *yes, template.as_deref())?;
+            }
         }
 
-        Commands::Install { package } => {
+        Commands::Install {
+            package,
+            save_dev,
+            no_save,
+            no_hooks,
+            frozen,
+            timings: timings_format,
+        } => {
+            if let Some(format) = timings_format {
+                if !timings::FORMATS.contains(&format.as_str()) {
+                    return Err(anyhow::anyhow!(
+                        "Unknown timings format '{}'. Pick one of: {}",
+                        format,
+                        timings::FORMATS.join(", ")
+                    ));
+                }
+                timings::enable();
+            }
+
+            // A mosaic.toml this version can't read (a newer schema, say) stops us here, not
+            // after the package is already in the place file and the save quietly skipped.
+            if std::path::Path::new("mosaic.toml").exists() {
+                config::Config::load()?;
+            }
+
+            // What the lockfile said before, so the post-install hook and the history can be
+            // told what changed.
+            let before = lockfile::Lockfile::load().unwrap_or_default();
+            let started = std::time::Instant::now();
+
             // Two modes:
-            // 1. Install a specific package: mosaic install logger@1.0.0
+            // 1. Install a specific package: mosaic install logger@1.0.0 (or logger@^1.2)
             // 2. Install all from mosaic.toml: mosaic install (no args)
-            if let Some(query) = package {
-                let (package_name, resolved_version) = installer::install_package(query).await?;
-
-                // Update mosaic.toml with the newly installed package.
-                // We wrap this in a try-load because users might not have a config yet (weird edge case).
-                if let Ok(mut config) = config::Config::load() {
-                    config.add_dependency(&package_name, &resolved_version);
-                    config.save()?;
-                    Logger::info(format!(
-                        "Added {} to mosaic.toml",
-                        Logger::brand_text(&package_name)
-                    ));
+            let result: anyhow::Result<()> = async {
+                if let Some(query) = package {
+                    // `requirement` is the range if the user typed one (logger@^1.2), otherwise the exact pin.
+                    let (package_name, requirement) = installer::install_package(query).await?;
+
+                    // Update mosaic.toml with the newly installed package, unless the user opted out.
+                    // We wrap this in a try-load because users might not have a config yet (weird edge case).
+                    if *no_save {
+                        Logger::info("Skipped mosaic.toml (--no-save)");
+                    } else if let Ok(mut config) = config::Config::load() {
+                        let table = if *save_dev {
+                            config.add_dev_dependency(&package_name, &requirement);
+                            "dev-dependencies"
+                        } else {
+                            config.add_dependency(&package_name, &requirement);
+                            "dependencies"
+                        };
+                        config.save()?;
+                        Logger::info(format!(
+                            "Added {} to mosaic.toml [{}]",
+                            Logger::brand_text(&package_name),
+                            table
+                        ));
+                    }
+                } else {
+                    // No package specified—install everything from mosaic.toml
+                    installer::set_frozen(*frozen);
+                    installer::install_all().await?;
                 }
-            } else {
-                // No package specified—install everything from mosaic.toml
-                installer::install_all().await?;
+                Ok(())
             }
+            .await;
+            let command = match package {
+                Some(query) => format!("install {}", query),
+                None => "install".to_string(),
+            };
+            history::record(&command, &before, started, &result);
+            result?;
+
+            if let Some(format) = timings_format {
+                timings::report(format)?;
+            }
+
+            hooks::post_install(&before, *no_hooks)?;
         }
 
         Commands::Remove { package } => {
-            installer::remove_package(package).await?;
+            let before = lockfile::Lockfile::load().unwrap_or_default();
+            let started = std::time::Instant::now();
+            let declared = config::Config::load().is_ok_and(|c| c.has_dependency(package));
+            let result = installer::remove_package(package).await;
+            history::record_removal(package, declared, &before, started, &result);
+            result?;
+        }
+
+        Commands::History { package, limit } => {
+            history::show(package.as_deref(), *limit)?;
+        }
+
+        Commands::List { installed, long } => {
+            if *installed {
+                installer::list_installed().await?;
+            } else {
+                installer::list_packages(*long).await?;
+            }
+        }
+
+        Commands::Outdated { exit_code } => {
+            installer::outdated(*exit_code).await?;
         }
 
-        Commands::List => {
-            installer::list_packages().await?;
+        Commands::Link { package } => {
+            link::link(package.as_deref()).await?;
         }
 
-        Commands::Update => {
-            // Update is basically just reinstall everything.
-            // Could be smarter about checking what's out of date, but this works for now.
-            installer::update_all().await?;
+        Commands::Unlink { package } => {
+            link::unlink(package.as_deref()).await?;
+        }
+
+        Commands::Doctor { fix } => {
+            doctor::doctor(*fix).await?;
+        }
+
+        Commands::Verify => {
+            installer::verify().await?;
+        }
+
+        Commands::Cache { command } => match command {
+            CacheCommands::Ls => {
+                blobs::list()?;
+            }
+            CacheCommands::Clean { metadata } => {
+                let removed = metadata::clean()?;
+                if !*metadata {
+                    completions::clear_cache()?;
+                    let zips = blobs::clean()?;
+                    if zips > 0 {
+                        Logger::info(format!("Removed {} cached package zip(s)", zips));
+                    }
+                    let partials = registry::clear_partial_downloads()?;
+                    if partials > 0 {
+                        Logger::info(format!("Removed {} partial download(s)", partials));
+                    }
+                }
+                Logger::success(format!(
+                    "Removed {} cached metadata response{}",
+                    removed,
+                    if removed == 1 { "" } else { "s" }
+                ));
+            }
+        },
+
+        Commands::Update {
+            packages,
+            latest,
+            dry_run,
+        } => {
+            // No names means "update everything". Names narrow it down and leave the rest of the lock alone.
+            let before = lockfile::Lockfile::load().unwrap_or_default();
+            let started = std::time::Instant::now();
+            let result = installer::update(packages, *latest, *dry_run).await;
+            // A dry run changes nothing, so there's nothing to remember.
+            if !*dry_run {
+                let mut command = vec!["update".to_string()];
+                command.extend(packages.iter().cloned());
+                if *latest {
+                    command.push("--latest".to_string());
+                }
+                history::record(&command.join(" "), &before, started, &result);
+            }
+            result?;
+        }
+
+        Commands::Dedupe => {
+            installer::dedupe().await?;
+        }
+
+        Commands::Lock { repair } => {
+            installer::lock(*repair).await?;
         }
 
         Commands::Login => {
@@ -102,40 +311,212 @@ async fn main() -> anyhow::Result<()> {
             registry::logout().await?;
         }
 
+        Commands::Claim { name, reason, list } => {
+            if *list {
+                registry::list_claims().await?;
+            } else if let Some(name) = name {
+                registry::claim_name(name, reason.as_deref()).await?;
+            }
+        }
+
+        Commands::Account { command } => match command {
+            AccountCommands::Rename { username } => {
+                registry::rename_account(username).await?;
+            }
+        },
+
+        Commands::Token { command } => match command {
+            TokenCommands::Create { name, packages, days } => {
+                registry::create_token(name, packages, *days).await?;
+            }
+            TokenCommands::List => {
+                registry::list_tokens().await?;
+            }
+            TokenCommands::Revoke { id } => {
+                registry::revoke_token(id).await?;
+            }
+        },
+
         Commands::Signup => {
             Logger::banner();
             registry::signup().await?;
         }
 
-        Commands::Publish { version } => {
+        Commands::Publish {
+            version,
+            tag,
+            follow_symlinks,
+            allow_syntax_errors,
+            yes,
+        } => {
             // If the user provides --version, use that. Otherwise let the registry module handle it.
-            registry::publish(version.as_deref()).await?;
+            registry::publish(
+                version.as_deref(),
+                tag.as_deref(),
+                *follow_symlinks,
+                *allow_syntax_errors,
+                *yes,
+            )
+            .await?;
+        }
+
+        Commands::Pack { out, follow_symlinks } => {
+            registry::pack_to(out.as_deref(), *follow_symlinks)?;
         }
 
-        Commands::Search { query } => {
-            registry::search(query.clone()).await?;
+        Commands::Search { query, sort } => {
+            registry::search(query.clone(), sort.as_deref()).await?;
         }
 
         Commands::Info { package } => {
             registry::info(package).await?;
         }
 
-        Commands::Unpublish { package } => {
-            registry::unpublish(package).await?;
+        Commands::View { package, file } => {
+            registry::view(package, file).await?;
         }
 
-        Commands::Upgrade => {
-            crate::updater::upgrade().await?;
+        Commands::Exec { package, save } => {
+            registry::exec(package, save.as_deref()).await?;
+        }
+
+        Commands::Diff { package, from, to } => {
+            registry::diff(package, from.as_deref(), to.as_deref()).await?;
         }
-    }
 
-    // Check for updates in the background (fire and forget-ish, or just quick check)
-    // We do this AFTER the command so we don't slow down the actual work,
-    // but before the process exits.
-    if let Err(e) = crate::updater::check_for_updates().await {
-        // Don't crash if update check fails, just log debug
-        Logger::debug(format!("Update check failed: {}", e));
+        Commands::Changelog { package } => {
+            registry::changelog(package).await?;
+        }
+
+        Commands::MigratePublish { dir, dry_run } => {
+            migrate::migrate_publish(dir, *dry_run).await?;
+        }
+
+        Commands::Attest { verify, poly, file } => {
+            if *verify {
+                attest::verify(poly.as_deref(), file.as_deref())?;
+            } else {
+                attest::create(poly.as_deref(), file.as_deref())?;
+            }
+        }
+
+        Commands::Unpublish { package, yes } => {
+            registry::unpublish(package, *yes).await?;
+        }
+
+        Commands::Protect { package, off } => {
+            registry::set_protection(package, !off).await?;
+        }
+
+        Commands::Visibility { package, visibility } => {
+            registry::set_visibility(package, visibility).await?;
+        }
+
+        Commands::Members { package, add, remove } => {
+            registry::members(package, add, remove).await?;
+        }
+
+        Commands::Approve { package, token } => {
+            registry::approve(package, token).await?;
+        }
+
+        Commands::Reject { package } => {
+            registry::reject(package).await?;
+        }
+
+        Commands::Completions { shell } => {
+            completions::generate(*shell);
+        }
+
+        Commands::CompletePackages { prefix } => {
+            completions::complete_packages(prefix.as_deref().unwrap_or("")).await;
+        }
+
+        Commands::CheckUpdate { action } => {
+            updater::check_update(action.as_deref()).await?;
+        }
+
+        Commands::Telemetry { action } => {
+            crate::telemetry::run(action.as_deref())?;
+        }
+
+        Commands::Theme { theme } => {
+            crate::theme::run(theme.as_deref())?;
+        }
+
+        Commands::Upgrade {
+            channel,
+            version,
+            allow_downgrade,
+        } => {
+            updater::upgrade(channel.as_deref(), version.as_deref(), *allow_downgrade)
+                .await?;
+        }
+
+        Commands::Admin { command } => {
+            admin::run(command).await?;
+        }
     }
 
     Ok(())
 }
+
+/// Offers to log in after a command failed for lack of credentials.
+/// Returns true if we're now logged in to the right registry and the command should run again.
+async fn login_inline(needed: &auth::AuthRequired) -> anyhow::Result<bool> {
+    Logger::warn(needed);
+    let go = inquire::Confirm::new(&format!("Log in to {} now?", needed.registry))
+        .with_default(true)
+        .prompt()?;
+    if !go {
+        return Ok(false);
+    }
+
+    registry::login().await?;
+    // login() reports a bad password itself and returns Ok, so check we actually got a token.
+    let logged_in = auth::AuthConfig::load()?.token_for(&needed.registry).is_ok();
+    if logged_in {
+        Logger::info("Picking up where we left off...");
+    }
+    Ok(logged_in)
+}
+
+/// The commands that change project files, named for the "another mosaic process" message.
+fn writes_project(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Init { from_poly, poly, .. } if *from_poly || poly.is_some() => Some("init --from-poly"),
+        Commands::Install { .. } => Some("install"),
+        Commands::Remove { .. } => Some("remove"),
+        Commands::Update { dry_run: false, .. } => Some("update"),
+        Commands::Dedupe => Some("dedupe"),
+        Commands::Lock { repair: true } => Some("lock --repair"),
+        Commands::Doctor { fix: true } => Some("doctor --fix"),
+        Commands::Link { .. } => Some("link"),
+        Commands::Unlink { .. } => Some("unlink"),
+        _ => None,
+    }
+}
+
+/// Reports a failed command. Humans get anyhow's usual output; `--json` gets one object on
+/// stdout with a stable `code`, and CI logs get the code up front so it's easy to grep for.
+fn fail(e: anyhow::Error) -> anyhow::Result<()> {
+    let auth = e.downcast_ref::<auth::AuthRequired>();
+    let code = if auth.is_some() { auth::AuthRequired::CODE } else { "ERROR" };
+
+    if mode::is_json() {
+        let mut error = serde_json::json!({ "code": code, "message": e.to_string() });
+        if let Some(auth) = auth {
+            error["registry"] = serde_json::json!(auth.registry);
+            error["login_command"] = serde_json::json!(auth.login_command());
+        }
+        println!("{}", serde_json::json!({ "error": error }));
+        std::process::exit(1);
+    }
+
+    if auth.is_some() && mode::is_ci() {
+        eprintln!("{}: {}", code, e);
+        std::process::exit(1);
+    }
+
+    Err(e)
+}
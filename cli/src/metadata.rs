@@ -0,0 +1,168 @@
+use crate::endpoint;
+use crate::logger::Logger;
+use crate::paths;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long a cached response is trusted without asking the registry at all.
+/// Short on purpose: a fresh publish should show up within a coffee break, not a day.
+/// `MOSAIC_METADATA_TTL` (seconds) overrides it; 0 means always revalidate.
+const DEFAULT_TTL_SECS: u64 = 5 * 60;
+
+/// Whether `--no-cache` was passed. Set once in main, same deal as the registry override.
+static NO_CACHE: OnceLock<bool> = OnceLock::new();
+
+/// Records the `--no-cache` flag. Only the first call counts.
+pub fn set_no_cache(no_cache: bool) {
+    let _ = NO_CACHE.set(no_cache);
+}
+
+/// Whether `--no-cache` was passed. The blob cache skips its reads on it too.
+pub fn no_cache() -> bool {
+    NO_CACHE.get().copied().unwrap_or(false)
+}
+
+fn ttl_secs() -> u64 {
+    std::env::var("MOSAIC_METADATA_TTL")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_TTL_SECS)
+}
+
+/// One cached registry response. The URL is kept so a hash collision (ha) or a
+/// registry switch can't hand back someone else's JSON.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    etag: Option<String>,
+    fetched_at: u64,
+    body: serde_json::Value,
+}
+
+/// Where cached metadata lives: `<cache dir>/metadata/`.
+pub fn dir() -> Result<PathBuf> {
+    let dir = paths::cache_dir()?.join("metadata");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Deletes every cached response. Returns how many files went.
+pub fn clean() -> Result<usize> {
+    let dir = dir()?;
+    let mut removed = 0;
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Keyed on the full URL, so two registries never share entries.
+fn entry_path(url: &str) -> Result<PathBuf> {
+    let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+    Ok(dir()?.join(format!("{}.json", key)))
+}
+
+fn load(url: &str) -> Option<Entry> {
+    let content = fs::read_to_string(entry_path(url).ok()?).ok()?;
+    let entry: Entry = serde_json::from_str(&content).ok()?;
+    (entry.url == url).then_some(entry)
+}
+
+fn store(entry: &Entry) {
+    // A cache we can't write is just a slower run, not a failure.
+    let written = entry_path(&entry.url)
+        .and_then(|path| Ok(fs::write(path, serde_json::to_vec(entry)?)?));
+    if let Err(e) = written {
+        Logger::debug(format!("Couldn't cache {}: {}", entry.url, e));
+    }
+}
+
+/// Whatever the cache has for `path`, however old, and when it was fetched. Never touches the
+/// network. For showing *something* when the registry can't be reached at all.
+pub fn cached(path: &str) -> Option<(serde_json::Value, u64)> {
+    let entry = load(&format!("{}{}", endpoint::registry_url(), path))?;
+    Some((entry.body, entry.fetched_at))
+}
+
+/// GETs a JSON document from the registry, going through the metadata cache.
+///
+/// Returns `Ok(None)` for a 404 so callers can say "package not found" their own way.
+///
+/// 1. Cached and younger than the TTL: returned without touching the network.
+/// 2. Cached but stale: revalidated with `If-None-Match` if we have an ETag. A 304 just
+///    bumps the timestamp.
+/// 3. The registry is unreachable: a stale entry is better than nothing, so it's used with a warning.
+///
+/// `--no-cache` skips 1 and 3 (and the ETag), but still writes the fresh answer back.
+#[tracing::instrument(level = "debug")]
+pub async fn get_json(path: &str) -> Result<Option<serde_json::Value>> {
+    let url = format!("{}{}", endpoint::registry_url(), path);
+    let cached = if no_cache() { None } else { load(&url) };
+
+    if let Some(entry) = &cached {
+        let age = now().saturating_sub(entry.fetched_at);
+        if age < ttl_secs() {
+            tracing::debug!(%url, age, "metadata cache hit");
+            return Ok(Some(entry.body.clone()));
+        }
+    }
+
+    let etag = cached.as_ref().and_then(|e| e.etag.as_deref());
+    let res = match endpoint::get_conditional(path, etag).await {
+        Ok(res) => res,
+        Err(e) => match cached {
+            Some(entry) => {
+                Logger::warn(format!("{} — using cached metadata", e));
+                return Ok(Some(entry.body));
+            }
+            None => return Err(e),
+        },
+    };
+
+    if res.status() == reqwest::StatusCode::NOT_MODIFIED
+        && let Some(mut entry) = cached
+    {
+        tracing::debug!(%url, "metadata revalidated (304)");
+        entry.fetched_at = now();
+        store(&entry);
+        return Ok(Some(entry.body));
+    }
+
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+
+    let etag = res
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = endpoint::json(res).await?;
+
+    store(&Entry {
+        url,
+        etag,
+        fetched_at: now(),
+        body: body.clone(),
+    });
+    Ok(Some(body))
+}
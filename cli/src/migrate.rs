@@ -0,0 +1,250 @@
+use crate::config::Config;
+use crate::endpoint;
+use crate::logger::Logger;
+use crate::registry;
+use anyhow::{Result, anyhow};
+use comfy_table::Table;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One package folder found under the migration root.
+struct LocalPackage {
+    dir: PathBuf,
+    name: String,
+    version: String,
+    /// Only the dependencies that are *also* in this migration. Anything else
+    /// has to be on the registry already, which isn't our problem to order.
+    local_deps: Vec<String>,
+}
+
+/// How each package ended up. Printed as the summary table at the end.
+enum Outcome {
+    Published,
+    AlreadyPublished,
+    Failed(String),
+    Skipped(String),
+}
+
+/// `mosaic migrate-publish <dir>`: publishes every package folder under `dir`, dependencies first.
+///
+/// Resumable by design: before publishing anything we ask the registry whether that exact
+/// version is already there (with a blob), and skip it if so. Ctrl+C halfway through a 300-package
+/// seed and just run it again.
+pub async fn migrate_publish(root: &Path, dry_run: bool) -> Result<()> {
+    let packages = discover(root)?;
+    if packages.is_empty() {
+        return Err(anyhow!(
+            "No packages found in {}. Each package needs its own folder with a mosaic.toml.",
+            root.display()
+        ));
+    }
+
+    let order = publish_order(&packages)?;
+
+    Logger::header(format!(
+        "Migrating {} packages to {}",
+        packages.len(),
+        endpoint::registry_url()
+    ));
+
+    if dry_run {
+        for (i, name) in order.iter().enumerate() {
+            let pkg = &packages[name];
+            println!(
+                "  {} {}@{} {}",
+                Logger::dim(format!("{:>3}.", i + 1)),
+                Logger::brand_text(&pkg.name),
+                pkg.version,
+                Logger::dim(pkg.dir.display())
+            );
+        }
+        Logger::info("Dry run: nothing was published.");
+        return Ok(());
+    }
+
+    let mut outcomes: BTreeMap<String, Outcome> = BTreeMap::new();
+    let mut failed: HashSet<String> = HashSet::new();
+
+    for (i, name) in order.iter().enumerate() {
+        let pkg = &packages[name];
+        Logger::info(format!(
+            "[{}/{}] {}@{}",
+            i + 1,
+            order.len(),
+            Logger::brand_text(&pkg.name),
+            pkg.version
+        ));
+
+        // If a dependency didn't make it, this one would publish but be uninstallable.
+        if let Some(dep) = pkg.local_deps.iter().find(|d| failed.contains(*d)) {
+            failed.insert(name.clone());
+            outcomes.insert(name.clone(), Outcome::Skipped(format!("{} failed", dep)));
+            continue;
+        }
+
+        match already_published(&pkg.name, &pkg.version).await {
+            Ok(true) => {
+                outcomes.insert(name.clone(), Outcome::AlreadyPublished);
+                continue;
+            }
+            Ok(false) => {}
+            Err(e) => Logger::debug(format!("Could not check {}: {}", pkg.name, e)),
+        }
+
+        match registry::publish_dir(&pkg.dir, None, None, false, false, false).await {
+            Ok(_) => {
+                outcomes.insert(name.clone(), Outcome::Published);
+            }
+            Err(e) => {
+                Logger::error(format!("{}: {}", pkg.name, e));
+                failed.insert(name.clone());
+                outcomes.insert(name.clone(), Outcome::Failed(e.to_string()));
+            }
+        }
+    }
+
+    // Summary, in publish order so it reads like the log above.
+    let mut table = Table::new();
+    table.set_header(vec!["Package", "Version", "Result"]);
+    let (mut published, mut existing) = (0, 0);
+    for name in &order {
+        let result = match &outcomes[name] {
+            Outcome::Published => {
+                published += 1;
+                "published".to_string()
+            }
+            Outcome::AlreadyPublished => {
+                existing += 1;
+                "already published".to_string()
+            }
+            Outcome::Failed(e) => format!("failed: {}", e),
+            Outcome::Skipped(why) => format!("skipped: {}", why),
+        };
+        table.add_row(vec![name.clone(), packages[name].version.clone(), result]);
+    }
+    println!("{table}");
+
+    if failed.is_empty() {
+        Logger::success(format!(
+            "Done. {} published, {} already there.",
+            published, existing
+        ));
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} packages didn't make it. Fix them and re-run—finished ones are skipped.",
+            failed.len()
+        ))
+    }
+}
+
+/// Finds every immediate subfolder of `root` with a mosaic.toml in it.
+fn discover(root: &Path) -> Result<BTreeMap<String, LocalPackage>> {
+    let mut configs = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let dir = entry?.path();
+        if !dir.is_dir() || !dir.join("mosaic.toml").exists() {
+            continue;
+        }
+        let config = Config::load_from(&dir)
+            .map_err(|e| anyhow!("{}: invalid mosaic.toml: {}", dir.display(), e))?;
+        configs.push((dir, config));
+    }
+
+    let names: HashSet<String> = configs
+        .iter()
+        .map(|(_, c)| c.package.name.clone())
+        .collect();
+
+    let mut packages = BTreeMap::new();
+    for (dir, config) in configs {
+        let name = config.package.name.clone();
+        if packages.contains_key(&name) {
+            return Err(anyhow!(
+                "Two folders both claim to be '{}'. Package names have to be unique.",
+                name
+            ));
+        }
+
+        // Dev-dependencies don't get published, so they don't constrain the order either.
+        let local_deps = config
+            .dependencies
+            .keys()
+            .filter(|d| names.contains(*d))
+            .cloned()
+            .collect();
+
+        packages.insert(
+            name.clone(),
+            LocalPackage {
+                dir,
+                name,
+                version: config.package.version,
+                local_deps,
+            },
+        );
+    }
+
+    Ok(packages)
+}
+
+/// Topological sort (Kahn's algorithm), dependencies before dependents.
+/// Ties are broken alphabetically so the order is the same every run.
+fn publish_order(packages: &BTreeMap<String, LocalPackage>) -> Result<Vec<String>> {
+    let mut remaining: BTreeMap<&str, HashSet<&str>> = packages
+        .iter()
+        .map(|(name, pkg)| {
+            (
+                name.as_str(),
+                pkg.local_deps.iter().map(|d| d.as_str()).collect(),
+            )
+        })
+        .collect();
+
+    let mut order = Vec::with_capacity(packages.len());
+    while !remaining.is_empty() {
+        let ready: Vec<&str> = remaining
+            .iter()
+            .filter(|(_, deps)| deps.is_empty())
+            .map(|(name, _)| *name)
+            .collect();
+
+        if ready.is_empty() {
+            let stuck: Vec<&str> = remaining.keys().copied().collect();
+            return Err(anyhow!(
+                "Dependency cycle between: {}. Can't pick a publish order.",
+                stuck.join(", ")
+            ));
+        }
+
+        for name in ready {
+            remaining.remove(name);
+            for deps in remaining.values_mut() {
+                deps.remove(name);
+            }
+            order.push(name.to_string());
+        }
+    }
+
+    Ok(order)
+}
+
+/// True if the registry already has this exact version with an uploaded blob.
+/// A version stuck at "tbd" (registered, upload never finished) counts as not published,
+/// so re-running finishes it off.
+async fn already_published(name: &str, version: &str) -> Result<bool> {
+    let res = endpoint::get(&format!("/packages/{}/versions", name)).await?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(false);
+    }
+    if !res.status().is_success() {
+        return Err(anyhow!("registry returned {}", res.status()));
+    }
+
+    let versions: Vec<serde_json::Value> = res.json().await?;
+    Ok(versions.iter().any(|v| {
+        v["version"].as_str() == Some(version)
+            && v["lua_source_url"].as_str().is_some_and(|u| u != "tbd")
+    }))
+}
@@ -0,0 +1,26 @@
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// Whether `--json` was passed. Set once in main, same deal as the registry override.
+static JSON: OnceLock<bool> = OnceLock::new();
+
+/// Records the `--json` flag. Only the first call counts.
+pub fn set_json(json: bool) {
+    let _ = JSON.set(json);
+}
+
+/// True when output is for a machine: errors come out as one JSON object on stdout.
+pub fn is_json() -> bool {
+    JSON.get().copied().unwrap_or(false)
+}
+
+/// True on CI runners. Pretty much all of them set `CI`; we only ignore an explicit "false"/"0".
+pub fn is_ci() -> bool {
+    std::env::var("CI").is_ok_and(|v| !v.is_empty() && v != "false" && v != "0")
+}
+
+/// Whether we can ask the user things. Needs a real terminal on both ends, and nobody
+/// watching for JSON—a prompt in the middle of a CI log just hangs until the job times out.
+pub fn is_interactive() -> bool {
+    !is_json() && !is_ci() && std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
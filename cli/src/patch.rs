@@ -0,0 +1,228 @@
+use crate::config::{Config, PatchSource};
+use crate::logger::Logger;
+use crate::paths;
+use crate::registry::{self, DownloadedBlob};
+use crate::spec::GithubSource;
+use anyhow::{Context, Result, anyhow};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// A package that `[patch]` took from somewhere other than the registry, packed and ready to
+/// install like a download.
+pub struct Patched {
+    /// From the patch's own mosaic.toml, like the version.
+    pub name: String,
+    pub version: String,
+    /// What goes in the lockfile's `source`: `path+../logger`, `git+<url>#<commit>`,
+    /// `github+<owner>/<repo>?rev=<ref>#<commit>`.
+    pub source: String,
+    pub dependencies: BTreeMap<String, String>,
+    pub license: Option<String>,
+    pub blob: DownloadedBlob,
+}
+
+/// The project's `[patch]` table. Read once per run: resolution asks about every package in
+/// the tree, and the table doesn't change halfway through an install.
+fn patches() -> &'static HashMap<String, PatchSource> {
+    static PATCHES: OnceLock<HashMap<String, PatchSource>> = OnceLock::new();
+    PATCHES.get_or_init(|| Config::load().map(|c| c.patch).unwrap_or_default())
+}
+
+/// Where `[patch]` says `name` should come from, if anywhere.
+pub fn lookup(name: &str) -> Option<&'static PatchSource> {
+    patches().get(name)
+}
+
+/// The project's GitHub dependencies (`{ github = "owner/repo" }`), read once like `[patch]`.
+fn github_dependencies() -> &'static HashMap<String, GithubSource> {
+    static GITHUB: OnceLock<HashMap<String, GithubSource>> = OnceLock::new();
+    GITHUB.get_or_init(|| Config::load().map(|c| c.github).unwrap_or_default())
+}
+
+/// The GitHub repo mosaic.toml takes `name` from, if it's a GitHub dependency.
+pub fn github(name: &str) -> Option<&'static GithubSource> {
+    github_dependencies().get(name)
+}
+
+/// The project's aliases (`log = { package = "super-logger", ... }`), read once like `[patch]`.
+fn aliases() -> &'static HashMap<String, String> {
+    static ALIASES: OnceLock<HashMap<String, String>> = OnceLock::new();
+    ALIASES.get_or_init(|| Config::load().map(|c| c.aliases).unwrap_or_default())
+}
+
+/// What the registry calls `name`: the aliased package if mosaic.toml has one, else `name`.
+pub fn registry_name(name: &str) -> &str {
+    aliases().get(name).map(String::as_str).unwrap_or(name)
+}
+
+/// Gets a package from a GitHub repo ready to install, same as a git `[patch]`.
+///
+/// Unlike a patch, which is there to try out whatever the branch has today, a GitHub
+/// dependency is part of the build, so it's locked: given the `source` from mosaic.lock for the
+/// same repo and ref, that commit is checked out even if the tag has moved since. `expected`
+/// is the name mosaic.toml files it under, which the repo's mosaic.toml has to agree with.
+pub fn load_github(
+    source: &GithubSource,
+    expected: Option<&str>,
+    locked: Option<&str>,
+) -> Result<Patched> {
+    let url = source.url();
+    let rev = locked
+        .and_then(|l| source.locked_commit(l))
+        .or(source.rev.as_deref());
+    let (dir, commit) = checkout(&url, rev)?;
+
+    let config = Config::load_from(&dir).with_context(|| {
+        format!(
+            "{} has no mosaic.toml, so it isn't a mosaic package",
+            source
+        )
+    })?;
+    if let Some(expected) = expected
+        && config.package.name != expected
+    {
+        return Err(anyhow!(
+            "mosaic.toml lists {} as {}, but that repo's package is called '{}'",
+            source,
+            expected,
+            config.package.name
+        ));
+    }
+
+    let blob = registry::pack_local(&dir)?;
+    let dependencies = declared_dependencies(&config);
+    Ok(Patched {
+        name: config.package.name,
+        version: config.package.version,
+        source: format!("{}#{}", source.lock_prefix(), commit),
+        dependencies,
+        license: config.package.license,
+        blob,
+    })
+}
+
+/// Gets a patched package ready to install.
+///
+/// 1. Find the folder: the path as given, or a checkout of the git repo
+/// 2. Make sure it really is the package it's patching (a typo'd path shouldn't install
+///    `ui` under the name `logger`)
+/// 3. Pack it exactly like `mosaic publish` would, so it hashes and extracts like a download
+pub fn load(name: &str, source: &PatchSource) -> Result<Patched> {
+    // 1. Folder
+    let (dir, recorded) = match source {
+        PatchSource::Path { path } => (PathBuf::from(path), format!("path+{}", path)),
+        PatchSource::Git { git, rev } => {
+            let (dir, commit) = checkout(git, rev.as_deref())?;
+            (dir, format!("git+{}#{}", git, commit))
+        }
+    };
+
+    // 2. Right package?
+    let config = Config::load_from(&dir).with_context(|| {
+        format!("[patch] for {} points at {}, which has no mosaic.toml", name, dir.display())
+    })?;
+    if config.package.name != name {
+        return Err(anyhow!(
+            "[patch] for {} points at {}, but that's the package '{}'",
+            name,
+            dir.display(),
+            config.package.name
+        ));
+    }
+
+    // 3. Pack
+    let blob = registry::pack_local(&dir)?;
+    let dependencies = declared_dependencies(&config);
+    Ok(Patched {
+        name: config.package.name,
+        version: config.package.version,
+        source: recorded,
+        dependencies,
+        license: config.package.license,
+        blob,
+    })
+}
+
+/// Gets a package `mosaic link` points this project at, same as a path `[patch]`.
+pub fn load_linked(name: &str, dir: &Path) -> Result<Patched> {
+    let config = Config::load_from(dir).with_context(|| {
+        format!("{} is linked to {}, which has no mosaic.toml anymore. Run 'mosaic unlink {}'.", name, dir.display(), name)
+    })?;
+    if config.package.name != name {
+        return Err(anyhow!(
+            "{} is linked to {}, but that's the package '{}' now. Run 'mosaic unlink {}'.",
+            name,
+            dir.display(),
+            config.package.name,
+            name
+        ));
+    }
+
+    let blob = registry::pack_local(dir)?;
+    let dependencies = declared_dependencies(&config);
+    Ok(Patched {
+        name: config.package.name,
+        version: config.package.version,
+        source: crate::link::source(dir),
+        dependencies,
+        license: config.package.license,
+        blob,
+    })
+}
+
+/// A local package's dependencies as `name -> query`. Its own GitHub dependencies keep their
+/// `github:owner/repo@ref` query, since our mosaic.toml has never heard of them.
+fn declared_dependencies(config: &Config) -> BTreeMap<String, String> {
+    config
+        .dependencies
+        .iter()
+        .map(|(name, query)| match config.github.get(name) {
+            Some(source) => (name.clone(), source.to_string()),
+            None => (name.clone(), query.clone()),
+        })
+        .collect()
+}
+
+/// Fetches `rev` (or the default branch) of a git repo into the cache and checks it out.
+/// Returns the folder and the commit it landed on, which is what the lockfile records.
+///
+/// One shallow clone per URL, reused across runs. Fetching by name covers branches, tags and
+/// (on every host worth using) full commit hashes, so we don't have to tell them apart.
+fn checkout(url: &str, rev: Option<&str>) -> Result<(PathBuf, String)> {
+    let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+    let dir = paths::cache_dir()?.join("git").join(&key[..16]);
+
+    if !dir.join(".git").exists() {
+        std::fs::create_dir_all(&dir)?;
+        git(&dir, &["init", "--quiet"])?;
+        git(&dir, &["remote", "add", "--end-of-options", "origin", url])?;
+    }
+
+    Logger::info(format!("Fetching {} {}...", url, rev.unwrap_or("(default branch)")));
+    // `rev` and `url` come from mosaic.toml. Neither gets to be read as an option.
+    git(&dir, &["fetch", "--quiet", "--depth", "1", "--end-of-options", "origin", rev.unwrap_or("HEAD")])?;
+    git(&dir, &["checkout", "--quiet", "--force", "FETCH_HEAD"])?;
+    let commit = git(&dir, &["rev-parse", "HEAD"])?;
+    Ok((dir, commit))
+}
+
+/// Runs git in `dir` and returns its trimmed stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Couldn't run git (is it installed?): {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Name of the folder portable mode keeps everything in, next to the binary.
+/// If it already exists there, we're portable without being told—copy the folder and the
+/// binary onto a USB stick together and it just works.
+pub const PORTABLE_DIR: &str = "mosaic-home";
+
+/// Whether `--portable` was passed. Set once in main, same deal as the registry override.
+static PORTABLE_FLAG: OnceLock<bool> = OnceLock::new();
+
+/// Records the `--portable` flag. Only the first call counts.
+pub fn set_portable(portable: bool) {
+    let _ = PORTABLE_FLAG.set(portable);
+}
+
+/// Where portable mode keeps config, cache and credentials, or None for a normal install.
+/// First match wins:
+/// 1. `MOSAIC_HOME` env var
+/// 2. `--portable` flag (`mosaic-home/` next to the binary)
+/// 3. A `mosaic-home/` folder that already exists next to the binary
+pub fn portable_root() -> Option<PathBuf> {
+    if let Some(home) = std::env::var_os("MOSAIC_HOME").filter(|h| !h.is_empty()) {
+        return Some(PathBuf::from(home));
+    }
+
+    let next_to_binary = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(PORTABLE_DIR)));
+
+    if PORTABLE_FLAG.get().copied().unwrap_or(false) {
+        return next_to_binary;
+    }
+    next_to_binary.filter(|dir| dir.is_dir())
+}
+
+/// True when we're keeping everything in one folder instead of OS config dirs and the keyring.
+pub fn is_portable() -> bool {
+    portable_root().is_some()
+}
+
+/// Where auth.toml lives. Created if missing.
+pub fn config_dir() -> Result<PathBuf> {
+    let dir = match portable_root() {
+        Some(root) => root.join("config"),
+        None => project_dirs()?.config_dir().to_path_buf(),
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Where throwaway stuff (completion cache) lives. Created if missing.
+pub fn cache_dir() -> Result<PathBuf> {
+    let dir = match portable_root() {
+        Some(root) => root.join("cache"),
+        None => project_dirs()?.cache_dir().to_path_buf(),
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn project_dirs() -> Result<ProjectDirs> {
+    // Will fail if your OS is from the 90s.
+    ProjectDirs::from("com", "mosaic", "mosaic").context("Could not determine config directory")
+}
@@ -0,0 +1,139 @@
+use crate::mode;
+use crate::theme::{self, Theme};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::cell::Cell;
+use std::io::{IsTerminal, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Everything that draws on the terminal while work is in flight goes through here.
+///
+/// Spinners all live in one `MultiProgress`, so two lookups running side by side each get
+/// their own line instead of fighting over one. Logger output and tracing lines suspend
+/// the spinners while they print, so a warning never lands in the middle of a redraw.
+///
+/// When stderr isn't a terminal (CI logs, `2> file`) there's nothing to animate: each new
+/// spinner message is printed once as a plain line instead. `--json` gets neither.
+fn multi() -> &'static MultiProgress {
+    static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI.get_or_init(|| {
+        if is_live() {
+            MultiProgress::with_draw_target(ProgressDrawTarget::stderr())
+        } else {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        }
+    })
+}
+
+/// Whether spinners are actually drawn: a real terminal on stderr and nobody parsing JSON.
+pub fn is_live() -> bool {
+    !mode::is_json() && std::io::stderr().is_terminal()
+}
+
+thread_local! {
+    /// Set while this thread is inside `suspend`, so a Logger call made from inside
+    /// `Task::suspend` doesn't try to take the lock it's already holding.
+    static SUSPENDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Clears every spinner, runs `f`, then redraws them. Anything that prints while a spinner
+/// might be up should go through this (Logger already does).
+pub fn suspend<F: FnOnce() -> R, R>(f: F) -> R {
+    if SUSPENDED.with(Cell::get) {
+        return f();
+    }
+    multi().suspend(|| {
+        SUSPENDED.with(|s| s.set(true));
+        let result = f();
+        SUSPENDED.with(|s| s.set(false));
+        result
+    })
+}
+
+/// One line of "what's happening now". Made with [`spinner`].
+pub struct Task {
+    bar: ProgressBar,
+    live: bool,
+}
+
+/// Starts a spinner with `msg`. `color` is the spinner glyph's color (an indicatif style
+/// name like "cyan"); it's dropped in the plain theme.
+pub fn spinner(color: &str, msg: impl Into<String>) -> Task {
+    let live = is_live();
+    let task = if live {
+        let template = if theme::current() == Theme::Plain {
+            "{spinner} {msg}".to_string()
+        } else {
+            format!("{{spinner:.{}}} {{msg}}", color)
+        };
+        let bar = multi().add(ProgressBar::new_spinner());
+        bar.set_style(ProgressStyle::default_spinner().template(&template).unwrap());
+        bar.enable_steady_tick(Duration::from_millis(120));
+        Task { bar, live }
+    } else {
+        Task {
+            bar: ProgressBar::hidden(),
+            live,
+        }
+    };
+    task.set_message(msg);
+    task
+}
+
+impl Task {
+    /// Changes what the spinner says. Without a terminal, prints it as its own line
+    /// (unless it's the same as the last one).
+    pub fn set_message(&self, msg: impl Into<String>) {
+        let msg = msg.into();
+        if self.live {
+            self.bar.set_message(msg);
+            return;
+        }
+        if mode::is_json() {
+            return;
+        }
+        static LAST: Mutex<String> = Mutex::new(String::new());
+        let mut last = LAST.lock().unwrap_or_else(|e| e.into_inner());
+        if *last != msg {
+            eprintln!("  {}", msg);
+            *last = msg;
+        }
+    }
+
+    /// Runs `f` with the spinners out of the way. For things that print on their own,
+    /// like a `git clone`.
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        suspend(f)
+    }
+
+    /// Removes the spinner. Whatever gets printed next takes its line.
+    pub fn finish_and_clear(&self) {
+        self.bar.finish_and_clear();
+        multi().remove(&self.bar);
+    }
+}
+
+/// An early `?` return shouldn't leave a spinner spinning over the error message.
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.finish_and_clear();
+    }
+}
+
+/// stderr for tracing, with the spinners suspended for each line. Passed to
+/// `with_writer` so `--verbose` output doesn't tear through a spinner.
+pub struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        suspend(|| std::io::stderr().write(buf))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        suspend(|| std::io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
@@ -0,0 +1,139 @@
+use crate::logger::Logger;
+use crate::registry;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const LOCK_FILE: &str = ".mosaic/lock";
+
+/// How long to wait for another mosaic process before giving up. `MOSAIC_LOCK_TIMEOUT`
+/// (seconds) overrides it.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Who holds the lock. Only for the message the next process prints; the lock itself is the
+/// OS's.
+#[derive(Serialize, Deserialize)]
+struct Holder {
+    pid: u32,
+    command: String,
+    started: i64,
+}
+
+/// Held while a command writes mosaic.lock, mosaic.toml or the .poly. Dropping it lets the
+/// next process in.
+pub struct ProjectLock {
+    file: File,
+}
+
+/// Takes the project lock for `command`, waiting for another mosaic process to finish first.
+///
+/// The lock is an OS advisory lock on `.mosaic/lock`, so a process that crashes or gets
+/// killed releases it with everything else it had open. What's left in the file afterwards is
+/// just a stale note, and the next process takes over from it.
+///
+/// 1. Open (or create) the lock file
+/// 2. Try to lock it. Busy: say who has it, and retry until the timeout
+/// 3. Write our pid and command into it for whoever comes next
+pub fn acquire(command: &str) -> Result<Option<ProjectLock>> {
+    // A post-install hook running mosaic: the process that ran the hook holds the lock and is
+    // waiting for the hook to finish, so waiting for it here would never end.
+    if std::env::var_os("MOSAIC_HOOK").is_some() {
+        return Ok(None);
+    }
+
+    // 1. The file
+    fs::create_dir_all(".mosaic")?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(LOCK_FILE)?;
+
+    // 2. The lock
+    let timeout = timeout();
+    let started = Instant::now();
+    let mut announced = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {
+                let holder = describe(read_holder(&mut file));
+                if started.elapsed() >= timeout {
+                    return Err(anyhow!(
+                        "Another mosaic process is running in this project ({}). Gave up after {}s. If it's stuck, stop it and try again.",
+                        holder,
+                        timeout.as_secs()
+                    ));
+                }
+                if !announced {
+                    Logger::warn(format!(
+                        "Another mosaic process is running in this project ({}). Waiting for it to finish...",
+                        holder
+                    ));
+                    announced = true;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            // Some network drives can't lock at all. Better to run unprotected than not at all.
+            Err(TryLockError::Error(e)) => {
+                Logger::debug(format!("Couldn't lock {}: {}. Carrying on without it.", LOCK_FILE, e));
+                return Ok(None);
+            }
+        }
+    }
+
+    // 3. The note
+    if let Some(stale) = read_holder(&mut file) {
+        Logger::debug(format!(
+            "{} was left behind by pid {} ({}). It isn't running any more, so taking over.",
+            LOCK_FILE, stale.pid, stale.command
+        ));
+    }
+    let holder = Holder {
+        pid: std::process::id(),
+        command: command.to_string(),
+        started: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0),
+    };
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(serde_json::to_string(&holder)?.as_bytes())?;
+    Ok(Some(ProjectLock { file }))
+}
+
+impl Drop for ProjectLock {
+    /// Clears the note, then unlocks. The file stays: deleting it would let a process that
+    /// already has it open lock a file nobody else can see.
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+fn read_holder(file: &mut File) -> Option<Holder> {
+    let mut contents = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn describe(holder: Option<Holder>) -> String {
+    match holder {
+        Some(h) => format!("pid {}, `mosaic {}`, started {}", h.pid, h.command, registry::time_ago(h.started)),
+        // Just starting up, or on Windows, where a locked file can't be read by anyone else.
+        None => "pid unknown".to_string(),
+    }
+}
+
+fn timeout() -> Duration {
+    std::env::var("MOSAIC_LOCK_TIMEOUT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
@@ -1,18 +1,24 @@
-use crate::auth::AuthConfig;
+use crate::auth::{self, AuthConfig};
 use crate::config::Config;
+use crate::endpoint;
 use crate::logger::Logger;
+use crate::metadata;
+use crate::mode;
 use anyhow::{Context, Result, anyhow};
-use bytes::Bytes;
 use colored::*;
 use comfy_table::Table;
 use ignore::WalkBuilder;
 use inquire::{Confirm, Password, Text};
 use serde_json::json;
-use std::io::{Cursor, Read, Write};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path};
+use tracing::debug;
 use zip::write::FileOptions;
 
 /// Prompts for username/password and authenticates with the registry.
-/// Stores the token in the system keyring on success.
+/// Stores the token in the system keyring on success, bound to the registry it came from.
+/// Use `--registry` to log in to a self-hosted instance.
 pub async fn login() -> Result<()> {
     let username = Text::new("Username:").prompt()?;
     let username = username.trim().to_string();
@@ -21,11 +27,13 @@ pub async fn login() -> Result<()> {
         .without_confirmation()
         .prompt()?;
 
-    Logger::info("Authenticating with registry...");
-
     let client = reqwest::Client::new();
-    let registry_url = std::env::var("MOSAIC_REGISTRY_URL")
-        .unwrap_or_else(|_| "https://api.getmosaic.run".to_string());
+    let registry_url = endpoint::registry_url();
+
+    Logger::info(format!(
+        "Authenticating with {}...",
+        Logger::highlight(&registry_url)
+    ));
 
     let response = client
         .post(format!("{}/auth/login", registry_url))
@@ -57,12 +65,13 @@ pub async fn login() -> Result<()> {
         let mut auth = AuthConfig::load()?;
         auth.token = Some(token.to_string());
         auth.username = Some(username.clone());
-        auth.registry_url = Some(registry_url);
+        auth.registry_url = Some(registry_url.clone());
         auth.save()?;
 
         Logger::success(format!(
-            "Successfully logged in as {}!",
-            Logger::highlight(&username)
+            "Successfully logged in as {} on {}!",
+            Logger::highlight(&username),
+            registry_url
         ));
     } else {
         // Try to parse the error message from the response.
@@ -88,8 +97,7 @@ pub async fn signup() -> Result<()> {
     Logger::info("Creating account on Mosaic Registry...");
 
     let client = reqwest::Client::new();
-    let registry_url = std::env::var("MOSAIC_REGISTRY_URL")
-        .unwrap_or_else(|_| "https://api.getmosaic.run".to_string());
+    let registry_url = endpoint::registry_url();
 
     let response = client
         .post(format!("{}/auth/signup", registry_url))
@@ -142,6 +150,254 @@ pub async fn signup() -> Result<()> {
     Ok(())
 }
 
+/// Files a claim for a reserved name or somebody else's package. An admin reviews it;
+/// `mosaic claim --list` shows where it's at.
+pub async fn claim_name(name: &str, reason: Option<&str>) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?.clone();
+
+    let reason = match reason {
+        Some(r) => r.to_string(),
+        None => Text::new("Why should this name be yours?")
+            .with_help_message("Who you are, what you'll publish under it, and any links that back it up")
+            .prompt()?,
+    };
+
+    let res = reqwest::Client::new()
+        .post(format!("{}/claims", registry_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({"name": name, "reason": reason}))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+
+    let status = res.status();
+    let text = res.text().await?;
+    let body: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+    if !status.is_success() {
+        return Err(anyhow!("Claim failed: {}", body["error"].as_str().unwrap_or(&text)));
+    }
+
+    Logger::success(body["message"].as_str().unwrap_or("Claim filed."));
+    if let Some(owner) = body["owner"].as_str() {
+        Logger::info(format!("{} owns {} right now and has been told about your claim.", owner, name));
+        if body["abandoned"] == json!(false) {
+            Logger::warn(format!(
+                "{} has seen a publish in the last {} days, so it doesn't count as abandoned yet. \
+                 Expect a no unless there's a trademark or legal reason.",
+                name, body["policy"]["abandoned_after_days"]
+            ));
+        }
+    }
+    if let Some(summary) = body["policy"]["summary"].as_str() {
+        Logger::info(Logger::dim(summary));
+    }
+    Ok(())
+}
+
+/// Lists your claims and how they went.
+pub async fn list_claims() -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?.clone();
+
+    let res = reqwest::Client::new()
+        .get(format!("{}/claims/mine", registry_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        let status = res.status();
+        return Err(anyhow!("Couldn't load claims ({}): {}", status, res.text().await?));
+    }
+
+    let claims: Vec<serde_json::Value> = res.json().await?;
+    if claims.is_empty() {
+        Logger::info("You haven't claimed any names.");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["#", "Name", "Status", "Note"]);
+    for claim in &claims {
+        table.add_row(vec![
+            claim["id"].to_string(),
+            claim["name"].as_str().unwrap_or("?").to_string(),
+            claim["status"].as_str().unwrap_or("?").to_string(),
+            claim["resolution"].as_str().unwrap_or("-").to_string(),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
+
+/// Creates an API token, optionally restricted to some packages, and prints it once.
+pub async fn create_token(name: &str, packages: &[String], days: Option<i64>) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let mut body = json!({ "name": name });
+    if !packages.is_empty() {
+        body["packages"] = json!(packages);
+    }
+    if let Some(days) = days {
+        body["expires_in_days"] = json!(days);
+    }
+
+    let res = reqwest::Client::new()
+        .post(format!("{}/auth/me/tokens", registry_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&body)
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+    let created = endpoint::json(res).await?;
+    let secret = created["token"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Token missing in response"))?;
+
+    Logger::success(format!("Created token {}.", Logger::highlight(name)));
+    match created["packages"].as_array() {
+        Some(list) => Logger::info(format!(
+            "It can only publish and manage: {}",
+            list.iter().filter_map(|p| p.as_str()).collect::<Vec<_>>().join(", ")
+        )),
+        None => Logger::warn("It isn't restricted to any packages. Anyone holding it can publish all of yours."),
+    }
+    Logger::info("This is the only time it's shown. Set it as MOSAIC_TOKEN in your CI:");
+    println!("{}", secret);
+    Ok(())
+}
+
+/// Lists your API tokens. The tokens themselves never come back from the registry.
+pub async fn list_tokens() -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let res = reqwest::Client::new()
+        .get(format!("{}/auth/me/tokens", registry_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+
+    let tokens = endpoint::json(res).await?;
+    let tokens = tokens.as_array().map(|a| a.as_slice()).unwrap_or_default();
+    if tokens.is_empty() {
+        Logger::info("You don't have any API tokens. Create one with `mosaic token create`.");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["ID", "Name", "Packages", "Created", "Expires"]);
+    for t in tokens {
+        let packages = match t["packages"].as_array() {
+            Some(list) => list.iter().filter_map(|p| p.as_str()).collect::<Vec<_>>().join(", "),
+            None => "all".to_string(),
+        };
+        table.add_row(vec![
+            t["id"].as_str().unwrap_or("?").to_string(),
+            t["name"].as_str().unwrap_or("?").to_string(),
+            packages,
+            t["created_at"].as_i64().map(time_ago).unwrap_or_default(),
+            t["expires_at"].as_i64().map(time_until).unwrap_or_default(),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
+
+/// Revokes one of your API tokens by id.
+pub async fn revoke_token(id: &str) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let res = reqwest::Client::new()
+        .delete(format!("{}/auth/me/tokens/{}", registry_url, id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+
+    Logger::success(format!("Revoked token {}.", id));
+    Ok(())
+}
+
+/// Renames your account on the registry.
+///
+/// Asks for your password again (the registry insists), then swaps the stored token for the
+/// new one it hands back. Packages you own move to the new name. The old name keeps pointing
+/// at you and can't be claimed by anyone else. Every other login you have stops working.
+pub async fn rename_account(new_username: &str) -> Result<()> {
+    let mut auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?.clone();
+    let old_username = auth.username.clone().unwrap_or_else(|| "unknown".to_string());
+
+    let password = Password::new("Password:")
+        .with_display_mode(inquire::PasswordDisplayMode::Masked)
+        .without_confirmation()
+        .prompt()?;
+
+    let res = reqwest::Client::new()
+        .put(format!("{}/auth/username", registry_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({
+            "username": new_username,
+            "password": password
+        }))
+        .send()
+        .await?;
+
+    let status = res.status();
+    let text = res.text().await?;
+    let body: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+
+    if !status.is_success() {
+        let msg = body["error"].as_str().unwrap_or(&text);
+        // A 401 here is usually the password, not the token—don't send them off to log in again.
+        return Err(anyhow!("Rename failed ({}): {}", status, msg));
+    }
+
+    let new_username = body["username"].as_str().unwrap_or(new_username);
+    match body["token"].as_str() {
+        Some(new_token) => auth.rename(new_username, new_token.to_string())?,
+        None => {
+            // Renamed, but no token came back. The old one is dead, so clear it out.
+            AuthConfig::logout()?;
+            Logger::warn("Renamed, but the registry didn't send a new token. Run 'mosaic login'.");
+        }
+    }
+
+    Logger::success(format!(
+        "Renamed {} to {}.",
+        old_username,
+        Logger::highlight(new_username)
+    ));
+    if let Some(n) = body["packages_updated"].as_u64().filter(|n| *n > 0) {
+        Logger::info(format!(
+            "{} package{} now list you as the author.",
+            n,
+            if n == 1 { "" } else { "s" }
+        ));
+    }
+    Ok(())
+}
+
 /// Clears all credentials from disk and keyring.
 /// Also tells the server to invalidate the token so it can't be used anymore.
 pub async fn logout() -> Result<()> {
@@ -168,58 +424,73 @@ pub async fn logout() -> Result<()> {
 
 /// Searches the registry for packages matching a query.
 /// Displays results in a nice table.
-pub async fn search(query: String) -> Result<()> {
-    let auth = AuthConfig::load()?;
-    let registry_url = auth
-        .registry_url
-        .unwrap_or_else(|| "https://api.getmosaic.run".to_string());
-
+pub async fn search(query: String, sort: Option<&str>) -> Result<()> {
     Logger::info(format!(
         "Searching registry for {}...",
         Logger::highlight(&query)
     ));
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(format!("{}/packages/search", registry_url))
-        .query(&[("q", &query)])
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        let packages: Vec<serde_json::Value> = response.json().await?;
-        if packages.is_empty() {
-            Logger::error("No packages found.");
-        } else {
-            let mut table = Table::new();
-            table.set_header(vec!["Package", "Version", "Author", "Description"]);
-
-            for pkg in packages {
-                table.add_row(vec![
-                    pkg["name"].as_str().unwrap_or("unknown"),
-                    pkg["version"].as_str().unwrap_or("0.0.0"),
-                    pkg["author"].as_str().unwrap_or("unknown"),
-                    pkg["description"].as_str().unwrap_or("No description"),
-                ]);
-            }
-            println!("\n{}", table);
-        }
+    let encoded: String = url::form_urlencoded::byte_serialize(query.as_bytes()).collect();
+    // Relevance is what the registry does when no sort is given.
+    let sort = match sort {
+        Some(sort) if sort != "relevance" => format!("&sort={}", sort),
+        _ => String::new(),
+    };
+    let body = metadata::get_json(&format!("/packages/search?q={}{}", encoded, sort))
+        .await?
+        .ok_or_else(|| anyhow!("Search failed: this registry doesn't have a search endpoint."))?;
+    let packages: Vec<serde_json::Value> = serde_json::from_value(body)?;
+    if packages.is_empty() {
+        Logger::error("No packages found.");
     } else {
-        Logger::error("Search failed.");
+        let mut table = Table::new();
+        table.set_header(vec!["Package", "Version", "Author", "This week", "Description"]);
+
+        for pkg in packages {
+            table.add_row(vec![
+                featured_name(&pkg),
+                pkg["version"].as_str().unwrap_or("0.0.0").to_string(),
+                verified_author(&pkg),
+                // Older registries don't send it.
+                pkg["recent_downloads"]
+                    .as_i64()
+                    .map_or_else(|| "-".to_string(), |n| n.to_string()),
+                pkg["description"].as_str().unwrap_or("No description").to_string(),
+            ]);
+        }
+        println!("\n{}", table);
     }
 
     Ok(())
 }
 
+/// Package name with a ★ if an admin featured it. Older registries don't send the flag.
+fn featured_name(pkg: &serde_json::Value) -> String {
+    let name = pkg["name"].as_str().unwrap_or("unknown");
+    if pkg["featured"].as_bool().unwrap_or(false) {
+        format!("{} ★", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Author with a ✓ if their account is verified.
+fn verified_author(pkg: &serde_json::Value) -> String {
+    let author = pkg["author"].as_str().unwrap_or("unknown");
+    if pkg["verified"].as_bool().unwrap_or(false) {
+        format!("{} ✓", author)
+    } else {
+        author.to_string()
+    }
+}
+
 /// Fetches and displays detailed information about a package.
 ///
 /// Hits the registry API to get metadata like author, latest version, description, and dependencies.
 /// Think of it as `npm view` or `cargo search` but specific to a single package.
+#[tracing::instrument(level = "debug")]
 pub async fn info(package_name: &str) -> Result<()> {
-    let auth = AuthConfig::load()?;
-    let registry_url = auth
-        .registry_url
-        .unwrap_or_else(|| "https://api.getmosaic.run".to_string());
+    let registry_url = endpoint::registry_url();
 
     Logger::info(format!(
         "Fetching info for {}...",
@@ -229,34 +500,31 @@ pub async fn info(package_name: &str) -> Result<()> {
     let client = reqwest::Client::new();
 
     // 1. Fetch package metadata (name, description, author, etc.)
-    let pkg_res = client
-        .get(format!("{}/packages/{}", registry_url, package_name))
-        .send()
-        .await?;
-
-    if !pkg_res.status().is_success() {
-        if pkg_res.status() == reqwest::StatusCode::NOT_FOUND {
+    // Goes through the metadata cache, same as the versions below.
+    // Registry unreachable and nothing cached for this exact request? Show what we know locally.
+    let pkg = match metadata::get_json(&format!("/packages/{}", package_name)).await {
+        Ok(Some(pkg)) => pkg,
+        Ok(None) => {
             Logger::error(format!("Package {} not found in registry.", package_name));
             return Ok(());
         }
-        let text = pkg_res.text().await?;
-        return Err(anyhow!("Registry error: {}", text));
-    }
-
-    let pkg: serde_json::Value = pkg_res.json().await?;
+        Err(e) => return offline_info(package_name, e),
+    };
 
     // 2. Fetch versions to list dependencies of the latest one
     // The main package object has the *latest* version number, but we might want more details
     // or the specific dependencies for that version.
-    let versions_res = client
-        .get(format!(
-            "{}/packages/{}/versions",
-            registry_url, package_name
-        ))
-        .send()
-        .await?;
-
-    let versions: Vec<serde_json::Value> = versions_res.json().await?;
+    // include=downloads gets us per-version counts in the same call.
+    // The package itself may have come out of a stale cache, so not having these is no reason to stop.
+    let versions: Vec<serde_json::Value> = metadata::get_json(&format!(
+        "/packages/{}/versions?include=downloads",
+        package_name
+    ))
+    .await
+    .ok()
+    .flatten()
+    .and_then(|body| serde_json::from_value(body).ok())
+    .unwrap_or_default();
 
     // Find the latest version object to get dependencies
     // We default to "0.0.0" if version field is missing, though the API guarantees it now.
@@ -270,18 +538,15 @@ pub async fn info(package_name: &str) -> Result<()> {
     // --- Display Section ---
     println!("");
     // Use standard print if Logger::header is just for sections, but let's stick to the brand.
-    println!(
-        "{}",
-        Logger::highlight(pkg["name"].as_str().unwrap_or(package_name))
-    );
+    println!("{}", Logger::highlight(featured_name(&pkg)));
 
     println!("  {} {}", Logger::brand_text("Latest:"), latest_version_str);
+    println!("  {} {}", Logger::brand_text("Author:"), verified_author(&pkg));
     println!(
         "  {} {}",
-        Logger::brand_text("Author:"),
-        pkg["author"].as_str().unwrap_or("unknown")
+        Logger::brand_text("License:"),
+        pkg["license"].as_str().unwrap_or("Not specified")
     );
-    println!("  {} {}", Logger::brand_text("License:"), "MIT"); // Hardcoded for now until we add license to DB
 
     if let Some(repo) = pkg["repository"].as_str() {
         if !repo.is_empty() {
@@ -289,9 +554,78 @@ pub async fn info(package_name: &str) -> Result<()> {
         }
     }
 
+    if pkg["visibility"].as_str() == Some("private") {
+        println!("  {} yes, only its owner and members can see it", Logger::brand_text("Private:"));
+    }
+
+    if pkg["protected"].as_bool() == Some(true) {
+        let note = match pkg["unprotect_at"].as_i64() {
+            Some(at) => format!("yes, turns off {}", time_until(at)),
+            None => "yes, new versions wait for the owner's approval".to_string(),
+        };
+        println!("  {} {}", Logger::brand_text("Protected:"), note);
+    }
+
+    // Dist-tags (latest, beta, next...). Only worth showing when there's more than just "latest".
+    if let Some(tags) = pkg["dist_tags"].as_object()
+        && tags.len() > 1
+    {
+        let mut tags: Vec<_> = tags.iter().collect();
+        tags.sort_by(|a, b| a.0.cmp(b.0));
+        let rendered: Vec<String> = tags
+            .iter()
+            .map(|(tag, v)| format!("{}: {}", tag, v.as_str().unwrap_or("?")))
+            .collect();
+        println!("  {} {}", Logger::brand_text("Tags:  "), rendered.join(", "));
+    }
+
+    // When it was first published, and when the latest version went out.
+    if let Some(created) = pkg["created_at"].as_i64() {
+        println!("  {} {}", Logger::brand_text("Created:"), time_ago(created));
+    }
+    if let Some(published) = latest_version_obj.and_then(|v| v["created_at"].as_i64()) {
+        println!(
+            "  {} {} ({})",
+            Logger::brand_text("Published:"),
+            time_ago(published),
+            latest_version_str
+        );
+    }
+
     // Helper to format download count with commas/separators if possible, otherwise just raw.
+    // All-time is the package counter; the latest version's share comes from the versions list
+    // (older registries don't send it, so it's optional).
     let downloads = pkg["download_count"].as_i64().unwrap_or(0);
-    println!("  {} {}", Logger::brand_text("Downloads:"), downloads);
+    match latest_version_obj.and_then(|v| v["downloads"].as_i64()) {
+        Some(latest) => println!(
+            "  {} {} all-time, {} for {}",
+            Logger::brand_text("Downloads:"),
+            downloads,
+            latest,
+            latest_version_str
+        ),
+        None => println!("  {} {}", Logger::brand_text("Downloads:"), downloads),
+    }
+
+    // Lint summary for the latest version. Older registries don't have the endpoint, and a
+    // fresh publish may not be linted yet, so anything but a report just means no line.
+    let lint_res = endpoint::with_auth(
+        client.get(format!(
+            "{}/packages/{}/versions/{}/lint",
+            registry_url, package_name, latest_version_str
+        )),
+        &registry_url,
+    )
+    .send()
+        .await;
+    if let Ok(res) = lint_res
+        && res.status().is_success()
+    {
+        let body: serde_json::Value = res.json().await.unwrap_or_default();
+        if let Some(line) = lint_summary(&body["lint"]) {
+            println!("  {} {}", Logger::brand_text("Lint:  "), line);
+        }
+    }
 
     println!(
         "\n  {}",
@@ -322,379 +656,1833 @@ pub async fn info(package_name: &str) -> Result<()> {
         }
     }
 
+    // Downloads by version, newest first. Capped so a package with 200 releases doesn't scroll forever.
+    if versions.iter().any(|v| v["downloads"].is_i64()) {
+        let mut table = Table::new();
+        table.set_header(vec!["Version", "Published", "Downloads"]);
+        for v in versions.iter().take(10) {
+            table.add_row(vec![
+                v["version"].as_str().unwrap_or("?").to_string(),
+                v["created_at"].as_i64().map_or_else(|| "-".to_string(), time_ago),
+                v["downloads"].as_i64().unwrap_or(0).to_string(),
+            ]);
+        }
+        println!("\n  Downloads by version:");
+        println!("{}", table);
+        if versions.len() > 10 {
+            println!("  {}", Logger::dim(format!("...and {} older versions", versions.len() - 10)));
+        }
+    }
+
     Ok(())
 }
 
-/// Removes a package version from the registry.
+/// `mosaic info` when the registry can't be reached: whatever mosaic.lock and the metadata cache know.
 ///
-/// This is a sensitive operation and only works under strict conditions:
-/// 1. You are the author.
-/// 2. It's been less than 24 hours since you published.
-/// 3. No one else has listed this package as a dependency.
-pub async fn unpublish(package_query: &str) -> Result<()> {
-    let auth = AuthConfig::load()?;
-    let token = auth
-        .token
-        .as_ref()
-        .context("Not logged in. Run 'mosaic login' first.")?;
-    let registry_url = auth
-        .registry_url
-        .as_ref()
-        .context("Registry URL missing in config.")?;
-
-    // 1. Parse name and version
-    if !package_query.contains('@') {
-        return Err(anyhow!("Please specify a version: name@version"));
+/// Every line here is from an earlier run, so the whole thing is labelled as cached. If we know
+/// nothing at all, the original error is more honest than an empty page.
+fn offline_info(package_name: &str, err: anyhow::Error) -> Result<()> {
+    let locked = crate::lockfile::Lockfile::load()
+        .ok()
+        .and_then(|l| l.get(package_name).cloned());
+    // Installs ask for the stable view (see installer::fetch_tag), so that one's cached more often.
+    let cached = [
+        format!("/packages/{}", package_name),
+        format!("/packages/{}?include_prereleases=false", package_name),
+    ]
+    .iter()
+    .filter_map(|path| metadata::cached(path))
+    .max_by_key(|(_, fetched_at)| *fetched_at);
+
+    if locked.is_none() && cached.is_none() {
+        return Err(err);
     }
-    let parts: Vec<&str> = package_query.split('@').collect();
-    if parts.len() != 2 {
-        return Err(anyhow!("Invalid format. Use name@version"));
-    }
-    let name = parts[0];
-    let version = parts[1];
+    Logger::warn(format!("{} — showing what's known locally", err));
 
-    // 2. Double check with the user
-    println!("{} {}", "[!]".yellow().bold(), "Unpublishing is permanent.");
-    let confirm = Confirm::new(&format!("Are you sure you want to unpublish {}@{}?", name, version))
-        .with_default(false)
-        .prompt()?;
+    println!();
+    println!(
+        "{} {}",
+        Logger::highlight(package_name),
+        Logger::dim("(cached, registry unreachable)")
+    );
 
-    if !confirm {
-        Logger::info("Aborted.");
-        return Ok(());
+    if let Some((pkg, fetched_at)) = &cached {
+        println!(
+            "  {} {} {}",
+            Logger::brand_text("Latest:"),
+            pkg["version"].as_str().unwrap_or("?"),
+            Logger::dim(format!("(as of {})", time_ago(*fetched_at as i64)))
+        );
+        if let Some(author) = pkg["author"].as_str() {
+            println!("  {} {}", Logger::brand_text("Author:"), author);
+        }
     }
 
-    // 3. Send request
-    let client = reqwest::Client::new();
-    let res = client
-        .delete(format!("{}/packages/{}/versions/{}", registry_url, name, version))
-        .header("Authorization", format!("Bearer {}", token))
-        .send()
-        .await?;
+    let license = locked
+        .as_ref()
+        .and_then(|l| l.license.as_deref())
+        .or_else(|| cached.as_ref().and_then(|(pkg, _)| pkg["license"].as_str()));
+    if let Some(license) = license {
+        println!("  {} {}", Logger::brand_text("License:"), license);
+    }
 
-    if res.status().is_success() {
-        Logger::success(format!("Successfully unpublished {}@{}", name, version));
-    } else {
-        let text = res.text().await?;
-        let msg = match serde_json::from_str::<serde_json::Value>(&text) {
-            Ok(json) => json["error"].as_str().unwrap_or(&text).to_string(),
-            Err(_) => text,
-        };
-        Logger::error(format!("Failed to unpublish: {}", msg));
+    if let Some(locked) = &locked {
+        println!("  {} {}", Logger::brand_text("Locked:"), locked.version);
+        println!("  {} sha256:{}", Logger::brand_text("Integrity:"), locked.integrity);
+    }
+
+    if let Some(description) = cached.as_ref().and_then(|(pkg, _)| pkg["description"].as_str()) {
+        println!("\n  {}", description);
+    }
+    println!();
+
+    // The lockfile has what the dependencies resolved to, which beats the ranges we don't have.
+    if let Some(locked) = locked.filter(|l| !l.dependencies.is_empty()) {
+        let mut deps: Vec<_> = locked.dependencies.into_iter().collect();
+        deps.sort();
+        let mut table = Table::new();
+        table.set_header(vec!["Dependency", "Locked"]);
+        for (dep_name, version) in deps {
+            table.add_row(vec![dep_name, version]);
+        }
+        println!("  Dependencies:");
+        println!("{}", table);
     }
 
     Ok(())
 }
 
-/// Publishes a package to the registry.
-///
-/// This is the big one. Does a lot of work:
-/// 1. Zips up all non-ignored files in the project
-/// 2. Registers the version with the registry (creates package if needed)
-/// 3. Uploads the zip blob to storage
-pub async fn publish(version_override: Option<&str>) -> Result<()> {
-    let auth = AuthConfig::load()?;
-    let token = auth
-        .token
-        .as_ref()
-        .context("Not logged in. Run 'mosaic login' first.")?;
-    let registry_url = auth
-        .registry_url
-        .as_ref()
-        .context("Registry URL missing in config.")?;
+/// An epoch as "just now", "5 minutes ago", "3 days ago", "2 years ago".
+/// Months and years are the 30/365-day kind—close enough for "how stale is this package".
+pub(crate) fn time_ago(epoch: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(epoch);
+    let secs = now - epoch;
+    if secs < 60 {
+        // Includes a clock that's a bit behind the registry's.
+        return "just now".to_string();
+    }
 
-    let config = Config::load().context("Could not find mosaic.toml in current directory.")?;
-    let name = &config.package.name;
-    let version = version_override.unwrap_or(&config.package.version);
+    let (n, unit) = match secs {
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 30 * 86400 => (s / 86400, "day"),
+        s if s < 365 * 86400 => (s / (30 * 86400), "month"),
+        s => (s / (365 * 86400), "year"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
 
-    // --- PRE-PUBLISH CHECK ---
-    // Walk the directory first to show the user what they are about to publish.
-    // This prevents accidental uploads of node_modules, secrets, or wrong versions.
-    let mut files_to_publish = Vec::new();
-    let mut total_size: u64 = 0;
+/// An epoch in the future as "now", "in 5 minutes", "in 3 hours". For registry-side waits.
+fn time_until(epoch: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(epoch);
+    let minutes = (epoch - now + 59) / 60;
+    match minutes {
+        m if m <= 0 => "now".to_string(),
+        1 => "in a minute".to_string(),
+        m if m < 120 => format!("in {} minutes", m),
+        m if m < 48 * 60 => format!("in {} hours", m / 60),
+        m => format!("in {} days", m / (24 * 60)),
+    }
+}
 
-    let walker = WalkBuilder::new(".")
-        .hidden(true)
-        .add_custom_ignore_filename(".mosaicignore")
-        .build();
+/// One line out of a lint report: "clean (4 files)" or "3 findings: 2 global-leak, 1 shadowing".
+fn lint_summary(report: &serde_json::Value) -> Option<String> {
+    let files = report["files_checked"].as_u64()?;
+    let total = report["total"].as_u64().unwrap_or(0);
+    let parse_errors = report["parse_errors"].as_u64().unwrap_or(0);
 
-    for result in walker {
-        if let Ok(entry) = result {
-            let path = entry.path();
-            if path.is_dir() { continue; }
-
-            let path_str = path.to_string_lossy();
-            
-            // Same exclusion logic as below
-            if path_str.contains("node_modules") || path_str.contains("target") {
-                continue;
-            }
-            if path.file_name().map(|s| s == "mosaic.toml").unwrap_or(false) {
-                continue;
-            }
+    let mut line = if total == 0 {
+        format!("clean ({} file{})", files, if files == 1 { "" } else { "s" })
+    } else {
+        let mut rules: Vec<(String, u64)> = report["summary"]
+            .as_object()
+            .map(|m| {
+                m.iter()
+                    .map(|(rule, n)| (rule.clone(), n.as_u64().unwrap_or(0)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        rules.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let breakdown: Vec<String> = rules.iter().map(|(r, n)| format!("{} {}", n, r)).collect();
+        format!(
+            "{} finding{}: {}",
+            total,
+            if total == 1 { "" } else { "s" },
+            breakdown.join(", ")
+        )
+    };
+    if parse_errors > 0 {
+        line.push_str(&format!(", {} file(s) didn't parse", parse_errors));
+    }
+    Some(line)
+}
 
-            // Normalize path for display
-            let display_path = if path.starts_with(".") {
-                path.strip_prefix(".").unwrap_or(path).to_string_lossy().to_string()
-            } else {
-                path_str.to_string()
-            };
+/// Shows what changed between the installed version of a package and the latest one.
+///
+/// Pulls the CHANGELOG.md from the latest version (it usually has the full history) and only
+/// prints the sections for versions newer than what's in mosaic.lock. If the package isn't
+/// installed, you get the whole thing.
+pub async fn changelog(package_name: &str) -> Result<()> {
+    let registry_url = endpoint::registry_url();
 
-            if display_path.is_empty() { continue; }
+    let installed = crate::lockfile::Lockfile::load()?
+        .get(package_name)
+        .map(|p| p.version.clone());
 
-            if let Ok(metadata) = std::fs::metadata(path) {
-                total_size += metadata.len();
-                files_to_publish.push(display_path);
-            }
-        }
+    let client = reqwest::Client::new();
+    let pkg_res = endpoint::with_auth(client.get(format!("{}/packages/{}", registry_url, package_name)), &registry_url)
+        .send()
+        .await?;
+
+    if !pkg_res.status().is_success() {
+        Logger::error(format!("Package {} not found in registry.", package_name));
+        return Ok(());
     }
 
-    // Display Summary
-    println!("");
-    Logger::header("Publish Summary");
-    println!("  {} {}", Logger::brand_text("Package:"), name);
-    println!("  {} {}", Logger::brand_text("Version:"), version);
-    println!("  {} {}", Logger::brand_text("Files:  "), files_to_publish.len());
-    println!("  {} {:.2} KB", Logger::brand_text("Size:   "), total_size as f64 / 1024.0);
-    println!("");
-    println!("  Included files:");
-    for file in &files_to_publish {
-        println!("    {}", Logger::dim(file));
+    let pkg: serde_json::Value = pkg_res.json().await?;
+    let latest = pkg["version"].as_str().unwrap_or("0.0.0").to_string();
+
+    if installed.as_deref() == Some(latest.as_str()) {
+        Logger::success(format!(
+            "{} is already on the latest version ({}).",
+            Logger::highlight(package_name),
+            latest
+        ));
+        return Ok(());
     }
-    println!("");
 
-    let confirm = Confirm::new("Are you sure you want to publish?")
-        .with_default(false)
-        .prompt()?;
+    let res = endpoint::with_auth(
+        client.get(format!(
+            "{}/packages/{}/versions/{}/changelog",
+            registry_url, package_name, latest
+        )),
+        &registry_url,
+    )
+    .send()
+        .await?;
 
-    if !confirm {
-        Logger::warn("Publish cancelled.");
+    if !res.status().is_success() {
+        Logger::warn(format!(
+            "{}@{} doesn't ship a CHANGELOG.md.",
+            package_name, latest
+        ));
         return Ok(());
     }
-    // -------------------------
 
-    Logger::command("publish", format!("{}@{}", name, version));
+    let data: serde_json::Value = res.json().await?;
+    let text = data["changelog"].as_str().unwrap_or("");
 
-    // Step 1: Create a zip file of all publishable source files
-    let mut buf = Vec::new();
-    {
-        Logger::info("Packaging source files...");
-        let mut zip = zip::ZipWriter::new(Cursor::new(&mut buf));
-        let options: FileOptions<'_, ()> = FileOptions::default()
-            .compression_method(zip::CompressionMethod::Stored)
-            .unix_permissions(0o755);
+    let since = installed.as_deref().and_then(|v| semver::Version::parse(v).ok());
+    let until = semver::Version::parse(&latest).ok();
 
-        // Use `ignore` crate to walk files, respecting .gitignore and .mosaicignore
-        let walker = WalkBuilder::new(".")
-            .hidden(true) // Ignore hidden files (.git, .env, etc.)
-            .add_custom_ignore_filename(".mosaicignore")
-            .build();
-
-        for result in walker {
-            match result {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path.is_dir() {
-                        continue;
-                    }
-
-                    let path_str = path.to_string_lossy();
-
-                    // Extra paranoia: manually skip common build directories even if not ignored.
-                    // The `ignore` crate is usually good about this, but belt + suspenders.
-                    if path_str.contains("node_modules") || path_str.contains("target") {
-                        continue;
-                    }
-
-                    // Don't publish the manifest itself—that would be weird.
-                    if path
-                        .file_name()
-                        .map(|s| s == "mosaic.toml")
-                        .unwrap_or(false)
-                    {
-                        continue;
-                    }
-
-                    // Normalize the path for the zip file.
-                    // Remove leading "./" and fix Windows path separators.
-                    let name_str = if path.starts_with(".") {
-                        path.strip_prefix(".")
-                            .unwrap_or(path)
-                            .to_string_lossy()
-                            .trim_start_matches('\\')
-                            .trim_start_matches('/')
-                            .to_string()
-                    } else {
-                        path_str.to_string()
-                    };
-
-                    if name_str.is_empty() {
-                        continue;
-                    }
-
-                    zip.start_file(name_str.clone(), options)?;
-                    let content = std::fs::read(path)?;
-                    zip.write_all(&content)?;
-                }
-                Err(err) => {
-                    // A single file access error shouldn't kill the whole publish.
-                    // Just warn and skip it.
-                    Logger::warn(format!("Skipping file access error: {}", err));
-                }
-            }
-        }
-        zip.finish()?;
+    Logger::header(format!(
+        "{}: {} -> {}",
+        package_name,
+        installed.as_deref().unwrap_or("not installed"),
+        latest
+    ));
+
+    let entries = changelog_entries_between(text, since.as_ref(), until.as_ref());
+    if entries.trim().is_empty() {
+        Logger::info("The changelog has no entries for these versions.");
+    } else {
+        println!("{}", entries.trim_end());
     }
 
-    let client = reqwest::Client::new();
+    Ok(())
+}
 
-    // Step 2: Register the version with the registry.
-    // If the package doesn't exist, we have to create it first.
-    Logger::info("Registering version with registry...");
-    let reg_res = client
-        .post(format!("{}/packages/{}/versions", registry_url, name))
-        .header("Authorization", format!("Bearer {}", token))
-        .json(&json!({
-            "version": version,
-            "lua_source_url": "tbd", // Will be updated after upload
-            "dependencies": config.dependencies // Send dependencies to registry
-        }))
+/// Cuts a markdown changelog down to the sections for versions in (since, until].
+///
+/// A section starts at any heading that mentions a version, e.g. `## [1.2.0] - 2026-01-01`
+/// or `### v1.2.0`. Text before the first versioned heading (title, intro) is skipped.
+/// Headings we can't parse a version out of belong to whatever section they're in.
+fn changelog_entries_between(
+    text: &str,
+    since: Option<&semver::Version>,
+    until: Option<&semver::Version>,
+) -> String {
+    let mut out = String::new();
+    let mut including = false;
+
+    for line in text.lines() {
+        if line.trim_start().starts_with('#') {
+            let heading_version = line
+                .split(|c: char| c.is_whitespace() || c == '[' || c == ']' || c == '#')
+                .map(|token| token.trim_start_matches('v'))
+                .find_map(|token| semver::Version::parse(token).ok());
+
+            if let Some(v) = heading_version {
+                including = since.is_none_or(|s| &v > s) && until.is_none_or(|u| &v <= u);
+            }
+        }
+
+        if including {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Prints a single file from a published package without installing it.
+///
+/// Accepts the same specs as install (`logger`, `logger@1.2.0`, `logger@beta`...).
+/// The raw file goes to stdout so you can pipe it into a pager or an editor.
+pub async fn view(package_query: &str, file: &str) -> Result<()> {
+    let registry_url = endpoint::registry_url();
+
+    let spec = crate::spec::PackageSpec::parse(package_query)?;
+    let version = crate::installer::resolve_version(&spec).await?;
+
+    let client = reqwest::Client::new();
+    let res = endpoint::with_auth(
+        client.get(format!(
+            "{}/packages/{}/versions/{}/files/{}",
+            registry_url,
+            spec.name,
+            version,
+            file.trim_start_matches('/')
+        )),
+        &registry_url,
+    )
+    .send()
+        .await?;
+
+    let status = res.status();
+    let text = res.text().await?;
+
+    if !status.is_success() {
+        let msg = match serde_json::from_str::<serde_json::Value>(&text) {
+            Ok(json) => json["error"].as_str().unwrap_or(&text).to_string(),
+            Err(_) => text,
+        };
+        Logger::error(format!("Could not view {}: {}", file, msg));
+        return Ok(());
+    }
+
+    print!("{}", text);
+    Ok(())
+}
+
+/// `mosaic exec`: shows the module a package would inject, without installing or running it.
+///
+/// The try-before-you-install path. Same specs as install. Prints the entry module (the first
+/// .lua file in the zip, same one the installer injects) with syntax highlighting and control
+/// characters stripped when stdout is a terminal, raw otherwise, and flags globals that reach
+/// outside the module. `--save` writes it to a file
+/// instead, to read in an editor or paste into a test place. Nothing here ever runs the code.
+pub async fn exec(package_query: &str, save: Option<&Path>) -> Result<()> {
+    let spec = crate::spec::PackageSpec::parse(package_query)?;
+    let version = crate::installer::resolve_version(&spec).await?;
+
+    // The same blob and the same pick as install, so this is exactly what would be injected.
+    let (blob, _) = download_from_registry(&spec.name, &version).await?;
+    let (entry, source) = lua_entry_from_archive(blob.file)
+        .map_err(|e| anyhow!("{}@{}: {}, so there's nothing it would inject", spec.name, version, e))?;
+
+    // Notes go to stderr, so `mosaic exec x > x.lua` is just the source.
+    eprintln!(
+        "{} {}@{} {} (not run)",
+        "[!]".yellow().bold(),
+        spec.name,
+        version,
+        crate::syntax::printable(&entry)
+    );
+    for (line, name, why) in crate::syntax::sensitive_calls(&source) {
+        eprintln!("{} line {}: uses {}, which {}", "[!]".yellow().bold(), line, name, why);
+    }
+
+    match save {
+        Some(path) => {
+            std::fs::write(path, &source).with_context(|| format!("Could not write {}", path.display()))?;
+            Logger::success(format!(
+                "Saved {} to {}",
+                crate::syntax::printable(&entry),
+                Logger::highlight(path.display())
+            ));
+        }
+        None if console::Term::stdout().is_term() => {
+            print!("{}", crate::syntax::highlight(&crate::syntax::printable(&source)))
+        }
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+/// Shows what changed between two published versions of a package.
+///
+/// `from` defaults to whatever's in mosaic.lock, `to` defaults to the latest release.
+/// The registry does the actual diffing; we just print it with some color.
+pub async fn diff(package_name: &str, from: Option<&str>, to: Option<&str>) -> Result<()> {
+    let registry_url = endpoint::registry_url();
+
+    let from = match from {
+        Some(v) => v.to_string(),
+        None => crate::lockfile::Lockfile::load()?
+            .get(package_name)
+            .map(|p| p.version.clone())
+            .context("Package isn't in mosaic.lock. Pass the version to diff from explicitly.")?,
+    };
+
+    let spec = crate::spec::PackageSpec::parse(&format!(
+        "{}@{}",
+        package_name,
+        to.unwrap_or("latest")
+    ))?;
+    let to = crate::installer::resolve_version(&spec).await?;
+
+    if from == to {
+        Logger::info(format!("{} and {} are the same version.", from, to));
+        return Ok(());
+    }
+
+    let client = reqwest::Client::new();
+    let res = endpoint::with_auth(client.get(format!("{}/packages/{}/diff", registry_url, package_name)), &registry_url)
+        .query(&[("from", &from), ("to", &to)])
         .send()
         .await?;
 
-    if !reg_res.status().is_success() && reg_res.status() != reqwest::StatusCode::CONFLICT {
-        // 409 CONFLICT means version already exists, which is fine. Anything else is an error.
-        if reg_res.status() == reqwest::StatusCode::NOT_FOUND {
-            // Package doesn't exist—have to create it first before registering versions.
-            Logger::info("Package doesn't exist. Creating package...");
-            let create_pkg_res = client
-                .post(format!("{}/packages", registry_url))
-                .header("Authorization", format!("Bearer {}", token))
-                .json(&json!({
-                    "name": name,
-                    "description": "A Mosaic package", // Placeholder, user can update later
-                    "repository": "",
-                    "author": auth.username.as_ref().unwrap_or(&"unknown".to_string()),
-                    "created_at": 0,
-                    "updated_at": 0
-                }))
-                .send()
-                .await?;
-
-            if !create_pkg_res.status().is_success() {
-                let status = create_pkg_res.status();
-                let text = create_pkg_res.text().await?;
-                let msg = match serde_json::from_str::<serde_json::Value>(&text) {
-                    Ok(json) => json["error"].as_str().unwrap_or(&text).to_string(),
-                    Err(_) => text,
-                };
-                return Err(anyhow!("Failed to create package ({}): {}", status, msg));
+    let status = res.status();
+    let text = res.text().await?;
+    let data: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+
+    if !status.is_success() {
+        Logger::error(format!(
+            "Diff failed: {}",
+            data["error"].as_str().unwrap_or(&text)
+        ));
+        return Ok(());
+    }
+
+    Logger::header(format!("{}: {} -> {}", package_name, from, to));
+
+    let files = data["files"].as_array().cloned().unwrap_or_default();
+    if files.is_empty() {
+        Logger::info("No file changes.");
+        return Ok(());
+    }
+
+    for file in files {
+        let path = file["path"].as_str().unwrap_or("?");
+        let label = match file["status"].as_str() {
+            Some("added") => "added".green().bold(),
+            Some("removed") => "removed".red().bold(),
+            _ => "modified".yellow().bold(),
+        };
+        println!("\n{} {}", label, Logger::highlight(path));
+
+        if file["binary"].as_bool().unwrap_or(false) {
+            println!("  {}", Logger::dim("Binary file changed"));
+            continue;
+        }
+
+        for hunk in file["hunks"].as_array().into_iter().flatten() {
+            println!("{}", hunk["header"].as_str().unwrap_or("").cyan());
+            for line in hunk["lines"].as_array().into_iter().flatten() {
+                let line = line.as_str().unwrap_or("");
+                match line.chars().next() {
+                    Some('+') => println!("{}", line.green()),
+                    Some('-') => println!("{}", line.red()),
+                    _ => println!("{}", line),
+                }
             }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes a package version from the registry.
+///
+/// This is a sensitive operation and only works under strict conditions:
+/// 1. You are the author.
+/// 2. It's been less than 24 hours since you published.
+/// 3. No one else has listed this package as a dependency.
+///
+/// When the registry says no, the command fails with the reason spelled out: how old the
+/// version is, or every package that depends on it.
+pub async fn unpublish(package_query: &str, yes: bool) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    // 1. Parse name and version
+    let (name, version) = name_at_version(package_query)?;
+
+    // 2. Double check with the user
+    if !yes {
+        if !mode::is_interactive() {
+            return Err(anyhow!(
+                "Not unpublishing {}@{} without confirmation. Run it in a terminal, or pass --yes to skip the prompt.",
+                name,
+                version
+            ));
+        }
+        println!("{} Unpublishing is permanent.", "[!]".yellow().bold());
+        let confirm = Confirm::new(&format!("Are you sure you want to unpublish {}@{}?", name, version))
+            .with_default(false)
+            .prompt()?;
+
+        if !confirm {
+            Logger::info("Aborted.");
+            return Ok(());
+        }
+    }
+
+    // 3. Send request
+    let client = reqwest::Client::new();
+    let res = client
+        .delete(format!("{}/packages/{}/versions/{}", registry_url, name, version))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+
+    if res.status().is_success() {
+        Logger::success(format!("Successfully unpublished {}@{}", name, version));
+        return Ok(());
+    }
 
-            // Now retry registering the version.
-            let retry_res = client
-                .post(format!("{}/packages/{}/versions", registry_url, name))
-                .header("Authorization", format!("Bearer {}", token))
-                .json(&json!({
-                    "version": version,
-                    "lua_source_url": "tbd",
-                    "dependencies": config.dependencies
-                }))
-                .send()
-                .await?;
-
-            if !retry_res.status().is_success()
-                && retry_res.status() != reqwest::StatusCode::CONFLICT
-            {
-                let err: serde_json::Value = retry_res.json().await?;
+    // 4. Explain the policy instead of just relaying it
+    let status = res.status();
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(endpoint::error(res).await);
+    }
+    let body = endpoint::error_body(res).await;
+    match (status, body["code"].as_str()) {
+        (_, Some("unpublish_window_closed")) => {
+            let when = body["published_at"]
+                .as_i64()
+                .map(|at| format!(" (published {})", time_ago(at)))
+                .unwrap_or_default();
+            Err(anyhow!(
+                "{}@{} can't be unpublished: that's only allowed in the first 24 hours{}. \
+                 Publish a fixed version and deprecate this one instead.",
+                name,
+                version,
+                when
+            ))
+        }
+        (_, Some("has_dependents")) => {
+            let dependents: Vec<&str> = body["dependents"]
+                .as_array()
+                .map(|a| a.iter().filter_map(|d| d.as_str()).collect())
+                .unwrap_or_default();
+            Err(anyhow!(
+                "{}@{} can't be unpublished: other packages on the registry depend on it.\n{}\n\
+                 Publish a fixed version and deprecate this one instead.",
+                name,
+                version,
+                dependents.iter().map(|d| format!("  - {}", d)).collect::<Vec<_>>().join("\n")
+            ))
+        }
+        (reqwest::StatusCode::NOT_FOUND, _) => Err(anyhow!(
+            "{}@{} isn't on {}. Check the name and version with `mosaic info {}`.",
+            name,
+            version,
+            registry_url,
+            name
+        )),
+        (reqwest::StatusCode::FORBIDDEN, _) => Err(anyhow!(
+            "You can't unpublish {}@{}: {}",
+            name,
+            version,
+            body["error"].as_str().unwrap_or("not allowed")
+        )),
+        _ => Err(anyhow!(
+            "Failed to unpublish {}@{} ({}): {}",
+            name,
+            version,
+            status,
+            body["error"].as_str().unwrap_or("unknown error")
+        )),
+    }
+}
+
+/// Splits `name@version`. Protect, approve and reject all need an exact version.
+fn name_at_version(query: &str) -> Result<(&str, &str)> {
+    match query.split_once('@') {
+        Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok((name, version)),
+        _ => Err(anyhow!("Please specify a version: name@version")),
+    }
+}
+
+/// Turns protected publishing on or off for one of your packages.
+///
+/// Protected packages hold every new version until you approve it with the token the registry
+/// sends your webhooks. Turning it off only takes effect after the registry's delay, so a
+/// stolen login can't just switch it off and publish.
+pub async fn set_protection(name: &str, on: bool) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let res = reqwest::Client::new()
+        .put(format!("{}/packages/{}/protection", registry_url, name))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "protected": on }))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+    let body = endpoint::json(res).await?;
+
+    match (body["protected"].as_bool(), body["unprotect_at"].as_i64()) {
+        (Some(true), Some(at)) => Logger::warn(format!(
+            "Protection for {} turns off {}. Your webhooks were told. Run `mosaic protect {}` to cancel.",
+            Logger::highlight(name),
+            time_until(at),
+            name
+        )),
+        (Some(true), None) => {
+            Logger::success(format!("{} is protected.", Logger::highlight(name)));
+            Logger::info("New versions wait for your approval. The confirmation link goes to your webhooks, so make sure you have one.");
+        }
+        _ => Logger::success(format!("{} is no longer protected.", Logger::highlight(name))),
+    }
+    Ok(())
+}
+
+/// Publishes a held version of a protected package, with the token from its confirmation link.
+pub async fn approve(package_query: &str, approval_token: &str) -> Result<()> {
+    let (name, version) = name_at_version(package_query)?;
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let res = reqwest::Client::new()
+        .post(format!("{}/packages/{}/versions/{}/approve", registry_url, name, version))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "token": approval_token }))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+
+    Logger::success(format!(
+        "Approved {}@{}. It's published.",
+        Logger::highlight(name),
+        Logger::brand_text(version)
+    ));
+    Ok(())
+}
+
+/// Throws away a held version of a protected package. Its version number is free again.
+pub async fn reject(package_query: &str) -> Result<()> {
+    let (name, version) = name_at_version(package_query)?;
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let res = reqwest::Client::new()
+        .post(format!("{}/packages/{}/versions/{}/reject", registry_url, name, version))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+
+    Logger::success(format!("Rejected {}@{}. Nobody can install it.", name, version));
+    Ok(())
+}
+
+/// Makes one of your packages public or private.
+///
+/// A private package only exists for you and its members (`mosaic members`): everyone else
+/// gets "not found" from search, info and install alike.
+pub async fn set_visibility(name: &str, visibility: &str) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let res = reqwest::Client::new()
+        .put(format!("{}/packages/{}/visibility", registry_url, name))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&json!({ "visibility": visibility }))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+
+    if visibility == "private" {
+        Logger::success(format!("{} is private.", Logger::highlight(name)));
+        Logger::info(format!(
+            "Only you and its members can see or install it. Add your team with `mosaic members {} --add <username>`.",
+            name
+        ));
+    } else {
+        Logger::success(format!("{} is public.", Logger::highlight(name)));
+    }
+    Ok(())
+}
+
+/// Lists who can see a private package, or adds and removes people first.
+///
+/// Adding and removing is the owner's job; anyone on the list can read it.
+pub async fn members(name: &str, add: &[String], remove: &[String]) -> Result<()> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+    let client = reqwest::Client::new();
+
+    for (username, adding) in add.iter().map(|u| (u, true)).chain(remove.iter().map(|u| (u, false))) {
+        let url = format!("{}/packages/{}/members/{}", registry_url, name, username);
+        let req = if adding { client.put(url) } else { client.delete(url) };
+        let res = req
+            .header("Authorization", format!("Bearer {}", token))
+            .send()
+            .await?;
+        auth::ensure_authorized(res.status(), &registry_url)?;
+        if !res.status().is_success() {
+            return Err(endpoint::error(res).await);
+        }
+        if adding {
+            Logger::success(format!("Added {} to {}.", Logger::highlight(username), name));
+        } else {
+            Logger::success(format!("Removed {} from {}.", Logger::highlight(username), name));
+        }
+    }
+
+    let res = client
+        .get(format!("{}/packages/{}/members", registry_url, name))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await?;
+    auth::ensure_authorized(res.status(), &registry_url)?;
+    if !res.status().is_success() {
+        return Err(endpoint::error(res).await);
+    }
+    let body = endpoint::json(res).await?;
+    let members = body["members"].as_array().map(|a| a.as_slice()).unwrap_or_default();
+    if members.is_empty() {
+        Logger::info(format!("{} has no members. Only the owner can see it if it's private.", name));
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Member", "Added"]);
+    for m in members {
+        table.add_row(vec![
+            m["username"].as_str().unwrap_or("?").to_string(),
+            m["added_at"].as_i64().map(time_ago).unwrap_or_default(),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
+
+/// Publishes a package to the registry.
+///
+/// This is the big one. Does a lot of work:
+/// 1. Zips up all non-ignored files in the project
+/// 2. Registers the version with the registry (creates package if needed)
+/// 3. Uploads the zip blob to storage
+///
+/// `tag` points a dist-tag at the new version. The registry defaults to `latest` when it's None.
+///
+/// Symlinks are skipped unless `follow_symlinks` is set, and even then only ones that stay inside
+/// the project. See `collect_publishable`. Lua that doesn't parse stops the publish unless
+/// `allow_syntax_errors` is set. `yes` skips the confirmation prompt.
+pub async fn publish(
+    version_override: Option<&str>,
+    tag: Option<&str>,
+    follow_symlinks: bool,
+    allow_syntax_errors: bool,
+    yes: bool,
+) -> Result<()> {
+    publish_dir(
+        Path::new("."),
+        version_override,
+        tag,
+        !yes,
+        follow_symlinks,
+        allow_syntax_errors,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Does the actual publishing for the package rooted at `dir`.
+///
+/// `interactive` controls the summary + confirmation prompt. `mosaic migrate-publish` turns it off
+/// because nobody wants to hit "y" 300 times. Returns false if the user cancelled.
+#[tracing::instrument(level = "debug", skip_all, fields(dir = %dir.display(), version = version_override, tag))]
+pub async fn publish_dir(
+    dir: &Path,
+    version_override: Option<&str>,
+    tag: Option<&str>,
+    interactive: bool,
+    follow_symlinks: bool,
+    allow_syntax_errors: bool,
+) -> Result<bool> {
+    let auth = AuthConfig::load()?;
+    let registry_url = endpoint::registry_url();
+    let token = auth.token_for(&registry_url)?;
+
+    let config = Config::load_from(dir)
+        .with_context(|| format!("Could not find mosaic.toml in {}.", dir.display()))?;
+    let name = &config.package.name;
+    let version = version_override.unwrap_or(&config.package.version);
+
+    // Catch a bad tag locally instead of after we've zipped everything up.
+    // A tag that parses as a version would make `name@tag` ambiguous, so the spec parser rejects it too.
+    if let Some(tag) = tag {
+        match crate::spec::VersionSelector::parse(tag) {
+            Ok(crate::spec::VersionSelector::Tag(_)) => {}
+            _ => {
                 return Err(anyhow!(
-                    "Failed to register version after package creation: {}",
-                    err["error"]
+                    "Invalid tag '{}'. Tags must be lowercase letters, digits and hyphens, and can't look like a version.",
+                    tag
                 ));
             }
+        }
+    }
+
+    let dependencies = published_dependencies(&config)?;
+
+    // --- PRE-PUBLISH CHECK ---
+    // Walk the directory first to show the user what they are about to publish.
+    // This prevents accidental uploads of node_modules, secrets, or wrong versions.
+    // The same list is what gets zipped below, so what you confirm is exactly what ships.
+    let (files_to_publish, skipped) = collect_publishable(dir, follow_symlinks)?;
+    let total_size: u64 = files_to_publish.iter().map(|f| f.size).sum();
+
+    // Always shown, prompt or not. A skipped symlink is exactly the kind of thing you want to know about.
+    for reason in &skipped {
+        Logger::warn(reason);
+    }
+
+    // A typo in a published package breaks every project that installs it, so catch it here.
+    let mut syntax_errors = Vec::new();
+    for file in files_to_publish.iter().filter(|f| crate::syntax::is_lua(&f.name)) {
+        syntax_errors.extend(crate::syntax::check_file(&file.path, &file.name)?);
+    }
+    if !syntax_errors.is_empty() {
+        for e in &syntax_errors {
+            Logger::error(e);
+        }
+        if !allow_syntax_errors {
+            return Err(anyhow!(
+                "{} syntax error{} in {}@{}. Fix them, or pass --allow-syntax-errors if you really mean to publish this.",
+                syntax_errors.len(),
+                if syntax_errors.len() == 1 { "" } else { "s" },
+                name,
+                version
+            ));
+        }
+        Logger::warn("Publishing anyway (--allow-syntax-errors).");
+    }
+
+    // Display Summary
+    // Where it's going and as whom come first: an internal package published to the public
+    // registry can't be taken back, and `--registry`/mosaic.toml/auth.toml all get a say in that.
+    let username = auth.username.as_deref().unwrap_or("unknown");
+    if interactive {
+        if !mode::is_interactive() {
+            return Err(anyhow!(
+                "Not publishing {}@{} to {} without confirmation. Run it in a terminal, or pass --yes to skip the prompt.",
+                name,
+                version,
+                registry_url
+            ));
+        }
+
+        println!();
+        Logger::header("Publish Summary");
+        println!("  {} {}", Logger::brand_text("Package: "), name);
+        println!("  {} {}", Logger::brand_text("Version: "), version);
+        println!("  {} {}", Logger::brand_text("Tag:     "), tag.unwrap_or("latest"));
+        if registry_url == endpoint::DEFAULT_REGISTRY {
+            println!("  {} {} {}", Logger::brand_text("Registry:"), registry_url, "(public)".yellow().bold());
         } else {
-            let err: serde_json::Value = reg_res.json().await?;
-            return Err(anyhow!("Failed to register version: {}", err["error"]));
+            println!("  {} {}", Logger::brand_text("Registry:"), registry_url);
+        }
+        println!("  {} {}", Logger::brand_text("Account: "), username);
+        println!("  {} {}", Logger::brand_text("Files:   "), files_to_publish.len());
+        println!("  {} {:.2} KB", Logger::brand_text("Size:    "), total_size as f64 / 1024.0);
+        println!();
+        println!("  Included files:");
+        for file in &files_to_publish {
+            println!("    {}", Logger::dim(&file.name));
+        }
+        println!();
+
+        let confirm = Confirm::new(&format!("Publish {}@{} to {}?", name, version, registry_url))
+            .with_default(false)
+            .prompt()?;
+
+        if !confirm {
+            Logger::warn("Publish cancelled.");
+            return Ok(false);
         }
     }
+    // -------------------------
 
-    // Step 3: Upload the zip blob to storage.
-    // This is where the actual package code lives.
-    Logger::info("Uploading package blob to storage...");
-    let upload_res = client
-        .post(format!(
-            "{}/packages/{}/versions/{}/upload",
-            registry_url, name, version
-        ))
+    Logger::command("publish", format!("{}@{}", name, version));
+    Logger::info(format!("Publishing to {} as {}", registry_url, username));
+
+    // Step 1: Create a zip file of all publishable source files (see `pack`).
+    Logger::info("Packaging source files...");
+    let (mut archive, archive_len, archive_sha256) = pack(&files_to_publish, follow_symlinks)?;
+    debug!(
+        files = files_to_publish.len(),
+        bytes = archive_len,
+        sha256 = %archive_sha256,
+        "packaged archive"
+    );
+
+    let client = reqwest::Client::new();
+    let registration = json!({
+        "version": version,
+        "lua_source_url": "tbd", // Will be updated after upload
+        "dependencies": dependencies,
+        "tag": tag,
+        "license": config.package.license
+    });
+
+    // Step 2: Register the version with the registry. This is phase one of the publish:
+    // the version exists but stays pending (invisible) until the upload lands, and we get back
+    // a one-time token that phase two has to present.
+    // If the package doesn't exist, we have to create it first.
+    Logger::info("Registering version with registry...");
+    let mut reg_res = client
+        .post(format!("{}/packages/{}/versions", registry_url, name))
         .header("Authorization", format!("Bearer {}", token))
-        .body(buf)
+        .json(&registration)
         .send()
         .await?;
 
+    debug!(package = %name, status = %reg_res.status(), "POST /packages/{}/versions", name);
+    auth::ensure_authorized(reg_res.status(), &registry_url)?;
+
+    if reg_res.status() == reqwest::StatusCode::NOT_FOUND {
+        // Package doesn't exist—have to create it first before registering versions.
+        Logger::info("Package doesn't exist. Creating package...");
+        let create_pkg_res = client
+            .post(format!("{}/packages", registry_url))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "name": name,
+                "description": config.package.description.as_deref().unwrap_or("A Mosaic package"),
+                "repository": config.package.repository.as_deref().unwrap_or(""),
+                "author": auth.username.as_ref().unwrap_or(&"unknown".to_string()),
+                "created_at": 0,
+                "updated_at": 0,
+                "visibility": if config.package.private { "private" } else { "public" }
+            }))
+            .send()
+            .await?;
+
+        debug!(status = %create_pkg_res.status(), "POST /packages");
+        if !create_pkg_res.status().is_success() {
+            return Err(publish_error("Failed to create package", create_pkg_res).await);
+        }
+
+        // Now retry registering the version.
+        reg_res = client
+            .post(format!("{}/packages/{}/versions", registry_url, name))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&registration)
+            .send()
+            .await?;
+    }
+
+    let upload_token = if reg_res.status() == reqwest::StatusCode::CONFLICT {
+        // 409 means the version is already registered. If it's still pending, a previous
+        // publish died between the two phases—pick up where it left off with a fresh session.
+        // If it's published, there's nothing to resume.
+        let session_res = client
+            .post(format!(
+                "{}/packages/{}/versions/{}/upload-session",
+                registry_url, name, version
+            ))
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "dependencies": dependencies,
+                "tag": tag,
+                "license": config.package.license
+            }))
+            .send()
+            .await?;
+
+        debug!(status = %session_res.status(), "version already registered, asked for an upload session");
+        auth::ensure_authorized(session_res.status(), &registry_url)?;
+        if session_res.status() == reqwest::StatusCode::CONFLICT {
+            if endpoint::error_body(session_res).await["status"] == "held" {
+                return Err(anyhow!(
+                    "{}@{} is uploaded and waiting for approval. Run `mosaic approve` or `mosaic reject` on it first.",
+                    name,
+                    version
+                ));
+            }
+            return Err(anyhow!(
+                "{}@{} is already published. Bump the version in mosaic.toml.",
+                name,
+                version
+            ));
+        }
+        if !session_res.status().is_success() {
+            return Err(publish_error("Failed to resume publish", session_res).await);
+        }
+
+        Logger::info("Resuming an interrupted publish of this version...");
+        parse_upload_token(endpoint::json(session_res).await?)?
+    } else if reg_res.status().is_success() {
+        let body = endpoint::json(reg_res).await?;
+        if body["protected"].as_bool() == Some(true) {
+            Logger::info(format!(
+                "{} is protected: this version will wait for your approval once it's uploaded.",
+                name
+            ));
+        }
+        parse_upload_token(body)?
+    } else {
+        return Err(publish_error("Failed to register version", reg_res).await);
+    };
+
+    // Step 3: Upload the zip blob to storage. Phase two: the registry checks the token and
+    // the hash, and flips the version to published in the same step.
+    // Network hiccups get a couple of retries. If it still fails, the version stays pending and
+    // running publish again resumes it.
+    Logger::info("Uploading package blob to storage...");
+    let mut attempt = 1;
+    let upload_res = loop {
+        let body = archive.try_clone()?;
+        let res = client
+            .post(format!(
+                "{}/packages/{}/versions/{}/upload",
+                registry_url, name, version
+            ))
+            .header("Authorization", format!("Bearer {}", token))
+            .header("X-Upload-Token", &upload_token)
+            .header("X-Content-Sha256", &archive_sha256)
+            .header("Content-Length", archive_len)
+            .body(tokio::fs::File::from_std(body))
+            .send()
+            .await;
+
+        match &res {
+            Ok(res) => debug!(attempt, status = %res.status(), "upload"),
+            Err(e) => debug!(attempt, error = %e, "upload"),
+        }
+        match res {
+            Ok(res) if !res.status().is_server_error() || attempt == 3 => break res,
+            Ok(res) => Logger::warn(format!("Upload failed ({}), retrying...", res.status())),
+            Err(e) if attempt == 3 => {
+                return Err(anyhow!(
+                    "Upload failed: {}. The version is registered but not published—run publish again to resume.",
+                    e
+                ));
+            }
+            Err(e) => Logger::warn(format!("Upload failed ({}), retrying...", e)),
+        }
+
+        attempt += 1;
+        archive.seek(SeekFrom::Start(0))?;
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    };
+
     if upload_res.status().is_success() {
+        // The registry cleans up READMEs/CHANGELOGs on the way in and tells us what it changed.
+        let body: serde_json::Value = upload_res.json().await.unwrap_or_default();
+        // Which files the registry picked up as docs. Answers "why isn't my readme showing?"
+        for (label, key) in [("readme", "readme"), ("changelog", "changelog"), ("license", "license")] {
+            if let Some(path) = body["docs"][key].as_str() {
+                println!("  {} {}", Logger::dim(format!("{:<10}", label)), path);
+            }
+        }
+        for warning in body["warnings"].as_array().map(|a| a.as_slice()).unwrap_or_default() {
+            if let Some(w) = warning.as_str() {
+                Logger::warn(w);
+            }
+        }
+        if body["status"] == "held" {
+            let when = body["approve_after"].as_i64().map_or_else(|| "later".to_string(), time_until);
+            Logger::warn(format!(
+                "{}@{} is uploaded but held for approval: {} is protected. Your webhooks got a confirmation \
+                 link; approve it {} with `mosaic approve {}@{} --token <token>`, or throw it away with \
+                 `mosaic reject {}@{}`.",
+                name, version, name, when, name, version, name, version
+            ));
+            return Ok(true);
+        }
         Logger::success(format!(
             "Successfully published {}@{}!",
             Logger::highlight(name),
             Logger::brand_text(version)
         ));
     } else {
-        let err: serde_json::Value = upload_res.json().await?;
-        Logger::error(format!("Publish failed: {}", err["error"]));
+        let err = endpoint::error_body(upload_res).await;
+        return Err(anyhow!(
+            "Publish failed: {}. Run publish again to resume.",
+            err["error"].as_str().unwrap_or_default()
+        ));
     }
 
+    Ok(true)
+}
+
+/// A file that's going into the package: where it is on disk and what it's called in the zip.
+struct PublishFile {
+    path: std::path::PathBuf,
+    name: String,
+    size: u64,
+}
+
+/// Zips `files` into a tempfile, the way a package ships. Returns the archive (rewound),
+/// its length and its SHA256—the same hash the registry and the lockfile know it by.
+///
+/// The archive goes straight into a tempfile instead of a Vec<u8> so memory stays flat
+/// no matter how many files the package has. The OS cleans the tempfile up for us.
+///
+/// The same files give the same bytes on any machine: entries come in the order
+/// `collect_publishable` sorted them, every timestamp is the zip epoch (1980-01-01) and every
+/// file gets the same permissions, whatever the disk says. Otherwise two people packing one
+/// commit get two hashes, and the blob cache and attestations can't tell they're the same.
+fn pack(files: &[PublishFile], follow_symlinks: bool) -> Result<(std::fs::File, u64, String)> {
+    let mut archive = tempfile::tempfile()?;
+    {
+        let mut zip = zip::ZipWriter::new(&mut archive);
+        let options: FileOptions<'_, ()> = FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .last_modified_time(zip::DateTime::DEFAULT)
+            .unix_permissions(0o755);
+
+        for file in files {
+            // The walk was a moment ago. If something got swapped for a symlink since, don't follow it.
+            if !follow_symlinks && std::fs::symlink_metadata(&file.path)?.file_type().is_symlink() {
+                return Err(anyhow!(
+                    "{} turned into a symlink while packaging. Refusing to continue.",
+                    file.name
+                ));
+            }
+
+            zip.start_file(file.name.clone(), options)?;
+            // Copy in chunks rather than reading the whole file into memory.
+            let mut source = std::fs::File::open(&file.path)?;
+            std::io::copy(&mut source, &mut zip)?;
+        }
+        zip.finish()?;
+    }
+
+    // Rewind so whoever's next reads from the start. We grab the length on the way
+    // so an upload gets a proper Content-Length instead of a chunked body.
+    let len = archive.seek(SeekFrom::End(0))?;
+    archive.seek(SeekFrom::Start(0))?;
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut archive, &mut hasher)?;
+    let sha256 = format!("{:x}", hasher.finalize());
+    archive.seek(SeekFrom::Start(0))?;
+    Ok((archive, len, sha256))
+}
+
+/// Packages a local project directory as if it were published, without publishing it.
+/// For `[patch]` sources: the result installs exactly like a download would.
+pub fn pack_local(dir: &Path) -> Result<DownloadedBlob> {
+    let (files, skipped) = collect_publishable(dir, false)?;
+    for reason in &skipped {
+        debug!(%reason, "skipped while packing {}", dir.display());
+    }
+    let (file, _, integrity) = pack(&files, false)?;
+    Ok(DownloadedBlob { file, integrity })
+}
+
+/// `mosaic pack`: zips the package in the current folder exactly as `mosaic publish` would,
+/// without the registry. Goes to `out`, or `.mosaic/<name>-<version>.zip` (hidden, so the next
+/// pack doesn't pick it up). The SHA-256 printed is the one publishing would upload.
+pub fn pack_to(out: Option<&Path>, follow_symlinks: bool) -> Result<()> {
+    let config = Config::load()?;
+    let out = match out {
+        Some(out) => out.to_path_buf(),
+        None => {
+            std::fs::create_dir_all(".mosaic")?;
+            Path::new(".mosaic").join(format!("{}-{}.zip", config.package.name, config.package.version))
+        }
+    };
+
+    let (mut files, skipped) = collect_publishable(Path::new("."), follow_symlinks)?;
+    for reason in &skipped {
+        Logger::warn(reason);
+    }
+    // An --out inside the project would otherwise end up in the next pack.
+    if let Ok(out) = out.canonicalize() {
+        files.retain(|f| f.path.canonicalize().ok().as_ref() != Some(&out));
+    }
+
+    let (mut archive, len, sha256) = pack(&files, follow_symlinks)?;
+    std::io::copy(&mut archive, &mut std::fs::File::create(&out)?)?;
+    Logger::success(format!(
+        "Packed {}@{} into {}",
+        Logger::highlight(&config.package.name),
+        Logger::brand_text(&config.package.version),
+        out.display()
+    ));
+    Logger::info(format!(
+        "{} file{}, {:.2} KB, sha256 {}",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" },
+        len as f64 / 1024.0,
+        sha256
+    ));
     Ok(())
 }
 
-/// Downloads a package from the registry.
-/// Returns the raw bytes of the zip blob and the resolved version.
-/// We return raw bytes so the installer can verify the SHA256 hash before extraction.
-pub async fn download_from_registry(name: &str, version: &str) -> Result<(Bytes, String)> {
-    let auth = AuthConfig::load()?;
-    let registry_url = auth
-        .registry_url
-        .unwrap_or_else(|| "https://api.getmosaic.run".to_string());
+/// Walks the package folder and decides what gets published.
+///
+/// Respects .gitignore/.mosaicignore and skips hidden files, like it always has. On top of that,
+/// anything that could drag in files from outside the project is skipped with a reason:
+/// - Symlinks, unless `follow_symlinks`. A `keys -> ~/.ssh` link would otherwise ship your keys.
+/// - With `follow_symlinks`, links that resolve outside the project are *still* skipped.
+/// - Device files, sockets and pipes. Reading /dev/zero would never finish anyway.
+///
+/// Returns the files, sorted by zip path with `entry` from mosaic.toml first, and the reasons
+/// for everything skipped.
+fn collect_publishable(dir: &Path, follow_symlinks: bool) -> Result<(Vec<PublishFile>, Vec<String>)> {
+    let root = dir
+        .canonicalize()
+        .with_context(|| format!("Could not read {}", dir.display()))?;
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+
+    // Use `ignore` crate to walk files, respecting .gitignore and .mosaicignore
+    let walker = WalkBuilder::new(dir)
+        .hidden(true) // Ignore hidden files (.git, .env, etc.)
+        .add_custom_ignore_filename(".mosaicignore")
+        .follow_links(follow_symlinks)
+        .build();
 
-    let client = reqwest::Client::new();
+    for result in walker {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(err) => {
+                // A single file access error shouldn't kill the whole publish.
+                // Just warn and skip it.
+                skipped.push(format!("Skipping file access error: {}", err));
+                continue;
+            }
+        };
+        let path = entry.path();
+        // `file_type` doesn't follow links unless the walker does, so a symlink shows up as one here.
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            continue;
+        }
 
-    // Fetch the list of versions for this package to get the download URL.
-    let versions_res = client
-        .get(format!("{}/packages/{}/versions", registry_url, name))
-        .send()
-        .await?;
+        let name = archive_path(dir, path)?;
+        if name.is_empty() {
+            continue;
+        }
+
+        // Extra paranoia: manually skip common build directories even if not ignored.
+        // The `ignore` crate is usually good about this, but belt + suspenders.
+        if name.contains("node_modules") || name.contains("target") {
+            continue;
+        }
+
+        // Don't publish the manifest itself—that would be weird. Same for the backups a
+        // schema migration leaves next to it, and any other `.bak` lying around.
+        if path.file_name().map(|s| s == "mosaic.toml").unwrap_or(false)
+            || path.extension().is_some_and(|e| e == "bak")
+        {
+            continue;
+        }
+
+        if file_type.is_symlink() {
+            skipped.push(format!(
+                "Skipping {}: it's a symlink. Pass --follow-symlinks to include links that stay inside the project.",
+                name
+            ));
+            continue;
+        }
+
+        // Only plain files from here on. With --follow-symlinks, file_type is the target's.
+        if !file_type.is_file() {
+            skipped.push(format!(
+                "Skipping {}: not a regular file (device, socket or pipe)",
+                name
+            ));
+            continue;
+        }
+
+        // Whatever the path went through to get here, where it really lives has to be inside the project.
+        let real = match path.canonicalize() {
+            Ok(real) => real,
+            Err(e) => {
+                skipped.push(format!("Skipping {}: {}", name, e));
+                continue;
+            }
+        };
+        if !real.starts_with(&root) {
+            skipped.push(format!(
+                "Skipping {}: it resolves to {}, outside the project",
+                name,
+                real.display()
+            ));
+            continue;
+        }
+
+        let size = std::fs::metadata(&real).map(|m| m.len()).unwrap_or(0);
+        files.push(PublishFile {
+            path: path.to_path_buf(),
+            name,
+            size,
+        });
+    }
+
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Installs inject the first .lua file in the zip, so a declared entry goes first.
+    if let Some(entry) = Config::load_from(dir).ok().and_then(|c| c.package.entry) {
+        let entry = entry.trim_start_matches("./").replace('\\', "/");
+        let Some(i) = files.iter().position(|f| f.name == entry) else {
+            return Err(anyhow!(
+                "The entry file in mosaic.toml, {}, isn't among the files being published",
+                entry
+            ));
+        };
+        let file = files.remove(i);
+        files.insert(0, file);
+    }
+    Ok((files, skipped))
+}
+
+/// Turns a file under the package root into its path inside the zip.
+///
+/// Zip paths are always forward-slash and relative, whatever OS built the package—otherwise a
+/// package published from Windows unpacks with `src\init.lua` as a *file name* everywhere else.
+/// So we walk the path's components instead of trimming strings:
+/// - `.` is dropped, real names are joined with `/`.
+/// - Anything absolute (a drive like `C:`, a UNC share, a leading `/`) is an error.
+/// - `..` is an error. Nothing outside the package root gets in, symlink tricks included.
+/// - A name with a backslash in it (legal on Unix) is an error, since Windows would split it.
+///   Same for a colon: `C:` as a folder name is a drive on Windows.
+///
+/// Returns an empty string for the root itself, which callers skip.
+pub fn archive_path(root: &Path, file: &Path) -> Result<String> {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    let mut parts = Vec::new();
+
+    for component in relative.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(name) => {
+                let name = name.to_str().ok_or_else(|| {
+                    anyhow!("{} isn't valid UTF-8, which zip paths need to be", relative.display())
+                })?;
+                if name.contains('\\') {
+                    return Err(anyhow!(
+                        "{} has a backslash in its name. Rename it—it would turn into a folder on Windows.",
+                        relative.display()
+                    ));
+                }
+                if name.contains(':') {
+                    return Err(anyhow!(
+                        "{} has a colon in its name. Rename it—Windows would read it as a drive.",
+                        relative.display()
+                    ));
+                }
+                parts.push(name);
+            }
+            Component::ParentDir => {
+                return Err(anyhow!(
+                    "{} points outside the package folder. Refusing to publish it.",
+                    relative.display()
+                ));
+            }
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(anyhow!(
+                    "{} is an absolute path outside the package folder. Refusing to publish it.",
+                    relative.display()
+                ));
+            }
+        }
+    }
+
+    Ok(parts.join("/"))
+}
+
+/// Turns a rejected publish into an error. When the registry flagged individual
+/// dependencies, each one gets its own line instead of one long run-on sentence.
+/// Rate limits read the same as everywhere else (see `endpoint::error`).
+async fn publish_error(context: &str, res: reqwest::Response) -> anyhow::Error {
+    if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return endpoint::error(res).await;
+    }
+    let err = endpoint::error_body(res).await;
+    let Some(deps) = err["dependency_errors"].as_object().filter(|d| !d.is_empty()) else {
+        return anyhow!("{}: {}", context, err["error"].as_str().unwrap_or_default());
+    };
+    let lines: Vec<String> = deps
+        .iter()
+        .map(|(name, e)| format!("  {} — {}", name, e.as_str().unwrap_or_default()))
+        .collect();
+    anyhow!(
+        "{}: invalid dependencies in mosaic.toml\n{}",
+        context,
+        lines.join("\n")
+    )
+}
+
+/// `[dependencies]` the way the registry knows them: an alias goes up under the package it stands
+/// for, since that's the only name someone installing this package can resolve. The alias itself
+/// doesn't travel—the package's dependents get it injected under its real name.
+fn published_dependencies(config: &Config) -> Result<std::collections::HashMap<String, String>> {
+    // A GitHub dependency is "*" in `dependencies`. Published, that would mean "whatever the
+    // registry has under this name"—someone else's package, quite possibly.
+    let mut github: Vec<&String> = config.dependencies.keys().filter(|n| config.github.contains_key(*n)).collect();
+    if !github.is_empty() {
+        github.sort();
+        return Err(anyhow!(
+            "Can't publish a package with GitHub dependencies ({}). Publish them to the registry and depend on them from there.",
+            github.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    let mut dependencies = std::collections::HashMap::new();
+    for (name, query) in &config.dependencies {
+        let package = config.aliases.get(name).unwrap_or(name);
+        if package != name {
+            Logger::warn(format!(
+                "{} is an alias: it's published as a dependency on {}, and that's the name it gets in projects that install {}.",
+                name, package, config.package.name
+            ));
+        }
+        if dependencies.insert(package.clone(), query.clone()).is_some() {
+            return Err(anyhow!(
+                "{} is in [dependencies] under more than one name. A published package can only depend on it once.",
+                package
+            ));
+        }
+    }
+    Ok(dependencies)
+}
 
-    let versions: Vec<serde_json::Value> = versions_res.json().await?;
-    let target_version = versions
+/// Pulls the upload token out of a register or upload-session response.
+fn parse_upload_token(body: serde_json::Value) -> Result<String> {
+    body["upload_token"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Registry didn't return an upload token. Is it running an older version?"))
+}
+
+/// A package blob that has been streamed to disk.
+///
+/// The SHA256 is computed while the bytes come in, so the installer can verify
+/// integrity without ever holding the whole zip in memory.
+pub struct DownloadedBlob {
+    pub file: std::fs::File,
+    pub integrity: String,
+}
+
+/// Metadata for one published version: dependencies, hash, size, download URL.
+///
+/// Asks for just that version rather than the package's whole version list, which can run to
+/// hundreds of entries. Registries older than the single-version endpoint don't have it, so if
+/// that comes back empty-handed we look the version up in the full list like we used to.
+pub async fn fetch_version(name: &str, version: &str) -> Result<serde_json::Value> {
+    match metadata::get_json(&format!("/packages/{}/versions/{}", name, version)).await {
+        Ok(Some(meta)) => return Ok(meta),
+        Ok(None) => {}
+        Err(e) => debug!(package = name, version, error = %e, "single-version lookup failed, using the full list"),
+    }
+
+    let versions: Vec<serde_json::Value> =
+        match metadata::get_json(&format!("/packages/{}/versions", name)).await? {
+            Some(body) => serde_json::from_value(body)?,
+            None => return Err(anyhow!("Package not found in registry: {}", name)),
+        };
+    versions
         .into_iter()
         .find(|v| v["version"].as_str() == Some(version))
-        .ok_or_else(|| anyhow!("Version {} not found for package {}", version, name))?;
+        .ok_or_else(|| anyhow!("Version {} not found for package {}", version, name))
+}
+
+/// The hash and size of one version's zip, from `GET /packages/{name}/{version}/integrity`.
+/// `None` when the registry doesn't have that endpoint (or the version).
+pub async fn fetch_integrity(name: &str, version: &str) -> Result<Option<serde_json::Value>> {
+    metadata::get_json(&format!("/packages/{}/{}/integrity", name, version)).await
+}
+
+/// Downloads a package from the registry.
+/// Streams the zip blob into a tempfile and returns it along with the resolved version.
+/// The hash is calculated on the fly so the installer can check it before extraction.
+///
+/// Falls back to mirrors if the primary registry is down (see `endpoint::get`), then to any
+/// public bucket URLs listed in the version's `mirror_urls`. Blobs are content-addressed
+/// (`/packages/blobs/<sha256>`), so we check the bytes against the hash (and size, when the
+/// registry sends one) and refuse anything that doesn't match—no matter who served it.
+#[tracing::instrument(level = "debug")]
+pub async fn download_from_registry(name: &str, version: &str) -> Result<(DownloadedBlob, String)> {
+    // Fetch this version's metadata to get the download URL.
+    let target_version = fetch_version(name, version).await?;
 
     let source_url = target_version["lua_source_url"]
         .as_str()
         .ok_or_else(|| anyhow!("Source URL missing for package {}@{}", name, version))?;
+    debug!(source_url, "found blob URL");
 
-    // Download the zip blob from storage.
-    let blob_res = client
-        .get(format!("{}{}", registry_url, source_url))
-        .send()
-        .await?;
+    // Newer registries send the hash and size outright. Older ones only have the hash in the URL.
+    // Metadata that came without them (the versions-list fallback, a stale cache) gets topped up
+    // from the integrity endpoint, which is tiny.
+    let mut expected = target_version["sha256"]
+        .as_str()
+        .or_else(|| source_url.strip_prefix("/packages/blobs/"))
+        .map(str::to_string);
+    let mut expected_size = target_version["size"].as_u64();
+    if expected.is_none() || expected_size.is_none() {
+        match fetch_integrity(name, version).await {
+            Ok(Some(found)) => {
+                expected = expected.or_else(|| found["sha256"].as_str().map(str::to_string));
+                expected_size = expected_size.or_else(|| found["size"].as_u64());
+            }
+            Ok(None) => {}
+            Err(e) => debug!(package = name, version, error = %e, "integrity lookup failed"),
+        }
+    }
+    let expected = expected.as_deref();
+    debug!(sha256 = ?expected, bytes = ?expected_size, "expecting blob");
+
+    // Downloaded before, by this project or any other? Same hash, same bytes. It still goes
+    // through the size check below, same as a fresh download.
+    let cached = expected.and_then(|hash| crate::blobs::get(hash).map(|file| (file, hash.to_string())));
+    let from_cache = cached.is_some();
+    let (file, integrity) = match cached {
+        Some(cached) => {
+            debug!(package = name, version, sha256 = %cached.1, "using cached blob");
+            cached
+        }
+        None => {
+            // Public bucket URLs for the same blob, if the registry advertises any. They don't go
+            // through the API server at all, so installs keep working while it's down.
+            let direct_urls: Vec<&str> = target_version["mirror_urls"]
+                .as_array()
+                .map(|urls| urls.iter().filter_map(|u| u.as_str()).collect())
+                .unwrap_or_default();
+
+            // Newer registries have a by-name endpoint that credits the download to this exact version.
+            // Older ones only serve by hash.
+            let download_path = target_version["download_url"].as_str().unwrap_or(source_url);
+
+            // Download the zip blob from storage, one chunk at a time.
+            let mut downloaded =
+                fetch_blob(BlobSource::Registry(download_path), expected, expected_size).await;
+            for url in &direct_urls {
+                let Err(e) = &downloaded else { break };
+                Logger::warn(format!("{} — trying {}", e, url));
+                downloaded = fetch_blob(BlobSource::Direct(url), expected, expected_size).await;
+            }
+            downloaded.map_err(|e| anyhow!("Failed to download {}@{} ({})", name, version, e))?
+        }
+    };
 
-    let bytes = blob_res.bytes().await?;
-    Ok((bytes, version.to_string()))
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    debug!(bytes = size, %integrity, "downloaded blob");
+    if let Some(expected) = expected
+        && expected != integrity
+    {
+        return Err(anyhow!(
+            "Integrity check failed for {}@{}: expected {}, got {}. Refusing to install.",
+            name,
+            version,
+            expected,
+            integrity
+        ));
+    }
+    if let Some(expected_size) = expected_size
+        && expected_size != size
+    {
+        return Err(anyhow!(
+            "Size check failed for {}@{}: expected {} bytes, got {}. Refusing to install.",
+            name,
+            version,
+            expected_size,
+            size
+        ));
+    }
+
+    let mut blob = DownloadedBlob { file, integrity };
+    if !from_cache {
+        crate::blobs::put(&blob.integrity, &mut blob.file, &format!("{}@{}", name, version));
+    }
+    Ok((blob, version.to_string()))
+}
+
+/// How many times one download may pick up where it left off before we give up on that URL.
+const RESUME_ATTEMPTS: u32 = 3;
+
+/// Where a blob can come from: the registry API (with its own mirror fallback) or a bucket URL.
+#[derive(Clone, Copy)]
+enum BlobSource<'a> {
+    Registry(&'a str),
+    Direct(&'a str),
+}
+
+/// Whether `hash` is a SHA256 the way we write them: 64 lowercase hex characters.
+/// Hashes from a registry name files in the cache, so anything else mustn't get near a path.
+pub(crate) fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Where half-finished downloads wait for the next attempt: `<cache dir>/downloads/<sha256>.part`.
+/// Keyed on the hash, so whichever URL finishes the file, the bytes are the same.
+fn partial_path(hash: &str) -> Result<std::path::PathBuf> {
+    if !is_sha256(hash) {
+        return Err(anyhow!("The registry sent an invalid sha256 ({:?})", hash));
+    }
+    let dir = crate::paths::cache_dir()?.join("downloads");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.part", hash)))
+}
+
+/// Deletes every leftover partial download. Returns how many went. Used by `mosaic cache clean`.
+pub fn clear_partial_downloads() -> Result<usize> {
+    let dir = crate::paths::cache_dir()?.join("downloads");
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(0);
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "part") {
+            std::fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// GETs a blob, asking for `bytes=<offset>-` when we already have the start of it.
+///
+/// Registry requests carry the login like every other read (`endpoint::bearer`). A private
+/// package's `download_url` is also signed for us, but the signature only lasts a few minutes
+/// and metadata can come from the cache, so the login is what the download relies on.
+async fn request_blob(source: BlobSource<'_>, offset: u64) -> Result<reqwest::Response> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if offset > 0 {
+        headers.insert(reqwest::header::RANGE, format!("bytes={}-", offset).parse()?);
+    }
+    // Opt-in only. Only our own registry gets it; a bucket URL has no use for it.
+    if let (BlobSource::Registry(_), Some(client)) = (source, crate::telemetry::client_header()) {
+        headers.insert(crate::telemetry::CLIENT_HEADER, client.parse()?);
+    }
+    match source {
+        BlobSource::Registry(path) => endpoint::get_with_headers(path, headers).await,
+        BlobSource::Direct(url) => Ok(reqwest::Client::new().get(url).headers(headers).send().await?),
+    }
+}
+
+/// Downloads a blob into a tempfile and returns it with its SHA256.
+///
+/// With a known hash, the bytes go to a partial file in the cache first. If the connection drops,
+/// the partial file stays put and the next attempt (this run or the next `mosaic install`) asks
+/// for just the rest with a Range request. Blobs never change once published, so stitching two
+/// halves together is safe—and the hash check at the end catches it if it somehow isn't.
+///
+/// With a known size, a response that ends early counts as an interruption and is resumed
+/// instead of failing the hash check, and a partial that's already complete isn't fetched again.
+async fn fetch_blob(
+    source: BlobSource<'_>,
+    expected: Option<&str>,
+    size: Option<u64>,
+) -> Result<(std::fs::File, String)> {
+    // Old registries don't tell us the hash up front, so there's nothing to key a partial on.
+    let Some(hash) = expected else {
+        let mut file = tempfile::tempfile()?;
+        let mut res = request_blob(source, 0).await?;
+        if !res.status().is_success() {
+            return Err(anyhow!("{} returned {}", res.url(), res.status()));
+        }
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk)?;
+        }
+        return finish_blob(file);
+    };
+
+    let part = partial_path(hash)?;
+    let mut attempt = 1;
+    loop {
+        let before = part.metadata().map(|m| m.len()).unwrap_or(0);
+        if size.is_some_and(|size| size > 0 && before == size) {
+            debug!(bytes = before, "partial download is already complete");
+            break;
+        }
+        let result = match download_into(source, &part, before).await {
+            Ok(()) => check_length(&part, size),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => break,
+            Err(e) => {
+                // Only worth another go if that attempt changed something. A 404 won't fix itself.
+                let after = part.metadata().map(|m| m.len()).unwrap_or(0);
+                if after == before || attempt >= RESUME_ATTEMPTS {
+                    return Err(e);
+                }
+                attempt += 1;
+                Logger::warn(format!("Download interrupted at {} bytes ({}), resuming...", after, e));
+            }
+        }
+    }
+
+    // Copy out into a tempfile and drop the partial, so a bad hash doesn't stick around to be resumed.
+    let mut file = tempfile::tempfile()?;
+    std::io::copy(&mut std::fs::File::open(&part)?, &mut file)?;
+    std::fs::remove_file(&part)?;
+    finish_blob(file)
+}
+
+/// One attempt at filling `part`, starting from whatever's already in it.
+async fn download_into(source: BlobSource<'_>, part: &Path, offset: u64) -> Result<()> {
+    let mut res = request_blob(source, offset).await?;
+
+    let mut file = match res.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT if offset > 0 => {
+            debug!(offset, "resuming partial download");
+            std::fs::OpenOptions::new().append(true).open(part)?
+        }
+        // Our partial is as long as the blob or longer. Either way it's not trustworthy.
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+            std::fs::remove_file(part)?;
+            return Err(anyhow!("saved partial download didn't fit the blob"));
+        }
+        // A plain 200 means the server ignored the Range header. Start over.
+        status if status.is_success() => std::fs::File::create(part)?,
+        status => return Err(anyhow!("{} returned {}", res.url(), status)),
+    };
+
+    while let Some(chunk) = res.chunk().await? {
+        file.write_all(&chunk)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Compares a finished attempt with the size the registry told us. A short file stays put so
+/// the next attempt resumes it. A long one can't be trusted and is deleted.
+fn check_length(part: &Path, size: Option<u64>) -> Result<()> {
+    let Some(size) = size else {
+        return Ok(());
+    };
+    let len = part.metadata()?.len();
+    if len < size {
+        return Err(anyhow!("connection closed after {} of {} bytes", len, size));
+    }
+    if len > size {
+        std::fs::remove_file(part)?;
+        return Err(anyhow!("got {} bytes, expected {}", len, size));
+    }
+    Ok(())
+}
+
+/// Rewinds a finished download and hashes it.
+fn finish_blob(mut file: std::fs::File) -> Result<(std::fs::File, String)> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok((file, format!("{:x}", hasher.finalize())))
 }
 
 /// Helper to extract the main Lua file from a package zip.
-pub fn extract_lua_from_bytes(bytes: &[u8]) -> Result<String> {
-    let reader = Cursor::new(bytes);
+/// Takes any seekable reader so we can extract straight from the downloaded tempfile.
+pub fn extract_lua_from_archive<R: Read + Seek>(reader: R) -> Result<String> {
+    lua_entry_from_archive(reader).map(|(_, content)| content)
+}
+
+/// The main Lua file of a package zip and its path: the first `.lua` in archive order.
+pub fn lua_entry_from_archive<R: Read + Seek>(reader: R) -> Result<(String, String)> {
     let mut zip = zip::ZipArchive::new(reader)?;
 
     for i in 0..zip.len() {
         let mut file = zip.by_index(i)?;
         if file.name().ends_with(".lua") {
+            debug!(entry = file.name(), "using Lua file from archive");
             let mut content = String::new();
             file.read_to_string(&mut content)?;
-            return Ok(content);
+            return Ok((file.name().to_string(), content));
         }
     }
 
     Err(anyhow!("No .lua file found in package zip"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{archive_path, is_sha256};
+    use std::path::Path;
+
+    fn path(file: &str) -> anyhow::Result<String> {
+        archive_path(Path::new("pkg"), Path::new(file))
+    }
+
+    #[test]
+    fn archive_path_joins_names_with_slashes() {
+        assert_eq!(path("pkg/src/init.lua").unwrap(), "src/init.lua");
+        assert_eq!(path("pkg/./src/./init.lua").unwrap(), "src/init.lua");
+        assert_eq!(archive_path(Path::new("pkg"), Path::new("pkg")).unwrap(), "");
+    }
+
+    #[test]
+    fn archive_path_refuses_to_leave_the_root() {
+        assert!(path("pkg/../secrets.lua").is_err());
+        assert!(path("pkg/src/../../secrets.lua").is_err());
+        assert!(path("/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn archive_path_refuses_windows_roots() {
+        // On Windows these are prefixes. Elsewhere they're names no Windows machine can unpack.
+        assert!(path(r"C:\pkg\init.lua").is_err());
+        assert!(path("C:/pkg/init.lua").is_err());
+        assert!(path(r"\\server\share\init.lua").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archive_path_refuses_backslashes_in_names() {
+        // One file called `src\init.lua` here, a folder and a file once unzipped on Windows.
+        assert!(path("pkg/src\\init.lua").is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn archive_path_uses_slashes_on_windows() {
+        assert_eq!(archive_path(Path::new(r"C:\pkg"), Path::new(r"C:\pkg\src\init.lua")).unwrap(), "src/init.lua");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archive_path_needs_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let name = std::ffi::OsStr::from_bytes(b"pkg/caf\xe9.lua");
+        assert!(archive_path(Path::new("pkg"), Path::new(name)).is_err());
+    }
+
+    #[test]
+    fn only_lowercase_sha256_hashes_name_cache_files() {
+        assert!(is_sha256(&"ab12".repeat(16)));
+        assert!(!is_sha256(&"AB12".repeat(16)));
+        assert!(!is_sha256(&"ab12".repeat(15)));
+        assert!(!is_sha256(&format!("../../{}", "a".repeat(58))));
+        assert!(!is_sha256(&format!("{}/x", "a".repeat(62))));
+    }
+}
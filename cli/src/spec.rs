@@ -0,0 +1,174 @@
+use anyhow::{Result, anyhow};
+use semver::{Version, VersionReq};
+
+/// A parsed install argument like `logger`, `logger@1.0.0`, `logger@^1.2` or `router@beta`.
+///
+/// Used to be a naive `split('@')` in the installer, which was fine until tags showed up.
+/// Everything that takes a package from the command line should go through `PackageSpec::parse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    pub name: String,
+    pub selector: VersionSelector,
+}
+
+/// What the user asked for after the `@`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSelector {
+    /// No `@` at all. Whatever the registry considers the newest release.
+    Latest,
+    /// A concrete semver version, e.g. `1.0.0`.
+    Exact(String),
+    /// A dist-tag like `beta` or `next`. Resolved against the registry's tag table.
+    Tag(String),
+    /// A semver requirement like `^1.2`, `~1.4.0` or `>=1.0, <2.0`.
+    /// We keep the raw string so it can go into mosaic.toml exactly as the user typed it.
+    Range(String),
+}
+
+impl PackageSpec {
+    /// Parses `name`, `name@version`, `name@range` or `name@tag`.
+    ///
+    /// We split on the *first* `@` only so a stray second one gives a useful error
+    /// instead of silently being dropped.
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = query.trim();
+
+        let Some((name, rest)) = query.split_once('@') else {
+            if query.is_empty() {
+                return Err(anyhow!("Package name cannot be empty"));
+            }
+            return Ok(Self {
+                name: query.to_string(),
+                selector: VersionSelector::Latest,
+            });
+        };
+
+        if name.is_empty() {
+            return Err(anyhow!(
+                "Invalid package format. Expected: name, name@version, name@range or name@tag"
+            ));
+        }
+        if rest.is_empty() || rest.contains('@') {
+            return Err(anyhow!(
+                "Invalid package format '{}'. Expected: name, name@version, name@range or name@tag",
+                query
+            ));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            selector: VersionSelector::parse(rest)?,
+        })
+    }
+}
+
+impl VersionSelector {
+    /// Anything that parses as semver is a version. Tags have to look like tags
+    /// (lowercase letters, digits and hyphens, starting with a letter), same rules as the registry.
+    /// Whatever's left gets a shot at being a semver range.
+    pub fn parse(raw: &str) -> Result<Self> {
+        if Version::parse(raw).is_ok() {
+            return Ok(Self::Exact(raw.to_string()));
+        }
+
+        let looks_like_tag = raw.starts_with(|c: char| c.is_ascii_lowercase())
+            && raw
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+        if looks_like_tag {
+            return Ok(Self::Tag(raw.to_string()));
+        }
+
+        if VersionReq::parse(raw).is_ok() {
+            return Ok(Self::Range(raw.to_string()));
+        }
+
+        Err(anyhow!(
+            "'{}' is not a valid version, version range or tag name",
+            raw
+        ))
+    }
+}
+
+impl std::fmt::Display for VersionSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Latest => write!(f, "latest"),
+            Self::Exact(v) => write!(f, "{}", v),
+            Self::Tag(t) => write!(f, "{}", t),
+            Self::Range(r) => write!(f, "{}", r),
+        }
+    }
+}
+
+/// A package straight from a GitHub repo: `github:alice/cool-lib` or `github:alice/cool-lib@v1.2.0`.
+///
+/// The ref after `@` can be a tag, a branch or a commit. Left out, it's the default branch.
+/// The package's name and version come from the repo's own mosaic.toml, not from the URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubSource {
+    /// `owner/repo`
+    pub repo: String,
+    pub rev: Option<String>,
+}
+
+impl GithubSource {
+    /// None if `query` isn't a `github:` query at all, so callers can fall back to `PackageSpec`.
+    pub fn parse(query: &str) -> Option<Result<Self>> {
+        let rest = query.trim().strip_prefix("github:")?;
+        let (repo, rev) = match rest.split_once('@') {
+            Some((repo, rev)) => (repo, Some(rev)),
+            None => (rest, None),
+        };
+        let valid_part = |s: &str| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        };
+        let valid = matches!(repo.split_once('/'), Some((owner, name)) if valid_part(owner) && valid_part(name))
+            // A ref starting with '-' would reach `git fetch` as an option.
+            && rev.is_none_or(|r| !r.is_empty() && !r.starts_with('-') && !r.contains(char::is_whitespace));
+        if !valid {
+            return Some(Err(anyhow!(
+                "Invalid GitHub package '{}'. Expected: github:owner/repo or github:owner/repo@ref",
+                query
+            )));
+        }
+        Some(Ok(Self {
+            repo: repo.to_string(),
+            rev: rev.map(str::to_string),
+        }))
+    }
+
+    /// Where to clone it from. `MOSAIC_GITHUB_URL` swaps github.com for something else
+    /// (a GitHub Enterprise host, or a folder of bare repos in tests).
+    pub fn url(&self) -> String {
+        let base =
+            std::env::var("MOSAIC_GITHUB_URL").unwrap_or_else(|_| "https://github.com".to_string());
+        format!("{}/{}", base.trim_end_matches('/'), self.repo)
+    }
+
+    /// What mosaic.lock records before the `#<commit>`: `github+alice/cool-lib?rev=v1.2.0`.
+    pub fn lock_prefix(&self) -> String {
+        match &self.rev {
+            Some(rev) => format!("github+{}?rev={}", self.repo, rev),
+            None => format!("github+{}", self.repo),
+        }
+    }
+
+    /// The commit a lockfile `source` pinned this repo and ref to, if it's for this repo and ref.
+    pub fn locked_commit<'a>(&self, source: &'a str) -> Option<&'a str> {
+        let (prefix, commit) = source.rsplit_once('#')?;
+        (prefix == self.lock_prefix()).then_some(commit)
+    }
+}
+
+impl std::fmt::Display for GithubSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.rev {
+            Some(rev) => write!(f, "github:{}@{}", self.repo, rev),
+            None => write!(f, "github:{}", self.repo),
+        }
+    }
+}
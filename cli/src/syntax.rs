@@ -0,0 +1,138 @@
+use colored::Colorize;
+use full_moon::LuaVersion;
+use full_moon::tokenizer::{Lexer, LexerResult, Token, TokenType};
+use std::path::Path;
+
+/// One thing full-moon didn't like, ready to print as `file:line:col: message`.
+pub struct SyntaxError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+/// Whether a file in a package is Lua we should parse.
+pub fn is_lua(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".lua") || name.ends_with(".luau")
+}
+
+/// Parses a Lua file and returns every syntax error in it. Empty means it parses.
+///
+/// `name` is only used for the report, so pass the path as it appears in the package.
+/// A file that isn't valid UTF-8 counts as one error—the installer couldn't inject it either.
+pub fn check_file(path: &Path, name: &str) -> std::io::Result<Vec<SyntaxError>> {
+    let bytes = std::fs::read(path)?;
+    let Ok(source) = String::from_utf8(bytes) else {
+        return Ok(vec![SyntaxError {
+            file: name.to_string(),
+            line: 1,
+            column: 1,
+            message: "file isn't valid UTF-8".to_string(),
+        }]);
+    };
+
+    Ok(check_source(&source, name))
+}
+
+/// Same as `check_file`, for source that's already in memory.
+pub fn check_source(source: &str, name: &str) -> Vec<SyntaxError> {
+    match full_moon::parse(source) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .into_iter()
+            .map(|e| {
+                let (start, _) = e.range();
+                SyntaxError {
+                    file: name.to_string(),
+                    line: start.line(),
+                    column: start.character(),
+                    message: e.error_message().to_string(),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Globals that let a script reach past its own module: run code it downloaded, poke at other
+/// scripts' environments, talk to the network, touch saved data. Not wrong by themselves, just
+/// worth knowing about before the script goes anywhere near your game.
+const SENSITIVE_GLOBALS: &[(&str, &str)] = &[
+    ("loadstring", "runs a string as code"),
+    ("load", "runs a string or function as code"),
+    ("getfenv", "reads another function's environment"),
+    ("setfenv", "replaces another function's environment"),
+    ("Http", "makes web requests"),
+    ("Datastore", "reads and writes saved data"),
+];
+
+/// Where `source` uses one of the sensitive globals, as (line, name, what it does).
+///
+/// Goes by tokens, so a mention in a comment or a string doesn't count. It's a heads-up,
+/// not an audit: `_G["load" .. "string"]` gets past it.
+pub fn sensitive_calls(source: &str) -> Vec<(usize, &'static str, &'static str)> {
+    tokens(source)
+        .iter()
+        .filter_map(|token| match token.token_type() {
+            TokenType::Identifier { identifier } => SENSITIVE_GLOBALS
+                .iter()
+                .find(|(name, _)| *name == identifier.as_str())
+                .map(|(name, why)| (token.start_position().line(), *name, *why)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `source` without control characters other than newlines and tabs, so printing a package's
+/// code can't move the cursor, retitle the window or hide lines behind escape sequences.
+pub fn printable(source: &str) -> String {
+    source
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect()
+}
+
+/// `source` with keywords, strings, numbers and comments colored for a terminal.
+///
+/// Source the tokenizer gives up on comes back as it was, since plain beats half-colored.
+pub fn highlight(source: &str) -> String {
+    let tokens = tokens(source);
+    if tokens.is_empty() {
+        return source.to_string();
+    }
+    tokens
+        .iter()
+        .map(|token| {
+            let text = token.to_string();
+            match token.token_type() {
+                TokenType::Symbol { .. } if text.chars().all(|c| c.is_ascii_alphabetic()) => {
+                    text.magenta().bold().to_string()
+                }
+                TokenType::StringLiteral { .. } => text.green().to_string(),
+                TokenType::Number { .. } => text.yellow().to_string(),
+                TokenType::SingleLineComment { .. } | TokenType::MultiLineComment { .. } => {
+                    text.dimmed().to_string()
+                }
+                _ => text,
+            }
+        })
+        .collect()
+}
+
+/// Every token in `source`, whitespace and comments included. Empty if it doesn't tokenize.
+fn tokens(source: &str) -> Vec<Token> {
+    match Lexer::new(source, LuaVersion::lua52()).collect() {
+        LexerResult::Ok(tokens) => tokens,
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::auth::AuthConfig;
+use crate::logger::Logger;
+use anyhow::Result;
+use std::sync::OnceLock;
+
+/// Header the CLI adds to package downloads when telemetry is on: `mosaic/0.4.2 (windows)`.
+///
+/// That's the whole payload—CLI version and OS, nothing that identifies you or your project.
+/// The registry only keeps daily totals per version/OS, so operators can tell when nobody's
+/// running the version that needs some old behavior anymore.
+pub const CLIENT_HEADER: &str = "x-mosaic-client";
+
+/// Whether to send [`CLIENT_HEADER`]. Off unless you turned it on with `mosaic telemetry on`.
+/// `MOSAIC_TELEMETRY=0` or `DO_NOT_TRACK=1` switch it off regardless, for machines where
+/// somebody else's config is in charge.
+///
+/// Worked out once per run: an install downloads a lot of packages, and loading the config
+/// means a keyring lookup every time.
+pub fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        let off = |key: &str, values: &[&str]| {
+            std::env::var(key).is_ok_and(|v| values.contains(&v.trim().to_lowercase().as_str()))
+        };
+        if off("MOSAIC_TELEMETRY", &["0", "false", "off"]) || off("DO_NOT_TRACK", &["1", "true"]) {
+            return false;
+        }
+        AuthConfig::load()
+            .ok()
+            .and_then(|c| c.telemetry)
+            .unwrap_or(false)
+    })
+}
+
+/// The header value, or None when telemetry is off.
+pub fn client_header() -> Option<String> {
+    enabled().then(|| format!("mosaic/{} ({})", env!("CARGO_PKG_VERSION"), std::env::consts::OS))
+}
+
+/// `mosaic telemetry [on|off|status]`.
+pub fn run(action: Option<&str>) -> Result<()> {
+    match action {
+        Some("on") | Some("off") => {
+            let mut config = AuthConfig::load()?;
+            config.telemetry = Some(action == Some("on"));
+            config.save()?;
+            if action == Some("on") {
+                Logger::success("Telemetry is on. Thanks! Downloads will include your CLI version and OS.");
+            } else {
+                Logger::success("Telemetry is off.");
+            }
+        }
+        _ => {
+            if enabled() {
+                Logger::info(format!(
+                    "Telemetry is on. Downloads send: {}",
+                    Logger::highlight(client_header().unwrap_or_default())
+                ));
+            } else {
+                Logger::info("Telemetry is off. Turn it on with 'mosaic telemetry on'.");
+            }
+        }
+    }
+    Ok(())
+}
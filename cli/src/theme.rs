@@ -0,0 +1,153 @@
+use crate::auth::AuthConfig;
+use crate::logger::Logger;
+use anyhow::{Result, anyhow};
+use colored::{ColoredString, Colorize};
+use std::sync::OnceLock;
+
+/// Which colors the output uses.
+///
+/// The brand blue and purple were picked on a dark terminal, and the purple all but vanishes
+/// on a white one. `Light` swaps in darker shades of the same two; `Plain` drops color
+/// altogether, for screen readers, logs and anyone who'd just rather not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+    Plain,
+}
+
+/// Names `mosaic theme` and `MOSAIC_THEME` accept.
+pub const NAMES: [&str; 4] = ["auto", "dark", "light", "plain"];
+
+impl Theme {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "dark" => Some(Self::Dark),
+            "light" => Some(Self::Light),
+            "plain" | "none" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+
+    /// Accent (the old hardcoded blue): bullets, highlighted names.
+    fn accent(self) -> (u8, u8, u8) {
+        match self {
+            Self::Light => (0, 105, 150),
+            _ => (14, 173, 221),
+        }
+    }
+
+    /// Brand (the old hardcoded purple): headers, labels, versions.
+    fn brand(self) -> (u8, u8, u8) {
+        match self {
+            Self::Light => (95, 35, 125),
+            _ => (125, 59, 155),
+        }
+    }
+}
+
+/// The theme for this run. Worked out once:
+///
+/// 1. `NO_COLOR` (any value, per no-color.org) means plain, full stop
+/// 2. `MOSAIC_THEME`, then the saved choice from `mosaic theme`
+/// 3. Otherwise guess from the terminal: `COLORFGBG` is how most terminals say what their
+///    background is. No answer means dark, which is what most terminals are
+pub fn current() -> Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    *THEME.get_or_init(|| {
+        if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Theme::Plain;
+        }
+        let chosen = std::env::var("MOSAIC_THEME")
+            .ok()
+            .or_else(|| AuthConfig::load_settings().ok().and_then(|c| c.theme));
+        chosen
+            .as_deref()
+            .and_then(Theme::parse)
+            .unwrap_or_else(detect)
+    })
+}
+
+/// Applies the theme to everything that prints. Call once in main, before any output.
+/// Plain also switches off the `colored` calls that don't go through the theme (red errors,
+/// green diff lines), so there isn't a single escape code left.
+pub fn init() {
+    if current() == Theme::Plain {
+        colored::control::set_override(false);
+    }
+}
+
+/// `COLORFGBG` looks like `15;0`: foreground;background as 16-color indexes. 7 and 15 are the
+/// whites, so those backgrounds are light.
+fn detect() -> Theme {
+    let background = std::env::var("COLORFGBG").ok().and_then(|v| {
+        v.rsplit(';')
+            .next()
+            .and_then(|bg| bg.trim().parse::<u8>().ok())
+    });
+    match background {
+        Some(7) | Some(15) => Theme::Light,
+        _ => Theme::Dark,
+    }
+}
+
+/// Text in the accent color.
+pub fn accent(text: &str) -> ColoredString {
+    let (r, g, b) = current().accent();
+    paint(text, r, g, b)
+}
+
+/// Text in the brand color.
+pub fn brand(text: &str) -> ColoredString {
+    let (r, g, b) = current().brand();
+    paint(text, r, g, b)
+}
+
+fn paint(text: &str, r: u8, g: u8, b: u8) -> ColoredString {
+    if current() == Theme::Plain {
+        text.normal()
+    } else {
+        text.truecolor(r, g, b)
+    }
+}
+
+/// `mosaic theme [auto|dark|light|plain]`. No argument shows what's in effect and why.
+pub fn run(choice: Option<&str>) -> Result<()> {
+    let Some(choice) = choice else {
+        let source = if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            "NO_COLOR is set"
+        } else if std::env::var("MOSAIC_THEME").is_ok() {
+            "from MOSAIC_THEME"
+        } else if AuthConfig::load_settings()
+            .ok()
+            .and_then(|c| c.theme)
+            .is_some()
+        {
+            "saved"
+        } else {
+            "detected"
+        };
+        Logger::info(format!(
+            "Theme: {} ({})",
+            Logger::highlight(format!("{:?}", current()).to_lowercase()),
+            source
+        ));
+        return Ok(());
+    };
+
+    if !NAMES.contains(&choice) {
+        return Err(anyhow!(
+            "Unknown theme '{}'. Pick one of: {}",
+            choice,
+            NAMES.join(", ")
+        ));
+    }
+    let mut config = AuthConfig::load()?;
+    config.theme = (choice != "auto").then(|| choice.to_string());
+    config.save()?;
+    Logger::success(format!(
+        "Theme set to {}. It applies from the next command.",
+        choice
+    ));
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use crate::logger::Logger;
+use anyhow::{Result, anyhow};
+use comfy_table::Table;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// What an install spends its time on. Network phases first, local ones after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Working out which version to install (dist-tags, ranges, version metadata).
+    Resolve,
+    /// Streaming the blob from the registry. Hashing happens on the way in, so it's in here too.
+    Download,
+    /// Comparing the downloaded hash against the lockfile.
+    Verify,
+    /// Pulling the Lua source out of the zip.
+    Extract,
+    /// Reading, rewriting and writing the .poly file.
+    Inject,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Resolve,
+        Phase::Download,
+        Phase::Verify,
+        Phase::Extract,
+        Phase::Inject,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Resolve => "resolve",
+            Phase::Download => "download",
+            Phase::Verify => "verify",
+            Phase::Extract => "extract",
+            Phase::Inject => "inject",
+        }
+    }
+
+    /// Whether this phase is waiting on the registry rather than on the disk/CPU.
+    fn is_network(self) -> bool {
+        matches!(self, Phase::Resolve | Phase::Download)
+    }
+}
+
+/// One timed chunk of work. Offsets are from when recording started, for the HTML timeline.
+#[derive(Debug, Clone, Serialize)]
+struct Event {
+    package: String,
+    phase: Phase,
+    start_ms: f64,
+    duration_ms: f64,
+}
+
+struct Recorder {
+    started: Instant,
+    events: Vec<Event>,
+}
+
+/// Set by `--timings`. Everything below is a no-op until then, so the installer can call
+/// `record` unconditionally without paying for it.
+static RECORDER: OnceLock<Mutex<Recorder>> = OnceLock::new();
+
+/// Report formats for `--timings`.
+pub const FORMATS: &[&str] = &["text", "json", "html"];
+
+/// Starts recording. Only the first call counts.
+pub fn enable() {
+    let _ = RECORDER.set(Mutex::new(Recorder {
+        started: Instant::now(),
+        events: Vec::new(),
+    }));
+}
+
+/// Records that `package` spent from `start` until now in `phase`.
+pub fn record(package: &str, phase: Phase, start: Instant) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    let Ok(mut recorder) = recorder.lock() else {
+        return;
+    };
+
+    let duration = start.elapsed();
+    let offset = start.saturating_duration_since(recorder.started);
+    recorder.events.push(Event {
+        package: package.to_string(),
+        phase,
+        start_ms: ms(offset),
+        duration_ms: ms(duration),
+    });
+}
+
+/// Prints the breakdown, and writes `mosaic-timings.json` / `mosaic-timings.html` if asked.
+/// Does nothing if `--timings` wasn't passed.
+pub fn report(format: &str) -> Result<()> {
+    let Some(recorder) = RECORDER.get() else {
+        return Ok(());
+    };
+    let recorder = recorder
+        .lock()
+        .map_err(|_| anyhow!("Timings recorder was poisoned"))?;
+    let total = recorder.started.elapsed();
+    let events = &recorder.events;
+
+    // Per package, per phase. BTreeMap so the table comes out alphabetical.
+    let mut by_package: BTreeMap<&str, BTreeMap<Phase, f64>> = BTreeMap::new();
+    for e in events {
+        *by_package
+            .entry(e.package.as_str())
+            .or_default()
+            .entry(e.phase)
+            .or_default() += e.duration_ms;
+    }
+
+    // 1. Text breakdown. Always printed—the files are extras.
+    Logger::header("Install timings");
+    let mut table = Table::new();
+    let mut header = vec!["Package".to_string()];
+    header.extend(Phase::ALL.iter().map(|p| p.label().to_string()));
+    header.push("total".to_string());
+    table.set_header(header);
+
+    let mut phase_totals: BTreeMap<Phase, f64> = BTreeMap::new();
+    for (package, phases) in &by_package {
+        let mut row = vec![package.to_string()];
+        for phase in Phase::ALL {
+            let t = phases.get(&phase).copied().unwrap_or(0.0);
+            *phase_totals.entry(phase).or_default() += t;
+            row.push(fmt_ms(t));
+        }
+        row.push(fmt_ms(phases.values().sum()));
+        table.add_row(row);
+    }
+    println!("{}", table);
+
+    let network: f64 = phase_totals
+        .iter()
+        .filter(|(p, _)| p.is_network())
+        .map(|(_, t)| t)
+        .sum();
+    let local: f64 = phase_totals.values().sum::<f64>() - network;
+    let inject = phase_totals.get(&Phase::Inject).copied().unwrap_or(0.0);
+    println!(
+        "  {} {}   {} {}   {} {}   {} {}",
+        Logger::brand_text("Total:"),
+        fmt_ms(ms(total)),
+        Logger::brand_text("Network:"),
+        percent(network, ms(total)),
+        Logger::brand_text("Local:"),
+        percent(local, ms(total)),
+        Logger::brand_text(".poly rewrite:"),
+        percent(inject, ms(total))
+    );
+    // The point of all this: say which side is the problem.
+    if network > local {
+        println!("  {}", Logger::dim("Mostly waiting on the registry."));
+    } else if local > 0.0 {
+        println!("  {}", Logger::dim("Mostly local work (.poly rewrite, extraction)."));
+    }
+
+    // 2. Files
+    match format {
+        "text" => {}
+        "json" => {
+            let packages: Vec<_> = by_package
+                .iter()
+                .map(|(name, phases)| {
+                    let phases: BTreeMap<&str, f64> =
+                        phases.iter().map(|(p, t)| (p.label(), round(*t))).collect();
+                    json!({ "name": name, "phases_ms": phases })
+                })
+                .collect();
+            let doc = json!({
+                "total_ms": round(ms(total)),
+                "network_ms": round(network),
+                "local_ms": round(local),
+                "packages": packages,
+                "events": events,
+            });
+            std::fs::write("mosaic-timings.json", serde_json::to_string_pretty(&doc)?)?;
+            Logger::info(format!("Wrote {}", Logger::highlight("mosaic-timings.json")));
+        }
+        "html" => {
+            std::fs::write("mosaic-timings.html", html(events, ms(total)))?;
+            Logger::info(format!("Wrote {}", Logger::highlight("mosaic-timings.html")));
+        }
+        other => {
+            return Err(anyhow!(
+                "Unknown timings format '{}'. Pick one of: {}",
+                other,
+                FORMATS.join(", ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// A self-contained page with one bar per event on a shared timeline. No JS, no assets.
+fn html(events: &[Event], total_ms: f64) -> String {
+    let scale = if total_ms > 0.0 { 100.0 / total_ms } else { 0.0 };
+    let mut rows = String::new();
+    for e in events {
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td class=\"lane\"><div class=\"bar {}\" style=\"margin-left:{:.2}%;width:{:.2}%\"></div></td><td>{}</td></tr>\n",
+            escape(&e.package),
+            e.phase.label(),
+            e.phase.label(),
+            e.start_ms * scale,
+            (e.duration_ms * scale).max(0.2),
+            fmt_ms(e.duration_ms)
+        ));
+    }
+
+    format!(
+        r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>mosaic install timings</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2rem; color: #222; }}
+table {{ border-collapse: collapse; width: 100%; }}
+td, th {{ padding: 2px 8px; text-align: left; white-space: nowrap; }}
+td.lane {{ width: 70%; }}
+.bar {{ height: 12px; border-radius: 2px; }}
+.resolve {{ background: #0eaddd; }}
+.download {{ background: #7d3b9b; }}
+.verify {{ background: #3bb273; }}
+.extract {{ background: #e1a03b; }}
+.inject {{ background: #d9534f; }}
+</style>
+</head>
+<body>
+<h1>mosaic install timings</h1>
+<p>Total: {}</p>
+<table>
+<tr><th>Package</th><th>Phase</th><th>Timeline</th><th>Took</th></tr>
+{}</table>
+</body>
+</html>
+"#,
+        fmt_ms(total_ms),
+        rows
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn round(ms: f64) -> f64 {
+    (ms * 10.0).round() / 10.0
+}
+
+fn fmt_ms(ms: f64) -> String {
+    if ms >= 1000.0 {
+        format!("{:.2}s", ms / 1000.0)
+    } else {
+        format!("{:.0}ms", ms)
+    }
+}
+
+fn percent(part: f64, whole: f64) -> String {
+    if whole <= 0.0 {
+        return "0%".to_string();
+    }
+    format!("{:.0}%", part / whole * 100.0)
+}
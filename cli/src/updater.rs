@@ -1,77 +1,331 @@
+use crate::auth::AuthConfig;
 use crate::logger::Logger;
+use crate::mode;
+use crate::paths;
 use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use ed25519_dalek::{Signature, VerifyingKey};
 use self_update::cargo_crate_version;
+use sha2::{Digest, Sha256};
+use std::io::Write;
 
-/// Checks if GitHub has a shiny new version for us.
-///
-/// Runs in the background because nobody likes waiting for network calls.
-/// If there's an update, we nudge the user gently.
-pub async fn check_for_updates() -> Result<()> {
-    let current_version = cargo_crate_version!();
-    
-    // We wrap the synchronous update check in spawn_blocking because
-    // blocking the async runtime for a network call is rude.
-    let status = tokio::task::spawn_blocking(move || {
-        self_update::backends::github::Update::configure()
+/// Release channels. Stable only ever sees full releases; beta sees prereleases too.
+pub const CHANNELS: &[&str] = &["stable", "beta"];
+
+/// The channel `upgrade` and the update nag follow: whatever was last picked with
+/// `mosaic upgrade --channel`, or stable.
+fn saved_channel() -> String {
+    AuthConfig::load()
+        .ok()
+        .and_then(|c| c.update_channel)
+        .filter(|c| CHANNELS.contains(&c.as_str()))
+        .unwrap_or_else(|| "stable".to_string())
+}
+
+/// Every published release we can see, newest first. Drafts aren't visible to us anyway.
+/// Tags that don't parse as semver are skipped.
+async fn releases() -> Result<Vec<semver::Version>> {
+    let list = tokio::task::spawn_blocking(|| {
+        self_update::backends::github::ReleaseList::configure()
             .repo_owner("doshibadev")
             .repo_name("mosaic")
-            .bin_name("mosaic")
-            .current_version(current_version)
             .build()
-            .map(|u| u.get_latest_release())
-    }).await??;
-
-    if let Ok(latest) = status {
-        let latest_version = latest.version;
-        if latest_version != current_version {
-            println!();
-            Logger::warn(format!(
-                "Update available! {} -> {}",
-                current_version,
-                Logger::highlight(&latest_version)
-            ));
-            println!("  Run {} to upgrade.", Logger::brand_text("mosaic upgrade"));
-            println!();
-        }
+            .and_then(|l| l.fetch())
+    })
+    .await?
+    .map_err(|e| anyhow!("Couldn't list releases: {}", e))?;
+
+    let mut versions: Vec<semver::Version> = list
+        .iter()
+        .filter_map(|r| semver::Version::parse(r.version.trim_start_matches('v')).ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    Ok(versions)
+}
+
+/// The newest release on `channel`.
+fn latest_on(channel: &str, versions: &[semver::Version]) -> Option<semver::Version> {
+    versions
+        .iter()
+        .find(|v| channel == "beta" || v.pre.is_empty())
+        .cloned()
+}
+
+/// How long the automatic check leaves GitHub alone after asking. Releases aren't hourly,
+/// and unauthenticated API calls are rate limited per IP, which a busy CI box shares.
+const CHECK_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// When the automatic check last asked GitHub, as a unix timestamp in the cache dir.
+fn stamp_path() -> Result<std::path::PathBuf> {
+    Ok(paths::cache_dir()?.join("update-check"))
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Whether to look for a new release while a command runs. Off unless `mosaic check-update on`,
+/// never on CI, and at most once every [`CHECK_INTERVAL_SECS`].
+pub fn auto_check_due() -> bool {
+    if mode::is_ci() || mode::is_json() {
+        return false;
+    }
+    let enabled = AuthConfig::load_settings()
+        .ok()
+        .and_then(|c| c.update_check)
+        .unwrap_or(false);
+    if !enabled {
+        return false;
     }
+    let last = stamp_path()
+        .and_then(|p| Ok(std::fs::read_to_string(p)?))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    now().saturating_sub(last) >= CHECK_INTERVAL_SECS
+}
+
+/// The newest release on your channel, if it's newer than this binary.
+///
+/// Only newer counts: someone on a prerelease doesn't need to hear about an older stable.
+pub async fn check_for_updates() -> Result<Option<semver::Version>> {
+    let current = semver::Version::parse(cargo_crate_version!())?;
+    let latest = latest_on(&saved_channel(), &releases().await?);
+    // Asked and answered, whatever the answer was.
+    if let Ok(path) = stamp_path() {
+        let _ = std::fs::write(path, now().to_string());
+    }
+    Ok(latest.filter(|latest| *latest > current))
+}
 
+/// The gentle nudge printed after a command when [`check_for_updates`] found something.
+pub fn announce(latest: &semver::Version) {
+    println!();
+    Logger::warn(format!(
+        "Update available! {} -> {}",
+        cargo_crate_version!(),
+        Logger::highlight(latest.to_string())
+    ));
+    println!("  Run {} to upgrade.", Logger::brand_text("mosaic upgrade"));
+    println!();
+}
+
+/// `mosaic check-update [on|off]`. No argument checks right now.
+pub async fn check_update(action: Option<&str>) -> Result<()> {
+    match action {
+        Some("on") | Some("off") => {
+            let mut config = AuthConfig::load()?;
+            config.update_check = Some(action == Some("on"));
+            config.save()?;
+            if action == Some("on") {
+                Logger::success("mosaic will check for new releases once a day, while your commands run.");
+            } else {
+                Logger::success("Automatic update checks are off.");
+            }
+        }
+        _ => {
+            Logger::info(format!("Checking the {} channel...", saved_channel()));
+            match check_for_updates().await? {
+                Some(latest) => announce(&latest),
+                None => Logger::success(format!("mosaic {} is the latest.", cargo_crate_version!())),
+            }
+        }
+    }
     Ok(())
 }
 
-/// Downloads the latest binary and replaces the current executable.
+/// Where release binaries live. Tags are `v<version>`.
+const RELEASES_URL: &str = "https://github.com/doshibadev/mosaic/releases/download";
+
+/// The ed25519 public key release checksums are signed with (base64, raw 32 bytes).
+///
+/// Baked in at build time by the release workflow. A local `cargo build` doesn't have it,
+/// which means a dev build can't self-upgrade—fine, you built it yourself, you can build again.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("MOSAIC_RELEASE_PUBKEY");
+
+/// Downloads a release binary and replaces the current executable.
 ///
 /// Yes, it modifies the running binary. It's magic (and supported by the OS).
-pub async fn upgrade() -> Result<()> {
-    Logger::info("Checking for updates...");
-    
+/// Which is exactly why we don't take GitHub's word for what we downloaded:
+/// 1. Pick the target: `--version` if given, else the newest release on the channel.
+///    Going backwards needs `--allow-downgrade` (an explicit `--version` counts as asking).
+/// 2. Download the binary for this platform, plus SHA256SUMS and its signature.
+/// 3. Check the signature against the key baked into this binary.
+/// 4. Check the binary's hash against the signed SHA256SUMS.
+/// 5. Only then swap it in.
+///
+/// `--channel` is remembered in the user config, so `mosaic upgrade --channel beta` once
+/// keeps you on betas until you switch back.
+pub async fn upgrade(
+    channel: Option<&str>,
+    version: Option<&str>,
+    allow_downgrade: bool,
+) -> Result<()> {
     let current_version = cargo_crate_version!();
-    
-    let status = tokio::task::spawn_blocking(move || {
-        self_update::backends::github::Update::configure()
-            .repo_owner("doshibadev")
-            .repo_name("mosaic")
-            .bin_name("mosaic")
-            .show_download_progress(true)
-            .current_version(current_version)
-            .build()
-            .map(|u| u.update())
-    }).await??;
-
-    match status {
-        Ok(status) => {
-            if status.updated() {
-                Logger::success(format!(
-                    "Upgraded to version {}!",
-                    Logger::highlight(status.version())
+    let Some(public_key) = RELEASE_PUBLIC_KEY else {
+        return Err(anyhow!(
+            "This build of mosaic has no release key, so it can't verify an upgrade. Reinstall from https://github.com/doshibadev/mosaic/releases"
+        ));
+    };
+    let asset = asset_name()?;
+
+    let channel = match channel {
+        Some(c) => {
+            let c = c.trim().to_ascii_lowercase();
+            if !CHANNELS.contains(&c.as_str()) {
+                return Err(anyhow!(
+                    "Unknown channel '{}'. Pick one of: {}",
+                    c,
+                    CHANNELS.join(", ")
                 ));
-            } else {
-                Logger::info("Already up to date.");
             }
+            let mut config = AuthConfig::load()?;
+            if config.update_channel.as_deref() != Some(c.as_str()) {
+                config.update_channel = Some(c.clone());
+                config.save()?;
+                Logger::info(format!("Switched to the {} channel.", Logger::highlight(&c)));
+            }
+            c
         }
-        Err(e) => {
-            return Err(anyhow!("Update failed: {}", e));
+        None => saved_channel(),
+    };
+
+    // 1. Target
+    Logger::info("Checking for updates...");
+    let current = semver::Version::parse(current_version)?;
+    let versions = releases().await?;
+
+    let target = match version {
+        Some(v) => {
+            let wanted = semver::Version::parse(v.trim().trim_start_matches('v'))
+                .map_err(|e| anyhow!("'{}' isn't a version: {}", v, e))?;
+            if !versions.contains(&wanted) {
+                return Err(anyhow!("There's no mosaic release {}.", wanted));
+            }
+            wanted
         }
+        None => latest_on(&channel, &versions)
+            .ok_or_else(|| anyhow!("There are no releases on the {} channel yet.", channel))?,
+    };
+
+    if target == current {
+        Logger::info("Already up to date.");
+        return Ok(());
+    }
+    if target < current && version.is_none() && !allow_downgrade {
+        // Usually means you're on a prerelease newer than the latest stable one.
+        Logger::info(format!(
+            "You're on {}, which is newer than the latest {} release ({}).",
+            Logger::highlight(current_version),
+            channel,
+            target
+        ));
+        println!(
+            "  Run {} if you really want to go back.",
+            Logger::brand_text("mosaic upgrade --allow-downgrade")
+        );
+        return Ok(());
+    }
+
+    // 2. Download
+    let base = format!("{}/v{}", RELEASES_URL, target);
+    Logger::info(format!("Downloading mosaic {}...", Logger::highlight(target.to_string())));
+    let binary = fetch(&format!("{}/{}", base, asset)).await?;
+    let sums = fetch(&format!("{}/SHA256SUMS", base)).await?;
+    let signature = fetch(&format!("{}/SHA256SUMS.sig", base)).await?;
+
+    // 3. Signature
+    verify_signature(public_key, &sums, &signature)?;
+
+    // 4. Checksum
+    let sums = String::from_utf8(sums)
+        .map_err(|_| anyhow!("SHA256SUMS for {} isn't valid UTF-8", target))?;
+    let expected = expected_hash(&sums, &asset)
+        .ok_or_else(|| anyhow!("SHA256SUMS for {} has no entry for {}", target, asset))?;
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}. Not installing it.",
+            asset,
+            expected,
+            actual
+        ));
+    }
+    Logger::debug(format!("Verified {} ({})", asset, actual));
+
+    // 5. Replace. self_replace handles the swap, including Windows refusing to overwrite a running exe.
+    let mut tmp = tempfile::NamedTempFile::new()?;
+    tmp.write_all(&binary)?;
+    tmp.flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(tmp.path(), std::fs::Permissions::from_mode(0o755))?;
+    }
+    self_update::self_replace::self_replace(tmp.path())
+        .map_err(|e| anyhow!("Update failed: {}", e))?;
+
+    if target < current {
+        Logger::success(format!("Downgraded to version {}.", Logger::highlight(target.to_string())));
+    } else {
+        Logger::success(format!("Upgraded to version {}!", Logger::highlight(target.to_string())));
     }
 
     Ok(())
 }
+
+/// The release asset for the platform we're running on. Matches the names in release.yml.
+fn asset_name() -> Result<String> {
+    let name = match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => "mosaic-linux-amd64",
+        ("windows", "x86_64") => "mosaic-windows-amd64.exe",
+        // Apple Silicon runs the x86 build under Rosetta until we ship a native one.
+        ("macos", "x86_64") | ("macos", "aarch64") => "mosaic-macos-amd64",
+        (os, arch) => {
+            return Err(anyhow!(
+                "There's no prebuilt mosaic for {}-{}. Build it from source instead.",
+                os,
+                arch
+            ));
+        }
+    };
+    Ok(name.to_string())
+}
+
+async fn fetch(url: &str) -> Result<Vec<u8>> {
+    let res = reqwest::Client::new().get(url).send().await?;
+    if !res.status().is_success() {
+        return Err(anyhow!("Couldn't download {} ({})", url, res.status()));
+    }
+    Ok(res.bytes().await?.to_vec())
+}
+
+/// Checks that `signature` (base64 ed25519) is `public_key`'s signature over `message`.
+fn verify_signature(public_key: &str, message: &[u8], signature: &[u8]) -> Result<()> {
+    let key_bytes: [u8; 32] = BASE64
+        .decode(public_key.trim())?
+        .try_into()
+        .map_err(|_| anyhow!("Release public key is the wrong length"))?;
+    let key = VerifyingKey::from_bytes(&key_bytes)?;
+
+    let sig_bytes: [u8; 64] = BASE64
+        .decode(String::from_utf8_lossy(signature).trim())
+        .map_err(|_| anyhow!("SHA256SUMS.sig isn't valid base64"))?
+        .try_into()
+        .map_err(|_| anyhow!("SHA256SUMS.sig is the wrong length"))?;
+
+    key.verify_strict(message, &Signature::from_bytes(&sig_bytes))
+        .map_err(|_| anyhow!("SHA256SUMS signature doesn't match the release key. Not installing anything."))
+}
+
+/// Finds `asset`'s hash in a `sha256sum`-style file (`<hash>  <name>`, optionally `*<name>`).
+fn expected_hash(sums: &str, asset: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        let name = name.trim_start().trim_start_matches('*');
+        (name == asset).then(|| hash.to_string())
+    })
+}
@@ -1,19 +1,39 @@
 use anyhow::Result;
-use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::reader::Reader;
 use quick_xml::writer::Writer;
+use std::collections::BTreeMap;
 use std::io::Cursor;
+use tracing::{debug, instrument, warn};
+
+/// Property holding the version mosaic installed into a ModuleScript.
+const VERSION_PROP: &str = "MosaicVersion";
+/// Property holding the SHA256 of the Source mosaic installed, same hash `mosaic attest` uses.
+const SHA256_PROP: &str = "MosaicSha256";
+
+/// What mosaic writes into every module it installs, next to the Source.
+///
+/// It travels with the .poly, so it still says what's embedded when mosaic.lock is stale,
+/// missing, or belongs to a collaborator who never committed theirs. If the Source no longer
+/// hashes to `sha256`, somebody edited it after the install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    pub version: String,
+    pub sha256: String,
+}
 
 /// Injects a package as a ModuleScript into the .poly XML file.
 ///
 /// If the module already exists (by name), updates it instead.
 /// Otherwise, finds the ScriptService and adds the new ModuleScript as a child.
-pub fn inject_module_script(poly_xml: &str, name: &str, source: &str) -> Result<String> {
+#[instrument(level = "debug", skip(poly_xml, source), fields(source_len = source.len()))]
+pub fn inject_module_script(poly_xml: &str, name: &str, source: &str, stamp: &Stamp) -> Result<String> {
     // Quick check: does this module already exist?
     // If so, just update it instead of trying to inject a duplicate.
     let exists = poly_xml.contains(&format!("<string name=\"Name\">{}</string>", name));
     if exists {
-        return update_module_script(poly_xml, name, source);
+        debug!("module already exists, updating in place");
+        return update_module_script(poly_xml, name, source, stamp);
     }
 
     let mut reader = Reader::from_str(poly_xml);
@@ -23,6 +43,7 @@ pub fn inject_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
 
     let mut in_script_service = false;
     let mut depth = 0;
+    let mut injected = false;
 
     loop {
         match reader.read_event_into(&mut buf)? {
@@ -32,6 +53,7 @@ pub fn inject_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
                 if e.local_name().as_ref() == b"Item" {
                     if let Some(attr) = e.try_get_attribute("class")? {
                         if attr.value.as_ref() as &[u8] == b"ScriptService" {
+                            debug!(depth, position = reader.buffer_position(), "found ScriptService");
                             in_script_service = true;
                         }
                     }
@@ -43,6 +65,8 @@ pub fn inject_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
                 // When we close the ScriptService Item, that's our cue to inject the module.
                 if in_script_service && e.local_name().as_ref() == b"Item" && depth == 1 {
                     // Insert the new ModuleScript before closing ScriptService
+                    debug!(position = reader.buffer_position(), "injecting ModuleScript at the end of ScriptService");
+                    injected = true;
 
                     // Indentation (matches the style of other Items in ScriptService)
                     writer.write_event(Event::Text(quick_xml::events::BytesText::new("\n    ")))?;
@@ -79,6 +103,18 @@ pub fn inject_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
                     writer.write_event(Event::Text(quick_xml::events::BytesText::new(name)))?;
                     writer.write_event(Event::End(BytesEnd::new("string")))?;
 
+                    // Which version this is, so drift shows up without the lockfile
+                    for (prop, value) in [(VERSION_PROP, &stamp.version), (SHA256_PROP, &stamp.sha256)] {
+                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
+                            "\n        ",
+                        )))?;
+                        let mut prop_start = BytesStart::new("string");
+                        prop_start.push_attribute(("name", prop));
+                        writer.write_event(Event::Start(prop_start))?;
+                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(value)))?;
+                        writer.write_event(Event::End(BytesEnd::new("string")))?;
+                    }
+
                     // Close Properties
                     writer
                         .write_event(Event::Text(quick_xml::events::BytesText::new("\n      ")))?;
@@ -103,20 +139,29 @@ pub fn inject_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
         buf.clear();
     }
 
+    // The file comes back unchanged in this case, so at least say so.
+    if !injected {
+        warn!("no top-level ScriptService in the .poly file, {} was not injected", name);
+    }
+
     let result = writer.into_inner().into_inner();
     Ok(String::from_utf8(result)?)
 }
 
-/// Replaces an existing ModuleScript with new source code.
+/// Replaces an existing ModuleScript's source code and stamp.
 ///
 /// This is more complex than injection because we have to:
 /// 1. Find the right ModuleScript (by Name property)
 /// 2. Buffer up all its XML events
-/// 3. Decide whether to keep it or replace it
+/// 3. Decide whether to keep it or swap its Source and stamp
 /// 4. Write out the result
 ///
+/// Only the Source and stamp change. Any other properties people set on the module (attributes,
+/// tags, whatever Polytoria adds next) and any children survive the update.
+///
 /// It's a bit stateful and gross, but XML is like that sometimes.
-pub fn update_module_script(poly_xml: &str, name: &str, source: &str) -> Result<String> {
+#[instrument(level = "debug", skip(poly_xml, source), fields(source_len = source.len()))]
+pub fn update_module_script(poly_xml: &str, name: &str, source: &str, stamp: &Stamp) -> Result<String> {
     let mut reader = Reader::from_str(poly_xml);
     reader.config_mut().trim_text(false);
     let mut writer = Writer::new(Cursor::new(Vec::new()));
@@ -181,47 +226,12 @@ pub fn update_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
             if let Event::End(e) = module_buffer.last().unwrap() {
                 if e.local_name().as_ref() == b"Item" && depth == 2 {
                     if is_target_module {
-                        // This is the one we're updating—write a fresh replacement
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-                            "\n    ",
-                        )))?;
-                        let mut script_item = BytesStart::new("Item");
-                        script_item.push_attribute(("class", "ModuleScript"));
-                        writer.write_event(Event::Start(script_item))?;
-
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-                            "\n      ",
-                        )))?;
-                        let props_start = BytesStart::new("Properties");
-                        writer.write_event(Event::Start(props_start))?;
-
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-                            "\n        ",
-                        )))?;
-                        let mut source_start = BytesStart::new("string");
-                        source_start.push_attribute(("name", "Source"));
-                        writer.write_event(Event::Start(source_start))?;
-                        writer
-                            .write_event(Event::Text(quick_xml::events::BytesText::new(source)))?;
-                        writer.write_event(Event::End(BytesEnd::new("string")))?;
-
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-                            "\n        ",
-                        )))?;
-                        let mut name_start = BytesStart::new("string");
-                        name_start.push_attribute(("name", "Name"));
-                        writer.write_event(Event::Start(name_start))?;
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(name)))?;
-                        writer.write_event(Event::End(BytesEnd::new("string")))?;
-
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-                            "\n      ",
-                        )))?;
-                        writer.write_event(Event::End(BytesEnd::new("Properties")))?;
-                        writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-                            "\n    ",
-                        )))?;
-                        writer.write_event(Event::End(BytesEnd::new("Item")))?;
+                        // This is the one we're updating—swap its Source, keep everything else
+                        debug!(position = reader.buffer_position(), "replacing ModuleScript source");
+                        let props = [("Source", source), (VERSION_PROP, &stamp.version), (SHA256_PROP, &stamp.sha256)];
+                        for ev in with_properties(module_buffer.drain(..), &props)? {
+                            writer.write_event(ev)?;
+                        }
                     } else {
                         // Not our target—preserve the original module as-is
                         for ev in module_buffer.drain(..) {
@@ -245,10 +255,86 @@ pub fn update_module_script(poly_xml: &str, name: &str, source: &str) -> Result<
     Ok(String::from_utf8(result)?)
 }
 
+/// Rewrites a buffered ModuleScript Item with new values for some of its string properties.
+///
+/// Depth is relative to the Item: its Properties sit at 2, and the properties themselves at 3.
+/// Children of the module have their own Properties deeper down, which are left alone.
+/// A property the module doesn't have yet is added at the end of its Properties.
+fn with_properties(
+    module: impl Iterator<Item = Event<'static>>,
+    props: &[(&str, &str)],
+) -> Result<Vec<Event<'static>>> {
+    let which = |e: &BytesStart| -> Result<Option<usize>> {
+        if e.local_name().as_ref() != b"string" {
+            return Ok(None);
+        }
+        Ok(e.try_get_attribute("name")?
+            .and_then(|attr| props.iter().position(|(prop, _)| attr.value.as_ref() as &[u8] == prop.as_bytes())))
+    };
+    let prop = |i: usize| {
+        let mut start = BytesStart::new("string");
+        start.push_attribute(("name", props[i].0));
+        [
+            Event::Start(start),
+            Event::Text(BytesText::new(props[i].1).into_owned()),
+            Event::End(BytesEnd::new("string")),
+        ]
+    };
+
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut in_properties = false;
+    let mut in_replaced = false;
+    let mut written = vec![false; props.len()];
+
+    for event in module {
+        match &event {
+            Event::Start(e) => {
+                depth += 1;
+                if depth == 2 && e.local_name().as_ref() == b"Properties" {
+                    in_properties = true;
+                } else if depth == 3 && in_properties && let Some(i) = which(e)? {
+                    // Keep the opening tag as written, drop the old text, write ours.
+                    out.push(event);
+                    out.push(Event::Text(BytesText::new(props[i].1).into_owned()));
+                    in_replaced = true;
+                    written[i] = true;
+                    continue;
+                }
+            }
+            Event::Empty(e) if depth == 2 && in_properties => {
+                if let Some(i) = which(e)? {
+                    out.extend(prop(i));
+                    written[i] = true;
+                    continue;
+                }
+            }
+            Event::End(e) => {
+                if in_replaced && depth == 3 {
+                    in_replaced = false;
+                } else if in_properties && depth == 2 && e.local_name().as_ref() == b"Properties" {
+                    for i in (0..props.len()).filter(|&i| !written[i]) {
+                        out.push(Event::Text(BytesText::new("  ")));
+                        out.extend(prop(i));
+                        out.push(Event::Text(BytesText::new("\n      ")));
+                    }
+                    in_properties = false;
+                }
+                depth -= 1;
+            }
+            _ if in_replaced => continue,
+            _ => {}
+        }
+        out.push(event);
+    }
+    Ok(out)
+}
+
 /// Removes a ModuleScript from the .poly file by name.
 ///
 /// Similar dance to update: walk the tree, find the matching module, skip it.
 /// Everything else gets written through unchanged.
+#[instrument(level = "debug", skip(poly_xml))]
 pub fn remove_module_script(poly_xml: &str, name: &str) -> Result<String> {
     let mut reader = Reader::from_str(poly_xml);
     reader.config_mut().trim_text(false);
@@ -319,6 +405,7 @@ pub fn remove_module_script(poly_xml: &str, name: &str) -> Result<String> {
                         }
                     } else {
                         // This is the one we're removing—just skip the buffer
+                        debug!(position = reader.buffer_position(), "removing ModuleScript");
                         item_buffer.clear();
                     }
                     capturing_item = false;
@@ -335,3 +422,142 @@ pub fn remove_module_script(poly_xml: &str, name: &str) -> Result<String> {
     let result = writer.into_inner().into_inner();
     Ok(String::from_utf8(result)?)
 }
+
+/// Reads every ModuleScript in ScriptService and returns its source, keyed by Name.
+///
+/// Read-only sibling of the functions above. Used by `mosaic attest` to hash what actually
+/// ended up in the .poly, which isn't necessarily what the lockfile thinks is there.
+/// If two modules share a name, the last one wins; see `duplicate_modules`.
+pub fn module_sources(poly_xml: &str) -> Result<BTreeMap<String, String>> {
+    Ok(modules(poly_xml)?.into_iter().map(|m| (m.name, m.source)).collect())
+}
+
+/// The stamp mosaic left in each ModuleScript it installed, keyed by Name.
+///
+/// Modules somebody pasted in by hand, or installed with a mosaic from before stamps, have none.
+pub fn module_stamps(poly_xml: &str) -> Result<BTreeMap<String, Stamp>> {
+    Ok(modules(poly_xml)?
+        .into_iter()
+        .filter_map(|m| Some((m.name, m.stamp?)))
+        .collect())
+}
+
+/// Names that more than one ModuleScript in ScriptService goes by, and how many there are.
+///
+/// Happens when a package was pasted in by hand and then installed, or copied between places
+/// twice. Polytoria doesn't mind, but `require` only ever sees one of them, and which one
+/// isn't something anybody should be relying on.
+pub fn duplicate_modules(poly_xml: &str) -> Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+    for module in modules(poly_xml)? {
+        *counts.entry(module.name).or_insert(0) += 1;
+    }
+    counts.retain(|_, n| *n > 1);
+    Ok(counts)
+}
+
+/// One ModuleScript in ScriptService, as far as mosaic cares.
+struct Module {
+    name: String,
+    source: String,
+    stamp: Option<Stamp>,
+}
+
+/// Every ModuleScript in ScriptService, in document order, duplicates and all.
+fn modules(poly_xml: &str) -> Result<Vec<Module>> {
+    let mut reader = Reader::from_str(poly_xml);
+    reader.config_mut().trim_text(false);
+    let mut buf = Vec::new();
+
+    let mut in_script_service = false;
+    let mut in_module = false;
+    let mut depth = 0;
+
+    // Which <string> we're inside (if any), plus what we've read for the current module so far.
+    let mut current_prop: Option<&'static str> = None;
+    let mut props: BTreeMap<&'static str, String> = BTreeMap::new();
+    let mut modules = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                depth += 1;
+                if e.local_name().as_ref() == b"Item" {
+                    if let Some(attr) = e.try_get_attribute("class")? {
+                        let class_val = attr.value.as_ref() as &[u8];
+                        if class_val == b"ScriptService" {
+                            in_script_service = true;
+                        } else if in_script_service && class_val == b"ModuleScript" && depth == 3 {
+                            in_module = true;
+                        }
+                    }
+                } else if in_module
+                    && e.local_name().as_ref() == b"string"
+                    && let Some(attr) = e.try_get_attribute("name")?
+                {
+                    current_prop = ["Name", "Source", VERSION_PROP, SHA256_PROP]
+                        .into_iter()
+                        .find(|prop| attr.value.as_ref() as &[u8] == prop.as_bytes());
+                }
+            }
+            Event::End(e) => {
+                depth -= 1;
+                if e.local_name().as_ref() == b"string" {
+                    current_prop = None;
+                } else if e.local_name().as_ref() == b"Item" {
+                    if in_module && depth == 2 {
+                        let mut take = |prop| props.remove(prop).unwrap_or_default().trim().to_string();
+                        let name = take("Name");
+                        let stamp = match (take(VERSION_PROP), take(SHA256_PROP)) {
+                            (version, sha256) if !version.is_empty() && !sha256.is_empty() => {
+                                Some(Stamp { version, sha256 })
+                            }
+                            _ => None,
+                        };
+                        if !name.is_empty() {
+                            let source = props.remove("Source").unwrap_or_default();
+                            modules.push(Module { name, source, stamp });
+                        }
+                        props.clear();
+                        in_module = false;
+                    } else if in_script_service && depth == 1 {
+                        in_script_service = false;
+                    }
+                }
+            }
+            // Text can arrive in several pieces, split around entity references like &lt;.
+            // Stitch them back together so we end up with the real Lua source.
+            Event::Text(t) => {
+                let text = reader.decoder().decode(t.as_ref())?;
+                push_prop(current_prop, &mut props, &text);
+            }
+            Event::CData(c) => {
+                let text = reader.decoder().decode(c.as_ref())?;
+                push_prop(current_prop, &mut props, &text);
+            }
+            Event::GeneralRef(r) => {
+                let resolved = match r.resolve_char_ref()? {
+                    Some(ch) => ch.to_string(),
+                    None => {
+                        let entity = reader.decoder().decode(r.as_ref())?;
+                        quick_xml::escape::resolve_predefined_entity(&entity)
+                            .map(|s| s.to_string())
+                            .unwrap_or_else(|| format!("&{};", entity))
+                    }
+                };
+                push_prop(current_prop, &mut props, &resolved);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(modules)
+}
+
+fn push_prop(prop: Option<&'static str>, props: &mut BTreeMap<&'static str, String>, text: &str) {
+    if let Some(prop) = prop {
+        props.entry(prop).or_default().push_str(text);
+    }
+}
@@ -0,0 +1,955 @@
+//! The CLI against recorded registry responses (see `test_support::mock`). Runs offline, so
+//! this is where resolution and the error paths get pinned down: 404s, 409s, rate limits,
+//! bodies that aren't JSON, blobs that don't match their hash.
+
+use sha2::Digest;
+use std::fs;
+use test_support::mock::{Body, Fixture, MockRegistry};
+use test_support::{Cli, EMPTY_POLY, Run, TestRegistry};
+
+const MOSAIC: &str = env!("CARGO_BIN_EXE_mosaic");
+
+/// Everything recorded for the two packages: greeter 0.1.0, greeter 0.2.0 (which depends on
+/// strings ^0.1) and strings 0.1.0.
+const PACKAGES: &[&str] = &[
+    "greeter.stable",
+    "greeter",
+    "greeter.versions",
+    "greeter@0.1.0",
+    "greeter@0.1.0.download",
+    "greeter@0.2.0",
+    "greeter@0.2.0.download",
+    "strings.stable",
+    "strings",
+    "strings.versions",
+    "strings@0.1.0",
+    "strings@0.1.0.download",
+];
+
+/// A mock registry serving `fixtures`, and a CLI with a game project pointed at it.
+async fn setup(fixtures: &[&str]) -> anyhow::Result<(MockRegistry, Cli, std::path::PathBuf)> {
+    let registry = MockRegistry::start().await;
+    registry.serve(fixtures).await?;
+    let cli = Cli::new(MOSAIC, &registry.url())?;
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    Ok((registry, cli, game))
+}
+
+/// Whatever the command printed, both streams, for matching error messages.
+fn output(run: &Run) -> String {
+    format!("{}{}", run.stdout, run.stderr)
+}
+
+#[tokio::test]
+async fn install_resolves_latest_and_dependencies() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    registry.serve(&["lock-status"]).await?;
+
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<string name=\"Name\">greeter</string>"), "{}", poly);
+    assert!(poly.contains("<string name=\"Name\">strings</string>"), "{}", poly);
+    assert!(fs::read_to_string(game.join("mosaic.toml"))?.contains("greeter = \"0.2.0\""));
+
+    // Latest comes from the stable view, not the full package.
+    let requests = registry.requests().await;
+    assert!(requests.contains(&"GET /packages/greeter?include_prereleases=false".to_string()), "{:#?}", requests);
+
+    cli.run(&game, &["verify"]).await?.ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_range_picks_the_newest_match() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+
+    cli.run(&game, &["install", "greeter@^0.1"]).await?.ok();
+
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    assert!(lock.contains("0.1.0"), "{}", lock);
+    assert!(!lock.contains("strings"), "0.1.0 has no dependencies\n{}", lock);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_resolves_ranges_written_in_mosaic_toml() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    fs::write(game.join("mosaic.toml"), toml + "greeter = \">=0.1, <0.2\"\n")?;
+
+    cli.run(&game, &["install"]).await?.ok();
+
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    assert!(lock.contains("0.1.0") && !lock.contains("0.2.0"), "{}", lock);
+    // The range stays a range; only the lockfile pins.
+    assert!(fs::read_to_string(game.join("mosaic.toml"))?.contains("greeter = \">=0.1, <0.2\""));
+    Ok(())
+}
+
+#[tokio::test]
+async fn aliased_dependency_is_injected_under_its_local_name() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    registry.serve(&["lock-status"]).await?;
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    fs::write(game.join("mosaic.toml"), toml.clone() + "greet = { package = \"greeter\", version = \"0.2.0\" }\n")?;
+
+    cli.run(&game, &["install"]).await?.ok();
+
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<string name=\"Name\">greet</string>"), "{}", poly);
+    assert!(!poly.contains("<string name=\"Name\">greeter</string>"), "{}", poly);
+    assert!(poly.contains("<string name=\"Name\">strings</string>"), "{}", poly);
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    assert!(lock.contains("[packages.greet]") && lock.contains("package = \"greeter\""), "{}", lock);
+    // The registry is asked about greeter, not greet.
+    cli.run(&game, &["verify"]).await?.ok();
+
+    // Pointing the alias somewhere else re-resolves it instead of failing the hash check.
+    fs::write(game.join("mosaic.toml"), toml + "greet = { package = \"strings\", version = \"0.1.0\" }\n")?;
+    cli.run(&game, &["install"]).await?.ok();
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    assert!(lock.contains("package = \"strings\""), "{}", lock);
+    Ok(())
+}
+
+#[tokio::test]
+async fn frozen_install_only_uses_the_lockfile() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    registry.serve(&["lock-status"]).await?;
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+
+    // No version lists or dist-tags: greeter's `latest` and strings' ^0.1 are answered by the lock.
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    fs::write(game.join("mosaic.toml"), toml.replace("greeter = \"0.2.0\"", "greeter = \"latest\""))?;
+    let seen = registry.requests().await.len();
+    cli.run(&game, &["install", "--frozen", "--no-cache"]).await?.ok();
+    let requests = registry.requests().await.split_off(seen);
+    assert!(
+        requests.iter().all(|r| !r.ends_with("/versions") && !r.contains("include_prereleases")),
+        "{:#?}",
+        requests
+    );
+
+    // A range the locked version doesn't satisfy: a normal install would re-resolve.
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    fs::write(game.join("mosaic.toml"), toml.replace("greeter = \"latest\"", "greeter = \"^0.1\""))?;
+    let run = cli.run(&game, &["install", "--frozen"]).await?.err();
+    assert!(output(&run).contains("mosaic.lock is out of sync with mosaic.toml: it has greeter@0.2.0, outside ^0.1"), "{:?}", run);
+    assert_eq!(fs::read_to_string(game.join("mosaic.lock"))?, lock);
+
+    fs::remove_file(game.join("mosaic.lock"))?;
+    let run = cli.run(&game, &["install", "--locked"]).await?.err();
+    assert!(output(&run).contains("there's no mosaic.lock"), "{:?}", run);
+    assert!(!game.join("mosaic.lock").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_migrates_a_mosaic_toml_from_before_schema() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    assert!(toml.starts_with("schema = 1\n"), "{}", toml);
+    let old = format!("# pinned for the jam\n{}", toml.replacen("schema = 1\n", "", 1));
+    fs::write(game.join("mosaic.toml"), &old)?;
+
+    // Reading it doesn't touch it.
+    cli.run(&game, &["list"]).await?.ok();
+    assert_eq!(fs::read_to_string(game.join("mosaic.toml"))?, old);
+
+    // Saving it stamps the schema on. Nothing else moved between 0 and 1, so there's no backup.
+    cli.run(&game, &["install", "greeter@0.1.0"]).await?.ok();
+    assert!(!game.join("mosaic.toml.schema0.bak").exists());
+    let migrated = fs::read_to_string(game.join("mosaic.toml"))?;
+    assert!(migrated.starts_with("schema = 1\n"), "{}", migrated);
+    assert!(migrated.contains("greeter = \"0.1.0\""), "{}", migrated);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_refuses_a_newer_schema() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    let future = toml.replacen("schema = 1", "schema = 99", 1) + "\n[workspace]\nmembers = [\"a\"]\n";
+    fs::write(game.join("mosaic.toml"), &future)?;
+
+    let run = cli.run(&game, &["install", "greeter"]).await?.err();
+    assert!(output(&run).contains("schema 99") && output(&run).contains("mosaic upgrade"), "{:?}", run);
+    // Left exactly as it was, and nothing was fetched on its behalf.
+    assert_eq!(fs::read_to_string(game.join("mosaic.toml"))?, future);
+    assert!(registry.requests().await.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn outdated_exit_code_fails_until_everything_is_latest() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    cli.run(&game, &["install", "greeter@^0.1"]).await?.ok();
+
+    // Informational by default.
+    let run = cli.run(&game, &["outdated"]).await?.ok();
+    assert!(run.stdout.contains("greeter") && run.stdout.contains("0.2.0"), "{:?}", run);
+
+    let run = cli.run(&game, &["outdated", "--exit-code"]).await?.err();
+    assert!(output(&run).contains("1 dependency is out of date"), "{:?}", run);
+
+    cli.run(&game, &["update", "--latest"]).await?.ok();
+    cli.run(&game, &["outdated", "--exit-code"]).await?.ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn init_from_poly_adopts_modules_that_match_a_release() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+    // A place file somebody has been maintaining by hand: strings as published, greeter tweaked.
+    let poly = fs::read_to_string(game.join("game.poly"))?.replace("hello =", "howdy =");
+    assert!(poly.contains("howdy"), "{}", poly);
+    let adopted = cli.project("adopted")?;
+    fs::write(adopted.join("game.poly"), &poly)?;
+
+    cli.run(&adopted, &["init", "--from-poly", "--yes"]).await?.ok();
+
+    let toml = fs::read_to_string(adopted.join("mosaic.toml"))?;
+    assert!(toml.contains("strings = \"0.1.0\"") && !toml.contains("greeter"), "{}", toml);
+    let lock = fs::read_to_string(adopted.join("mosaic.lock"))?;
+    assert!(lock.contains("[packages.strings]") && !lock.contains("greeter"), "{}", lock);
+    // The edited module is still the edited module.
+    assert!(fs::read_to_string(adopted.join("game.poly"))?.contains("howdy"));
+
+    let run = cli.run(&adopted, &["init", "--from-poly", "--yes"]).await?.err();
+    assert!(output(&run).contains("already a mosaic.toml"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn update_keeps_properties_set_on_the_module() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    cli.run(&game, &["install", "greeter@0.1.0"]).await?.ok();
+    // Somebody tagged the package's module in the editor.
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    let name = "<string name=\"Name\">greeter</string>";
+    let tagged = poly.replace(name, &format!("{}\n        <bool name=\"Enabled\">false</bool>", name));
+    fs::write(game.join("game.poly"), tagged)?;
+
+    cli.run(&game, &["install", "greeter@0.2.0"]).await?.ok();
+
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<bool name=\"Enabled\">false</bool>"), "{}", poly);
+    assert!(poly.contains("hello") && !poly.contains("&quot;hi&quot;"), "{}", poly);
+    assert_eq!(poly.matches(name).count(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn installed_modules_carry_their_version_for_doctor_and_verify() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    cli.run(&game, &["install", "greeter@0.1.0"]).await?.ok();
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<string name=\"MosaicVersion\">0.1.0</string>"), "{}", poly);
+    assert!(poly.contains("<string name=\"MosaicSha256\">"), "{}", poly);
+    cli.run(&game, &["doctor"]).await?.ok();
+
+    // A collaborator who never got mosaic.lock still finds out what's in the place.
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    fs::remove_file(game.join("mosaic.lock"))?;
+    let run = cli.run(&game, &["verify"]).await?.err();
+    assert!(output(&run).contains("not in mosaic.lock"), "{:?}", run);
+    fs::write(game.join("mosaic.lock"), lock)?;
+
+    // Editing the module by hand shows up too, and --fix puts the locked version back.
+    fs::write(game.join("game.poly"), poly.replace("&quot;hi&quot;", "&quot;yo&quot;"))?;
+    let run = cli.run(&game, &["doctor"]).await?.err();
+    assert!(output(&run).contains("edited since install"), "{:?}", run);
+    cli.run(&game, &["doctor", "--fix"]).await?.ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("&quot;hi&quot;"));
+    cli.run(&game, &["doctor"]).await?.ok();
+
+    cli.run(&game, &["install", "greeter@0.2.0"]).await?.ok();
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<string name=\"MosaicVersion\">0.2.0</string>"), "{}", poly);
+    assert_eq!(poly.matches("MosaicVersion").count(), 2, "{}", poly);
+    Ok(())
+}
+
+#[tokio::test]
+async fn doctor_fix_keeps_one_copy_of_a_duplicated_package() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    cli.run(&game, &["install", "greeter@0.1.0"]).await?.ok();
+    // Somebody pasted an old copy in next to the managed one.
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    let start = poly.find("<Item class=\"ModuleScript\">").expect("greeter module");
+    let end = poly[start..].find("</Item>").expect("end of module") + start + "</Item>".len();
+    let stale = poly[start..end].replace("\"hi\"", "\"yo\"").replace("&quot;hi&quot;", "&quot;yo&quot;");
+    fs::write(game.join("game.poly"), format!("{}\n    {}{}", &poly[..end], stale, &poly[end..]))?;
+    let copies = |game: &std::path::Path| -> anyhow::Result<usize> {
+        Ok(fs::read_to_string(game.join("game.poly"))?.matches("<string name=\"Name\">greeter</string>").count())
+    };
+    assert!(stale.contains("yo"), "{}", stale);
+    assert_eq!(copies(&game)?, 2);
+
+    // No terminal to ask, so install says so and leaves the choice to doctor.
+    let run = cli.run(&game, &["install"]).await?.ok();
+    assert!(output(&run).contains("2 ModuleScripts named greeter"), "{:?}", run);
+    assert_eq!(copies(&game)?, 2);
+
+    let run = cli.run(&game, &["doctor"]).await?.err();
+    assert!(output(&run).contains("mosaic doctor --fix"), "{:?}", run);
+
+    cli.run(&game, &["doctor", "--fix"]).await?.ok();
+    assert_eq!(copies(&game)?, 1);
+    cli.run(&game, &["doctor"]).await?.ok();
+    Ok(())
+}
+
+/// Runs git in `dir` with a throwaway identity and returns its stdout.
+fn git(dir: &std::path::Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = std::process::Command::new("git")
+        .args([
+            "-c",
+            "user.name=alice",
+            "-c",
+            "user.email=alice@example.com",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git {:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[tokio::test]
+async fn install_from_github_locks_the_commit() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(&[]).await?;
+    // A folder standing in for github.com, with alice/cool-lib in it.
+    let repo = cli.project("github/alice/cool-lib")?;
+    let github = format!(
+        "file://{}",
+        repo.parent()
+            .and_then(|p| p.parent())
+            .expect("github dir")
+            .display()
+    );
+    let env = [("MOSAIC_GITHUB_URL", github.as_str())];
+    git(&repo, &["init", "--quiet"])?;
+    git(&repo, &["config", "uploadpack.allowAnySHA1InWant", "true"])?;
+    fs::write(
+        repo.join("mosaic.toml"),
+        "[package]\nname = \"cool-lib\"\nversion = \"1.2.0\"\n\n[dependencies]\n",
+    )?;
+    fs::write(repo.join("init.lua"), "return { cool = true }\n")?;
+    git(&repo, &["add", "-A"])?;
+    git(&repo, &["commit", "--quiet", "-m", "v1.2.0"])?;
+    git(&repo, &["tag", "v1.2.0"])?;
+    let tagged = git(&repo, &["rev-parse", "HEAD"])?;
+
+    cli.run_with_env(&game, &["install", "github:alice/cool-lib@v1.2.0"], &env)
+        .await?
+        .ok();
+
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    assert!(
+        toml.contains("github = \"alice/cool-lib\"") && toml.contains("rev = \"v1.2.0\""),
+        "{}",
+        toml
+    );
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    assert!(
+        lock.contains(&format!(
+            "source = \"github+alice/cool-lib?rev=v1.2.0#{}\"",
+            tagged
+        )),
+        "{}",
+        lock
+    );
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("cool = true"));
+
+    // Somebody moves the tag. A plain install sticks to the locked commit...
+    fs::write(repo.join("init.lua"), "return { cool = false }\n")?;
+    git(&repo, &["commit", "--quiet", "-am", "oops"])?;
+    git(&repo, &["tag", "--force", "v1.2.0"])?;
+    cli.run_with_env(&game, &["install"], &env).await?.ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("cool = true"));
+    assert!(fs::read_to_string(game.join("mosaic.lock"))?.contains(&tagged));
+
+    // ...and asking for it again by name is how you take the new one.
+    cli.run_with_env(&game, &["install", "github:alice/cool-lib@v1.2.0"], &env)
+        .await?
+        .ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("cool = false"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn github_rev_cant_be_a_git_option() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(&[]).await?;
+    let run = cli
+        .run(&game, &["install", "github:alice/cool-lib@--upload-pack=./pwn"])
+        .await?
+        .err();
+    assert!(output(&run).contains("Invalid GitHub package"), "{:?}", run);
+
+    fs::write(
+        game.join("mosaic.toml"),
+        "[package]\nname = \"game\"\nversion = \"0.1.0\"\n\n[dependencies]\ncool-lib = { github = \"alice/cool-lib\", rev = \"--upload-pack=./pwn\" }\n",
+    )?;
+    let run = cli.run(&game, &["install"]).await?.err();
+    assert!(output(&run).contains("cool-lib's github"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn linked_package_is_reinjected_until_unlinked() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    cli.run(&game, &["install", "greeter@0.1.0"]).await?.ok();
+    let work = cli.project("greeter-work")?;
+    fs::write(work.join("mosaic.toml"), "[package]\nname = \"greeter\"\nversion = \"0.3.0\"\n\n[dependencies]\n")?;
+    fs::write(work.join("init.lua"), "return { work = 1 }\n")?;
+
+    let run = cli.run(&game, &["link", "greeter"]).await?.err();
+    assert!(output(&run).contains("mosaic link"), "{:?}", run);
+    cli.run(&work, &["link"]).await?.ok();
+    cli.run(&game, &["link", "greeter"]).await?.ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("work = 1"));
+    assert!(fs::read_to_string(game.join("mosaic.lock"))?.contains("source = \"link+"));
+    assert!(fs::read_to_string(game.join("mosaic.toml"))?.contains("greeter = \"0.1.0\""));
+
+    // Edits show up on the next install, no publish needed.
+    fs::write(work.join("init.lua"), "return { work = 2 }\n")?;
+    cli.run(&game, &["install"]).await?.ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("work = 2"));
+
+    cli.run(&game, &["unlink", "greeter"]).await?.ok();
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("&quot;hi&quot;") && !poly.contains("work = 2"), "{}", poly);
+    assert!(!fs::read_to_string(game.join("mosaic.lock"))?.contains("link+"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn exec_prints_the_entry_module_without_installing_it() -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    // The installer injects the first .lua in the zip, which isn't the first one by path.
+    let source = "-- loadstring is mentioned here, but not called\nreturn loadstring(\"return 1\")\n";
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zip.start_file("init.lua", zip::write::SimpleFileOptions::default())?;
+    zip.write_all(source.as_bytes())?;
+    zip.start_file("a.lua", zip::write::SimpleFileOptions::default())?;
+    zip.write_all(b"return 'not the entry'\n")?;
+    let blob = zip.finish()?.into_inner();
+    let blob_path = cli.project("blobs")?.join("greeter.zip");
+    fs::write(&blob_path, &blob)?;
+
+    let mut meta = Fixture::load("greeter@0.1.0")?;
+    if let Body::Json(json) = &mut meta.body {
+        json["sha256"] = serde_json::json!(format!("{:x}", sha2::Sha256::digest(&blob)));
+        json["size"] = serde_json::json!(blob.len());
+    }
+    registry.mount_over(meta).await?;
+    // An absolute path replaces the fixtures dir when joined onto it.
+    let download = Fixture::load("greeter@0.1.0.download")?;
+    registry
+        .mount_over(Fixture { body: Body::File(blob_path.display().to_string()), ..download })
+        .await?;
+
+    let run = cli.run(&game, &["exec", "greeter@0.1.0"]).await?.ok();
+    assert_eq!(run.stdout, source);
+    assert!(run.stderr.contains("init.lua (not run)"), "{:?}", run);
+    assert!(run.stderr.contains("line 2: uses loadstring"), "{:?}", run);
+    assert!(!run.stderr.contains("line 1"), "{:?}", run);
+
+    cli.run(&game, &["exec", "greeter@0.1.0", "--save", "greeter.lua"]).await?.ok();
+    assert_eq!(fs::read_to_string(game.join("greeter.lua"))?, source);
+    assert!(!fs::read_to_string(game.join("game.poly"))?.contains("greeter"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn dedupe_leaves_single_versions_alone() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    registry.serve(&["lock-status"]).await?;
+    cli.run(&game, &["install", "greeter@0.1.0"]).await?.ok();
+    // Any version would do, 0.2.0 included. Still only one greeter, so nothing to collapse.
+    let toml = fs::read_to_string(game.join("mosaic.toml"))?;
+    fs::write(game.join("mosaic.toml"), toml.replacen("greeter = \"0.1.0\"", "greeter = \"*\"", 1))?;
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+
+    let run = cli.run(&game, &["dedupe"]).await?.ok();
+    assert!(output(&run).contains("already deduplicated"), "{:?}", run);
+    assert_eq!(fs::read_to_string(game.join("mosaic.lock"))?, lock);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_missing_package() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(&["missing"]).await?;
+
+    let run = cli.run(&game, &["install", "missing"]).await?.err();
+    assert!(output(&run).contains("not found"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_waits_for_another_process_then_gives_up() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(PACKAGES).await?;
+    fs::create_dir_all(game.join(".mosaic"))?;
+    let lock = game.join(".mosaic/lock");
+
+    // Another mosaic, mid-install.
+    let held = fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&lock)?;
+    held.lock()?;
+    fs::write(&lock, r#"{"pid":4242,"command":"install","started":0}"#)?;
+    let run = cli.run_with_env(&game, &["install", "greeter"], &[("MOSAIC_LOCK_TIMEOUT", "1")]).await?.err();
+    assert!(output(&run).contains("Another mosaic process is running in this project (pid 4242"), "{:?}", run);
+    assert!(!fs::read_to_string(game.join("game.poly"))?.contains("greeter"));
+
+    // The same note with nobody holding the lock: left by a process that died. Taken over.
+    held.unlock()?;
+    drop(held);
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("greeter"));
+    assert_eq!(fs::read_to_string(&lock)?, "");
+    Ok(())
+}
+
+#[tokio::test]
+async fn zips_are_cached_across_projects() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    registry.serve(&["lock-status"]).await?;
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+    let downloads = |requests: &[String]| requests.iter().filter(|r| r.ends_with("/download")).count();
+    assert_eq!(downloads(&registry.requests().await), 2);
+
+    // Another project on the same machine: the zips come out of the cache.
+    let other = cli.project("other")?;
+    cli.run(&other, &["init"]).await?.ok();
+    fs::write(other.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&other, &["install", "greeter"]).await?.ok();
+    assert!(fs::read_to_string(other.join("game.poly"))?.contains("<string name=\"Name\">strings</string>"));
+    assert_eq!(downloads(&registry.requests().await), 2);
+
+    let ls = cli.run(&other, &["cache", "ls"]).await?.ok();
+    assert!(ls.stdout.contains("greeter@0.2.0") && ls.stdout.contains("strings@0.1.0"), "{}", ls.stdout);
+
+    // --no-cache downloads them again.
+    cli.run(&other, &["install", "--no-cache"]).await?.ok();
+    assert_eq!(downloads(&registry.requests().await), 4);
+
+    cli.run(&other, &["cache", "clean"]).await?.ok();
+    let ls = cli.run(&other, &["cache", "ls"]).await?.ok();
+    assert!(ls.stdout.contains("No cached packages"), "{}", ls.stdout);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cached_zips_still_get_the_size_check() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(PACKAGES).await?;
+    registry.serve(&["lock-status"]).await?;
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+
+    // Same hash, but the registry now disagrees about the size. The cached zip doesn't get a pass.
+    let mut meta = Fixture::load("greeter@0.2.0")?;
+    if let Body::Json(json) = &mut meta.body {
+        json["size"] = serde_json::json!(json["size"].as_u64().unwrap_or_default() + 1);
+    }
+    registry.mount_over(meta).await?;
+
+    let other = cli.project("other")?;
+    cli.run(&other, &["init"]).await?.ok();
+    fs::write(other.join("game.poly"), EMPTY_POLY)?;
+    let run = cli.run_with_env(&other, &["install", "greeter"], &[("MOSAIC_METADATA_TTL", "0")]).await?.err();
+    assert!(output(&run).contains("Size check failed"), "{:?}", run);
+    let downloads = registry.requests().await.iter().filter(|r| r.ends_with("greeter/0.2.0/download")).count();
+    assert_eq!(downloads, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_refuses_a_blob_that_doesnt_match_its_hash() -> anyhow::Result<()> {
+    let fixtures: Vec<&str> = PACKAGES
+        .iter()
+        .copied()
+        .filter(|f| *f != "greeter@0.2.0.download" && *f != "greeter@0.2.0")
+        .collect();
+    let (registry, cli, game) = setup(&fixtures).await?;
+    // No size to go by, so it's down to the hash.
+    let mut meta = Fixture::load("greeter@0.2.0")?;
+    if let Body::Json(json) = &mut meta.body {
+        json.as_object_mut().expect("version object").remove("size");
+    }
+    registry.mount(meta).await?;
+    // The right URL, somebody else's bytes.
+    let download = Fixture::load("greeter@0.2.0.download")?;
+    let other = Fixture::load("strings@0.1.0.download")?;
+    registry
+        .mount(Fixture { body: other.body, ..download })
+        .await?;
+
+    let run = cli.run(&game, &["install", "greeter"]).await?.err();
+    assert!(output(&run).contains("Integrity check failed"), "{:?}", run);
+    assert!(!fs::read_to_string(game.join("game.poly"))?.contains("greeter"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_resumes_a_download_that_ends_early() -> anyhow::Result<()> {
+    let fixtures: Vec<&str> = PACKAGES
+        .iter()
+        .copied()
+        .filter(|f| *f != "greeter@0.2.0.download" && *f != "greeter@0.2.0")
+        .chain(["greeter@0.2.0.integrity"])
+        .collect();
+    let (registry, cli, game) = setup(&fixtures).await?;
+    // Metadata without a size, so it has to come from the integrity endpoint. Without it the
+    // short response would only show up as a hash mismatch.
+    let mut meta = Fixture::load("greeter@0.2.0")?;
+    if let Body::Json(json) = &mut meta.body {
+        json.as_object_mut().expect("version object").remove("size");
+    }
+    registry.mount(meta).await?;
+    registry.mount_interrupted(Fixture::load("greeter@0.2.0.download")?, 40).await?;
+
+    let run = cli.run(&game, &["install", "greeter"]).await?.ok();
+    assert!(output(&run).contains("resuming"), "{:?}", run);
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("greeter"));
+    assert!(
+        registry.requests().await.contains(&"GET /packages/greeter/0.2.0/integrity".to_string()),
+        "{:?}",
+        registry.requests().await
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limited_search_says_when_to_retry() -> anyhow::Result<()> {
+    let (_registry, cli, game) = setup(&["search.rate-limited"]).await?;
+
+    let run = cli.run(&game, &["search", "greet"]).await?.err();
+    assert!(output(&run).contains("rate limiting you. Try again in"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limited_install_says_when_to_retry() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(&[]).await?;
+    // tower_governor answers every limited route the same way.
+    let limited = Fixture::load("search.rate-limited")?.at("GET", "/packages/greeter?include_prereleases=false");
+    registry.mount(limited).await?;
+
+    let run = cli.run(&game, &["install", "greeter"]).await?.err();
+    assert!(output(&run).contains("rate limiting you. Try again in"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_reports_a_body_that_isnt_json() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(&[]).await?;
+    let portal = Body::Text("<html><body>Please sign in to the Wi-Fi</body></html>".to_string());
+    registry
+        .mount(Fixture::new("GET", "/packages/greeter?include_prereleases=false", 200, portal))
+        .await?;
+
+    let run = cli.run(&game, &["install", "greeter"]).await?.err();
+    let out = output(&run);
+    assert!(out.contains("isn't JSON"), "{:?}", run);
+    assert!(out.contains("Please sign in to the Wi-Fi"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn install_reports_a_server_error() -> anyhow::Result<()> {
+    let (registry, cli, game) = setup(&[]).await?;
+    let down = Body::Text("upstream connect error".to_string());
+    registry
+        .mount(Fixture::new("GET", "/packages/greeter?include_prereleases=false", 503, down))
+        .await?;
+
+    let run = cli.run(&game, &["install", "greeter"]).await?.err();
+    assert!(output(&run).contains("503"), "{:?}", run);
+    Ok(())
+}
+
+/// A greeter project at `version`, logged in, ready to publish.
+async fn publisher(registry: &MockRegistry, version: &str) -> anyhow::Result<(Cli, std::path::PathBuf)> {
+    let cli = Cli::new(MOSAIC, &registry.url())?;
+    cli.login("fixtures", "not-checked-by-the-mock")?;
+    let dir = cli.project("greeter")?;
+    cli.run(&dir, &["init"]).await?.ok();
+    let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    fs::write(dir.join("mosaic.toml"), toml.replace("version = \"0.1.0\"", &format!("version = \"{}\"", version)))?;
+    fs::write(dir.join("init.lua"), "return {}\n")?;
+    Ok((cli, dir))
+}
+
+#[tokio::test]
+async fn pack_gives_the_same_bytes_for_the_same_source() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    let (cli, dir) = publisher(&registry, "0.3.0").await?;
+    fs::create_dir(dir.join("src"))?;
+    fs::write(dir.join("src/util.lua"), "return {}\n")?;
+    cli.run(&dir, &["pack"]).await?.ok();
+    let first = fs::read(dir.join(".mosaic/greeter-0.3.0.zip"))?;
+
+    // Packed later (zip times go in two-second steps), from a checkout with other permissions.
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir.join("src/util.lua"), fs::Permissions::from_mode(0o600))?;
+    }
+
+    // Twice, so the first --out zip is in the folder for the second.
+    for _ in 0..2 {
+        let run = cli.run(&dir, &["pack", "--out", "greeter.zip"]).await?.ok();
+        assert_eq!(fs::read(dir.join("greeter.zip"))?, first);
+        let sha256 = sha2::Sha256::digest(&first);
+        assert!(output(&run).contains(&format!("{:x}", sha256)), "{:?}", run);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_registers_then_uploads() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    registry.serve(&["publish.register", "publish.upload"]).await?;
+    let (cli, dir) = publisher(&registry, "0.3.0").await?;
+
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    let out = output(&run);
+    assert!(out.contains("Successfully published"), "{:?}", run);
+    assert!(out.contains(&format!("Publishing to {} as fixtures", registry.url())), "{:?}", run);
+
+    let requests = registry.requests().await;
+    let register = requests.iter().position(|r| r == "POST /packages/greeter/versions");
+    let upload = requests.iter().position(|r| r == "POST /packages/greeter/versions/0.3.0/upload");
+    assert!(register.is_some() && upload > register, "{:#?}", requests);
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_sends_aliases_under_their_package() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    registry.serve(&["publish.register", "publish.upload"]).await?;
+    let (cli, dir) = publisher(&registry, "0.3.0").await?;
+    let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    fs::write(
+        dir.join("mosaic.toml"),
+        toml.replace("[dependencies]", "[dependencies]\nstr = { package = \"strings\", version = \"^0.1\" }"),
+    )?;
+
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    assert!(output(&run).contains("str is an alias"), "{:?}", run);
+    let body = registry.body("POST /packages/greeter/versions").await.expect("registration body");
+    assert_eq!(body["dependencies"], serde_json::json!({ "strings": "^0.1" }));
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_refuses_github_dependencies() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    registry.serve(&["publish.register", "publish.upload"]).await?;
+    let (cli, dir) = publisher(&registry, "0.3.0").await?;
+    let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    fs::write(
+        dir.join("mosaic.toml"),
+        toml.replace("[dependencies]", "[dependencies]\ncool-lib = { github = \"alice/cool-lib\", rev = \"v1.2.0\" }"),
+    )?;
+
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.err();
+    assert!(output(&run).contains("GitHub dependencies (cool-lib)"), "{:?}", run);
+    assert!(registry.requests().await.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_without_a_terminal_needs_yes() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    registry.serve(&["publish.register", "publish.upload"]).await?;
+    let (cli, dir) = publisher(&registry, "0.3.0").await?;
+
+    let run = cli.run(&dir, &["publish"]).await?.err();
+    let out = output(&run);
+    assert!(out.contains("--yes"), "{:?}", run);
+    assert!(out.contains(&registry.url()), "{:?}", run);
+    assert!(registry.requests().await.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_an_existing_version_says_bump_it() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    registry.serve(&["publish.conflict", "publish.already-published"]).await?;
+    let (cli, dir) = publisher(&registry, "0.2.0").await?;
+
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.err();
+    assert!(output(&run).contains("already published. Bump the version"), "{:?}", run);
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_rate_limited() -> anyhow::Result<()> {
+    let registry = MockRegistry::start().await;
+    registry.serve(&["publish.rate-limited"]).await?;
+    let (cli, dir) = publisher(&registry, "0.3.0").await?;
+
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.err();
+    assert!(output(&run).contains("rate limiting you. Try again in"), "{:?}", run);
+    Ok(())
+}
+
+/// Rebuilds `test-support/fixtures/` from a real registry. Run it after changing what the
+/// registry sends back, then look over the diff.
+#[tokio::test]
+#[ignore = "re-records the fixtures; needs MOSAIC_TEST_DATABASE_URL"]
+async fn record_fixtures() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    // Publishing is limited per user, so strings gets an owner of its own.
+    let token = registry.signup("fixtures").await?;
+    let strings_token = registry.signup("fixtures-strings").await?;
+
+    for (name, version, source, deps) in [
+        ("strings", "0.1.0", "return { upper = string.upper }\n", ""),
+        ("greeter", "0.1.0", "return { hello = function() return \"hi\" end }\n", ""),
+        ("greeter", "0.2.0", "return { hello = function() return \"hello\" end }\n", "strings = \"^0.1\"\n"),
+    ] {
+        match name {
+            "strings" => cli.login("fixtures-strings", &strings_token)?,
+            _ => cli.login("fixtures", &token)?,
+        }
+        let dir = cli.project(&format!("{}-{}", name, version))?;
+        fs::write(
+            dir.join("mosaic.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\ndescription = \"Recorded for the CLI's contract tests\"\nlicense = \"MIT\"\n\n[dependencies]\n{}",
+                name, version, deps
+            ),
+        )?;
+        fs::write(dir.join("init.lua"), source)?;
+        cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    }
+
+    let base = registry.url.as_str();
+    let get = async |name: &str, path: &str, file: Option<&str>| -> anyhow::Result<Fixture> {
+        let fixture = Fixture::record(base, "GET", path, |req| req, file).await?;
+        fixture.save(name)?;
+        Ok(fixture)
+    };
+
+    for name in ["greeter", "strings"] {
+        get(&format!("{}.stable", name), &format!("/packages/{}?include_prereleases=false", name), None).await?;
+        get(name, &format!("/packages/{}", name), None).await?;
+        get(&format!("{}.versions", name), &format!("/packages/{}/versions", name), None).await?;
+    }
+    for (name, version) in [("greeter", "0.1.0"), ("greeter", "0.2.0"), ("strings", "0.1.0")] {
+        let meta = get(&format!("{}@{}", name, version), &format!("/packages/{}/versions/{}", name, version), None).await?;
+        let download = meta.json()["download_url"].as_str().expect("download_url").to_string();
+        let file = format!("blobs/{}-{}.zip", name, version);
+        get(&format!("{}@{}.download", name, version), &download, Some(&file)).await?;
+        get(
+            &format!("{}@{}.integrity", name, version),
+            &format!("/packages/{}/{}/integrity", name, version),
+            None,
+        )
+        .await?;
+    }
+    get("missing", "/packages/missing", None).await?;
+
+    let lock = serde_json::json!({ "packages": { "greeter": "0.2.0", "strings": "0.1.0" } });
+    Fixture::record(base, "POST", "/packages/lock-status", |req| req.json(&lock), None)
+        .await?
+        .save("lock-status")?;
+
+    // Both phases of a publish, done by hand so each response can be kept.
+    let auth = format!("Bearer {}", token);
+    let register = serde_json::json!({ "version": "0.3.0", "lua_source_url": "tbd", "dependencies": {}, "license": "MIT" });
+    let registered = Fixture::record(
+        base,
+        "POST",
+        "/packages/greeter/versions",
+        |req| req.header("Authorization", &auth).json(&register),
+        None,
+    )
+    .await?;
+    registered.save("publish.register")?;
+    let upload_token = registered.json()["upload_token"].as_str().expect("upload_token").to_string();
+    let blob = fs::read(format!("{}/blobs/greeter-0.1.0.zip", test_support::mock::FIXTURES))?;
+    let sha = format!("{:x}", <sha2::Sha256 as sha2::Digest>::digest(&blob));
+    Fixture::record(
+        base,
+        "POST",
+        "/packages/greeter/versions/0.3.0/upload",
+        |req| {
+            req.header("Authorization", &auth)
+                .header("X-Upload-Token", upload_token)
+                .header("X-Content-Sha256", sha)
+                .body(blob)
+        },
+        None,
+    )
+    .await?
+    .save("publish.upload")?;
+
+    // And again for a version that's already out.
+    let register = serde_json::json!({ "version": "0.2.0", "lua_source_url": "tbd", "dependencies": {} });
+    Fixture::record(
+        base,
+        "POST",
+        "/packages/greeter/versions",
+        |req| req.header("Authorization", &auth).json(&register),
+        None,
+    )
+    .await?
+    .save("publish.conflict")?;
+    Fixture::record(
+        base,
+        "POST",
+        "/packages/greeter/versions/0.2.0/upload-session",
+        |req| req.header("Authorization", &auth).json(&serde_json::json!({})),
+        None,
+    )
+    .await?
+    .save("publish.already-published")?;
+
+    // Then keep registering until the per-user limit kicks in.
+    for patch in 0..20 {
+        let register = serde_json::json!({ "version": format!("0.4.{}", patch), "lua_source_url": "tbd", "dependencies": {} });
+        let fixture = Fixture::record(
+            base,
+            "POST",
+            "/packages/greeter/versions",
+            |req| req.header("Authorization", &auth).json(&register),
+            None,
+        )
+        .await?;
+        if fixture.status == 429 {
+            fixture.save("publish.rate-limited")?;
+            break;
+        }
+    }
+
+    // Search allows a burst of 60 per IP. Keep asking until it says no.
+    let path = "/packages/search?q=greet";
+    get("search", path, None).await?;
+    for _ in 0..100 {
+        let fixture = Fixture::record(base, "GET", path, |req| req, None).await?;
+        if fixture.status == 429 {
+            fixture.save("search.rate-limited")?;
+            break;
+        }
+    }
+
+    registry.stop().await
+}
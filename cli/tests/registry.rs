@@ -0,0 +1,884 @@
+//! The CLI against a real registry: publish on one side, install on the other.
+//! Needs MOSAIC_TEST_DATABASE_URL (see test-support); skips without it.
+
+use std::fs;
+use test_support::{ADMIN_TOKEN, Cli, EMPTY_POLY, TestRegistry};
+
+const MOSAIC: &str = env!("CARGO_BIN_EXE_mosaic");
+
+/// Publishes a one-file package from a fresh project called `name`.
+async fn publish(cli: &Cli, name: &str, source: &str, dependencies: &[(&str, &str)]) -> anyhow::Result<()> {
+    let dir = cli.project(name)?;
+    cli.run(&dir, &["init"]).await?.ok();
+    // init leaves an empty [dependencies] at the end.
+    let mut toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    for (dep, range) in dependencies {
+        toml.push_str(&format!("{} = \"{}\"\n", dep, range));
+    }
+    fs::write(dir.join("mosaic.toml"), toml)?;
+    fs::write(dir.join("init.lua"), source)?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn publish_install_remove() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("alice", &registry.signup("alice").await?)?;
+
+    publish(&cli, "greeter", "return { hello = function() return \"hi\" end }\n", &[]).await?;
+
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<string name=\"Name\">greeter</string>"), "{}", poly);
+    assert!(poly.contains("hello = function()"), "{}", poly);
+    assert!(fs::read_to_string(game.join("mosaic.toml"))?.contains("greeter = \"0.1.0\""));
+    let lock = fs::read_to_string(game.join("mosaic.lock"))?;
+    assert!(lock.contains("greeter"), "{}", lock);
+
+    // Same blob on a second install, so the lockfile's hash has to match what's served.
+    cli.run(&game, &["install"]).await?.ok();
+
+    cli.run(&game, &["remove", "greeter"]).await?.ok();
+    assert!(!fs::read_to_string(game.join("game.poly"))?.contains("greeter"));
+    assert!(!fs::read_to_string(game.join("mosaic.toml"))?.contains("greeter"));
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn install_pulls_in_dependencies() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("bob", &registry.signup("bob").await?)?;
+
+    publish(&cli, "strings", "return { upper = string.upper }\n", &[]).await?;
+    publish(&cli, "shout", "return function(s) return s .. \"!\" end\n", &[("strings", "^0.1")]).await?;
+
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["install", "shout"]).await?.ok();
+
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("<string name=\"Name\">shout</string>"), "{}", poly);
+    assert!(poly.contains("<string name=\"Name\">strings</string>"), "{}", poly);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn publish_needs_login_and_a_new_version() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+
+    let dir = cli.project("lonely")?;
+    cli.run(&dir, &["init"]).await?.ok();
+    fs::write(dir.join("init.lua"), "return {}\n")?;
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.err();
+    assert!(run.stderr.contains("login") || run.stdout.contains("login"), "{:?}", run);
+
+    cli.login("carol", &registry.signup("carol").await?)?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    // Published versions are immutable.
+    cli.run(&dir, &["publish", "--yes"]).await?.err();
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn protected_package_holds_new_versions() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("dave", &registry.signup("dave").await?)?;
+
+    let dir = cli.project("vault")?;
+    cli.run(&dir, &["init"]).await?.ok();
+    fs::write(dir.join("init.lua"), "return { locked = true }\n")?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    cli.run(&dir, &["protect", "vault"]).await?.ok();
+
+    let bump = |from: &str, to: &str| -> anyhow::Result<()> {
+        let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+        let (from, to) = (format!("version = \"{}\"", from), format!("version = \"{}\"", to));
+        fs::write(dir.join("mosaic.toml"), toml.replacen(&from, &to, 1))?;
+        Ok(())
+    };
+    bump("0.1.0", "0.2.0")?;
+    let run = cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    assert!(run.stderr.contains("held for approval") || run.stdout.contains("held for approval"), "{:?}", run);
+
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["install", "vault@0.2.0"]).await?.err();
+    cli.run(&game, &["install", "vault"]).await?.ok();
+    assert!(fs::read_to_string(game.join("mosaic.toml"))?.contains("vault = \"0.1.0\""));
+
+    // The real link can't be approved for an hour.
+    cli.run(&dir, &["approve", "vault@0.2.0", "--token", "guess"]).await?.err();
+    let token = registry.approval_token("vault", "0.2.0").await?;
+    cli.run(&dir, &["approve", "vault@0.2.0", "--token", &token]).await?.ok();
+    cli.run(&game, &["install", "vault@0.2.0"]).await?.ok();
+
+    bump("0.2.0", "0.3.0")?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    cli.run(&dir, &["reject", "vault@0.3.0"]).await?.ok();
+    cli.run(&game, &["install", "vault@0.3.0"]).await?.err();
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn second_session_cant_redirect_the_approval_link() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let hooks = format!("{}/auth/me/webhooks", registry.url);
+    let client = reqwest::Client::new();
+    let owner_token = registry.signup("erin").await?;
+    let owner = Cli::new(MOSAIC, &registry.url)?;
+    owner.login("erin", &owner_token)?;
+    let dir = owner.project("vault")?;
+    owner.run(&dir, &["init"]).await?.ok();
+    fs::write(dir.join("init.lua"), "return {}\n")?;
+    owner.run(&dir, &["publish", "--yes"]).await?.ok();
+    let res = client
+        .post(&hooks)
+        .bearer_auth(&owner_token)
+        .json(&serde_json::json!({ "url": "https://owner.example/hook" }))
+        .send()
+        .await?;
+    let owner_hook: serde_json::Value = res.json().await?;
+    owner.run(&dir, &["protect", "vault"]).await?.ok();
+
+    // Someone else with the same login: their hook waits out the delay, and the owner's stays.
+    let thief_token = registry.login("erin").await?;
+    let res = client
+        .post(&hooks)
+        .bearer_auth(&thief_token)
+        .json(&serde_json::json!({ "url": "https://thief.example/hook" }))
+        .send()
+        .await?;
+    assert_eq!(res.status(), 201);
+    let thief_hook: serde_json::Value = res.json().await?;
+    assert!(thief_hook["active_at"].as_i64() > owner_hook["active_at"].as_i64(), "{}", thief_hook);
+    let res = client
+        .delete(format!("{}/{}", hooks, owner_hook["id"].as_str().unwrap_or_default()))
+        .bearer_auth(&thief_token)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 202);
+    assert_eq!(registry.webhook_recipients("erin").await?, ["https://owner.example/hook"]);
+
+    // So the held version's token only goes to the owner, and guessing it doesn't work.
+    let thief = Cli::new(MOSAIC, &registry.url)?;
+    thief.login("erin", &thief_token)?;
+    let copy = thief.project("vault")?;
+    fs::write(copy.join("mosaic.toml"), fs::read_to_string(dir.join("mosaic.toml"))?.replacen("0.1.0", "0.2.0", 1))?;
+    fs::write(copy.join("init.lua"), "return { stolen = true }\n")?;
+    thief.run(&copy, &["publish", "--yes"]).await?.ok();
+    thief.run(&copy, &["approve", "vault@0.2.0", "--token", "guess"]).await?.err();
+
+    // A hook that isn't live yet can be dropped straight away: it never got anything.
+    let res = client
+        .delete(format!("{}/{}", hooks, thief_hook["id"].as_str().unwrap_or_default()))
+        .bearer_auth(&owner_token)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 200);
+    owner.run(&dir, &["reject", "vault@0.2.0"]).await?.ok();
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn webhooks_cant_point_inside_the_network() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let token = registry.signup("ivan").await?;
+    let client = reqwest::Client::new();
+    let create = async |url: &str| -> anyhow::Result<reqwest::StatusCode> {
+        let res = client
+            .post(format!("{}/auth/me/webhooks", registry.url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({ "url": url }))
+            .send()
+            .await?;
+        Ok(res.status())
+    };
+
+    for url in [
+        "http://hooks.example.com/mosaic",
+        "https://localhost/hook",
+        "https://api.localhost./hook",
+        "https://127.0.0.1/hook",
+        "https://10.1.2.3/hook",
+        "https://192.168.0.10:8443/hook",
+        "https://169.254.169.254/latest/meta-data",
+        "https://[::1]/hook",
+        "https://[::ffff:127.0.0.1]/hook",
+        "https://[fd00::1]/hook",
+    ] {
+        assert_eq!(create(url).await?, 400, "{}", url);
+    }
+    assert_eq!(create("https://hooks.example.com/mosaic").await?, 201);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn integrity_repair_keeps_held_versions() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let token = registry.signup("gina").await?;
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("gina", &token)?;
+    let dir = cli.project("vault")?;
+    cli.run(&dir, &["init"]).await?.ok();
+    fs::write(dir.join("init.lua"), "return {}\n")?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+    cli.run(&dir, &["protect", "vault"]).await?.ok();
+    let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    fs::write(dir.join("mosaic.toml"), toml.replacen("0.1.0", "0.2.0", 1))?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+
+    // A registration whose upload never came, next to the held version.
+    let client = reqwest::Client::new();
+    let res = client
+        .post(format!("{}/packages/vault/versions", registry.url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "version": "0.3.0", "lua_source_url": "tbd", "dependencies": {} }))
+        .send()
+        .await?;
+    assert!(res.status().is_success(), "{}", res.text().await?);
+    registry.backdate("vault", "0.2.0", 2 * 60 * 60).await?;
+    registry.backdate("vault", "0.3.0", 2 * 60 * 60).await?;
+
+    let res = client
+        .post(format!("{}/admin/jobs/integrity?repair=true", registry.url))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await?;
+    let report: serde_json::Value = res.json().await?;
+    assert_eq!(report["incomplete_versions"], serde_json::json!(["vault@0.3.0"]), "{}", report);
+
+    // Only the abandoned registration went. The held version can still be approved.
+    let approval = registry.approval_token("vault", "0.2.0").await?;
+    cli.run(&dir, &["approve", "vault@0.2.0", "--token", &approval]).await?.ok();
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn package_token_only_publishes_its_packages() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("erin", &registry.signup("erin").await?)?;
+
+    publish(&cli, "ui-lib", "return {}\n", &[]).await?;
+
+    let home = cli.project("home")?;
+    let run = cli.run(&home, &["token", "create", "ui-lib CI", "--package", "ui-lib"]).await?.ok();
+    let token = run.stdout.lines().last().unwrap_or_default().trim().to_string();
+    let env = [("MOSAIC_TOKEN", token.as_str())];
+
+    let toml = cli.project("ui-lib")?.join("mosaic.toml");
+    fs::write(&toml, fs::read_to_string(&toml)?.replacen("version = \"0.1.0\"", "version = \"0.2.0\"", 1))?;
+    cli.run_with_env(&cli.project("ui-lib")?, &["publish", "--yes"], &env).await?.ok();
+
+    let logger = cli.project("logger")?;
+    cli.run(&logger, &["init"]).await?.ok();
+    fs::write(logger.join("init.lua"), "return {}\n")?;
+    let run = cli.run_with_env(&logger, &["publish", "--yes"], &env).await?.err();
+    assert!(run.stderr.contains("isn't allowed to publish logger"), "{:?}", run);
+    // Nor can it mint itself a wider token.
+    let run = cli.run_with_env(&home, &["token", "create", "escape"], &env).await?.err();
+    assert!(run.stderr.contains("restricted"), "{:?}", run);
+
+    let list = cli.run(&home, &["token", "list"]).await?.ok();
+    assert!(list.stdout.contains("ui-lib CI"), "{}", list.stdout);
+    let id = list
+        .stdout
+        .split(|c: char| !(c.is_ascii_hexdigit() || c == '-'))
+        .find(|word| word.len() == 36)
+        .expect("token id in the list")
+        .to_string();
+    cli.run(&home, &["token", "revoke", &id]).await?.ok();
+
+    let toml = cli.project("ui-lib")?.join("mosaic.toml");
+    fs::write(&toml, fs::read_to_string(&toml)?.replacen("version = \"0.2.0\"", "version = \"0.3.0\"", 1))?;
+    let run = cli.run_with_env(&cli.project("ui-lib")?, &["publish", "--yes"], &env).await?.err();
+    assert!(run.stderr.contains("rejected your credentials"), "{:?}", run);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn unpublish_removes_a_version_nobody_depends_on() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("frank", &registry.signup("frank").await?)?;
+
+    publish(&cli, "base", "return {}\n", &[]).await?;
+    publish(&cli, "leaf", "return {}\n", &[("base", "^0.1")]).await?;
+    let home = cli.project("home")?;
+
+    // Without a terminal it won't guess.
+    cli.run(&home, &["unpublish", "leaf@0.1.0"]).await?.err();
+
+    let run = cli.run(&home, &["unpublish", "base@0.1.0", "--yes"]).await?.err();
+    assert!(run.stderr.contains("depend on it") && run.stderr.contains("leaf@0.1.0"), "{:?}", run);
+
+    let run = cli.run(&home, &["unpublish", "leaf@9.9.9", "--yes"]).await?.err();
+    assert!(run.stderr.contains("isn't on"), "{:?}", run);
+
+    cli.run(&home, &["unpublish", "leaf@0.1.0", "--yes"]).await?.ok();
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["install", "leaf"]).await?.err();
+    // With its only dependent gone, base can go too.
+    cli.run(&home, &["unpublish", "base@0.1.0", "--yes"]).await?.ok();
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn private_package_is_only_visible_to_its_team() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let owner = Cli::new(MOSAIC, &registry.url)?;
+    owner.login("grace", &registry.signup("grace").await?)?;
+    let teammate = Cli::new(MOSAIC, &registry.url)?;
+    teammate.login("heidi", &registry.signup("heidi").await?)?;
+
+    let kit = owner.project("studio-kit")?;
+    owner.run(&kit, &["init"]).await?.ok();
+    let toml = fs::read_to_string(kit.join("mosaic.toml"))?;
+    fs::write(kit.join("mosaic.toml"), toml.replacen("version = \"0.1.0\"", "version = \"0.1.0\"\nprivate = true", 1))?;
+    fs::write(kit.join("init.lua"), "return { internal = true }\n")?;
+    owner.run(&kit, &["publish", "--yes"]).await?.ok();
+
+    let game = teammate.project("game")?;
+    teammate.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    teammate.run(&game, &["install", "studio-kit"]).await?.err();
+    let search = teammate.run(&game, &["search", "studio"]).await?.ok();
+    assert!(!search.stdout.contains("studio-kit"), "{}", search.stdout);
+
+    let run = owner.run(&kit, &["members", "studio-kit", "--add", "heidi"]).await?.ok();
+    assert!(run.stdout.contains("heidi"), "{:?}", run);
+    teammate.run(&game, &["install", "studio-kit"]).await?.ok();
+    assert!(fs::read_to_string(game.join("game.poly"))?.contains("internal = true"));
+
+    // Off the team, and the blob goes with the metadata.
+    owner.run(&kit, &["members", "studio-kit", "--remove", "heidi"]).await?.ok();
+    fs::remove_file(game.join("mosaic.lock"))?;
+    teammate.run(&game, &["install", "--no-cache"]).await?.err();
+
+    owner.run(&kit, &["visibility", "studio-kit", "public"]).await?.ok();
+    teammate.run(&game, &["install"]).await?.ok();
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn stars_are_counted_and_only_watchers_get_new_versions() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    let owner_token = registry.signup("judy").await?;
+    cli.login("judy", &owner_token)?;
+    publish(&cli, "lantern", "return {}\n", &[]).await?;
+
+    let client = reqwest::Client::new();
+    let star = format!("{}/packages/lantern/star", registry.url);
+    let watcher = registry.signup("mallory").await?;
+    let fan = registry.signup("niaj").await?;
+    let res = client.put(&star).bearer_auth(&watcher).json(&serde_json::json!({ "notify": true })).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(client.put(&star).bearer_auth(&fan).send().await?.status(), 200);
+
+    let package: serde_json::Value = client.get(format!("{}/packages/lantern", registry.url)).send().await?.json().await?;
+    assert_eq!(package["stars"], 2, "{}", package);
+    let listed: Vec<serde_json::Value> = client.get(format!("{}/packages", registry.url)).send().await?.json().await?;
+    assert!(listed.iter().any(|p| p["name"] == "lantern" && p["stars"] == 2), "{:?}", listed);
+
+    // Only the star that asked for it gets new versions, and only while it can see the package.
+    assert_eq!(registry.watchers("lantern").await?, ["mallory"]);
+    cli.run(&cli.project("lantern")?, &["visibility", "lantern", "private"]).await?.ok();
+    assert!(registry.watchers("lantern").await?.is_empty());
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn private_download_urls_are_signed_for_the_caller() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let token = registry.signup("ivan").await?;
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("ivan", &token)?;
+    publish(&cli, "secret-sauce", "return {}\n", &[]).await?;
+    cli.run(&cli.project("secret-sauce")?, &["visibility", "secret-sauce", "private"]).await?.ok();
+
+    let http = reqwest::Client::new();
+    let version: serde_json::Value = http
+        .get(format!("{}/packages/secret-sauce/versions/0.1.0", registry.url))
+        .bearer_auth(&token)
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(version["mirror_urls"], serde_json::json!([]), "{}", version);
+    let signed = version["download_url"].as_str().unwrap_or_default().to_string();
+    assert!(signed.contains("sig="), "{}", signed);
+
+    // The signed link works without a login; the plain one and a tampered one don't.
+    let get = |path: String| http.get(format!("{}{}", registry.url, path)).send();
+    assert_eq!(get(signed.clone()).await?.status(), 200);
+    assert_eq!(get("/packages/secret-sauce/0.1.0/download".into()).await?.status(), 404);
+    let tampered = signed.replace("expires=", "expires=9");
+    assert_eq!(get(tampered).await?.status(), 404);
+    // It only opens the download, not the rest of the package.
+    let query = signed.split_once('?').map(|(_, q)| q).unwrap_or_default();
+    assert_eq!(get(format!("/packages/secret-sauce?{}", query)).await?.status(), 404);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn publish_uses_the_manifest_metadata_and_entry() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("judy", &registry.signup("judy").await?)?;
+
+    let dir = cli.project("tweens")?;
+    cli.run(&dir, &["init", "--yes"]).await?.ok();
+    let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    let metadata = "version = \"0.1.0\"\ndescription = \"Easing curves\"\nrepository = \"https://example.com/tweens\"\nentry = \"src/main.lua\"";
+    fs::write(dir.join("mosaic.toml"), toml.replacen("version = \"0.1.0\"", metadata, 1))?;
+    // Sorts before src/main.lua, so without `entry` this is what would get injected.
+    fs::write(dir.join("helpers.lua"), "return { helper = true }\n")?;
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(dir.join("src/main.lua"), "return { easing = true }\n")?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    let info = cli.run(&game, &["info", "tweens"]).await?.ok();
+    assert!(info.stdout.contains("https://example.com/tweens"), "{}", info.stdout);
+    let search = cli.run(&game, &["search", "tweens"]).await?.ok();
+    assert!(search.stdout.contains("Easing curves"), "{}", search.stdout);
+    cli.run(&game, &["install", "tweens"]).await?.ok();
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("easing = true") && !poly.contains("helper = true"), "{}", poly);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn history_records_what_each_run_changed() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("kate", &registry.signup("kate").await?)?;
+
+    publish(&cli, "greeter", "return { hello = true }\n", &[]).await?;
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["install", "greeter"]).await?.ok();
+
+    let greeter = cli.project("greeter")?;
+    let toml = fs::read_to_string(greeter.join("mosaic.toml"))?;
+    fs::write(greeter.join("mosaic.toml"), toml.replacen("0.1.0", "0.2.0", 1))?;
+    cli.run(&greeter, &["publish", "--yes"]).await?.ok();
+    cli.run(&game, &["update", "--latest", "--no-cache"]).await?.ok();
+    cli.run(&game, &["install", "nosuch"]).await?.err();
+    cli.run(&game, &["remove", "greeter"]).await?.ok();
+
+    let history: serde_json::Value = serde_json::from_str(&fs::read_to_string(game.join(".mosaic/history.json"))?)?;
+    let commands: Vec<&str> = history.as_array().unwrap().iter().map(|e| e["command"].as_str().unwrap()).collect();
+    assert_eq!(commands, ["install greeter", "update --latest", "install nosuch", "remove greeter"]);
+    assert_eq!(history[2]["ok"], false);
+
+    let all = cli.run(&game, &["history"]).await?.ok();
+    assert!(all.stdout.contains("greeter 0.1.0 -> 0.2.0"), "{}", all.stdout);
+    assert!(all.stdout.contains("failed"), "{}", all.stdout);
+    let greeter_only = cli.run(&game, &["history", "greeter"]).await?.ok();
+    assert!(greeter_only.stdout.contains("-greeter 0.2.0"), "{}", greeter_only.stdout);
+    assert!(!greeter_only.stdout.contains("nosuch"), "{}", greeter_only.stdout);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn library_template_publishes_its_entry_point() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("liam", &registry.signup("liam").await?)?;
+
+    let dir = cli.project("text-utils")?;
+    fs::write(dir.join("README.md"), "# Mine\n")?;
+    cli.run(&dir, &["init", "--template", "library", "--yes"]).await?.ok();
+    assert!(fs::read_to_string(dir.join("mosaic.toml"))?.contains("entry = \"src/init.lua\""));
+    assert!(fs::read_to_string(dir.join("src/init.lua"))?.contains("function text_utils.greet"));
+    assert!(dir.join(".mosaicignore").exists());
+    // Already there, so left alone.
+    assert_eq!(fs::read_to_string(dir.join("README.md"))?, "# Mine\n");
+    fs::write(dir.join("place.poly"), EMPTY_POLY)?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["install", "text-utils"]).await?.ok();
+    let poly = fs::read_to_string(game.join("game.poly"))?;
+    assert!(poly.contains("function text_utils.greet"), "{}", poly);
+
+    cli.run(&game, &["init", "--template", "nope"]).await?.err();
+
+    registry.stop().await
+}
+
+const ADMIN: [(&str, &str); 1] = [("MOSAIC_ADMIN_TOKEN", ADMIN_TOKEN)];
+
+/// Moves `registry` into a fresh one with `mosaic admin export` and `import`, and stops the
+/// original. Returns the copy and a CLI pointed at it.
+async fn round_trip(cli: &Cli, registry: TestRegistry) -> anyhow::Result<(TestRegistry, Cli)> {
+    let dir = cli.project("admin")?;
+    let dump = dir.join("dump.ndjson");
+    let dump = dump.to_str().expect("utf-8 temp path");
+    cli.run_with_env(&dir, &["admin", "export", dump], &ADMIN).await?.ok();
+    registry.stop().await?;
+
+    let copy = TestRegistry::start().await?.expect("Postgres went away mid-test");
+    let copy_cli = Cli::new(MOSAIC, &copy.url)?;
+    copy_cli.run_with_env(&dir, &["admin", "import", dump], &ADMIN).await?.ok();
+    Ok((copy, copy_cli))
+}
+
+#[tokio::test]
+async fn export_carries_the_name_rules_over() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    registry.signup("acme").await?;
+    let dir = cli.project("admin")?;
+    cli.run_with_env(&dir, &["admin", "names", "add", "approved", "polytoria-acme", "--user", "acme"], &ADMIN)
+        .await?
+        .ok();
+    cli.run_with_env(&dir, &["admin", "names", "remove", "block", "undefined"], &ADMIN).await?.ok();
+
+    let (copy, cli) = round_trip(&cli, registry).await?;
+    let run = cli.run_with_env(&dir, &["admin", "names", "list"], &ADMIN).await?.ok();
+    assert!(run.stdout.contains("polytoria-acme") && run.stdout.contains("acme"), "{:?}", run);
+    // The copy seeded its own defaults, but the dump's list wins.
+    assert!(!run.stdout.contains("undefined"), "{:?}", run);
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn export_keeps_old_usernames_redirecting() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    let token = registry.signup("dora").await?;
+    let client = reqwest::Client::new();
+    let res = client
+        .put(format!("{}/auth/username", registry.url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "username": "dora-renamed", "password": test_support::PASSWORD }))
+        .send()
+        .await?;
+    assert!(res.status().is_success(), "{}", res.text().await?);
+
+    let (copy, _) = round_trip(&cli, registry).await?;
+    let user: serde_json::Value = client
+        .get(format!("{}/auth/users/dora", copy.url))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(user["username"], "dora-renamed", "{}", user);
+    // Still nobody else's to take.
+    assert!(copy.signup("dora").await.is_err());
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn export_keeps_name_claims_open() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("fay", &registry.signup("fay").await?)?;
+    let dir = cli.project("admin")?;
+    for name in ["acme-maps", "acme-tools"] {
+        cli.run_with_env(&dir, &["admin", "names", "add", "reserved", name], &ADMIN).await?.ok();
+    }
+    cli.run(&dir, &["claim", "acme-maps", "--reason", "I maintain the Acme map tools"]).await?.ok();
+
+    let (copy, cli) = round_trip(&cli, registry).await?;
+    let run = cli.run_with_env(&dir, &["admin", "claims", "list"], &ADMIN).await?.ok();
+    assert!(run.stdout.contains("acme-maps by fay"), "{:?}", run);
+
+    // Claims filed after the move get ids of their own.
+    cli.login("gus", &copy.signup("gus").await?)?;
+    cli.run(&dir, &["claim", "acme-tools", "--reason", "I maintain the Acme dev tools"]).await?.ok();
+    let run = cli.run_with_env(&dir, &["admin", "claims", "list"], &ADMIN).await?.ok();
+    assert!(run.stdout.contains("#2 ") && run.stdout.contains("acme-tools by gus"), "{:?}", run);
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn export_keeps_the_download_history() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("hana", &registry.signup("hana").await?)?;
+    publish(&cli, "counter", "return {}\n", &[]).await?;
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["install", "counter"]).await?.ok();
+
+    // Repair rebuilds the counter from the daily series, so the series has to come along.
+    let (copy, cli) = round_trip(&cli, registry).await?;
+    cli.run_with_env(&game, &["admin", "check", "--repair"], &ADMIN).await?.ok();
+    let package: serde_json::Value = reqwest::get(format!("{}/packages/counter", copy.url)).await?.json().await?;
+    assert_eq!(package["download_count"], 1, "{}", package);
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn export_keeps_logged_out_tokens_dead() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    let token = registry.signup("ivy").await?;
+    let client = reqwest::Client::new();
+    let res = client.post(format!("{}/auth/logout", registry.url)).bearer_auth(&token).send().await?;
+    assert!(res.status().is_success(), "{}", res.text().await?);
+
+    // Same JWT_SECRET on both sides, so only the revocation keeps the old token out.
+    let (copy, _) = round_trip(&cli, registry).await?;
+    let res = client.get(format!("{}/auth/me/tokens", copy.url)).bearer_auth(&token).send().await?;
+    assert_eq!(res.status(), 401);
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn export_keeps_api_tokens_revocable() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    let login = registry.signup("kai").await?;
+    cli.login("kai", &login)?;
+    let home = cli.project("home")?;
+    let run = cli.run(&home, &["token", "create", "kai CI"]).await?.ok();
+    let token = run.stdout.lines().last().unwrap_or_default().trim().to_string();
+
+    let (copy, cli) = round_trip(&cli, registry).await?;
+    cli.login("kai", &login)?;
+    let list = cli.run(&home, &["token", "list"]).await?.ok();
+    assert!(list.stdout.contains("kai CI"), "{}", list.stdout);
+    let id = list
+        .stdout
+        .split(|c: char| !(c.is_ascii_hexdigit() || c == '-'))
+        .find(|word| word.len() == 36)
+        .expect("token id in the list")
+        .to_string();
+    cli.run(&home, &["token", "revoke", &id]).await?.ok();
+
+    let res = reqwest::Client::new()
+        .get(format!("{}/auth/me/tokens", copy.url))
+        .bearer_auth(&token)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 401);
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn export_keeps_the_client_stats() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("lou", &registry.signup("lou").await?)?;
+    publish(&cli, "tally", "return {}\n", &[]).await?;
+    let game = cli.project("game")?;
+    cli.run(&game, &["init"]).await?.ok();
+    fs::write(game.join("game.poly"), EMPTY_POLY)?;
+    cli.run(&game, &["telemetry", "on"]).await?.ok();
+    cli.run(&game, &["install", "tally"]).await?.ok();
+
+    let (copy, cli) = round_trip(&cli, registry).await?;
+    let run = cli.run_with_env(&game, &["admin", "clients"], &ADMIN).await?.ok();
+    assert!(run.stdout.contains(env!("CARGO_PKG_VERSION")), "{:?}", run);
+
+    copy.stop().await
+}
+
+#[tokio::test]
+async fn taken_down_package_serves_no_contents() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("jade", &registry.signup("jade").await?)?;
+    publish(&cli, "shady", "return {}\n", &[]).await?;
+    let dir = cli.project("shady")?;
+    let toml = fs::read_to_string(dir.join("mosaic.toml"))?;
+    fs::write(dir.join("mosaic.toml"), toml.replacen("0.1.0", "0.2.0", 1))?;
+    fs::write(dir.join("init.lua"), "return { v = 2 }\n")?;
+    cli.run(&dir, &["publish", "--yes"]).await?.ok();
+
+    let paths = [
+        "/packages/shady/versions/0.2.0/files",
+        "/packages/shady/versions/0.2.0/files/init.lua",
+        "/packages/shady/versions/0.2.0/changelog",
+        "/packages/shady/versions/0.2.0/lint",
+        "/packages/shady/diff?from=0.1.0&to=0.2.0",
+    ];
+    let status = async |path: &str| -> anyhow::Result<reqwest::StatusCode> {
+        Ok(reqwest::get(format!("{}{}", registry.url, path)).await?.status())
+    };
+    assert_eq!(status(paths[0]).await?, 200);
+    assert_eq!(status(paths[1]).await?, 200);
+
+    cli.run_with_env(&dir, &["admin", "takedown", "shady", "--reason", "malware"], &ADMIN).await?.ok();
+    for path in paths {
+        assert_eq!(status(path).await?, 404, "{}", path);
+    }
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn oversized_files_are_not_inflated_for_viewing() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("kit", &registry.signup("kit").await?)?;
+    // Over the 1MB view limit but well under the 5MB upload limit.
+    fs::write(cli.project("bulky")?.join("data.txt"), "a".repeat(2 * 1024 * 1024))?;
+    publish(&cli, "bulky", "return {}\n", &[]).await?;
+
+    let get = async |path: &str| reqwest::get(format!("{}/packages/bulky/versions/0.1.0/files/{}", registry.url, path)).await;
+    assert_eq!(get("init.lua").await?.status(), 200);
+    assert_eq!(get("data.txt").await?.status(), 413);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn packages_that_inflate_too_far_are_refused() -> anyhow::Result<()> {
+    use sha2::{Digest, Sha256};
+    use std::io::Write;
+
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let token = registry.signup("kit").await?;
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("kit", &token)?;
+    publish(&cli, "hefty", "return {}\n", &[]).await?;
+
+    // The CLI stores entries uncompressed, so build the deflated zip by hand: 17MB of one byte
+    // packs down to a few KB.
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("init.lua", options)?;
+    zip.write_all(b"return {}\n")?;
+    zip.start_file("data.txt", options)?;
+    zip.write_all(&vec![b'a'; 17 * 1024 * 1024])?;
+    let body = zip.finish()?.into_inner();
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post(format!("{}/packages/hefty/versions", registry.url))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "version": "0.2.0", "lua_source_url": "tbd", "dependencies": {} }))
+        .send()
+        .await?;
+    let created: serde_json::Value = res.json().await?;
+    let res = client
+        .post(format!("{}/packages/hefty/versions/0.2.0/upload", registry.url))
+        .bearer_auth(&token)
+        .header("x-upload-token", created["upload_token"].as_str().unwrap_or_default())
+        .header("x-content-sha256", format!("{:x}", Sha256::digest(&body)))
+        .body(body)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+    assert!(res.text().await?.contains("unpacks to too much data"));
+
+    let status = reqwest::get(format!("{}/packages/hefty/versions/0.2.0", registry.url)).await?.status();
+    assert_eq!(status, 404);
+
+    registry.stop().await
+}
+
+#[tokio::test]
+async fn publish_leaves_manifest_backups_out() -> anyhow::Result<()> {
+    let Some(registry) = TestRegistry::start().await? else {
+        return Ok(());
+    };
+    let cli = Cli::new(MOSAIC, &registry.url)?;
+    cli.login("max", &registry.signup("max").await?)?;
+    fs::write(cli.project("tidy")?.join("mosaic.toml.schema0.bak"), "[package]\nname = \"tidy\"\n")?;
+    publish(&cli, "tidy", "return {}\n", &[]).await?;
+
+    let listing: serde_json::Value = reqwest::get(format!("{}/packages/tidy/versions/0.1.0/files", registry.url))
+        .await?
+        .json()
+        .await?;
+    let paths: Vec<&str> = listing["files"].as_array().into_iter().flatten().filter_map(|f| f["path"].as_str()).collect();
+    assert_eq!(paths, ["init.lua"], "{}", listing);
+
+    registry.stop().await
+}
@@ -0,0 +1,377 @@
+//! Fills a registry database with fake users, packages, versions and download history,
+//! so search, listings and the other hot queries can be measured against something bigger
+//! than a dev box's three test packages.
+//!
+//! ```text
+//! cargo run --release --bin registry-seed -- --packages 20000 --users 2000
+//! cargo run --release --bin registry-seed -- --clean
+//! ```
+//!
+//! Everything is generated inside Postgres (generate_series + random()), so even the big
+//! sizes take seconds. No blobs are uploaded: versions point at `seed://` URLs, so downloads
+//! of seeded packages 404. Metadata is the point.
+
+use anyhow::{Result, anyhow};
+use registry::db;
+use std::time::Instant;
+
+/// Password hash for seeded users. Not a valid argon2 hash, so nobody can log in as one,
+/// and it's how `--clean` tells seeded rows from real ones.
+const SEED_HASH: &str = "!seed";
+
+/// Words names, descriptions and readmes are made of. Roblox-flavored, so search queries
+/// look like the ones people actually type.
+const WORDS: &[&str] = &[
+    "signal", "promise", "tween", "spring", "camera", "input", "network", "remote", "datastore",
+    "profile", "inventory", "ui", "gui", "button", "layout", "animation", "sound", "physics",
+    "raycast", "pathfinding", "npc", "combat", "weapon", "vehicle", "terrain", "lighting",
+    "chat", "leaderboard", "quest", "dialog", "state", "store", "cache", "queue", "timer",
+    "logger", "debug", "test", "mock", "util", "math", "vector", "color", "string", "table",
+    "event", "maid", "janitor", "component", "entity", "system", "replica", "sync", "matchmaking",
+];
+
+const LICENSES: &[&str] = &["MIT", "MIT", "MIT", "Apache-2.0", "MPL-2.0", "BSD-3-Clause", "Custom"];
+
+struct Options {
+    users: i64,
+    packages: i64,
+    versions: i64,
+    days: i64,
+    prefix: String,
+    clean: bool,
+    force: bool,
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut opts = Options {
+            users: 500,
+            packages: 5000,
+            versions: 12,
+            days: 90,
+            prefix: "seed".to_string(),
+            clean: false,
+            force: false,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next().ok_or_else(|| anyhow!("{} needs a value", name))
+            };
+            match arg.as_str() {
+                "--users" => opts.users = value("--users")?.parse()?,
+                "--packages" => opts.packages = value("--packages")?.parse()?,
+                "--versions" => opts.versions = value("--versions")?.parse()?,
+                "--days" => opts.days = value("--days")?.parse()?,
+                "--prefix" => opts.prefix = value("--prefix")?,
+                "--clean" => opts.clean = true,
+                "--force" => opts.force = true,
+                "-h" | "--help" => {
+                    println!(
+                        "Usage: registry-seed [--users N] [--packages N] [--versions MAX] [--days N] [--prefix P] [--clean] [--force]\n\n\
+                         --users     fake accounts to create (default 500)\n\
+                         --packages  fake packages, spread over those accounts (default 5000)\n\
+                         --versions  most versions one package gets; each gets 1..=MAX (default 12)\n\
+                         --days      days of simulated download history (default 90)\n\
+                         --prefix    start of every seeded name (default \"seed\")\n\
+                         --clean     delete everything a previous run created, then stop\n\
+                         --force     allow a DATABASE_URL that isn't on this machine"
+                    );
+                    std::process::exit(0);
+                }
+                other => return Err(anyhow!("Unknown argument '{}'. See --help.", other)),
+            }
+        }
+        if opts.users < 1 || opts.packages < 1 || opts.versions < 1 || opts.days < 1 {
+            return Err(anyhow!("--users, --packages, --versions and --days must be at least 1"));
+        }
+        // It ends up in names, which have to pass the same rules as real ones.
+        if opts.prefix.is_empty() || !opts.prefix.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+            return Err(anyhow!("--prefix must be lowercase letters and digits"));
+        }
+        Ok(opts)
+    }
+}
+
+/// Whether DATABASE_URL points at this machine (or a compose service). Seeding production
+/// by accident would be a bad afternoon, so anything else needs `--force`.
+fn is_local(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.rsplit_once('@').map_or(rest, |(_, host)| host);
+    let host_port = rest.split(['/', '?']).next().unwrap_or("");
+    let host = match host_port.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host_port,
+    };
+    matches!(host, "" | "localhost" | "127.0.0.1" | "[::1]" | "db" | "postgres")
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    rustls::crypto::ring::default_provider()
+        .install_default()
+        .expect("Failed to install rustls crypto provider");
+
+    let opts = Options::parse()?;
+    let url = std::env::var("DATABASE_URL").map_err(|_| anyhow!("DATABASE_URL must be set"))?;
+    if !opts.force && !is_local(&url) {
+        return Err(anyhow!(
+            "DATABASE_URL doesn't look local. Pass --force if you really mean to seed it."
+        ));
+    }
+
+    // Runs the migrations too, so this works on an empty database.
+    let pool = db::connect().await?;
+
+    if opts.clean {
+        return clean(&pool).await;
+    }
+
+    let started = Instant::now();
+    let words: Vec<String> = WORDS.iter().map(|w| w.to_string()).collect();
+    let licenses: Vec<String> = LICENSES.iter().map(|l| l.to_string()).collect();
+    let now = chrono::Utc::now().timestamp();
+    let today = now / 86400;
+    let mut tx = pool.begin().await?;
+
+    // 1. Users, signed up some time in the last two years.
+    let users = sqlx::query(
+        r#"
+        INSERT INTO users (username, password_hash, created_at)
+        SELECT $1 || '-user-' || g, $2, $3 - (random() * 2 * 365 * 86400)::BIGINT
+        FROM generate_series(1, $4) g
+        ON CONFLICT (username) DO NOTHING
+    "#,
+    )
+    .bind(&opts.prefix)
+    .bind(SEED_HASH)
+    .bind(now)
+    .bind(opts.users)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    println!("users: {}", users);
+
+    // 2. Packages, each owned by one of those users. Names are prefix-word-word-n so they're
+    // unique and still give search something to match.
+    let packages = sqlx::query(
+        r#"
+        INSERT INTO packages (name, description, author, owner_id, created_at, updated_at)
+        SELECT
+            $1 || '-' || $2[1 + floor(random() * array_length($2, 1))::INT]
+               || '-' || $2[1 + floor(random() * array_length($2, 1))::INT] || '-' || g,
+            'A ' || $2[1 + floor(random() * array_length($2, 1))::INT] || ' '
+               || $2[1 + floor(random() * array_length($2, 1))::INT] || ' library for '
+               || $2[1 + floor(random() * array_length($2, 1))::INT] || ' and '
+               || $2[1 + floor(random() * array_length($2, 1))::INT] || '.',
+            u.username, u.id, created, created
+        FROM (
+            SELECT g, $1 || '-user-' || (1 + g % $3) AS owner, $4 - (random() * 365 * 86400)::BIGINT AS created
+            FROM generate_series(1, $5) g
+        ) s
+        JOIN users u ON u.username = s.owner
+        ON CONFLICT (name) DO NOTHING
+    "#,
+    )
+    .bind(&opts.prefix)
+    .bind(&words)
+    .bind(opts.users)
+    .bind(now)
+    .bind(opts.packages)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    println!("packages: {}", packages);
+
+    // 3. Versions: 1..=max per package, x.y.0 in publish order, with a readme for search to chew on.
+    let versions = sqlx::query(
+        r#"
+        INSERT INTO package_versions
+            (package_id, version, lua_source_url, readme, license, blob_size, status, created_at)
+        SELECT
+            p.id,
+            (i / 5) || '.' || (i % 5) || '.0',
+            'seed://' || p.name || '/' || (i / 5) || '.' || (i % 5) || '.0',
+            '# ' || p.name || E'\n\n' || p.description || E'\n\n' || array_to_string(ARRAY(
+                SELECT $1[1 + floor(random() * array_length($1, 1))::INT]
+                FROM generate_series(1, 30 + i)
+            ), ' '),
+            $2[1 + floor(random() * array_length($2, 1))::INT],
+            (1000 + random() * 200000)::BIGINT,
+            'published',
+            p.created_at + i * (($3 - p.created_at) / (n + 1))
+        FROM packages p
+        JOIN users u ON u.id = p.owner_id AND u.password_hash = $4
+        CROSS JOIN LATERAL (SELECT 1 + abs(hashtext(p.id::TEXT)) % $5 AS n) c
+        CROSS JOIN LATERAL generate_series(1, c.n) i
+        WHERE NOT EXISTS (SELECT 1 FROM package_versions v WHERE v.package_id = p.id)
+    "#,
+    )
+    .bind(&words)
+    .bind(&licenses)
+    .bind(now)
+    .bind(SEED_HASH)
+    .bind(opts.versions)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    println!("versions: {}", versions);
+
+    // 4. A fifth of versions depend on some other seeded package, for the dependents queries.
+    sqlx::query(
+        r#"
+        WITH seeded AS (
+            SELECT p.id, p.name, row_number() OVER (ORDER BY p.id) AS n, count(*) OVER () AS total
+            FROM packages p JOIN users u ON u.id = p.owner_id AND u.password_hash = $1
+        )
+        UPDATE package_versions v SET dependencies = jsonb_build_object(d.name, '*')
+        FROM seeded s, seeded d
+        WHERE v.package_id = s.id
+          AND d.n = 1 + (abs(hashtext(v.id::TEXT)) % s.total)
+          AND d.id <> s.id
+          AND abs(hashtext(v.id::TEXT || 'dep')) % 5 = 0
+    "#,
+    )
+    .bind(SEED_HASH)
+    .execute(&mut *tx)
+    .await?;
+
+    // 5. latest points at the newest version.
+    sqlx::query(
+        r#"
+        INSERT INTO dist_tags (package_id, tag, version, updated_at)
+        SELECT DISTINCT ON (v.package_id) v.package_id, 'latest', v.version, v.created_at
+        FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        JOIN users u ON u.id = p.owner_id AND u.password_hash = $1
+        ORDER BY v.package_id, v.created_at DESC
+        ON CONFLICT (package_id, tag) DO UPDATE SET version = EXCLUDED.version, updated_at = EXCLUDED.updated_at
+    "#,
+    )
+    .bind(SEED_HASH)
+    .execute(&mut *tx)
+    .await?;
+
+    // 6. Download traffic. Popularity is heavily skewed (random()^4), like a real registry:
+    // a few packages get almost everything, the long tail gets a handful a week.
+    let days = sqlx::query(
+        r#"
+        INSERT INTO package_downloads (package_id, day, count)
+        SELECT p.id, d, floor(p.popularity * random())::BIGINT
+        FROM (
+            SELECT pk.id, power(random(), 4) * 2000 AS popularity
+            FROM packages pk JOIN users u ON u.id = pk.owner_id AND u.password_hash = $1
+        ) p
+        CROSS JOIN generate_series($2 - $3 + 1, $2) d
+        WHERE floor(p.popularity * random()) > 0
+        ON CONFLICT (package_id, day) DO UPDATE SET count = EXCLUDED.count
+    "#,
+    )
+    .bind(SEED_HASH)
+    .bind(today)
+    .bind(opts.days)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    println!("daily download rows: {}", days);
+
+    sqlx::query(
+        r#"
+        UPDATE packages p SET download_count = t.total
+        FROM (SELECT package_id, SUM(count)::BIGINT AS total FROM package_downloads GROUP BY package_id) t
+        JOIN packages pk ON pk.id = t.package_id
+        JOIN users u ON u.id = pk.owner_id AND u.password_hash = $1
+        WHERE p.id = t.package_id
+    "#,
+    )
+    .bind(SEED_HASH)
+    .execute(&mut *tx)
+    .await?;
+
+    // Newer versions get most of a package's downloads.
+    sqlx::query(
+        r#"
+        UPDATE package_versions v
+        SET download_count = floor(p.download_count * random() / r.rank)::BIGINT
+        FROM (
+            SELECT id, package_id, row_number() OVER (PARTITION BY package_id ORDER BY created_at DESC) AS rank
+            FROM package_versions
+        ) r
+        JOIN packages p ON p.id = r.package_id
+        JOIN users u ON u.id = p.owner_id AND u.password_hash = $1
+        WHERE v.id = r.id
+    "#,
+    )
+    .bind(SEED_HASH)
+    .execute(&mut *tx)
+    .await?;
+
+    // Opted-in CLI telemetry, a couple of versions across the three OSes.
+    sqlx::query(
+        r#"
+        INSERT INTO client_downloads (day, cli_version, os, count)
+        SELECT d, cli, os, floor(random() * 500)::BIGINT
+        FROM generate_series($1 - $2 + 1, $1) d,
+             unnest(ARRAY['0.1.0', '0.2.0-beta.1']) cli,
+             unnest(ARRAY['windows', 'macos', 'linux']) os
+        ON CONFLICT (day, cli_version, os) DO UPDATE SET count = client_downloads.count + EXCLUDED.count
+    "#,
+    )
+    .bind(today)
+    .bind(opts.days)
+    .execute(&mut *tx)
+    .await?;
+
+    // 7. Every seeded user stars a few packages, so star counts aren't all zero.
+    let stars = sqlx::query(
+        r#"
+        WITH seeded AS (
+            SELECT p.id, row_number() OVER (ORDER BY p.id) AS n, count(*) OVER () AS total
+            FROM packages p JOIN users u ON u.id = p.owner_id AND u.password_hash = $1
+        )
+        INSERT INTO stars (user_id, package_id, notify, created_at)
+        SELECT u.id, s.id, random() < 0.1, $2
+        FROM users u
+        CROSS JOIN generate_series(1, 5) k
+        JOIN seeded s ON s.n = 1 + (abs(hashtext(u.id::TEXT || k)) % s.total)
+        WHERE u.password_hash = $1
+        ON CONFLICT DO NOTHING
+    "#,
+    )
+    .bind(SEED_HASH)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    println!("stars: {}", stars);
+
+    tx.commit().await?;
+
+    // Same rollup the server runs every 10 minutes, so sort=trending works straight away.
+    db::refresh_recent_downloads(&pool).await?;
+
+    println!("Seeded in {:.1}s", started.elapsed().as_secs_f64());
+    Ok(())
+}
+
+/// Deletes seeded users and everything they own. Packages cascade to their versions, tags,
+/// downloads and stars.
+async fn clean(pool: &db::DB) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let packages = sqlx::query(
+        "DELETE FROM packages WHERE owner_id IN (SELECT id FROM users WHERE password_hash = $1)",
+    )
+    .bind(SEED_HASH)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    let users = sqlx::query("DELETE FROM users WHERE password_hash = $1")
+        .bind(SEED_HASH)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+    println!("Removed {} packages and {} users", packages, users);
+    Ok(())
+}
@@ -12,10 +12,14 @@ pub type DB = PgPool;
 /// bit chatty with the database, but it's idempotent so it's fine.
 pub async fn connect() -> Result<DB> {
     let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    connect_to(&url).await
+}
 
+/// [`connect`] with an explicit URL, for tools and tests that don't go through `DATABASE_URL`.
+pub async fn connect_to(url: &str) -> Result<DB> {
     let pool = PgPoolOptions::new()
         .max_connections(5)
-        .connect(&url)
+        .connect(url)
         .await?;
 
     // --- Migrations (run on every startup) ---
@@ -25,10 +29,14 @@ pub async fn connect() -> Result<DB> {
 
     // 1. Extensions
     // pg_search used to be here but honestly we're not using it anymore.
-    // Keeping it in case someone wants to add it back later.
-    sqlx::query(r#"CREATE EXTENSION IF NOT EXISTS "pg_search";"#)
+    // Keeping it in case someone wants to add it back later. Nothing needs it, so a plain
+    // Postgres without it installed (a dev box, the integration tests) just gets a warning.
+    if let Err(e) = sqlx::query(r#"CREATE EXTENSION IF NOT EXISTS "pg_search";"#)
         .execute(&pool)
-        .await?;
+        .await
+    {
+        tracing::warn!("pg_search extension not available, skipping: {}", e);
+    }
 
     // 2. Users Table
     // Simple auth. username is UNIQUE because we assume usernames are the auth identifier.
@@ -48,7 +56,8 @@ pub async fn connect() -> Result<DB> {
 
     // 3. Packages Table
     // The registry's main table. Name is UNIQUE because package names can't collide.
-    // author is a string because we weren't fancy enough to FK to users (TODO someday?)
+    // author started out as a plain username string. Migration 31 added the owner_id FK;
+    // author is kept as a fallback for rows that couldn't be matched to a user.
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS packages (
@@ -161,5 +170,710 @@ pub async fn connect() -> Result<DB> {
     .execute(&pool)
     .await?;
 
+    // 11. Dist-Tags
+    // Named pointers to versions (latest, beta, next...). One row per (package, tag).
+    // Publishing with a tag just moves the pointer, so the PK doubles as the upsert target.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS dist_tags (
+            package_id UUID REFERENCES packages(id) ON DELETE CASCADE,
+            tag TEXT NOT NULL,
+            version TEXT NOT NULL,
+            updated_at BIGINT NOT NULL,
+            PRIMARY KEY(package_id, tag)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 12. Changelog
+    // Raw CHANGELOG.md pulled out of the uploaded zip, same deal as readme.
+    sqlx::query(
+        r#"
+        ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS changelog TEXT;
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 13. File Listing
+    // JSON array of {path, size, sha256} for every file in the blob, computed at upload.
+    // NULL for versions uploaded before this existed; the files endpoint backfills those lazily.
+    sqlx::query(
+        r#"
+        ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS files JSONB;
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 14. Version Diff Cache
+    // Published versions are immutable, so a diff between two of them never changes.
+    // We compute it once from the blobs and keep it here. Unpublish clears the affected rows.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS version_diffs (
+            package_id UUID REFERENCES packages(id) ON DELETE CASCADE,
+            from_version TEXT NOT NULL,
+            to_version TEXT NOT NULL,
+            diff JSONB NOT NULL,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY(package_id, from_version, to_version)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 15. Stars
+    // One row per (user, package). `notify` is the "watch" preference—starred users who
+    // want to hear about new versions. Cascades both ways so deleted users/packages clean up.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stars (
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            package_id UUID REFERENCES packages(id) ON DELETE CASCADE,
+            notify BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY(user_id, package_id)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS stars_package_idx ON stars (package_id);
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 16. Owner Webhooks
+    // Where we POST security-relevant events for a user's packages.
+    // `secret` signs each delivery (HMAC-SHA256) so receivers can tell it's really us.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS webhooks (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            created_at BIGINT NOT NULL
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 17. Publishing Session
+    // JTI of the token that registered each version. A publish from a different session
+    // than the previous version's is the closest thing we have to a "new device" signal.
+    sqlx::query(
+        r#"
+        ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS publish_jti UUID;
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 18. Takedowns
+    // Admin-only switch. A taken-down package disappears from listings and its blobs stop serving,
+    // but nothing is deleted so it can be restored if the takedown was a mistake.
+    sqlx::query(
+        r#"
+        ALTER TABLE packages
+        ADD COLUMN IF NOT EXISTS taken_down BOOLEAN NOT NULL DEFAULT FALSE,
+        ADD COLUMN IF NOT EXISTS takedown_reason TEXT
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 19. Banned Users
+    // Banned users can't log in, and their existing tokens stop working.
+    sqlx::query(
+        r#"
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS banned BOOLEAN NOT NULL DEFAULT FALSE;
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 20. Admin Audit Log
+    // Every admin action lands here. Append-only; nothing in the API deletes from it.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS admin_audit_log (
+            id BIGSERIAL PRIMARY KEY,
+            action TEXT NOT NULL,
+            target TEXT NOT NULL,
+            reason TEXT,
+            created_at BIGINT NOT NULL
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 21. Download Time Series
+    // One row per package per day (day = unix timestamp / 86400). download_count on packages is
+    // the fast path for listings; this is the source of truth it can be rebuilt from.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS package_downloads (
+            package_id UUID REFERENCES packages(id) ON DELETE CASCADE,
+            day BIGINT NOT NULL,
+            count BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (package_id, day)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Seed the series with everything counted before it existed, as a single "day 0" bucket.
+    // Only touches packages with no rows yet, so it's a no-op after the first run.
+    sqlx::query(
+        r#"
+        INSERT INTO package_downloads (package_id, day, count)
+        SELECT p.id, 0, p.download_count FROM packages p
+        WHERE p.download_count > 0
+          AND NOT EXISTS (SELECT 1 FROM package_downloads d WHERE d.package_id = p.id)
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 22. Version Status
+    // A version is 'pending' from registration until its blob lands, then 'published'.
+    // pending_tag holds the dist-tag to move once it's published. Old "tbd" rows are
+    // backfilled as pending so the expiry sweep cleans them up too.
+    sqlx::query(
+        r#"
+        ALTER TABLE package_versions
+        ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'published',
+        ADD COLUMN IF NOT EXISTS pending_tag TEXT
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        "UPDATE package_versions SET status = 'pending' WHERE lua_source_url = 'tbd' AND status = 'published'",
+    )
+    .execute(&pool)
+    .await?;
+
+    // 23. Upload Sessions
+    // create_version hands out a one-time upload token; we keep only its SHA256 here.
+    // upload_blob has to present it, and publishing clears it, so it can't be replayed.
+    sqlx::query("ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS upload_token_hash TEXT")
+        .execute(&pool)
+        .await?;
+
+    // 24. Verified Accounts
+    // Set by an admin. Right now it only buys a bigger per-package publish quota.
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS verified BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(&pool)
+        .await?;
+
+    // 25. Declared Licenses
+    // What mosaic.toml said, kept apart from `license` (the effective one) so we can cross-check
+    // it against the LICENSE file when the blob arrives.
+    sqlx::query("ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS declared_license TEXT")
+        .execute(&pool)
+        .await?;
+
+    // 26. Doc Paths
+    // Which file in the zip the readme/changelog/license came from. NULL means we didn't find one.
+    sqlx::query(
+        r#"
+        ALTER TABLE package_versions
+        ADD COLUMN IF NOT EXISTS readme_path TEXT,
+        ADD COLUMN IF NOT EXISTS changelog_path TEXT,
+        ADD COLUMN IF NOT EXISTS license_path TEXT
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 27. Per-Version Downloads
+    // Same idea as packages.download_count, one level down. Starts at zero; there's no way to
+    // split the old package totals by version after the fact.
+    sqlx::query("ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS download_count BIGINT NOT NULL DEFAULT 0")
+        .execute(&pool)
+        .await?;
+
+    // 28. Lint Reports
+    // Filled in after upload by a background lint pass (utils::lint). NULL means not linted
+    // yet—either it's still running, linting is off, or the version predates it.
+    sqlx::query("ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS lint_report JSONB")
+        .execute(&pool)
+        .await?;
+
+    // 29. Blob Sizes
+    // Recorded at upload so clients can sanity-check a download from a public bucket URL.
+    // Older versions stay NULL; the hash is what really matters anyway.
+    sqlx::query("ALTER TABLE package_versions ADD COLUMN IF NOT EXISTS blob_size BIGINT")
+        .execute(&pool)
+        .await?;
+
+    // 30. Username Redirects
+    // One row per name a user has renamed away from. Keeps old author names resolvable and
+    // stops anyone else signing up as the old name (and inheriting its reputation).
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS username_redirects (
+            old_username TEXT PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            renamed_at BIGINT NOT NULL
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 31. Package Owners
+    // The real users FK that author should have been. Backfilled from author—directly, or via
+    // a rename redirect if the user has moved on since. Rows nobody matches stay NULL and are
+    // owned by no one until an operator sorts them out. author stays for display fallback only.
+    sqlx::query("ALTER TABLE packages ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES users(id)")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        UPDATE packages p SET owner_id = u.id
+        FROM users u
+        WHERE p.owner_id IS NULL AND u.username = p.author
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE packages p SET owner_id = r.user_id
+        FROM username_redirects r
+        WHERE p.owner_id IS NULL AND r.old_username = p.author
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_packages_owner_id ON packages(owner_id)")
+        .execute(&pool)
+        .await?;
+
+    // 32. Name Rules
+    // The name blocklist/whitelist used to be arrays in utils::validation; now admins edit them
+    // at runtime (see /admin/names). kind is one of:
+    // - block: names equal to this (or containing it, if it's over 3 chars) are refused
+    // - allow: names containing this skip the substring part of block (the Scunthorpe list)
+    // - reserved_prefix: names starting with this need an approval
+    // - approved: this exact name may be claimed by user_id despite a reserved prefix
+    // The old arrays are seeded only when the table is first created, so rules an admin
+    // deletes stay deleted across restarts.
+    let name_rules_exist: bool = sqlx::query_scalar("SELECT to_regclass('name_rules') IS NOT NULL")
+        .fetch_one(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS name_rules (
+            kind TEXT NOT NULL CHECK (kind IN ('block', 'allow', 'reserved_prefix', 'approved')),
+            pattern TEXT NOT NULL,
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            reason TEXT,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY (kind, pattern)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    if !name_rules_exist {
+        let seed = [
+            ("block", crate::utils::validation::DEFAULT_BLOCKLIST.as_slice()),
+            ("allow", crate::utils::validation::DEFAULT_ALLOWLIST.as_slice()),
+            ("reserved_prefix", crate::utils::validation::DEFAULT_RESERVED_PREFIXES.as_slice()),
+        ];
+        for (kind, patterns) in seed {
+            sqlx::query(
+                r#"
+                INSERT INTO name_rules (kind, pattern, created_at)
+                SELECT $1, unnest($2::text[]), $3
+                ON CONFLICT DO NOTHING
+            "#,
+            )
+            .bind(kind)
+            .bind(patterns)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&pool)
+            .await?;
+        }
+    }
+
+    // 33. Name Claims
+    // Requests for a reserved name or somebody else's package, reviewed by an admin
+    // (see handlers::claim). Also adds the 'reserved' rule kind: one exact name held back,
+    // usually for a verified project, where reserved_prefix holds back a whole family.
+    sqlx::query("ALTER TABLE name_rules DROP CONSTRAINT IF EXISTS name_rules_kind_check")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        ALTER TABLE name_rules ADD CONSTRAINT name_rules_kind_check
+        CHECK (kind IN ('block', 'allow', 'reserved', 'reserved_prefix', 'approved'))
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS name_claims (
+            id BIGSERIAL PRIMARY KEY,
+            name TEXT NOT NULL,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            reason TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+            resolution TEXT,
+            created_at BIGINT NOT NULL,
+            resolved_at BIGINT
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    // One open claim per user per name. Rejected ones don't count, so people can try again.
+    sqlx::query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_name_claims_open ON name_claims(name, user_id) WHERE status = 'pending'",
+    )
+    .execute(&pool)
+    .await?;
+
+    // 34. Recent Downloads
+    // Downloads over the last 7 days, rolled up from package_downloads by the background job
+    // (see refresh_recent_downloads). Stored on the row so sort=trending is a plain index scan.
+    sqlx::query("ALTER TABLE packages ADD COLUMN IF NOT EXISTS recent_downloads BIGINT NOT NULL DEFAULT 0")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_packages_recent_downloads ON packages(recent_downloads DESC)")
+        .execute(&pool)
+        .await?;
+
+    // 35. Featured Packages
+    // Hand-picked by admins. Shows up as a badge and nudges search relevance; it doesn't
+    // change sort=downloads/trending/etc, which are meant to be plain numbers.
+    sqlx::query("ALTER TABLE packages ADD COLUMN IF NOT EXISTS featured BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(&pool)
+        .await?;
+
+    // 36. Search Vector
+    // Precomputed full-text index: name (A) and description (B), plus the latest published
+    // version's readme (C), which is where the actual keywords tend to be. Triggers keep it
+    // current, so search is a GIN lookup instead of a to_tsvector over every row.
+    // The readme is capped because a tsvector tops out at 1MB, and nobody's keywords live
+    // 100KB into their readme anyway.
+    sqlx::query("ALTER TABLE packages ADD COLUMN IF NOT EXISTS search_vector TSVECTOR")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE FUNCTION package_search_vector(pkg_id UUID, pkg_name TEXT, pkg_description TEXT)
+        RETURNS tsvector LANGUAGE sql STABLE AS $$
+            SELECT setweight(to_tsvector('english', coalesce(pkg_name, '')), 'A')
+                || setweight(to_tsvector('english', coalesce(pkg_description, '')), 'B')
+                || setweight(to_tsvector('english', coalesce((
+                    SELECT left(v.readme, 100000) FROM package_versions v
+                    WHERE v.package_id = pkg_id AND v.status = 'published'
+                    ORDER BY v.created_at DESC LIMIT 1
+                ), '')), 'C')
+        $$
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    // Renames and description edits.
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE FUNCTION packages_search_vector_trigger() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            NEW.search_vector := package_search_vector(NEW.id, NEW.name, NEW.description);
+            RETURN NEW;
+        END
+        $$
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    // Publishes (pending -> published) and unpublishes both change which readme is latest.
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE FUNCTION package_versions_search_vector_trigger() RETURNS trigger LANGUAGE plpgsql AS $$
+        DECLARE
+            pkg UUID;
+        BEGIN
+            IF TG_OP = 'DELETE' THEN
+                pkg := OLD.package_id;
+            ELSE
+                pkg := NEW.package_id;
+            END IF;
+            UPDATE packages SET search_vector = package_search_vector(id, name, description) WHERE id = pkg;
+            RETURN NULL;
+        END
+        $$
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    // No CREATE OR REPLACE TRIGGER before Postgres 14, so drop and recreate.
+    sqlx::query("DROP TRIGGER IF EXISTS packages_search_vector ON packages")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER packages_search_vector
+        BEFORE INSERT OR UPDATE OF name, description ON packages
+        FOR EACH ROW EXECUTE FUNCTION packages_search_vector_trigger()
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("DROP TRIGGER IF EXISTS package_versions_search_vector ON package_versions")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER package_versions_search_vector
+        AFTER INSERT OR DELETE OR UPDATE OF status, readme ON package_versions
+        FOR EACH ROW EXECUTE FUNCTION package_versions_search_vector_trigger()
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    // Backfill whatever predates the triggers.
+    sqlx::query(
+        "UPDATE packages SET search_vector = package_search_vector(id, name, description) WHERE search_vector IS NULL",
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_packages_search_vector ON packages USING GIN(search_vector)")
+        .execute(&pool)
+        .await?;
+
+    // 37. Client Downloads
+    // Daily download totals per CLI version and OS, from clients that opted in with
+    // `mosaic telemetry on` (see utils::clients). Nothing per-user, so nothing to anonymize.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS client_downloads (
+            day BIGINT NOT NULL,
+            cli_version TEXT NOT NULL,
+            os TEXT NOT NULL,
+            count BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (day, cli_version, os)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 38. Protected Publishing
+    // A protected package's new versions wait in 'held' until the owner confirms them
+    // (see handlers::protection). approval_token_hash is the SHA256 of the one-time token in
+    // the confirmation link; held_at starts the delay. unprotect_at is when turning protection
+    // off takes effect—it's delayed for the same reason approvals are.
+    sqlx::query(
+        r#"
+        ALTER TABLE packages
+        ADD COLUMN IF NOT EXISTS protected BOOLEAN NOT NULL DEFAULT FALSE,
+        ADD COLUMN IF NOT EXISTS unprotect_at BIGINT
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        r#"
+        ALTER TABLE package_versions
+        ADD COLUMN IF NOT EXISTS approval_token_hash TEXT,
+        ADD COLUMN IF NOT EXISTS held_at BIGINT
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 39. API Tokens
+    // Long-lived tokens for CI, optionally restricted to some packages (see handlers::token).
+    // The token is a JWT like a login; this table is what `mosaic token list` shows. Revoking
+    // one goes through revoked_tokens like a logout and drops the row.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS api_tokens (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            packages TEXT[],
+            created_at BIGINT NOT NULL,
+            expires_at BIGINT NOT NULL
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens(user_id)")
+        .execute(&pool)
+        .await?;
+
+    // 40. Private Packages
+    // A private package is only visible to its owner and the accounts on its member list—a
+    // studio's team (see handlers::visibility). Everyone else gets a 404, same as a name
+    // that was never published.
+    sqlx::query(
+        r#"
+        ALTER TABLE packages
+        ADD COLUMN IF NOT EXISTS visibility TEXT NOT NULL DEFAULT 'public'
+            CHECK (visibility IN ('public', 'private'))
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS package_members (
+            package_id UUID NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            added_at BIGINT NOT NULL,
+            PRIMARY KEY (package_id, user_id)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_package_members_user ON package_members(user_id)")
+        .execute(&pool)
+        .await?;
+
+    // 41. Dependents Edges
+    // One row per (version, dependency) out of package_versions.dependencies, so "who depends
+    // on X" is an index lookup on `dependency` instead of a `dependencies ? X` over every
+    // version row (see utils::dependencies::dependents_of). Like the search vector, a trigger
+    // keeps it current, so publish, import and the seed tool can't forget to; unpublishing a
+    // version cascades its edges away. `dependency` is a name, same as the JSONB keys.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS package_dependents (
+            version_id UUID NOT NULL REFERENCES package_versions(id) ON DELETE CASCADE,
+            package_id UUID NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            dependency TEXT NOT NULL,
+            requirement TEXT NOT NULL,
+            PRIMARY KEY (version_id, dependency)
+        )
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_package_dependents_dependency ON package_dependents(dependency)")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE FUNCTION package_versions_dependents_trigger() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            DELETE FROM package_dependents WHERE version_id = NEW.id;
+            INSERT INTO package_dependents (version_id, package_id, dependency, requirement)
+            SELECT NEW.id, NEW.package_id, d.key, d.value
+            FROM jsonb_each_text(
+                CASE WHEN jsonb_typeof(NEW.dependencies) = 'object' THEN NEW.dependencies ELSE '{}'::jsonb END
+            ) d;
+            RETURN NULL;
+        END
+        $$
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("DROP TRIGGER IF EXISTS package_versions_dependents ON package_versions")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER package_versions_dependents
+        AFTER INSERT OR UPDATE OF dependencies ON package_versions
+        FOR EACH ROW EXECUTE FUNCTION package_versions_dependents_trigger()
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+    // Backfill versions from before the trigger. Once they all have their edges, this only
+    // looks at versions with no dependencies at all.
+    sqlx::query(
+        r#"
+        INSERT INTO package_dependents (version_id, package_id, dependency, requirement)
+        SELECT v.id, v.package_id, d.key, d.value
+        FROM package_versions v, jsonb_each_text(v.dependencies) d
+        WHERE jsonb_typeof(v.dependencies) = 'object'
+          AND v.dependencies <> '{}'::jsonb
+          AND NOT EXISTS (SELECT 1 FROM package_dependents e WHERE e.version_id = v.id)
+        ON CONFLICT DO NOTHING
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 42. Webhook Delay
+    // While an account has a protected package, a new webhook only starts getting events at
+    // `active_at` and a deleted one keeps getting them until `delete_at` (see
+    // handlers::webhook). Otherwise a stolen session could point the approval link at itself.
+    sqlx::query(
+        r#"
+        ALTER TABLE webhooks
+        ADD COLUMN IF NOT EXISTS active_at BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN IF NOT EXISTS delete_at BIGINT
+    "#,
+    )
+    .execute(&pool)
+    .await?;
+
     Ok(pool)
 }
+
+/// How long a version may sit in 'pending' before we give up on its upload.
+/// An hour is plenty for even a slow connection, and short enough that a typo'd version
+/// number doesn't stay reserved all day.
+pub const PENDING_VERSION_TTL_SECS: i64 = 60 * 60;
+
+/// Deletes pending versions older than [`PENDING_VERSION_TTL_SECS`]. Returns how many went.
+/// Their version numbers become free again, so the next publish can just retry.
+pub async fn expire_pending_versions(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let cutoff = chrono::Utc::now().timestamp() - PENDING_VERSION_TTL_SECS;
+    let result = sqlx::query("DELETE FROM package_versions WHERE status = 'pending' AND created_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// How many days count as "recent" for `recent_downloads`. Today included.
+pub const RECENT_DOWNLOAD_DAYS: i64 = 7;
+
+/// Recomputes every package's `recent_downloads` from the daily series. Returns how many changed.
+/// Only rows whose number actually moved get written, so a quiet registry costs one read.
+pub async fn refresh_recent_downloads(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let since = chrono::Utc::now().timestamp() / 86400 - (RECENT_DOWNLOAD_DAYS - 1);
+    let result = sqlx::query(
+        r#"
+        UPDATE packages p SET recent_downloads = r.total
+        FROM (
+            SELECT pk.id, COALESCE(SUM(d.count), 0)::BIGINT AS total
+            FROM packages pk
+            LEFT JOIN package_downloads d ON d.package_id = pk.id AND d.day >= $1
+            GROUP BY pk.id
+        ) r
+        WHERE r.id = p.id AND p.recent_downloads <> r.total
+    "#,
+    )
+    .bind(since)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
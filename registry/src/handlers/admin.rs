@@ -0,0 +1,793 @@
+use crate::middleware::admin::AdminUser;
+use crate::models::admin::{AdminActionRequest, AuditEntry, NameRule, NameRuleRequest};
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Appends to the audit log. A failure here is logged but doesn't undo the action—
+/// we'd rather have a takedown without a log line than a malware package that stays up.
+pub(crate) async fn record(state: &AppState, action: &str, target: &str, reason: Option<&str>) {
+    let result = sqlx::query(
+        "INSERT INTO admin_audit_log (action, target, reason, created_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(action)
+    .bind(target)
+    .bind(reason)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to write audit log for {} {}: {}", action, target, e);
+    }
+    tracing::warn!("Admin action: {} {} ({})", action, target, reason.unwrap_or("no reason"));
+}
+
+/// Hides a package from listings and stops its blobs from serving. Reversible with restore.
+pub async fn takedown_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(name): Path<String>,
+    payload: Option<Json<AdminActionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+
+    let result = sqlx::query("UPDATE packages SET taken_down = TRUE, takedown_reason = $2 WHERE name = $1")
+        .bind(&name)
+        .bind(&payload.reason)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Package not found"})),
+        ),
+        Ok(_) => {
+            record(&state, "takedown", &name, payload.reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("{} has been taken down", name)})),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Undoes a takedown.
+pub async fn restore_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(name): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = sqlx::query("UPDATE packages SET taken_down = FALSE, takedown_reason = NULL WHERE name = $1")
+        .bind(&name)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Package not found"})),
+        ),
+        Ok(_) => {
+            record(&state, "restore", &name, None).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("{} has been restored", name)})),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Features a package: a badge on the website and CLI, and a bump in search relevance.
+pub async fn feature_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(name): Path<String>,
+    payload: Option<Json<AdminActionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+    set_featured(state, name, true, payload.reason).await
+}
+
+/// Takes a package off the featured list.
+pub async fn unfeature_package(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(name): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    set_featured(state, name, false, None).await
+}
+
+async fn set_featured(
+    state: AppState,
+    name: String,
+    featured: bool,
+    reason: Option<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = sqlx::query("UPDATE packages SET featured = $2 WHERE name = $1")
+        .bind(&name)
+        .bind(featured)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Package not found"})),
+        ),
+        Ok(_) => {
+            let action = if featured { "feature" } else { "unfeature" };
+            record(&state, action, &name, reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("{} is {}", name, if featured { "now featured" } else { "no longer featured" })})),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Deletes a package outright so the name can be claimed again.
+///
+/// This is the one admin action you can't undo. Versions, tags, stars and diffs go with it
+/// (ON DELETE CASCADE), and we make a best-effort pass at deleting the blobs from storage.
+pub async fn release_name(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(name): Path<String>,
+    payload: Option<Json<AdminActionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+
+    // Grab the blob URLs before the rows disappear.
+    let sources: Vec<String> = sqlx::query_scalar(
+        "SELECT v.lua_source_url FROM package_versions v JOIN packages p ON p.id = v.package_id WHERE p.name = $1",
+    )
+    .bind(&name)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    let result = sqlx::query("DELETE FROM packages WHERE name = $1")
+        .bind(&name)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Package not found"})),
+        ),
+        Ok(_) => {
+            // Blobs shared with versions of other packages stay put.
+            for source in &sources {
+                if let Some(hash) = source.strip_prefix("/packages/blobs/")
+                    && let Err(e) =
+                        crate::utils::storage::release_blob(&state.db, &state.storage, hash).await
+                {
+                    tracing::error!("Failed to delete blob {} while releasing {}: {}", hash, name, e);
+                }
+            }
+            record(&state, "release-name", &name, payload.reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "message": format!("{} has been deleted and the name is free", name),
+                    "versions_deleted": sources.len()
+                })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Bans a user. They can't log in, and their live tokens stop working on the next request.
+/// Their packages stay up—take those down separately if they're the problem.
+pub async fn ban_user(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(username): Path<String>,
+    payload: Option<Json<AdminActionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+    set_banned(state, username, true, payload.reason).await
+}
+
+/// Lifts a ban.
+pub async fn unban_user(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(username): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    set_banned(state, username, false, None).await
+}
+
+async fn set_banned(
+    state: AppState,
+    username: String,
+    banned: bool,
+    reason: Option<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = sqlx::query("UPDATE users SET banned = $2 WHERE username = $1")
+        .bind(&username)
+        .bind(banned)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
+        ),
+        Ok(_) => {
+            let action = if banned { "ban" } else { "unban" };
+            record(&state, action, &username, reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("{} is now {}", username, if banned { "banned" } else { "unbanned" })})),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Marks an account as verified. Verified accounts get the larger publish quota (see utils::quota).
+pub async fn verify_user(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(username): Path<String>,
+    payload: Option<Json<AdminActionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+    set_verified(state, username, true, payload.reason).await
+}
+
+/// Takes verification away again.
+pub async fn unverify_user(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(username): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    set_verified(state, username, false, None).await
+}
+
+async fn set_verified(
+    state: AppState,
+    username: String,
+    verified: bool,
+    reason: Option<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = sqlx::query("UPDATE users SET verified = $2 WHERE username = $1")
+        .bind(&username)
+        .bind(verified)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
+        ),
+        Ok(_) => {
+            let action = if verified { "verify" } else { "unverify" };
+            record(&state, action, &username, reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("{} is now {}", username, if verified { "verified" } else { "unverified" })})),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Registry-wide counts for the operator dashboard (or `mosaic admin stats`).
+pub async fn stats(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let row: Result<(i64, i64, i64, i64, i64, i64), _> = sqlx::query_as(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM users),
+            (SELECT COUNT(*) FROM users WHERE banned),
+            (SELECT COUNT(*) FROM packages),
+            (SELECT COUNT(*) FROM packages WHERE taken_down),
+            (SELECT COUNT(*) FROM package_versions),
+            (SELECT COALESCE(SUM(download_count), 0)::BIGINT FROM packages)
+    "#,
+    )
+    .fetch_one(&state.db)
+    .await;
+
+    match row {
+        Ok((users, banned, packages, taken_down, versions, downloads)) => (
+            StatusCode::OK,
+            Json(json!({
+                "users": users,
+                "banned_users": banned,
+                "packages": packages,
+                "taken_down_packages": taken_down,
+                "versions": versions,
+                "downloads": downloads
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Downloads per CLI version and OS, from clients that opted in to `mosaic telemetry`.
+/// For deciding when it's safe to stop humoring old clients: once a version's share is
+/// down in the noise, whatever it needs can go.
+///
+/// Query parameters:
+/// - days: how far back to look, today included (default 30, max 365)
+///
+/// Only opted-in downloads are counted, so treat shares as a sample, not a census.
+pub async fn client_stats(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let days = params
+        .get("days")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(30)
+        .clamp(1, 365);
+    let since = chrono::Utc::now().timestamp() / 86400 - (days - 1);
+
+    let rows: Vec<(String, String, i64, i64)> = match sqlx::query_as(
+        r#"
+        SELECT cli_version, os, SUM(count)::BIGINT, MAX(day)
+        FROM client_downloads WHERE day >= $1
+        GROUP BY cli_version, os
+    "#,
+    )
+    .bind(since)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    // Roll the version x OS rows up both ways. Versions sort newest first by semver,
+    // not by string—"0.10.0" comes after "0.9.0".
+    let total: i64 = rows.iter().map(|r| r.2).sum();
+    let share = |n: i64| if total > 0 { n as f64 / total as f64 } else { 0.0 };
+
+    let mut versions: HashMap<&str, (i64, i64, HashMap<&str, i64>)> = HashMap::new();
+    let mut oses: HashMap<&str, i64> = HashMap::new();
+    for (version, os, count, last_day) in &rows {
+        let entry = versions.entry(version.as_str()).or_default();
+        entry.0 += count;
+        entry.1 = entry.1.max(*last_day);
+        *entry.2.entry(os.as_str()).or_default() += count;
+        *oses.entry(os.as_str()).or_default() += count;
+    }
+
+    let mut versions: Vec<_> = versions.into_iter().collect();
+    versions.sort_by(|a, b| {
+        let parse = |v: &str| semver::Version::parse(v).ok();
+        parse(b.0).cmp(&parse(a.0))
+    });
+    let versions: Vec<serde_json::Value> = versions
+        .into_iter()
+        .map(|(version, (downloads, last_day, by_os))| {
+            json!({
+                "version": version,
+                "downloads": downloads,
+                "share": share(downloads),
+                "last_seen": last_day * 86400,
+                "os": by_os
+            })
+        })
+        .collect();
+
+    let mut oses: Vec<_> = oses.into_iter().collect();
+    oses.sort_by_key(|(_, downloads)| std::cmp::Reverse(*downloads));
+    let oses: Vec<serde_json::Value> = oses
+        .into_iter()
+        .map(|(os, downloads)| json!({"os": os, "downloads": downloads, "share": share(downloads)}))
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "days": days,
+            "total": total,
+            "versions": versions,
+            "os": oses
+        })),
+    )
+}
+
+/// The tail of the audit log, newest last (like `tail`).
+///
+/// Query parameters:
+/// - limit: how many entries (default 20, max 200)
+/// - after: only entries with an id greater than this. Lets `--follow` poll without repeats.
+pub async fn audit_log(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let limit = params
+        .get("limit")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(20)
+        .clamp(1, 200);
+    let after = params
+        .get("after")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(0);
+
+    let entries = sqlx::query_as::<_, AuditEntry>(
+        r#"
+        SELECT * FROM (
+            SELECT * FROM admin_audit_log WHERE id > $1 ORDER BY id DESC LIMIT $2
+        ) recent ORDER BY id ASC
+    "#,
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await;
+
+    match entries {
+        Ok(entries) => (StatusCode::OK, Json(json!(entries))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+const NAME_RULE_KINDS: [&str; 5] = ["block", "allow", "reserved", "reserved_prefix", "approved"];
+
+/// Every name rule, grouped by kind. See migration 32 for what each kind does.
+pub async fn list_name_rules(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let rules = sqlx::query_as::<_, NameRule>(
+        r#"
+        SELECT r.kind, r.pattern, u.username, r.reason, r.created_at
+        FROM name_rules r LEFT JOIN users u ON u.id = r.user_id
+        ORDER BY r.kind, r.pattern
+    "#,
+    )
+    .fetch_all(&state.db)
+    .await;
+
+    match rules {
+        Ok(rules) => (StatusCode::OK, Json(json!(rules))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Adds a name rule (or updates its reason/user if it already exists) and reloads the cache,
+/// so it applies to the very next signup or publish. Existing packages aren't touched—
+/// blocking a name that's already taken is a takedown's job.
+pub async fn add_name_rule(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Json(payload): Json<NameRuleRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let kind = payload.kind.trim().replace('-', "_");
+    if !NAME_RULE_KINDS.contains(&kind.as_str()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Unknown rule kind '{}'. Use one of: {}", payload.kind, NAME_RULE_KINDS.join(", "))})),
+        );
+    }
+
+    // Names are matched lowercase, so rules are stored that way too.
+    let pattern = payload.pattern.trim().to_lowercase();
+    if pattern.is_empty() || pattern.len() > 64 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Pattern must be between 1 and 64 characters"})),
+        );
+    }
+
+    // An approval is for one person. Everyone else still gets the reserved-prefix error.
+    let user_id: Option<uuid::Uuid> = match (kind.as_str(), &payload.username) {
+        ("approved", Some(username)) => {
+            match sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+                .bind(username)
+                .fetch_optional(&state.db)
+                .await
+            {
+                Ok(Some(id)) => Some(id),
+                Ok(None) => {
+                    return (
+                        StatusCode::NOT_FOUND,
+                        Json(json!({"error": "User not found"})),
+                    );
+                }
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": e.to_string()})),
+                    );
+                }
+            }
+        }
+        ("approved", None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "An approval needs a username"})),
+            );
+        }
+        _ => None,
+    };
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO name_rules (kind, pattern, user_id, reason, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (kind, pattern) DO UPDATE SET user_id = EXCLUDED.user_id, reason = EXCLUDED.reason
+    "#,
+    )
+    .bind(&kind)
+    .bind(&pattern)
+    .bind(user_id)
+    .bind(&payload.reason)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await;
+
+    if let Err(e) = result {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        );
+    }
+
+    if let Err(e) = crate::utils::validation::reload_name_rules(&state.db).await {
+        tracing::error!("Failed to reload name rules: {}", e);
+    }
+    let target = format!("{}:{}", kind, pattern);
+    record(&state, "name-rule.add", &target, payload.reason.as_deref()).await;
+    (
+        StatusCode::OK,
+        Json(json!({"message": format!("Added {} rule '{}'", kind, pattern)})),
+    )
+}
+
+/// Removes a name rule and reloads the cache.
+pub async fn remove_name_rule(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path((kind, pattern)): Path<(String, String)>,
+    payload: Option<Json<AdminActionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+    let kind = kind.replace('-', "_");
+    let pattern = pattern.to_lowercase();
+
+    let result = sqlx::query("DELETE FROM name_rules WHERE kind = $1 AND pattern = $2")
+        .bind(&kind)
+        .bind(&pattern)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Rule not found"})),
+        ),
+        Ok(_) => {
+            if let Err(e) = crate::utils::validation::reload_name_rules(&state.db).await {
+                tracing::error!("Failed to reload name rules: {}", e);
+            }
+            let target = format!("{}:{}", kind, pattern);
+            record(&state, "name-rule.remove", &target, payload.reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("Removed {} rule '{}'", kind, pattern)})),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Consistency sweep over the whole registry. Report-only unless `?repair=true`.
+///
+/// Checks three things:
+/// 1. download_count vs the package_downloads series. Repair rewrites the counter from the series.
+/// 2. Versions stuck in 'pending' (registered, upload never finished). Repair deletes the ones
+///    past the pending TTL—anything newer might still be mid-upload—and releases their blobs.
+///    The background sweep does this too; repair just means you don't have to wait for it.
+///    'held' versions are waiting for their owner, not stuck, so they're left alone.
+/// 3. Versions pointing at a blob that isn't in storage. Never auto-repaired: deleting a version
+///    people depend on is a human decision. Use the report to go fix or take down by hand.
+///
+/// Runs inline. It's one HEAD request per version, which is fine at our scale and means the
+/// operator gets the answer in the response instead of polling a job table.
+pub async fn integrity_check(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let repair = params.get("repair").is_some_and(|v| v == "true");
+
+    // 1. Download counters
+    let drifted: Vec<(String, i64, i64)> = match sqlx::query_as(
+        r#"
+        SELECT p.name, p.download_count, COALESCE(SUM(d.count), 0)::BIGINT AS actual
+        FROM packages p LEFT JOIN package_downloads d ON d.package_id = p.id
+        GROUP BY p.id, p.name, p.download_count
+        HAVING p.download_count <> COALESCE(SUM(d.count), 0)
+    "#,
+    )
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    if repair && !drifted.is_empty() {
+        let result = sqlx::query(
+            r#"
+            UPDATE packages p SET download_count = COALESCE(
+                (SELECT SUM(d.count) FROM package_downloads d WHERE d.package_id = p.id), 0
+            )
+        "#,
+        )
+        .execute(&state.db)
+        .await;
+        if let Err(e) = result {
+            tracing::error!("Integrity job: failed to rewrite download counts: {}", e);
+        }
+    }
+
+    // 2 + 3. Walk every version once.
+    let versions: Vec<(uuid::Uuid, String, String, String, String, i64)> = match sqlx::query_as(
+        r#"
+        SELECT v.id, p.name, v.version, v.status, v.lua_source_url, v.created_at
+        FROM package_versions v JOIN packages p ON p.id = v.package_id
+        ORDER BY p.name, v.created_at
+    "#,
+    )
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let stale_cutoff = chrono::Utc::now().timestamp() - crate::db::PENDING_VERSION_TTL_SECS;
+    let mut incomplete = Vec::new();
+    let mut missing_blobs = Vec::new();
+    let mut unchecked = Vec::new();
+    let mut removed = 0;
+
+    for (id, name, version, status, source_url, created_at) in &versions {
+        let label = format!("{}@{}", name, version);
+
+        let hash = source_url.strip_prefix("/packages/blobs/");
+        // Only 'pending' is ever deleted. A 'held' version is waiting for its owner, and the
+        // old ones are exactly those past the approval delay.
+        if status == "pending" {
+            incomplete.push(label);
+            if repair && *created_at < stale_cutoff {
+                let deleted: Result<Option<String>, _> = sqlx::query_scalar(
+                    "DELETE FROM package_versions WHERE id = $1 AND status = 'pending' RETURNING lua_source_url",
+                )
+                .bind(id)
+                .fetch_optional(&state.db)
+                .await;
+                match deleted {
+                    Ok(Some(source_url)) => {
+                        removed += 1;
+                        if let Some(hash) = source_url.strip_prefix("/packages/blobs/")
+                            && let Err(e) = crate::utils::storage::release_blob(&state.db, &state.storage, hash).await
+                        {
+                            tracing::warn!("Integrity job: couldn't release blob {} of {}@{}: {}", hash, name, version, e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::error!("Integrity job: failed to delete {}@{}: {}", name, version, e),
+                }
+            }
+            continue;
+        }
+        let Some(hash) = hash else {
+            incomplete.push(label);
+            continue;
+        };
+
+        match state.storage.blob_exists(hash).await {
+            Ok(true) => {}
+            Ok(false) => missing_blobs.push(label),
+            Err(e) => {
+                tracing::warn!("Integrity job: couldn't check blob for {}: {}", label, e);
+                unchecked.push(label);
+            }
+        }
+    }
+
+    let drift_report: Vec<serde_json::Value> = drifted
+        .iter()
+        .map(|(name, stored, actual)| json!({"package": name, "stored": stored, "actual": actual}))
+        .collect();
+
+    if repair {
+        record(
+            &state,
+            "integrity-repair",
+            "registry",
+            Some(&format!(
+                "{} counters rewritten, {} incomplete versions removed",
+                drifted.len(),
+                removed
+            )),
+        )
+        .await;
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "repaired": repair,
+            "versions_checked": versions.len(),
+            "download_count_drift": drift_report,
+            "incomplete_versions": incomplete,
+            "incomplete_versions_removed": removed,
+            "missing_blobs": missing_blobs,
+            "unchecked": unchecked
+        })),
+    )
+}
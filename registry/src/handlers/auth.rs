@@ -1,8 +1,13 @@
 use crate::middleware::auth::AuthenticatedUser;
-use crate::models::user::{AuthResponse, Claims, LoginRequest, SignupRequest, User};
+use crate::models::timestamp::with_rfc3339;
+use crate::models::user::{AuthResponse, Claims, LoginRequest, RenameRequest, SignupRequest, User};
 use crate::state::AppState;
 use crate::utils::auth::{hash_password, verify_password};
-use axum::{Json, extract::State, http::StatusCode};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
 use jsonwebtoken::{EncodingKey, Header, encode};
 use serde_json::json;
 use std::env;
@@ -38,7 +43,7 @@ pub async fn signup(
             }
         };
 
-    if existing.is_some() {
+    if existing.is_some() || is_redirected(&state, &payload.username).await {
         return (
             StatusCode::CONFLICT,
             Json(json!({"error": "Username already taken"})),
@@ -100,6 +105,7 @@ pub async fn signup(
         username: user.username.clone(),
         exp: expiration,
         jti,
+        packages: None,
     };
 
     let token = match encode(
@@ -179,6 +185,15 @@ pub async fn login(
         }
     }
 
+    // 2.5 Banned accounts stop here. Checked after the password so a ban doesn't
+    // reveal that the username exists to someone guessing.
+    if user.banned {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "This account has been suspended"})),
+        );
+    }
+
     // 3. Generate JWT
     // Same logic as signup—7-day expiration.
     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
@@ -194,6 +209,7 @@ pub async fn login(
         username: user.username.clone(),
         exp: expiration,
         jti,
+        packages: None,
     };
 
     let token = match encode(
@@ -246,4 +262,246 @@ pub async fn logout(
             Json(json!({"error": format!("Logout failed: {}", e)})),
         ),
     }
-}
\ No newline at end of file
+}
+
+/// Whether `name` is somebody's old username. Those stay reserved for good.
+async fn is_redirected(state: &AppState, name: &str) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM username_redirects WHERE old_username = $1")
+        .bind(name)
+        .fetch_one(&state.db)
+        .await
+        .map(|n| n > 0)
+        .unwrap_or(true) // If we can't tell, don't hand the name out.
+}
+
+/// Renames the logged-in account.
+///
+/// 1. Validate the new name and re-check the password
+/// 2. Make sure nobody has (or had) the name—unless it's your own old name
+/// 3. In one transaction: rename, sync `packages.author`, leave a redirect behind
+/// 4. Revoke the token that made the request and hand back a new one with the new name
+///
+/// Every other token for the account stops working too (the middleware checks the name in the
+/// token against the account), so a rename doubles as "log out everywhere".
+pub async fn rename_username(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(payload): Json<RenameRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let new_name = payload.username.trim().to_string();
+
+    // 1. Validate and re-authenticate
+    if let Err(e) = crate::utils::validation::validate_username(&new_name) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+    if new_name == user.username {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "That's already your username"})),
+        );
+    }
+
+    let account = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(&user.username)
+        .fetch_optional(&state.db)
+        .await
+    {
+        Ok(Some(u)) => u,
+        Ok(None) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Account not found. Log in again."})),
+            );
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Database error: {}", e)})),
+            );
+        }
+    };
+    let Some(user_id) = account.id else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Account has no id"})),
+        );
+    };
+
+    if !matches!(verify_password(&payload.password, &account.password_hash), Ok(true)) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid credentials"})),
+        );
+    }
+
+    // 2. Availability. Your own old names are fair game—renaming back just drops the redirect.
+    let taken: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM users WHERE username = $1)
+            OR EXISTS (SELECT 1 FROM username_redirects WHERE old_username = $1 AND user_id <> $2)
+        "#,
+    )
+    .bind(&new_name)
+    .bind(user_id)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(true);
+
+    if taken {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Username already taken"})),
+        );
+    }
+
+    // 3. The rename itself. Ownership goes by owner_id, so this can't break anything—the
+    // author column is only synced so exports and the display fallback don't go stale.
+    let now = chrono::Utc::now().timestamp();
+    let renamed: Result<u64, sqlx::Error> = async {
+        let mut tx = state.db.begin().await?;
+        sqlx::query("UPDATE users SET username = $1 WHERE id = $2")
+            .bind(&new_name)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let packages = sqlx::query("UPDATE packages SET author = $1 WHERE owner_id = $2")
+            .bind(&new_name)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM username_redirects WHERE old_username = $1")
+            .bind(&new_name)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO username_redirects (old_username, user_id, renamed_at) VALUES ($1, $2, $3)",
+        )
+        .bind(&user.username)
+        .bind(user_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(packages)
+    }
+    .await;
+
+    let packages = match renamed {
+        Ok(n) => n,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Rename failed: {}", e)})),
+            );
+        }
+    };
+
+    crate::handlers::admin::record(
+        &state,
+        "user.rename",
+        &user.username,
+        Some(&format!("renamed to {}", new_name)),
+    )
+    .await;
+
+    // 4. Swap tokens. The old one carries the old name, so it's dead weight now anyway.
+    let _ = sqlx::query("INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2)")
+        .bind(user.jti)
+        .bind(user.exp)
+        .execute(&state.db)
+        .await;
+
+    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(chrono::Duration::days(7))
+        .expect("valid timestamp")
+        .timestamp();
+    let claims = Claims {
+        sub: user_id.to_string(),
+        username: new_name.clone(),
+        exp: expiration,
+        jti: Uuid::new_v4(),
+        packages: None,
+    };
+
+    let token = match encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    ) {
+        Ok(t) => t,
+        Err(_) => {
+            // The rename went through; they just need to log in with the new name.
+            return (
+                StatusCode::OK,
+                Json(json!({
+                    "message": "Renamed, but token generation failed. Log in again.",
+                    "username": new_name,
+                    "previous_username": user.username
+                })),
+            );
+        }
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "token": token,
+            "username": new_name,
+            "previous_username": user.username,
+            "packages_updated": packages
+        })),
+    )
+}
+
+/// Looks up a user by name, following renames.
+///
+/// `author` on old package metadata (mirrors, exports, screenshots of `mosaic info`) may be a name
+/// the user has since dropped. This tells you who it is now. `redirected_from` is set when it
+/// went through a redirect.
+pub async fn get_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let found: Result<Option<(String, i64, bool)>, _> = sqlx::query_as(
+        r#"
+        SELECT u.username, u.created_at, u.verified FROM users u
+        WHERE u.username = $1 AND NOT u.banned
+        UNION ALL
+        SELECT u.username, u.created_at, u.verified FROM username_redirects r
+        JOIN users u ON u.id = r.user_id
+        WHERE r.old_username = $1 AND NOT u.banned
+        LIMIT 1
+        "#,
+    )
+    .bind(&username)
+    .fetch_optional(&state.db)
+    .await;
+
+    match found {
+        Ok(Some((current, created_at, verified))) => {
+            let redirected_from = (current != username).then_some(username);
+            (
+                StatusCode::OK,
+                Json(with_rfc3339(json!({
+                    "username": current,
+                    "created_at": created_at,
+                    "verified": verified,
+                    "redirected_from": redirected_from
+                }))),
+            )
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "User not found"})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
@@ -0,0 +1,512 @@
+use crate::handlers::admin::record;
+use crate::middleware::admin::AdminUser;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::claim::{Claim, ClaimRequest, ResolveClaimRequest};
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The rules for handing a package to someone else. Published at `GET /claims/policy` so
+/// nobody has to guess, and so an admin can point at it when saying no.
+///
+/// Tunable from the environment, defaults in parentheses:
+/// CLAIM_ABANDONED_DAYS (365): no publish for this long and a package counts as abandoned.
+/// CLAIM_NOTICE_DAYS (14): how long the owner gets to speak up after a claim is opened.
+#[derive(Debug, Clone, Copy)]
+pub struct ClaimPolicy {
+    pub abandoned_after_days: i64,
+    pub notice_days: i64,
+}
+
+impl ClaimPolicy {
+    pub fn current() -> Self {
+        Self {
+            abandoned_after_days: env_days("CLAIM_ABANDONED_DAYS", 365),
+            notice_days: env_days("CLAIM_NOTICE_DAYS", 14),
+        }
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        json!({
+            "abandoned_after_days": self.abandoned_after_days,
+            "notice_days": self.notice_days,
+            "summary": format!(
+                "Reserved names go to whoever an admin approves. A published package only changes hands \
+                 if nothing has been published to it for {} days and its owner was given {} days' notice \
+                 of the claim. Trademark and legal disputes are decided case by case.",
+                self.abandoned_after_days, self.notice_days
+            )
+        })
+    }
+}
+
+fn env_days(key: &str, default: i64) -> i64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|v: &i64| *v >= 0)
+        .unwrap_or(default)
+}
+
+const CLAIM_SELECT: &str = r#"
+    SELECT c.id, c.name, u.username AS claimant, COALESCE(o.username, p.author) AS owner,
+           c.reason, c.status, c.resolution, c.created_at, c.resolved_at
+    FROM name_claims c
+    JOIN users u ON u.id = c.user_id
+    LEFT JOIN packages p ON p.name = c.name
+    LEFT JOIN users o ON o.id = p.owner_id
+"#;
+
+/// Who owns a package right now and when it last saw a publish (its creation, if never).
+async fn package_activity(
+    state: &AppState,
+    name: &str,
+) -> Result<Option<(Uuid, Option<Uuid>, Option<String>, i64)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT p.id, p.owner_id, COALESCE(u.username, p.author), COALESCE(MAX(v.created_at), p.created_at)
+        FROM packages p
+        LEFT JOIN users u ON u.id = p.owner_id
+        LEFT JOIN package_versions v ON v.package_id = p.id AND v.status = 'published'
+        WHERE p.name = $1
+        GROUP BY p.id, u.username
+        "#,
+    )
+    .bind(name)
+    .fetch_optional(&state.db)
+    .await
+}
+
+/// The claim policy, for the CLI and the website.
+pub async fn get_policy() -> (StatusCode, Json<serde_json::Value>) {
+    (StatusCode::OK, Json(ClaimPolicy::current().to_json()))
+}
+
+/// Asks for a name. Two cases:
+/// 1. It's reserved and nobody has published it. An approval lets the claimant create it.
+/// 2. Someone else owns it. An approval transfers it, but only within the policy.
+///
+/// A free name isn't claimable—just publish it. The current owner hears about a claim on
+/// their package straight away, which is what starts the notice period.
+pub async fn create_claim(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(payload): Json<ClaimRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    let name = payload.name.trim().to_lowercase();
+    if let Err(e) = crate::utils::validation::validate_package_name(&name) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    let reason = payload.reason.trim();
+    if reason.len() < 10 || reason.len() > 2000 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Explain the claim in 10 to 2000 characters. An admin is going to read it."})),
+        );
+    }
+
+    let package = match package_activity(&state, &name).await {
+        Ok(p) => p,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let policy = ClaimPolicy::current();
+    let mut owner = None;
+    let mut abandoned = None;
+    match &package {
+        Some((_, owner_id, _, _)) if *owner_id == Some(user_id) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "You already own this package"})),
+            );
+        }
+        Some((_, _, owner_name, last_activity)) => {
+            owner = owner_name.clone();
+            let idle_days = (chrono::Utc::now().timestamp() - last_activity) / 86400;
+            abandoned = Some(idle_days >= policy.abandoned_after_days);
+        }
+        None if !crate::utils::validation::is_reserved(&name) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Nobody has this name and it isn't reserved. Just publish it."})),
+            );
+        }
+        None if crate::utils::validation::reservation(&name, &user.user_id).is_none() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "This name is already approved for you. Go ahead and publish it."})),
+            );
+        }
+        None => {}
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let id: Result<i64, sqlx::Error> = sqlx::query_scalar(
+        "INSERT INTO name_claims (name, user_id, reason, created_at) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(&name)
+    .bind(user_id)
+    .bind(reason)
+    .bind(now)
+    .fetch_one(&state.db)
+    .await;
+
+    let id = match id {
+        Ok(id) => id,
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "You already have an open claim for this name"})),
+            );
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    if let Some(owner) = &owner {
+        crate::utils::notify::notify_owner(
+            state.db.clone(),
+            owner.clone(),
+            crate::utils::notify::EVENT_NAME_CLAIMED,
+            json!({
+                "package": name,
+                "claim_id": id,
+                "claimant": user.username,
+                "notice_days": policy.notice_days,
+                "claimed_at": now
+            }),
+        );
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "message": format!("Claim #{} for {} is waiting for an admin", id, name),
+            "id": id,
+            "status": "pending",
+            "owner": owner,
+            "abandoned": abandoned,
+            "policy": policy.to_json()
+        })),
+    )
+}
+
+/// The logged-in user's claims, newest first.
+pub async fn list_my_claims(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    let claims = sqlx::query_as::<_, Claim>(&format!(
+        "{} WHERE c.user_id = $1 ORDER BY c.id DESC",
+        CLAIM_SELECT
+    ))
+    .bind(user_id)
+    .fetch_all(&state.db)
+    .await;
+
+    match claims {
+        Ok(claims) => (StatusCode::OK, Json(json!(claims))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Claims for review. `?status=` filters (default: pending), oldest first so the queue is fair.
+pub async fn list_claims(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let status = params.get("status").map(String::as_str).unwrap_or("pending");
+
+    let claims = sqlx::query_as::<_, Claim>(&format!(
+        "{} WHERE c.status = $1 OR $1 = 'all' ORDER BY c.id ASC LIMIT 200",
+        CLAIM_SELECT
+    ))
+    .bind(status)
+    .fetch_all(&state.db)
+    .await;
+
+    match claims {
+        Ok(claims) => (StatusCode::OK, Json(json!(claims))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Approves a claim.
+///
+/// 1. Unpublished reserved name: the claimant gets an `approved` name rule and can publish it.
+/// 2. Existing package: ownership moves to the claimant, as long as the package is abandoned
+///    and the owner's notice period is over. `force` skips both and is logged as such.
+///
+/// Either way, any other open claims for the name are closed.
+pub async fn approve_claim(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+    payload: Option<Json<ResolveClaimRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+
+    let claim: Option<(String, Uuid, String, String, i64)> = match sqlx::query_as(
+        r#"
+        SELECT c.name, c.user_id, u.username, c.status, c.created_at
+        FROM name_claims c JOIN users u ON u.id = c.user_id
+        WHERE c.id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let Some((name, claimant_id, claimant, status, claimed_at)) = claim else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Claim not found"})),
+        );
+    };
+    if status != "pending" {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": format!("Claim is already {}", status)})),
+        );
+    }
+
+    let package = match package_activity(&state, &name).await {
+        Ok(p) => p,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let policy = ClaimPolicy::current();
+
+    if let Some((_, _, _, last_activity)) = &package {
+        let idle_days = (now - last_activity) / 86400;
+        let notice_ends = claimed_at + policy.notice_days * 86400;
+        if !payload.force && idle_days < policy.abandoned_after_days {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": format!(
+                    "{} isn't abandoned under the policy: last publish {} days ago, the threshold is {}. Pass force to override.",
+                    name, idle_days, policy.abandoned_after_days
+                )})),
+            );
+        }
+        if !payload.force && now < notice_ends {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": format!(
+                    "The owner's notice period runs for another {} days. Pass force to override.",
+                    (notice_ends - now + 86399) / 86400
+                )})),
+            );
+        }
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let granted = match &package {
+        // author is kept in sync for the display fallback, same as a username rename does.
+        Some((package_id, _, _, _)) => {
+            sqlx::query("UPDATE packages SET owner_id = $1, author = $2, updated_at = $3 WHERE id = $4")
+                .bind(claimant_id)
+                .bind(&claimant)
+                .bind(now)
+                .bind(package_id)
+                .execute(&mut *tx)
+                .await
+        }
+        None => {
+            sqlx::query(
+                r#"
+                INSERT INTO name_rules (kind, pattern, user_id, reason, created_at)
+                VALUES ('approved', $1, $2, $3, $4)
+                ON CONFLICT (kind, pattern) DO UPDATE SET user_id = EXCLUDED.user_id, reason = EXCLUDED.reason
+                "#,
+            )
+            .bind(&name)
+            .bind(claimant_id)
+            .bind(format!("claim #{}", id))
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+        }
+    };
+    let approved = sqlx::query(
+        "UPDATE name_claims SET status = 'approved', resolution = $2, resolved_at = $3 WHERE id = $1",
+    )
+    .bind(id)
+    .bind(&payload.reason)
+    .bind(now)
+    .execute(&mut *tx)
+    .await;
+    let superseded = sqlx::query(
+        r#"
+        UPDATE name_claims SET status = 'rejected', resolution = $3, resolved_at = $4
+        WHERE name = $1 AND id <> $2 AND status = 'pending'
+        "#,
+    )
+    .bind(&name)
+    .bind(id)
+    .bind(format!("Superseded by claim #{}", id))
+    .bind(now)
+    .execute(&mut *tx)
+    .await;
+
+    if let Err(e) = granted.and(approved).and(superseded) {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        );
+    }
+    if let Err(e) = tx.commit().await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        );
+    }
+
+    if package.is_none()
+        && let Err(e) = crate::utils::validation::reload_name_rules(&state.db).await
+    {
+        tracing::error!("Failed to reload name rules: {}", e);
+    }
+
+    let previous_owner = package.as_ref().and_then(|(_, _, owner, _)| owner.clone());
+    if let Some(previous) = &previous_owner {
+        crate::utils::notify::notify_owner(
+            state.db.clone(),
+            previous.clone(),
+            crate::utils::notify::EVENT_OWNERSHIP_CHANGED,
+            json!({"package": name, "new_owner": claimant, "claim_id": id, "changed_at": now}),
+        );
+    }
+    crate::utils::notify::notify_owner(
+        state.db.clone(),
+        claimant.clone(),
+        crate::utils::notify::EVENT_CLAIM_RESOLVED,
+        json!({"package": name, "claim_id": id, "status": "approved", "reason": payload.reason}),
+    );
+
+    let audit_reason = match (&payload.reason, payload.force) {
+        (Some(r), true) => Some(format!("forced: {}", r)),
+        (None, true) => Some("forced".to_string()),
+        (r, false) => r.clone(),
+    };
+    record(&state, "claim.approve", &format!("{} -> {}", name, claimant), audit_reason.as_deref()).await;
+
+    let message = match &previous_owner {
+        Some(previous) => format!("{} moved from {} to {}", name, previous, claimant),
+        None if package.is_some() => format!("{} now belongs to {}", name, claimant),
+        None => format!("{} can now publish {}", claimant, name),
+    };
+    (StatusCode::OK, Json(json!({"message": message, "transferred": package.is_some()})))
+}
+
+/// Rejects a claim. The reason is shown to the claimant, so keep it civil.
+pub async fn reject_claim(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+    payload: Option<Json<ResolveClaimRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Json(payload) = payload.unwrap_or_default();
+
+    let rejected: Result<Option<(String, String)>, _> = sqlx::query_as(
+        r#"
+        UPDATE name_claims c SET status = 'rejected', resolution = $2, resolved_at = $3
+        FROM users u
+        WHERE c.id = $1 AND c.status = 'pending' AND u.id = c.user_id
+        RETURNING c.name, u.username
+        "#,
+    )
+    .bind(id)
+    .bind(&payload.reason)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(&state.db)
+    .await;
+
+    match rejected {
+        Ok(Some((name, claimant))) => {
+            crate::utils::notify::notify_owner(
+                state.db.clone(),
+                claimant.clone(),
+                crate::utils::notify::EVENT_CLAIM_RESOLVED,
+                json!({"package": name, "claim_id": id, "status": "rejected", "reason": payload.reason}),
+            );
+            record(&state, "claim.reject", &format!("{} -> {}", name, claimant), payload.reason.as_deref()).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": format!("Rejected claim #{} for {}", id, name)})),
+            )
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No pending claim with that id"})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
@@ -0,0 +1,329 @@
+use crate::handlers::admin::record;
+use crate::middleware::admin::AdminUser;
+use crate::state::AppState;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use base64::Engine;
+use serde_json::json;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Bumped whenever the dump layout changes in a way an older import can't handle.
+const DUMP_FORMAT: i64 = 1;
+
+/// Tables in the order they have to be imported (parents before children).
+/// Webhooks are left out on purpose—their secrets shouldn't leave the box.
+/// The audit log stays with the instance that wrote it.
+/// Revoked tokens come along so a logged-out token stays dead on the new host, which verifies
+/// with the same JWT_SECRET. API tokens are only their ids, names and scopes, not the tokens
+/// themselves; without them the tokens would keep working there but couldn't be listed or revoked.
+const TABLES: [&str; 13] = [
+    "users",
+    "username_redirects",
+    "api_tokens",
+    "name_rules",
+    "name_claims",
+    "packages",
+    "package_downloads",
+    "package_versions",
+    "dist_tags",
+    "stars",
+    "package_members",
+    "revoked_tokens",
+    "client_downloads",
+];
+
+/// Streams the whole registry as newline-delimited JSON.
+///
+/// One record per line: a header, then `{"type": "<table>", "row": {...}}` for every row,
+/// then (if asked) `{"type": "blob", "hash": ..., "data": <base64>}` for every package blob.
+/// NDJSON because it streams, and because you can `grep`/`jq` it without loading 2GB into memory.
+///
+/// Query parameters:
+/// - password_hashes=true: include users' password hashes. Off by default; without them
+///   imported users exist (so package ownership survives) but can't log in.
+/// - blobs=true: include the package zips. Off by default because it's the bulk of the size.
+pub async fn export_dump(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let include_hashes = params.get("password_hashes").is_some_and(|v| v == "true");
+    let include_blobs = params.get("blobs").is_some_and(|v| v == "true");
+
+    record(
+        &state,
+        "export",
+        "registry",
+        Some(&format!("password_hashes={} blobs={}", include_hashes, include_blobs)),
+    )
+    .await;
+
+    // Small buffer so a slow client applies backpressure to the DB reads instead of us buffering everything.
+    let (tx, rx) = mpsc::channel::<Result<String, std::io::Error>>(64);
+
+    tokio::spawn(async move {
+        if let Err(e) = write_dump(&state, &tx, include_hashes, include_blobs).await {
+            tracing::error!("Export failed: {}", e);
+            // Surface the failure to the client as a truncated stream rather than a silent success.
+            let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    (
+        StatusCode::OK,
+        [("content-type", "application/x-ndjson")],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+}
+
+async fn write_dump(
+    state: &AppState,
+    tx: &mpsc::Sender<Result<String, std::io::Error>>,
+    include_hashes: bool,
+    include_blobs: bool,
+) -> anyhow::Result<()> {
+    let send = |value: serde_json::Value| async move {
+        tx.send(Ok(format!("{}\n", value)))
+            .await
+            .map_err(|_| anyhow::anyhow!("client went away"))
+    };
+
+    send(json!({
+        "type": "header",
+        "format": DUMP_FORMAT,
+        "exported_at": chrono::Utc::now().timestamp(),
+        "password_hashes": include_hashes,
+        "blobs": include_blobs
+    }))
+    .await?;
+
+    for table in TABLES {
+        // row_to_json means we don't have to keep a struct in sync with every migration.
+        // Table names come from the const above, never from the request.
+        let mut query = format!("SELECT row_to_json(t)::jsonb FROM {} t", table);
+        // A revocation only matters until the token would have expired anyway.
+        if table == "revoked_tokens" {
+            query.push_str(&format!(" WHERE t.expires_at > {}", chrono::Utc::now().timestamp()));
+        }
+        let mut rows = sqlx::query_scalar::<_, serde_json::Value>(&query).fetch(&state.db);
+
+        while let Some(row) = rows.next().await {
+            let mut row = row?;
+            if table == "users"
+                && !include_hashes
+                && let Some(obj) = row.as_object_mut()
+            {
+                obj.remove("password_hash");
+            }
+            // Derived from the other columns, and rebuilt by trigger on import.
+            if table == "packages"
+                && let Some(obj) = row.as_object_mut()
+            {
+                obj.remove("search_vector");
+            }
+            send(json!({"type": table, "row": row})).await?;
+        }
+    }
+
+    if include_blobs {
+        let sources: Vec<String> =
+            sqlx::query_scalar("SELECT DISTINCT lua_source_url FROM package_versions")
+                .fetch_all(&state.db)
+                .await?;
+
+        for source in sources {
+            let Some(hash) = source.strip_prefix("/packages/blobs/") else {
+                continue;
+            };
+            // A missing blob shouldn't kill a 10k-package export. Log it and move on.
+            match state.storage.get_blob(hash).await {
+                Ok(data) => {
+                    send(json!({
+                        "type": "blob",
+                        "hash": hash,
+                        "data": base64::engine::general_purpose::STANDARD.encode(data)
+                    }))
+                    .await?
+                }
+                Err(e) => tracing::error!("Export: blob {} unreadable, skipping: {}", hash, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads a dump produced by `export_dump` into this registry.
+///
+/// Only works on an empty registry (no users, no packages). Merging two live registries is
+/// a conflict-resolution problem we're not going to solve in an HTTP handler.
+/// Rows go in inside a single transaction, so a bad dump leaves the database untouched.
+/// Blobs are uploaded as we go and aren't rolled back—they're content-addressed, so
+/// leftovers are harmless and get reused if you retry.
+pub async fn import_dump(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    body: Body,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let existing: i64 = sqlx::query_scalar(
+        "SELECT (SELECT COUNT(*) FROM users) + (SELECT COUNT(*) FROM packages)",
+    )
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(1);
+
+    if existing > 0 {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Import only works on an empty registry"})),
+        );
+    }
+
+    match run_import(&state, body).await {
+        Ok(counts) => {
+            record(&state, "import", "registry", Some(&counts.to_string())).await;
+            (
+                StatusCode::OK,
+                Json(json!({"message": "Import complete", "imported": counts})),
+            )
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Import failed: {}", e)})),
+        ),
+    }
+}
+
+async fn run_import(state: &AppState, body: Body) -> anyhow::Result<serde_json::Value> {
+    let mut tx = state.db.begin().await?;
+    let mut counts: HashMap<String, i64> = HashMap::new();
+    let mut saw_header = false;
+
+    let mut stream = body.into_data_stream();
+    let mut pending = Vec::new();
+
+    // Split the body into lines ourselves: chunks don't line up with records,
+    // and a single blob line can be several MB.
+    loop {
+        let chunk = stream.next().await.transpose()?;
+        let done = chunk.is_none();
+        if let Some(chunk) = chunk {
+            pending.extend_from_slice(&chunk);
+        }
+
+        while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=pos).collect();
+            import_line(state, &mut tx, &line, &mut saw_header, &mut counts).await?;
+        }
+
+        if done {
+            // Last line without a trailing newline.
+            if !pending.is_empty() {
+                let line = std::mem::take(&mut pending);
+                import_line(state, &mut tx, &line, &mut saw_header, &mut counts).await?;
+            }
+            break;
+        }
+    }
+
+    if !saw_header {
+        anyhow::bail!("missing header line. Is this a Mosaic export?");
+    }
+
+    // Claims come in with their ids. Move the sequence past them, or the next claim filed
+    // here gets id 1 all over again.
+    sqlx::query(
+        "SELECT setval(pg_get_serial_sequence('name_claims', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM name_claims",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    if counts.contains_key("name_rules")
+        && let Err(e) = crate::utils::validation::reload_name_rules(&state.db).await
+    {
+        tracing::error!("Failed to reload name rules after import: {}", e);
+    }
+    Ok(json!(counts))
+}
+
+async fn import_line(
+    state: &AppState,
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    line: &[u8],
+    saw_header: &mut bool,
+    counts: &mut HashMap<String, i64>,
+) -> anyhow::Result<()> {
+    let line = std::str::from_utf8(line)?.trim();
+    if line.is_empty() {
+        return Ok(());
+    }
+
+    let record: serde_json::Value = serde_json::from_str(line)?;
+    let kind = record["type"].as_str().unwrap_or_default();
+
+    if !*saw_header {
+        if kind != "header" {
+            anyhow::bail!("first line must be the export header");
+        }
+        if record["format"].as_i64() != Some(DUMP_FORMAT) {
+            anyhow::bail!("unsupported dump format {}", record["format"]);
+        }
+        *saw_header = true;
+        return Ok(());
+    }
+
+    match kind {
+        "blob" => {
+            let hash = record["hash"].as_str().unwrap_or_default();
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(record["data"].as_str().unwrap_or_default())?;
+
+            // Don't take the dump's word for it—blobs are content-addressed, so check.
+            let actual = {
+                use sha2::{Digest, Sha256};
+                format!("{:x}", Sha256::digest(&data))
+            };
+            if actual != hash {
+                anyhow::bail!("blob {} doesn't match its hash", hash);
+            }
+            state.storage.upload_blob(hash, data).await?;
+        }
+        table if TABLES.contains(&table) => {
+            let mut row = record["row"].clone();
+
+            // Users exported without hashes get one that no password can match.
+            // They keep their packages; an operator can reset them later.
+            if table == "users"
+                && let Some(obj) = row.as_object_mut()
+            {
+                obj.entry("password_hash").or_insert(json!("!"));
+            }
+
+            // The empty registry seeded the default name rules. The dump's rules replace them
+            // rather than merge, so a default the old registry deleted stays deleted.
+            if table == "name_rules" && !counts.contains_key("name_rules") {
+                sqlx::query("DELETE FROM name_rules").execute(&mut **tx).await?;
+            }
+
+            // jsonb_populate_record maps JSON keys onto the table's columns by name,
+            // so the dump survives columns being added on either side.
+            let query = format!(
+                "INSERT INTO {table} SELECT * FROM jsonb_populate_record(NULL::{table}, $1)"
+            );
+            sqlx::query(&query).bind(row).execute(&mut **tx).await?;
+        }
+        other => anyhow::bail!("unknown record type '{}'", other),
+    }
+
+    *counts.entry(kind.to_string()).or_default() += 1;
+    Ok(())
+}
@@ -1,3 +1,10 @@
+pub mod admin;
 pub mod auth;
+pub mod claim;
+pub mod export;
 pub mod health;
 pub mod package;
+pub mod protection;
+pub mod token;
+pub mod visibility;
+pub mod webhook;
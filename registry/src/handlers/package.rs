@@ -1,18 +1,82 @@
-use askalono::Store;
+use crate::handlers::visibility::{PrivateAccess, signed_download_url, viewer_id, visible_to};
 use crate::middleware::auth::AuthenticatedUser;
-use crate::models::package::{DeprecatePackageRequest, Package, PackageVersion, PublishVersionRequest};
+use crate::models::package::{
+    DeprecatePackageRequest, LockStatusRequest, PACKAGE_COLUMNS, PACKAGE_FROM, Package,
+    PackageVersion, PublishVersionRequest, StarRequest, UploadSessionRequest,
+};
+use crate::models::timestamp::with_rfc3339;
 use crate::state::AppState;
+use crate::utils::telemetry::db_span;
 use axum::{
     Json,
     body::Bytes,
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
 use semver::Version;
 use serde_json::json;
 use sha2::{Digest, Sha256};
-use std::io::{Cursor, Read};
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Header carrying the token `create_version` handed out for this upload.
+const UPLOAD_TOKEN_HEADER: &str = "x-upload-token";
+
+/// Header carrying the SHA256 the client computed over the zip it's sending.
+const CONTENT_SHA256_HEADER: &str = "x-content-sha256";
+
+/// A fresh upload token and the hash we store for it. The raw token only ever lives in the response.
+pub(crate) fn new_upload_token() -> (String, String) {
+    // Same recipe as webhook secrets: two v4 UUIDs, 244 bits of randomness.
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let hash = format!("{:x}", Sha256::digest(token.as_bytes()));
+    (token, hash)
+}
+
+/// Points a dist-tag at a version that just became downloadable. A failure is logged, not
+/// returned: the version is out either way, and the next `mosaic publish --tag` moves the pointer.
+pub(crate) async fn point_tag(db: &PgPool, pkg_id: Uuid, package: &str, tag: &str, version: &str) {
+    if let Err(e) = sqlx::query(
+        r#"
+        INSERT INTO dist_tags (package_id, tag, version, updated_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (package_id, tag) DO UPDATE SET version = EXCLUDED.version, updated_at = EXCLUDED.updated_at
+        "#,
+    )
+    .bind(pkg_id)
+    .bind(tag)
+    .bind(version)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(db)
+    .instrument(db_span("set dist-tag"))
+    .await
+    {
+        tracing::error!("Failed to set dist-tag {} for {}: {}", tag, package, e);
+    }
+}
+
+/// Tells everyone watching a package that a version just became downloadable. Like `point_tag`,
+/// a failed lookup is logged, not returned: the publish already went through.
+pub(crate) async fn notify_watchers(db: &PgPool, package: &str, version: &str) {
+    let watchers = match crate::utils::notify::watchers(db, package).await {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::error!("Failed to look up watchers of {}: {}", package, e);
+            return;
+        }
+    };
+
+    for username in watchers {
+        crate::utils::notify::notify_owner(
+            db.clone(),
+            username,
+            crate::utils::notify::EVENT_VERSION_PUBLISHED,
+            json!({"package": package, "version": version}),
+        );
+    }
+}
 
 /// Helper to get the latest version for a package.
 ///
@@ -23,7 +87,7 @@ async fn get_latest_version(state: &AppState, pkg: &Package) -> String {
         return "0.0.0".to_string();
     };
 
-    let version: Option<String> = match sqlx::query_scalar("SELECT version FROM package_versions WHERE package_id = $1 ORDER BY created_at DESC LIMIT 1")
+    let version: Option<String> = match sqlx::query_scalar("SELECT version FROM package_versions WHERE package_id = $1 AND status = 'published' ORDER BY created_at DESC LIMIT 1")
         .bind(pkg_id)
         .fetch_optional(&state.db)
         .await {
@@ -34,14 +98,54 @@ async fn get_latest_version(state: &AppState, pkg: &Package) -> String {
     version.unwrap_or_else(|| "0.0.0".to_string())
 }
 
+/// SQL condition for "not a pre-release": no `-` before any `+build` suffix. `1.0.0-beta.1` fails
+/// it, `1.0.0+build-5` passes. Versions are validated as semver on publish, so that's all it takes.
+const STABLE_VERSION: &str = "split_part(version, '+', 1) NOT LIKE '%-%'";
+
+/// `?include_prereleases=false` on the versions and metadata endpoints. Anything else (including
+/// leaving it off) keeps pre-releases in, which is what every client before this flag expects.
+fn include_prereleases(params: &std::collections::HashMap<String, String>) -> bool {
+    params.get("include_prereleases").is_none_or(|v| v != "false")
+}
+
+/// Same test as [`STABLE_VERSION`], for versions we already have in hand.
+fn is_prerelease(version: &str) -> bool {
+    semver::Version::parse(version).is_ok_and(|v| !v.pre.is_empty())
+}
+
+/// Helper to get all dist-tags for a package as a tag -> version map.
+///
+/// Errors are swallowed into an empty map, same as get_latest_version.
+/// Tags are a nice-to-have on the package page, not worth failing the request over.
+async fn get_dist_tags(state: &AppState, pkg_id: Option<Uuid>) -> serde_json::Map<String, serde_json::Value> {
+    let Some(pkg_id) = pkg_id else {
+        return serde_json::Map::new();
+    };
+
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT tag, version FROM dist_tags WHERE package_id = $1")
+        .bind(pkg_id)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    rows.into_iter()
+        .map(|(tag, version)| (tag, serde_json::Value::String(version)))
+        .collect()
+}
+
 /// Lists all packages in the registry.
 ///
 /// No filtering, no search—just returns everything. Useful for browsing.
 /// Each result includes the latest version so clients can see what's current.
-pub async fn list_packages(State(state): State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
-    let packages = match sqlx::query_as::<_, Package>(
-        "SELECT id, name, description, author, repository, created_at, updated_at, download_count, deprecated, deprecation_reason FROM packages"
-    )
+pub async fn list_packages(
+    State(state): State<AppState>,
+    viewer: Option<AuthenticatedUser>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let packages = match sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM {} WHERE NOT p.taken_down AND {}",
+        PACKAGE_COLUMNS, PACKAGE_FROM, visible_to(1)
+    ))
+        .bind(viewer_id(viewer.as_ref()))
         .fetch_all(&state.db)
         .await
     {
@@ -65,27 +169,36 @@ pub async fn list_packages(State(state): State<AppState>) -> (StatusCode, Json<s
             "version": version,
             "repository": pkg.repository,
             "download_count": pkg.download_count,
+            "recent_downloads": pkg.recent_downloads,
+            "created_at": pkg.created_at,
+            "updated_at": pkg.updated_at,
+            "stars": pkg.stars,
+            "featured": pkg.featured,
+            "verified": pkg.verified,
             "deprecated": pkg.deprecated,
             "deprecation_reason": pkg.deprecation_reason
         }));
     }
 
-    (StatusCode::OK, Json(json!(results)))
+    (StatusCode::OK, Json(with_rfc3339(json!(results))))
 }
 
 /// Searches for packages by name/description.
 ///
 /// Supports query parameters:
 /// - q: search term (uses Postgres full-text search)
-/// - sort: "downloads" | "newest" | "updated" (default: "updated")
+/// - sort: "downloads" | "trending" | "newest" | "updated" (default: "updated")
+///   trending is downloads over the last week, so a new package can outrank an old giant.
 /// - limit: how many results (capped at 100 for sanity)
 ///
 /// If no query, just returns packages sorted by your preference.
 /// If query is provided, uses Postgres's websearch_to_tsquery for better results.
 pub async fn search_packages(
     State(state): State<AppState>,
+    viewer: Option<AuthenticatedUser>,
     axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    let viewer = viewer_id(viewer.as_ref());
     let q = params.get("q").map(|s| s.as_str()).unwrap_or("");
     let sort = params.get("sort").map(|s| s.as_str()).unwrap_or("updated");
     let limit = params
@@ -95,20 +208,22 @@ pub async fn search_packages(
         .min(100);
 
     let order_clause = match sort {
-        "downloads" => "download_count DESC",
-        "newest" => "created_at DESC",
-        "updated" => "updated_at DESC",
-        _ => "updated_at DESC", // Default
+        "downloads" => "p.download_count DESC",
+        "trending" => "p.recent_downloads DESC, p.download_count DESC",
+        "newest" => "p.created_at DESC",
+        "updated" => "p.updated_at DESC",
+        _ => "p.updated_at DESC", // Default
     };
 
     let packages = if q.is_empty() {
         // No search query—just return sorted results
         let query_str = format!(
-            "SELECT id, name, description, author, repository, created_at, updated_at, download_count, deprecated, deprecation_reason FROM packages ORDER BY {} LIMIT $1", 
-            order_clause
+            "SELECT {} FROM {} WHERE NOT p.taken_down AND {} ORDER BY {} LIMIT $1",
+            PACKAGE_COLUMNS, PACKAGE_FROM, visible_to(2), order_clause
         );
         match sqlx::query_as::<_, Package>(&query_str)
             .bind(limit)
+            .bind(viewer)
             .fetch_all(&state.db)
             .await
         {
@@ -130,23 +245,28 @@ pub async fn search_packages(
         let order_sql = if params.contains_key("sort") {
             order_clause
         } else {
-            // Default to relevance ranking when searching
-            "ts_rank(to_tsvector('english', name || ' ' || description), websearch_to_tsquery('english', $1)) DESC"
+            // Default to relevance ranking when searching. ts_rank weighs name hits over
+            // description hits over readme hits (see the search_vector migration). Featured
+            // packages and verified publishers get a bump—enough to win a close call, not
+            // enough to bury a better match.
+            "ts_rank(p.search_vector, websearch_to_tsquery('english', $1)) \
+             * (1 + CASE WHEN p.featured THEN 0.5 ELSE 0 END + CASE WHEN u.verified THEN 0.25 ELSE 0 END) DESC"
         };
 
         let query_str = format!(
             r#"
-            SELECT id, name, description, author, repository, created_at, updated_at, download_count, deprecated, deprecation_reason FROM packages 
-            WHERE to_tsvector('english', name || ' ' || description) @@ websearch_to_tsquery('english', $1)
+            SELECT {} FROM {}
+            WHERE NOT p.taken_down AND p.search_vector @@ websearch_to_tsquery('english', $1) AND {}
             ORDER BY {}
             LIMIT $2
             "#,
-            order_sql
+            PACKAGE_COLUMNS, PACKAGE_FROM, visible_to(3), order_sql
         );
 
         match sqlx::query_as::<_, Package>(&query_str)
             .bind(q)
             .bind(limit)
+            .bind(viewer)
             .fetch_all(&state.db)
             .await
         {
@@ -171,22 +291,34 @@ pub async fn search_packages(
             "version": version,
             "repository": pkg.repository,
             "download_count": pkg.download_count,
+            "recent_downloads": pkg.recent_downloads,
+            "created_at": pkg.created_at,
+            "updated_at": pkg.updated_at,
+            "stars": pkg.stars,
+            "featured": pkg.featured,
+            "verified": pkg.verified,
             "deprecated": pkg.deprecated,
             "deprecation_reason": pkg.deprecation_reason
         }));
     }
 
-    (StatusCode::OK, Json(json!(results)))
+    (StatusCode::OK, Json(with_rfc3339(json!(results))))
 }
 
 /// Gets a single package by name.
+///
+/// `?include_prereleases=false` makes `version` the newest stable release and drops dist-tags that
+/// point at a pre-release, so a client resolving "latest" never lands on `2.0.0-alpha.1`.
 pub async fn get_package(
     State(state): State<AppState>,
     Path(name): Path<String>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let package = match sqlx::query_as::<_, Package>(
-        "SELECT id, name, description, author, repository, created_at, updated_at, download_count, deprecated, deprecation_reason FROM packages WHERE name = $1"
-    )
+    let include_prereleases = include_prereleases(&params);
+    let package = match sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM {} WHERE p.name = $1 AND NOT p.taken_down",
+        PACKAGE_COLUMNS, PACKAGE_FROM
+    ))
         .bind(name)
         .fetch_optional(&state.db)
         .await
@@ -204,10 +336,12 @@ pub async fn get_package(
     match package {
         Some(p) => {
             // Fetch the latest version AND its readme
-            let latest_version = match sqlx::query_as::<_, PackageVersion>(
-                "SELECT * FROM package_versions WHERE package_id = $1 ORDER BY created_at DESC LIMIT 1"
-            )
+            let latest_version = match sqlx::query_as::<_, PackageVersion>(&format!(
+                "SELECT * FROM package_versions WHERE package_id = $1 AND status = 'published' AND ($2 OR {}) ORDER BY created_at DESC LIMIT 1",
+                STABLE_VERSION
+            ))
             .bind(p.id)
+            .bind(include_prereleases)
             .fetch_optional(&state.db)
             .await {
                 Ok(v) => v,
@@ -219,9 +353,14 @@ pub async fn get_package(
                 None => ("0.0.0".to_string(), None, None),
             };
 
+            let mut dist_tags = get_dist_tags(&state, p.id).await;
+            if !include_prereleases {
+                dist_tags.retain(|_, v| v.as_str().is_some_and(|v| !is_prerelease(v)));
+            }
+
             (
                 StatusCode::OK,
-                Json(json!({
+                Json(with_rfc3339(json!({
                     "id": p.id,
                     "name": p.name,
                     "description": p.description,
@@ -230,12 +369,17 @@ pub async fn get_package(
                     "created_at": p.created_at,
                     "updated_at": p.updated_at,
                     "download_count": p.download_count,
+                    "stars": p.stars,
                     "version": version,
                     "readme": readme,
                     "license": license,
+                    "dist_tags": dist_tags,
+                    "featured": p.featured,
+                    "verified": p.verified,
                     "deprecated": p.deprecated,
-                    "deprecation_reason": p.deprecation_reason
-                })),
+                    "deprecation_reason": p.deprecation_reason,
+                    "visibility": p.visibility
+                }))),
             )
         }
         None => (
@@ -260,23 +404,62 @@ pub async fn create_package(
         return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
     }
 
+    // 0.1 A token restricted to some packages can only create those.
+    if !user.may_use(&payload.name) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": format!("This token isn't allowed to publish {}", payload.name)})),
+        );
+    }
+
+    // 0.25 Reserved names (and official-looking prefixes) need an admin to sign off on the
+    // exact name first. Anyone can ask through POST /claims.
+    if let Some(why) = crate::utils::validation::reservation(&payload.name, &user.user_id) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": format!(
+                    "{}. Request it with `mosaic claim {}` and a registry admin will review it.",
+                    why, payload.name
+                ),
+                "code": "reserved_name"
+            })),
+        );
+    }
+
+    // 0.5 Descriptions are plain text with a length cap. Markup is stripped rather than rejected.
+    let description = match crate::utils::sanitize::description(&payload.description) {
+        Ok(d) => d,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+
+    // 0.75 Private from the start, if asked (see handlers::visibility).
+    if !matches!(payload.visibility.as_str(), "public" | "private") {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Visibility must be \"public\" or \"private\""})),
+        );
+    }
+
     let now = chrono::Utc::now().timestamp();
 
     // Create the package. Author is always the authenticated user—can't lie about ownership.
     // We rely on the UNIQUE(name) constraint to prevent duplicates.
     let created = sqlx::query_as::<_, Package>(
         r#"
-        INSERT INTO packages (name, description, author, repository, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, $6)
+        INSERT INTO packages (name, description, author, owner_id, repository, created_at, updated_at, visibility)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         RETURNING *
         "#,
     )
     .bind(&payload.name)
-    .bind(payload.description)
-    .bind(user.username) // Force the author to be the logged-in user
+    .bind(&description)
+    .bind(&user.username) // Force the author to be the logged-in user
+    .bind(Uuid::parse_str(&user.user_id).ok())
     .bind(payload.repository)
     .bind(now)
     .bind(now)
+    .bind(&payload.visibility)
     .fetch_one(&state.db)
     .await;
 
@@ -320,9 +503,21 @@ pub async fn create_version(
         );
     }
 
+    let tag = payload.tag.clone().unwrap_or_else(|| "latest".to_string());
+    if let Err(e) = crate::utils::validation::validate_tag_name(&tag) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    if let Some(license) = &payload.license
+        && let Err(e) = crate::utils::license::validate_spdx(license)
+    {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
     let package = match sqlx::query_as::<_, Package>("SELECT * FROM packages WHERE name = $1")
         .bind(name)
         .fetch_optional(&state.db)
+        .instrument(db_span("find package"))
         .await
     {
         Ok(p) => p,
@@ -345,22 +540,86 @@ pub async fn create_version(
     };
 
     // Only the owner can publish versions of their package
-    if package.author != user.username {
+    if !user.may_use(&package.name) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": format!("This token isn't allowed to publish {}", package.name)})),
+        );
+    }
+    if !user.owns(&package) {
         return (
             StatusCode::FORBIDDEN,
             Json(json!({"error": "You do not own this package"})),
         );
     }
 
+    // Dependencies are checked before anything gets written, so a typo'd name or range
+    // never ends up baked into a published version.
+    match crate::utils::dependencies::check(&state.db, &package.name, &payload.dependencies).await {
+        Ok(errors) if errors.is_empty() => {}
+        Ok(errors) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(crate::utils::dependencies::error_body(&errors)),
+            );
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    }
+
     let pkg_id = package.id.expect("package should have an id");
     let now = chrono::Utc::now().timestamp();
 
+    // Per-package quota, on top of the per-user governor on this route.
+    let verified: bool = sqlx::query_scalar("SELECT verified FROM users WHERE id = $1")
+        .bind(package.owner_id)
+        .fetch_optional(&state.db)
+        .instrument(db_span("check account"))
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(false);
+
+    match crate::utils::quota::check(&state.db, pkg_id, crate::utils::quota::PublishQuota::for_account(verified)).await {
+        Ok(None) => {}
+        Ok(Some(exceeded)) => {
+            return (StatusCode::TOO_MANY_REQUESTS, Json(exceeded.to_json(&package.name)));
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    }
+
+    // Which session published the previous version? Grabbed before the insert so we compare
+    // against the old latest, not ourselves.
+    let previous_jti: Option<Option<Uuid>> = sqlx::query_scalar(
+        "SELECT publish_jti FROM package_versions WHERE package_id = $1 ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(pkg_id)
+    .fetch_optional(&state.db)
+    .instrument(db_span("previous publish session"))
+    .await
+    .unwrap_or(None);
+
     // Create the version record. lua_source_url will be updated later when the blob is uploaded.
+    // It starts out 'pending': invisible to installs until upload_blob flips it to 'published'.
+    // The requested dist-tag waits in pending_tag for the same reason—no tag should point at
+    // a version you can't download.
     // We rely on the UNIQUE(package_id, version) constraint to prevent duplicates.
+    // The upload token is the second half of a two-phase publish: only whoever holds it can
+    // attach a blob to this version, and only once.
+    let (upload_token, upload_token_hash) = new_upload_token();
     let created_version = sqlx::query_as::<_, PackageVersion>(
         r#"
-        INSERT INTO package_versions (package_id, version, lua_source_url, created_at, dependencies)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO package_versions (package_id, version, lua_source_url, created_at, dependencies, publish_jti, status, pending_tag, upload_token_hash, declared_license)
+        VALUES ($1, $2, $3, $4, $5, $6, 'pending', $7, $8, $9)
         RETURNING *
         "#,
     )
@@ -369,7 +628,12 @@ pub async fn create_version(
     .bind(payload.lua_source_url)
     .bind(now)
     .bind(serde_json::to_value(&payload.dependencies).unwrap_or(json!({})))
+    .bind(user.jti)
+    .bind(&tag)
+    .bind(&upload_token_hash)
+    .bind(&payload.license)
     .fetch_one(&state.db)
+    .instrument(db_span("insert version"))
     .await;
 
     // Update the package's updated_at timestamp so it shows as recently modified
@@ -381,11 +645,39 @@ pub async fn create_version(
             .bind(now)
             .bind(pkg_id)
             .execute(&state.db)
+            .instrument(db_span("touch package"))
             .await;
+
+        // Early warning for stolen tokens: if this session didn't publish the last version,
+        // ping the owner. Legit re-logins trigger it too, which is fine—it's cheap to ignore.
+        if let Some(Some(prev)) = previous_jti
+            && prev != user.jti
+        {
+            crate::utils::notify::notify_owner(
+                state.db.clone(),
+                package.author.clone(),
+                crate::utils::notify::EVENT_NEW_SESSION_PUBLISH,
+                json!({
+                    "package": package.name,
+                    "version": payload.version,
+                    "published_at": now
+                }),
+            );
+        }
     }
 
     match created_version {
-        Ok(v) => (StatusCode::CREATED, Json(json!(v))),
+        Ok(v) => {
+            let mut body = json!(v);
+            body["upload_token"] = json!(upload_token);
+            // So the CLI can say up front that this version will wait for approval.
+            body["protected"] = json!(
+                crate::handlers::protection::is_protected(&state.db, pkg_id)
+                    .await
+                    .unwrap_or(true)
+            );
+            (StatusCode::CREATED, Json(body))
+        }
         Err(e) => {
             // Check for unique constraint violation (Postgres code 23505)
             if let Some(db_err) = e.as_database_error() {
@@ -405,23 +697,186 @@ pub async fn create_version(
     }
 }
 
-/// Lists all versions of a package.
-pub async fn list_versions(
+/// Starts a new upload session for a version that's still pending.
+///
+/// This is how an interrupted publish resumes: the version was registered but the upload never
+/// finished, so the CLI asks for a fresh token (the old one is invalidated) and tries again.
+/// Also refreshes the dependencies and tag in case the manifest changed in between, and
+/// restarts the expiry clock.
+pub async fn create_upload_session(
     State(state): State<AppState>,
-    Path(name): Path<String>,
+    user: AuthenticatedUser,
+    Path((name, version)): Path<(String, String)>,
+    Json(payload): Json<UploadSessionRequest>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    let tag = payload.tag.unwrap_or_else(|| "latest".to_string());
+    if let Err(e) = crate::utils::validation::validate_tag_name(&tag) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+    if let Some(license) = &payload.license
+        && let Err(e) = crate::utils::license::validate_spdx(license)
+    {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
     let package = match sqlx::query_as::<_, Package>("SELECT * FROM packages WHERE name = $1")
-        .bind(name)
+        .bind(&name)
         .fetch_optional(&state.db)
+        .instrument(db_span("find package"))
         .await
     {
-        Ok(p) => p,
+        Ok(Some(p)) => p,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Package not found"})),
+            );
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    if !user.owns(&package) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "You do not own this package"})),
+        );
+    }
+
+    // The manifest may have changed since the first attempt, so check the refreshed map too.
+    match crate::utils::dependencies::check(&state.db, &package.name, &payload.dependencies).await {
+        Ok(errors) if errors.is_empty() => {}
+        Ok(errors) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(crate::utils::dependencies::error_body(&errors)),
+            );
+        }
         Err(e) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"error": e.to_string()})),
             );
         }
+    }
+
+    let (upload_token, upload_token_hash) = new_upload_token();
+    let result: Result<Option<String>, _> = sqlx::query_scalar(
+        r#"
+        UPDATE package_versions
+        SET upload_token_hash = $1, dependencies = $2, pending_tag = $3, created_at = $4, declared_license = $7
+        WHERE package_id = $5 AND version = $6 AND status = 'pending'
+        RETURNING version
+        "#,
+    )
+    .bind(&upload_token_hash)
+    .bind(serde_json::to_value(&payload.dependencies).unwrap_or(json!({})))
+    .bind(&tag)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(package.id)
+    .bind(&version)
+    .bind(&payload.license)
+    .fetch_optional(&state.db)
+    .instrument(db_span("renew upload session"))
+    .await;
+
+    match result {
+        Ok(Some(_)) => (
+            StatusCode::OK,
+            Json(json!({
+                "name": package.name,
+                "version": version,
+                "upload_token": upload_token
+            })),
+        ),
+        // Either it doesn't exist (maybe it expired) or it's already published. Tell them which.
+        Ok(None) => {
+            let status: Option<String> = sqlx::query_scalar(
+                "SELECT status FROM package_versions WHERE package_id = $1 AND version = $2",
+            )
+            .bind(package.id)
+            .bind(&version)
+            .fetch_optional(&state.db)
+            .instrument(db_span("check version exists"))
+            .await
+            .unwrap_or(None);
+
+            if status.as_deref() == Some("held") {
+                (
+                    StatusCode::CONFLICT,
+                    Json(json!({"error": "Version is uploaded and waiting for approval", "status": "held"})),
+                )
+            } else if status.is_some() {
+                (
+                    StatusCode::CONFLICT,
+                    Json(json!({"error": "Version is already published"})),
+                )
+            } else {
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"error": "Version not found. Register it again."})),
+                )
+            }
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Lists all versions of a package, newest first.
+///
+/// Only published versions by default. `?include_pending=true` also shows versions that were
+/// registered but haven't received their blob yet—handy when debugging a publish that died halfway.
+/// `?include=downloads` adds a per-version `downloads` count.
+/// `?include_prereleases=false` leaves out `1.0.0-beta.1` and friends.
+/// Published versions also carry `sha256`, `size`, `mirror_urls` (see `storage::public_blob_url`)
+/// and `download_url`, the by-name endpoint that counts downloads against the right version.
+///
+/// Paging: `?limit=` (at most 100) and `?offset=`. No limit means every version, as it always has.
+/// `?latest_only=true` is shorthand for `limit=1`. Either way `X-Total-Count` says how many
+/// versions match the filters, so a client can tell whether there's another page.
+pub async fn list_versions(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    access: Option<Extension<PrivateAccess>>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> axum::response::Response {
+    let include_pending = params.get("include_pending").is_some_and(|v| v == "true");
+    let include_downloads = wants_downloads(&params);
+    let include_prereleases = include_prereleases(&params);
+    let limit: Option<i64> = if params.get("latest_only").is_some_and(|v| v == "true") {
+        Some(1)
+    } else {
+        params
+            .get("limit")
+            .and_then(|s| s.parse::<i64>().ok())
+            .map(|n| n.clamp(1, 100))
+    };
+    let offset = params
+        .get("offset")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(0)
+        .max(0);
+
+    let package = match sqlx::query_as::<_, Package>("SELECT * FROM packages WHERE name = $1 AND NOT taken_down")
+        .bind(&name)
+        .fetch_optional(&state.db)
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
     };
 
     let package = match package {
@@ -430,15 +885,48 @@ pub async fn list_versions(
             return (
                 StatusCode::NOT_FOUND,
                 Json(json!({"error": "Package not found"})),
-            );
+            )
+                .into_response();
         }
     };
 
     let pkg_id = package.id.expect("package should have an id");
-    let versions = match sqlx::query_as::<_, PackageVersion>(
-        "SELECT * FROM package_versions WHERE package_id = $1 ORDER BY created_at DESC",
-    )
+    let filter = format!(
+        "package_id = $1 AND ($2 OR status = 'published') AND ($3 OR {})",
+        STABLE_VERSION
+    );
+
+    // 1. How many match, ignoring the page
+    let total: i64 = match sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM package_versions WHERE {}",
+        filter
+    ))
+    .bind(pkg_id)
+    .bind(include_pending)
+    .bind(include_prereleases)
+    .fetch_one(&state.db)
+    .await
+    {
+        Ok(n) => n,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    // 2. The page itself. LIMIT NULL is Postgres for "no limit".
+    let versions = match sqlx::query_as::<_, PackageVersion>(&format!(
+        "SELECT * FROM package_versions WHERE {} ORDER BY created_at DESC LIMIT $4 OFFSET $5",
+        filter
+    ))
     .bind(pkg_id)
+    .bind(include_pending)
+    .bind(include_prereleases)
+    .bind(limit)
+    .bind(offset)
     .fetch_all(&state.db)
     .await
     {
@@ -447,33 +935,47 @@ pub async fn list_versions(
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"error": e.to_string()})),
-            );
+            )
+                .into_response();
         }
     };
 
-    (StatusCode::OK, Json(json!(versions)))
+    let rows: Vec<serde_json::Value> = versions
+        .iter()
+        .map(|v| version_row(&name, v, include_downloads, access.as_deref()))
+        .collect();
+
+    (
+        StatusCode::OK,
+        [("x-total-count", total.to_string())],
+        Json(with_rfc3339(json!(rows))),
+    )
+        .into_response()
 }
 
-/// Uploads the package blob to R2 storage and updates the version record.
-///
-/// Multi-step process:
-/// 1. Verify the authenticated user owns the package (authorization check)
-/// 2. Hash the blob (SHA256) and extract any README.md for display
-/// 3. Upload the zip to R2 using the hash as the key
-/// 4. Update the version record with the R2 URL and README content
-pub async fn upload_blob(
+/// Gets one published version. Same shape as an entry from `list_versions`, minus the other
+/// few hundred entries—installs only ever need the one they're about to download.
+/// `?include=downloads` works here too.
+pub async fn get_version(
     State(state): State<AppState>,
-    user: AuthenticatedUser,
     Path((name, version)): Path<(String, String)>,
-    body: Bytes,
+    access: Option<Extension<PrivateAccess>>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // 1. Ownership check: make sure the user owns this package
-    let package = match sqlx::query_as::<_, Package>("SELECT * FROM packages WHERE name = $1")
-        .bind(name)
-        .fetch_optional(&state.db)
-        .await
+    let found = match sqlx::query_as::<_, PackageVersion>(
+        r#"
+        SELECT v.* FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+    "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .instrument(db_span("find version"))
+    .await
     {
-        Ok(p) => p,
+        Ok(v) => v,
         Err(e) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -482,96 +984,1010 @@ pub async fn upload_blob(
         }
     };
 
-    let package = match package {
-        Some(p) => p,
-        None => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(json!({"error": "Package not found"})),
-            );
-        }
-    };
+    match found {
+        Some(v) => (
+            StatusCode::OK,
+            Json(with_rfc3339(version_row(&name, &v, wants_downloads(&params), access.as_deref()))),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        ),
+    }
+}
+
+/// Most pins one lock-status call will look at. A real lockfile is a few dozen.
+const MAX_LOCK_STATUS_PACKAGES: usize = 2000;
 
-    if package.author != user.username {
+/// Checks a whole lockfile's pins against what's live right now. `mosaic verify` and CI
+/// installs use it to catch versions that went away after the lockfile was written.
+///
+/// Each package comes back with a `status`:
+/// - `ok`: the version's published and the package is up
+/// - `unpublished`: the package is fine but that version is gone
+/// - `taken_down`: the whole package was pulled (malware, legal), with the admin's `reason`
+/// - `missing`: no package by that name anymore
+///
+/// Plus `deprecated` (advice, not a failure), `sha256` for ok versions so clients can compare
+/// against their lock, and a `suggestion` for unpublished ones (see `closest_version`).
+pub async fn lock_status(
+    State(state): State<AppState>,
+    viewer: Option<AuthenticatedUser>,
+    Json(payload): Json<LockStatusRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if payload.packages.len() > MAX_LOCK_STATUS_PACKAGES {
         return (
-            StatusCode::FORBIDDEN,
-            Json(json!({"error": "Not the owner"})),
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("At most {} packages per request", MAX_LOCK_STATUS_PACKAGES)})),
         );
     }
-
+    let names: Vec<String> = payload.packages.keys().cloned().collect();
+
+    // 1. The packages themselves, taken down or not. Private ones the caller can't see are missing.
+    let packages: Vec<(String, bool, Option<String>, bool)> = match sqlx::query_as(&format!(
+        "SELECT p.name, p.taken_down, p.takedown_reason, p.deprecated FROM packages p WHERE p.name = ANY($1) AND {}",
+        visible_to(2)
+    ))
+    .bind(&names)
+    .bind(viewer_id(viewer.as_ref()))
+    .fetch_all(&state.db)
+    .instrument(db_span("lock status packages"))
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    // 2. Every published version of them, for the lookups and the suggestions
+    let versions: Vec<(String, String, String)> = match sqlx::query_as(
+        r#"
+        SELECT p.name, v.version, v.lua_source_url FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = ANY($1) AND v.status = 'published'
+    "#,
+    )
+    .bind(&names)
+    .fetch_all(&state.db)
+    .instrument(db_span("lock status versions"))
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let mut published: std::collections::HashMap<&str, Vec<(&str, &str)>> = std::collections::HashMap::new();
+    for (name, version, url) in &versions {
+        published.entry(name.as_str()).or_default().push((version.as_str(), url.as_str()));
+    }
+
+    let mut results = serde_json::Map::new();
+    for (name, locked) in &payload.packages {
+        let Some((_, taken_down, reason, deprecated)) = packages.iter().find(|p| &p.0 == name) else {
+            results.insert(name.clone(), json!({"version": locked, "status": "missing"}));
+            continue;
+        };
+        let available = published.get(name.as_str()).cloned().unwrap_or_default();
+
+        let entry = if *taken_down {
+            json!({"version": locked, "status": "taken_down", "reason": reason, "deprecated": deprecated})
+        } else if let Some((_, url)) = available.iter().find(|(v, _)| *v == locked.as_str()) {
+            json!({
+                "version": locked,
+                "status": "ok",
+                "deprecated": deprecated,
+                "sha256": url.strip_prefix("/packages/blobs/")
+            })
+        } else {
+            let candidates: Vec<&str> = available.iter().map(|(v, _)| *v).collect();
+            json!({
+                "version": locked,
+                "status": "unpublished",
+                "deprecated": deprecated,
+                "suggestion": closest_version(locked, &candidates)
+            })
+        };
+        results.insert(name.clone(), entry);
+    }
+
+    (StatusCode::OK, Json(json!({"packages": results})))
+}
+
+/// The best stand-in for a version that's gone: the nearest newer release with the same major
+/// (a patch that fixes whatever got it pulled, usually), else the newest older one with the same
+/// major, else the newest stable release of any major. Pre-releases only if nothing else exists.
+fn closest_version(locked: &str, available: &[&str]) -> Option<String> {
+    let mut parsed: Vec<Version> = available.iter().filter_map(|v| Version::parse(v).ok()).collect();
+    parsed.sort();
+    let Ok(locked) = Version::parse(locked) else {
+        return parsed.last().map(|v| v.to_string());
+    };
+    let stable: Vec<&Version> = parsed.iter().filter(|v| v.pre.is_empty()).collect();
+    let same_major = |v: &&&Version| v.major == locked.major;
+
+    stable
+        .iter()
+        .filter(same_major)
+        .find(|v| ***v > locked)
+        .or_else(|| stable.iter().rfind(same_major))
+        .or_else(|| stable.last())
+        .map(|v| v.to_string())
+        .or_else(|| parsed.last().map(|v| v.to_string()))
+}
+
+/// `?include=downloads`, possibly alongside other comma-separated includes.
+fn wants_downloads(params: &std::collections::HashMap<String, String>) -> bool {
+    params
+        .get("include")
+        .is_some_and(|v| v.split(',').any(|part| part.trim() == "downloads"))
+}
+
+/// One version as the versions endpoints return it.
+///
+/// `access` is set for private packages (see handlers::visibility): their `download_url` is
+/// signed for the caller and short-lived, and they get no bucket URLs, which anyone could use.
+fn version_row(
+    name: &str,
+    v: &PackageVersion,
+    include_downloads: bool,
+    access: Option<&PrivateAccess>,
+) -> serde_json::Value {
+    let mut row = json!(v);
+    // The hash is already in the URL; spelling it out saves clients the string surgery.
+    // Direct bucket URLs let installs carry on while this server is down.
+    if let Some(hash) = v.lua_source_url.strip_prefix("/packages/blobs/") {
+        row["sha256"] = json!(hash);
+        match access {
+            Some(access) => {
+                row["download_url"] = json!(signed_download_url(name, &v.version, access.user));
+                row["mirror_urls"] = json!([]);
+            }
+            None => {
+                row["download_url"] = json!(format!("/packages/{}/{}/download", name, v.version));
+                row["mirror_urls"] = json!(crate::utils::storage::public_blob_url(hash)
+                    .into_iter()
+                    .collect::<Vec<_>>());
+            }
+        }
+    }
+    if include_downloads {
+        row["downloads"] = json!(v.download_count);
+    }
+    row
+}
+
+/// Gets the CHANGELOG.md that shipped with a specific version.
+///
+/// 404s if the package/version doesn't exist or the author didn't include a changelog.
+pub async fn get_changelog(
+    State(state): State<AppState>,
+    Path((name, version)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let changelog: Option<Option<String>> = match sqlx::query_scalar(
+        r#"
+        SELECT v.changelog FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    match changelog {
+        Some(Some(changelog)) => (
+            StatusCode::OK,
+            Json(json!({"name": name, "version": version, "changelog": changelog})),
+        ),
+        Some(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "This version has no changelog"})),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        ),
+    }
+}
+
+/// Everything a published version pulls in, resolved against what's on the registry right now
+/// (see utils::dependencies::resolve). Also lists ranges nothing satisfies and any cycles found.
+pub async fn get_dependency_closure(
+    State(state): State<AppState>,
+    Path((name, version)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let dependencies: Option<Option<serde_json::Value>> = match sqlx::query_scalar(
+        r#"
+        SELECT v.dependencies FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(d) => d,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let Some(dependencies) = dependencies else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        );
+    };
+    let dependencies: std::collections::HashMap<String, String> = dependencies
+        .and_then(|d| serde_json::from_value(d).ok())
+        .unwrap_or_default();
+
+    match crate::utils::dependencies::resolve(&state.db, &name, &dependencies).await {
+        Ok(closure) => (
+            StatusCode::OK,
+            Json(json!({
+                "name": name,
+                "version": version,
+                "dependencies": closure.dependencies,
+                "unresolved": closure.unresolved,
+                "cycles": closure.cycles
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// The lint report for a published version (see utils::lint).
+///
+/// 404 with `"pending": true` if the version exists but hasn't been linted—the pass runs after
+/// upload, so a freshly published version may not have one for a few seconds.
+pub async fn get_lint_report(
+    State(state): State<AppState>,
+    Path((name, version)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let report: Option<Option<serde_json::Value>> = match sqlx::query_scalar(
+        r#"
+        SELECT v.lint_report FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    match report {
+        Some(Some(report)) => (
+            StatusCode::OK,
+            Json(json!({"name": name, "version": version, "lint": report})),
+        ),
+        Some(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "This version hasn't been linted", "pending": true})),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        ),
+    }
+}
+
+/// Lists the files inside a published version: path, size and SHA256 for each.
+///
+/// The listing is computed at upload time. Versions uploaded before that existed get it
+/// computed from the blob on first request and stored, so the cost is paid once.
+pub async fn list_version_files(
+    State(state): State<AppState>,
+    Path((name, version)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let row: Option<(Uuid, Option<serde_json::Value>, String)> = match sqlx::query_as(
+        r#"
+        SELECT v.id, v.files, v.lua_source_url FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let Some((version_id, files, source_url)) = row else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        );
+    };
+
+    let files = match files {
+        Some(f) => f,
+        None => {
+            // Legacy version: build the listing from the blob and remember it.
+            let Some(hash) = source_url.strip_prefix("/packages/blobs/") else {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"error": "This version has no uploaded blob"})),
+                );
+            };
+
+            let blob = match state.storage.get_blob(hash).await {
+                Ok(b) => b,
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Storage error: {}", e)})),
+                    );
+                }
+            };
+
+            // Inflating is CPU work, so keep it off the async workers.
+            let listing =
+                tokio::task::spawn_blocking(move || crate::utils::archive::list_files(&blob)).await;
+            let listing = match listing {
+                Ok(Ok(l)) => json!(l),
+                Ok(Err(e)) if e.is::<crate::utils::archive::TooLarge>() => {
+                    return (
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        Json(json!({"error": "This version is too large to list. Download it instead."})),
+                    );
+                }
+                Ok(Err(e)) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Could not read package archive: {}", e)})),
+                    );
+                }
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Could not read package archive: {}", e)})),
+                    );
+                }
+            };
+
+            let _ = sqlx::query("UPDATE package_versions SET files = $1 WHERE id = $2")
+                .bind(&listing)
+                .bind(version_id)
+                .execute(&state.db)
+                .await;
+
+            listing
+        }
+    };
+
+    let total_size: u64 = files
+        .as_array()
+        .map(|f| f.iter().filter_map(|e| e["size"].as_u64()).sum())
+        .unwrap_or(0);
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "name": name,
+            "version": version,
+            "total_size": total_size,
+            "files": files
+        })),
+    )
+}
+
+/// Looks up which package a version belongs to and the hash of its uploaded blob.
+/// The Err side is a ready-to-return response so handlers can just `?`-style match on it.
+async fn version_blob(
+    state: &AppState,
+    name: &str,
+    version: &str,
+) -> Result<(Uuid, String), (StatusCode, Json<serde_json::Value>)> {
+    let row: Option<(Uuid, String)> = sqlx::query_as(
+        r#"
+        SELECT v.package_id, v.lua_source_url FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(name)
+    .bind(version)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    let Some((pkg_id, source_url)) = row else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("Version {} not found", version)})),
+        ));
+    };
+
+    match source_url.strip_prefix("/packages/blobs/") {
+        Some(hash) => Ok((pkg_id, hash.to_string())),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("Version {} has no uploaded blob", version)})),
+        )),
+    }
+}
+
+/// Diffs two published versions of a package.
+///
+/// Query parameters: `from` and `to` (both required, exact versions).
+/// Returns a file-level diff (added/removed/modified) with unified hunks for text files.
+/// Results are cached in version_diffs since published versions never change.
+pub async fn diff_versions(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let (Some(from), Some(to)) = (params.get("from"), params.get("to")) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Both 'from' and 'to' query parameters are required"})),
+        );
+    };
+
+    let (pkg_id, from_hash) = match version_blob(&state, &name, from).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+    let (_, to_hash) = match version_blob(&state, &name, to).await {
+        Ok(v) => v,
+        Err(resp) => return resp,
+    };
+
+    // 1. Cache hit?
+    let cached: Option<serde_json::Value> = sqlx::query_scalar(
+        "SELECT diff FROM version_diffs WHERE package_id = $1 AND from_version = $2 AND to_version = $3",
+    )
+    .bind(pkg_id)
+    .bind(from)
+    .bind(to)
+    .fetch_optional(&state.db)
+    .await
+    .unwrap_or(None);
+
+    let files = match cached {
+        Some(files) => files,
+        None => {
+            // 2. Cache miss: pull both blobs and diff them.
+            let (old_blob, new_blob) = match tokio::try_join!(
+                state.storage.get_blob(&from_hash),
+                state.storage.get_blob(&to_hash)
+            ) {
+                Ok(blobs) => blobs,
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Storage error: {}", e)})),
+                    );
+                }
+            };
+
+            // Inflating and diffing is CPU work, so keep it off the async workers.
+            let diff = tokio::task::spawn_blocking(move || {
+                crate::utils::diff::diff_archives(&old_blob, &new_blob)
+            })
+            .await;
+            let files = match diff {
+                Ok(Ok(d)) => json!(d),
+                Ok(Err(e)) if e.is::<crate::utils::archive::TooLarge>() => {
+                    return (
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        Json(json!({"error": "These versions are too large to diff. Download them instead."})),
+                    );
+                }
+                Ok(Err(e)) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Could not diff package archives: {}", e)})),
+                    );
+                }
+                Err(e) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({"error": format!("Could not diff package archives: {}", e)})),
+                    );
+                }
+            };
+
+            let _ = sqlx::query(
+                r#"
+                INSERT INTO version_diffs (package_id, from_version, to_version, diff, created_at)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(pkg_id)
+            .bind(from)
+            .bind(to)
+            .bind(&files)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&state.db)
+            .await;
+
+            files
+        }
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "name": name,
+            "from": from,
+            "to": to,
+            "files": files
+        })),
+    )
+}
+
+/// Serves the text of a single file from a published version.
+///
+/// Only text files under 1MB are served—this is for reading source in a browser or
+/// `mosaic view`, not a second download endpoint. Binary files get a 415.
+pub async fn get_version_file(
+    State(state): State<AppState>,
+    Path((name, version, path)): Path<(String, String, String)>,
+) -> impl IntoResponse {
+    const MAX_VIEW_SIZE: usize = 1024 * 1024;
+
+    let source_url: Option<String> = match sqlx::query_scalar(
+        r#"
+        SELECT v.lua_source_url FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+                .into_response();
+        }
+    };
+
+    let Some(source_url) = source_url else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        )
+            .into_response();
+    };
+
+    let Some(hash) = source_url.strip_prefix("/packages/blobs/") else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "This version has no uploaded blob"})),
+        )
+            .into_response();
+    };
+
+    let blob = match state.storage.get_blob(hash).await {
+        Ok(b) => b,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Storage error: {}", e)})),
+            )
+                .into_response();
+        }
+    };
+
+    // Inflating is CPU work, so keep it off the async workers.
+    let read = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || {
+            crate::utils::archive::read_file(&blob, &path, MAX_VIEW_SIZE as u64)
+        })
+        .await
+    };
+    let read = match read {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Could not read package archive: {}", e)})),
+            )
+                .into_response();
+        }
+    };
+
+    let contents = match read {
+        Ok(Some(c)) => c,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": format!("File {} not found in {}@{}", path, name, version)})),
+            )
+                .into_response();
+        }
+        Err(e) if e.is::<crate::utils::archive::TooLarge>() => {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(json!({"error": "File is too large to view. Download the package instead."})),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Could not read package archive: {}", e)})),
+            )
+                .into_response();
+        }
+    };
+
+    let Ok(text) = String::from_utf8(contents) else {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(json!({"error": "Binary files can't be viewed"})),
+        )
+            .into_response();
+    };
+
+    (
+        StatusCode::OK,
+        [("content-type", crate::utils::archive::content_type_for(&path))],
+        text,
+    )
+        .into_response()
+}
+
+/// Uploads the package blob to R2 storage and updates the version record.
+///
+/// Multi-step process:
+/// 1. Verify the authenticated user owns the package (authorization check)
+/// 2. Hash the blob (SHA256) and extract any README.md / CHANGELOG.md for display
+/// 3. Upload the zip to R2 using the hash as the key
+/// 4. Update the version record with the R2 URL and README/CHANGELOG content
+pub async fn upload_blob(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((name, version)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let (Some(upload_token), Some(expected_hash)) =
+        (header(UPLOAD_TOKEN_HEADER), header(CONTENT_SHA256_HEADER))
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Missing X-Upload-Token or X-Content-Sha256 header. Update your CLI."})),
+        );
+    };
+    let token_hash = format!("{:x}", Sha256::digest(upload_token.as_bytes()));
+
+    // 1. Ownership check: make sure the user owns this package
+    let package = match sqlx::query_as::<_, Package>("SELECT * FROM packages WHERE name = $1")
+        .bind(name)
+        .fetch_optional(&state.db)
+        .instrument(db_span("find package"))
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let package = match package {
+        Some(p) => p,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Package not found"})),
+            );
+        }
+    };
+
+    if !user.owns(&package) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Not the owner"})),
+        );
+    }
+
+    let pkg_id = package.id.expect("id exists");
+    // 1.5 The version has to be registered, still pending, and this has to be its upload session.
+    // Checked up front so a bad upload doesn't leave a stray blob in R2; the UPDATE below
+    // re-checks in case two uploads race.
+    let session: Option<(String, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT status, upload_token_hash, declared_license FROM package_versions WHERE package_id = $1 AND version = $2",
+    )
+    .bind(pkg_id)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .instrument(db_span("find upload session"))
+    .await
+    .unwrap_or(None);
+
+    let declared_license = match session {
+        Some((status, stored, declared)) if status == "pending" => {
+            if stored.as_deref() != Some(token_hash.as_str()) {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(json!({"error": "Upload token doesn't match this version. Start a new upload session."})),
+                );
+            }
+            declared
+        }
+        Some(_) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "Version is already published. Published versions can't be changed."})),
+            );
+        }
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Version not found. Register it before uploading."})),
+            );
+        }
+    };
+
     // 2. Hash the blob so we can use it as the storage key.
     // SHA256 is overkill but makes it hard to guess URLs, so why not.
     let mut hasher = Sha256::new();
     hasher.update(&body);
     let hash = format!("{:x}", hasher.finalize());
 
+    // The client tells us what it meant to send. If that's not what arrived, the body got
+    // truncated or mangled on the way and we want nothing to do with it.
+    if !hash.eq_ignore_ascii_case(expected_hash.trim()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Blob hash mismatch: expected {}, got {}", expected_hash, hash)})),
+        );
+    }
+
     // 2.5 Extract README and License from the zip if they exist
     // Users can include documentation and we'll display it on the registry.
+    // find_docs looks past the root (docs/, .github/, LICENSE-MIT...) and tells us which file
+    // it picked, which we store and send back so authors can see why a readme is or isn't showing.
+    // Docs get sanitized on the way in (see utils::sanitize); anything we changed is reported
+    // back as a warning so the publisher isn't surprised by what shows up on the site.
     let mut readme_content: Option<String> = None;
+    let mut changelog_content: Option<String> = None;
     let mut license_detected: Option<String> = None;
+    let mut warnings: Vec<String> = Vec::new();
+
+    let doc_files = crate::utils::archive::find_docs(&body).unwrap_or_default();
+    let read_doc = |path: &Option<String>| {
+        path.as_deref()
+            .and_then(|p| {
+                crate::utils::archive::read_file(&body, p, crate::utils::archive::MAX_ENTRY_SIZE)
+                    .ok()
+                    .flatten()
+            })
+            .map(|bytes| (path.clone().unwrap_or_default(), bytes))
+    };
 
-    if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(&body)) {
-        for i in 0..archive.len() {
-            if let Ok(mut file) = archive.by_index(i) {
-                let name = file.name().to_string();
-                
-                // Check for README
-                if name.eq_ignore_ascii_case("README.md") {
-                    let mut s = String::new();
-                    if file.read_to_string(&mut s).is_ok() {
-                        readme_content = Some(s);
-                    }
-                }
-                
-                // Check for LICENSE
-                // We look for common names like LICENSE, LICENSE.md, LICENSE.txt
-                if name.eq_ignore_ascii_case("LICENSE") 
-                    || name.eq_ignore_ascii_case("LICENSE.md") 
-                    || name.eq_ignore_ascii_case("LICENSE.txt") 
-                {
-                    let mut s = String::new();
-                    if file.read_to_string(&mut s).is_ok() {
-                        // Detect license using askalono
-                        // We load the embedded cache. It's small (~300KB compressed).
-                        let cache_data = include_bytes!("../utils/license_cache.bin.zstd");
-                        if let Ok(store) = Store::from_cache(&cache_data[..]) {
-                            let analysis = store.analyze(&text_content(&s));
-                            if analysis.score > 0.8 {
-                                license_detected = Some(analysis.name.to_string());
-                            } else {
-                                license_detected = Some("Custom".to_string());
-                            }
-                        } else {
-                            // Fallback if cache fails (shouldn't happen)
-                            license_detected = Some("Custom".to_string());
-                        }
-                    }
-                }
+    if let Some((path, bytes)) = read_doc(&doc_files.readme) {
+        let doc = crate::utils::sanitize::document(&path, &bytes);
+        warnings.extend(doc.warnings);
+        readme_content = Some(doc.text);
+    } else {
+        warnings.push("No README found (looked for README.md at the root, docs/ and .github/)".to_string());
+    }
+
+    // Stored per version so the CLI can show what changed before you upgrade.
+    if let Some((path, bytes)) = read_doc(&doc_files.changelog) {
+        let doc = crate::utils::sanitize::document(&path, &bytes);
+        warnings.extend(doc.warnings);
+        changelog_content = Some(doc.text);
+    }
+
+    if let Some((_, bytes)) = read_doc(&doc_files.license) {
+        // Detect license using askalono
+        license_detected = Some(crate::utils::license::detect(&String::from_utf8_lossy(&bytes)));
+    }
+
+    // 2.55 Cross-check against what mosaic.toml declared. The declaration wins—it's what the
+    // author says they meant—but if the LICENSE file disagrees, they should hear about it now
+    // rather than from a confused user later.
+    let license = match (&declared_license, &license_detected) {
+        (Some(declared), Some(detected)) => {
+            if detected == "Custom" {
+                warnings.push(format!(
+                    "mosaic.toml declares {} but the LICENSE file doesn't look like a standard license text",
+                    declared
+                ));
+            } else if !crate::utils::license::matches(declared, detected) {
+                warnings.push(format!(
+                    "mosaic.toml declares {} but the LICENSE file looks like {}",
+                    declared, detected
+                ));
+            }
+            Some(declared.clone())
+        }
+        (Some(declared), None) => {
+            warnings.push(format!(
+                "mosaic.toml declares {} but the package has no LICENSE file",
+                declared
+            ));
+            Some(declared.clone())
+        }
+        (None, detected) => detected.clone(),
+    };
+
+    // 2.6 Build the file listing (paths, sizes, hashes) while we have the zip in hand.
+    // A zip we can't read gets no listing rather than a failed publish, but one that inflates
+    // past the caps is refused: every later read of it (views, diffs, lint) would fail anyway.
+    let listing = {
+        let body = body.clone();
+        tokio::task::spawn_blocking(move || crate::utils::archive::list_files(&body)).await
+    };
+    let files_manifest = match listing {
+        Ok(Ok(files)) => Some(json!(files)),
+        Ok(Err(e)) if e.is::<crate::utils::archive::TooLarge>() => {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(json!({"error": format!(
+                    "Package unpacks to too much data (at most {}MB in total and {}MB per file)",
+                    crate::utils::archive::MAX_UNPACKED_SIZE / (1024 * 1024),
+                    crate::utils::archive::MAX_ENTRY_SIZE / (1024 * 1024)
+                )})),
+            );
+        }
+        _ => None,
+    };
+
+    // 3. Upload the blob to R2, unless those exact bytes are already there. Blobs are
+    // content-addressed, so re-tagging the same zip under a new version just links to the
+    // existing object. If the check itself fails we upload anyway—a redundant put is harmless.
+    let reused = matches!(state.storage.blob_exists(&hash).await, Ok(true));
+    if !reused {
+        // If this fails, we bail before updating the version record, so the upload is "atomic" in spirit.
+        if let Err(e) = state.storage.upload_blob(&hash, body.to_vec()).await {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Storage error: {}", e)})),
+            );
+        }
+
+        // 3.5 Make sure the blob actually landed before we point anyone at it.
+        // A version is only ever published with a blob we've seen in storage.
+        match state.storage.blob_exists(&hash).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({"error": "Blob didn't show up in storage after upload. Try again."})),
+                );
+            }
+            Err(e) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({"error": format!("Couldn't verify upload: {}", e)})),
+                );
             }
         }
     }
 
-    // 3. Upload the blob to R2
-    // If this fails, we bail before updating the version record, so the upload is "atomic" in spirit.
-    if let Err(e) = state.storage.upload_blob(&hash, body.to_vec()).await {
+    // 4. Update the version record with the R2 URL and any README/CHANGELOG/License we found
+    let source_url = format!("/packages/blobs/{}", hash);
+
+    // Protected packages don't publish here: the version is held until the owner confirms it
+    // (see handlers::protection). If we can't tell, hold it—a late publish beats a bad one.
+    let held_token = match crate::handlers::protection::is_protected(&state.db, pkg_id).await {
+        Ok(false) => None,
+        Ok(true) => Some(new_upload_token()),
+        Err(e) => {
+            tracing::warn!("Couldn't check whether {} is protected, holding the version: {}", package.name, e);
+            Some(new_upload_token())
+        }
+    };
+    let held_at = chrono::Utc::now().timestamp();
+
+    // Only a pending version can take a blob. Once published, a version's bytes never change—
+    // otherwise lockfile hashes would start failing for everyone who installed it.
+    let result: Result<Option<Option<String>>, _> = sqlx::query_scalar(
+        r#"
+        UPDATE package_versions
+        SET lua_source_url = $1, readme = $2, license = $3, changelog = $4, files = $5,
+            status = CASE WHEN $13::TEXT IS NULL THEN 'published' ELSE 'held' END,
+            upload_token_hash = NULL, approval_token_hash = $13,
+            held_at = CASE WHEN $13::TEXT IS NULL THEN NULL ELSE $14 END,
+            readme_path = $9, changelog_path = $10, license_path = $11, blob_size = $12
+        WHERE package_id = $6 AND version = $7 AND status = 'pending' AND upload_token_hash = $8
+        RETURNING pending_tag
+        "#,
+    )
+    .bind(source_url)
+    .bind(readme_content)
+    .bind(license)
+    .bind(changelog_content)
+    .bind(files_manifest)
+    .bind(pkg_id)
+    .bind(&version)
+    .bind(&token_hash)
+    .bind(&doc_files.readme)
+    .bind(&doc_files.changelog)
+    .bind(&doc_files.license)
+    .bind(body.len() as i64)
+    .bind(held_token.as_ref().map(|(_, hash)| hash))
+    .bind(held_at)
+    .fetch_optional(&state.db)
+    .instrument(db_span("publish version"))
+    .await;
+
+    // No row back means another upload beat us to it. Leave the blob alone—it's
+    // content-addressed, so another version may be using the exact same bytes.
+    if let Ok(None) = result {
         return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Storage error: {}", e)})),
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Version is already published or was never registered"})),
         );
     }
 
-    // 4. Update the version record with the R2 URL and any README/License we found
-    let pkg_id = package.id.expect("id exists");
-    let source_url = format!("/packages/blobs/{}", hash);
-
-    let result = sqlx::query("UPDATE package_versions SET lua_source_url = $1, readme = $2, license = $3 WHERE package_id = $4 AND version = $5")
-        .bind(source_url)
-        .bind(readme_content)
-        .bind(license_detected)
-        .bind(pkg_id)
-        .bind(version)
-        .execute(&state.db)
-        .await;
+    if let Ok(Some(pending_tag)) = &result {
+        // Now that the version is downloadable, point its dist-tag at it. A held version
+        // isn't downloadable yet; its tag moves when it's approved.
+        if held_token.is_none() {
+            let tag = pending_tag.clone().unwrap_or_else(|| "latest".to_string());
+            point_tag(&state.db, pkg_id, &package.name, &tag, &version).await;
+            notify_watchers(&state.db, &package.name, &version).await;
+        }
+    }
 
     if let Err(e) = result {
         tracing::error!(
@@ -580,15 +1996,18 @@ pub async fn upload_blob(
             hash
         );
 
-        // Rollback: delete the uploaded blob to prevent orphaned files
-        if let Err(cleanup_err) = state.storage.delete_blob(&hash).await {
-            tracing::error!(
-                "CRITICAL: Rollback failed for blob {}: {}",
-                hash,
-                cleanup_err
-            );
-        } else {
-            tracing::info!("Rollback successful: blob {} deleted.", hash);
+        // Rollback: delete the uploaded blob to prevent orphaned files. A blob we linked to
+        // instead of uploading belongs to other versions, so it stays.
+        if !reused {
+            match crate::utils::storage::release_blob(&state.db, &state.storage, &hash).await {
+                Ok(true) => tracing::info!("Rollback successful: blob {} deleted.", hash),
+                Ok(false) => tracing::info!("Rollback: blob {} is shared now, leaving it.", hash),
+                Err(cleanup_err) => tracing::error!(
+                    "CRITICAL: Rollback failed for blob {}: {}",
+                    hash,
+                    cleanup_err
+                ),
+            }
         }
 
         return (
@@ -597,44 +2016,366 @@ pub async fn upload_blob(
         );
     }
 
+    // 5. Lint in the background. It's a quality signal for people browsing, not a gate, so the
+    // publisher doesn't wait for it. Parsing is CPU work, hence spawn_blocking.
+    if crate::utils::lint::enabled() {
+        let db = state.db.clone();
+        let package_name = package.name.clone();
+        let version = version.clone();
+        tokio::spawn(async move {
+            let report =
+                tokio::task::spawn_blocking(move || crate::utils::lint::lint_package(&body)).await;
+            let report = match report {
+                Ok(Ok(report)) => report,
+                Ok(Err(e)) => {
+                    tracing::warn!("Lint failed for {}@{}: {}", package_name, version, e);
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!("Lint task for {}@{} panicked: {}", package_name, version, e);
+                    return;
+                }
+            };
+            if let Err(e) = sqlx::query(
+                "UPDATE package_versions SET lint_report = $1 WHERE package_id = $2 AND version = $3",
+            )
+            .bind(report)
+            .bind(pkg_id)
+            .bind(&version)
+            .execute(&db)
+            .await
+            {
+                tracing::warn!("Couldn't store lint report for {}@{}: {}", package_name, version, e);
+            }
+        });
+    }
+
+    if let Some((token, _)) = &held_token {
+        crate::handlers::protection::notify_held(
+            state.db.clone(),
+            package.author.clone(),
+            &package.name,
+            &version,
+            token,
+            held_at,
+        );
+        return (
+            StatusCode::ACCEPTED,
+            Json(json!({
+                "message": "Uploaded. Held for approval: this package is protected",
+                "status": "held",
+                "approve_after": held_at + crate::handlers::protection::delay_secs(),
+                "hash": hash,
+                "deduplicated": reused,
+                "docs": doc_files,
+                "warnings": warnings
+            })),
+        );
+    }
+
     (
         StatusCode::OK,
-        Json(json!({"message": "Uploaded successfully", "hash": hash})),
+        Json(json!({
+            "message": "Uploaded successfully",
+            "status": "published",
+            "hash": hash,
+            "deduplicated": reused,
+            "docs": doc_files,
+            "warnings": warnings
+        })),
     )
 }
 
 /// Downloads a package blob from R2 and increments the download counter.
+///
+/// Supports a single `Range: bytes=start-[end]` so the CLI can resume an interrupted download.
+/// A resume (start > 0) isn't counted again—the first request already was.
+///
+/// Older clients only know the hash. Newer ones use `download_version`, which knows exactly
+/// which version it's serving; here we have to work it out from the hash.
 pub async fn download_blob(
     State(state): State<AppState>,
     Path(hash): Path<String>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    // 1. Increment the download count for this package.
-    // We have to do a subquery to find which package owns this blob hash,
-    // then bump its counter. A bit awkward but necessary since the hash lives in package_versions.
-    let url_pattern = format!("/packages/blobs/{}", hash);
+    let range = range_header(&headers);
 
-    let _ = sqlx::query(
+    // 0. Find who the blob belongs to. Identical zips can share a blob across versions;
+    // the oldest gets the credit. Taken-down packages don't serve, even to people who
+    // already know the hash—if any package using this blob is down, so is the blob.
+    // Published versions first: a blob only a held version uses isn't out yet.
+    let owner: Option<(Uuid, Uuid, bool, String)> = sqlx::query_as(
         r#"
-        UPDATE packages 
-        SET download_count = download_count + 1 
-        WHERE id = (
-            SELECT package_id FROM package_versions WHERE lua_source_url = $1 LIMIT 1
-        )
+        SELECT v.package_id, v.id, EXISTS (
+            SELECT 1 FROM package_versions sv JOIN packages sp ON sp.id = sv.package_id
+            WHERE sv.lua_source_url = v.lua_source_url AND sp.taken_down
+        ), v.status
+        FROM package_versions v
+        WHERE v.lua_source_url = $1
+        ORDER BY v.status = 'published' DESC, v.created_at LIMIT 1
     "#,
     )
-    .bind(&url_pattern)
-    .execute(&state.db)
-    .await;
+    .bind(format!("/packages/blobs/{}", hash))
+    .fetch_optional(&state.db)
+    .instrument(db_span("find blob owner"))
+    .await
+    .unwrap_or(None);
+
+    if let Some((package_id, version_id, taken_down, status)) = owner {
+        if status == "held" {
+            return (StatusCode::NOT_FOUND, "Blob not found").into_response();
+        }
+        if taken_down {
+            return (
+                StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                "This package has been taken down",
+            )
+                .into_response();
+        }
+        // 1. Count it, unless it's the tail end of a download we already counted.
+        if !is_resume(range.as_deref()) {
+            count_download(&state, package_id, version_id, &headers).await;
+        }
+    }
 
     // 2. Fetch and return the blob from R2
-    match state.storage.get_blob(&hash).await {
-        Ok(data) => (
+    serve_blob(&state, &hash, range).await
+}
+
+/// `GET /packages/{name}/{version}/integrity`: the hash and size of a published version's zip.
+///
+/// For clients that already know which version they want: enough to check a resumed download,
+/// or one that stopped early, without fetching version metadata. `size` is null for versions
+/// uploaded before sizes were recorded.
+pub async fn get_integrity(
+    State(state): State<AppState>,
+    Path((name, version)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let found: Option<(String, Option<i64>, bool)> = match sqlx::query_as(
+        r#"
+        SELECT v.lua_source_url, v.blob_size, p.taken_down
+        FROM package_versions v JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published'
+    "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .instrument(db_span("find version"))
+    .await
+    {
+        Ok(found) => found,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let Some((source_url, size, taken_down)) = found else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Version not found"})),
+        );
+    };
+    if taken_down {
+        return (
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            Json(json!({"error": "This package has been taken down"})),
+        );
+    }
+    let Some(hash) = source_url.strip_prefix("/packages/blobs/") else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "This version has no blob"})),
+        );
+    };
+
+    (
+        StatusCode::OK,
+        Json(json!({"name": name, "version": version, "sha256": hash, "size": size})),
+    )
+}
+
+/// `GET /packages/{name}/{version}/download`: the blob for a published version, by name.
+///
+/// Third-party tools don't have to dig the hash out of `lua_source_url`, and the counters
+/// go to exactly this version instead of whichever one uploaded the bytes first.
+/// Streams by default; `?redirect=true` sends a 302 to the public bucket URL when there is one,
+/// except for private packages, which always stream.
+/// `X-Content-Sha256` carries the hash either way, so the client can check what it got.
+pub async fn download_version(
+    State(state): State<AppState>,
+    Path((name, version)): Path<(String, String)>,
+    access: Option<Extension<PrivateAccess>>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let range = range_header(&headers);
+
+    let found: Option<(Uuid, Uuid, String, bool)> = match sqlx::query_as(
+        r#"
+        SELECT v.package_id, v.id, v.lua_source_url, p.taken_down
+        FROM package_versions v JOIN packages p ON p.id = v.package_id
+        WHERE p.name = $1 AND v.version = $2 AND v.status = 'published'
+    "#,
+    )
+    .bind(&name)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .instrument(db_span("find version"))
+    .await
+    {
+        Ok(found) => found,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let Some((package_id, version_id, source_url, taken_down)) = found else {
+        return (StatusCode::NOT_FOUND, "Version not found").into_response();
+    };
+    if taken_down {
+        return (
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            "This package has been taken down",
+        )
+            .into_response();
+    }
+    let Some(hash) = source_url.strip_prefix("/packages/blobs/") else {
+        return (StatusCode::NOT_FOUND, "This version has no blob").into_response();
+    };
+
+    if !is_resume(range.as_deref()) {
+        count_download(&state, package_id, version_id, &headers).await;
+    }
+
+    let redirect = params.get("redirect").is_some_and(|v| v == "true") && access.is_none();
+    if let Some(url) = crate::utils::storage::public_blob_url(hash).filter(|_| redirect) {
+        return (
+            StatusCode::FOUND,
+            [
+                ("location", url),
+                ("x-content-sha256", hash.to_string()),
+            ],
+        )
+            .into_response();
+    }
+
+    let mut res = serve_blob(&state, hash, range).await;
+    if let Ok(value) = hash.parse() {
+        res.headers_mut().insert("x-content-sha256", value);
+    }
+    if let Ok(value) = format!("attachment; filename=\"{}-{}.zip\"", name, version).parse() {
+        res.headers_mut().insert("content-disposition", value);
+    }
+    res
+}
+
+fn range_header(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(axum::http::header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn is_resume(range: Option<&str>) -> bool {
+    range.and_then(parse_range_start).is_some_and(|start| start > 0)
+}
+
+/// Sends a blob from R2, whole or the one range asked for.
+async fn serve_blob(state: &AppState, hash: &str, range: Option<String>) -> axum::response::Response {
+    let data = match state.storage.get_blob(hash).await {
+        Ok(data) => data,
+        Err(_) => return (StatusCode::NOT_FOUND, "Blob not found").into_response(),
+    };
+
+    let Some(range) = range else {
+        return (
             StatusCode::OK,
-            [("content-type", "application/octet-stream")],
+            [
+                ("content-type", "application/octet-stream"),
+                ("accept-ranges", "bytes"),
+            ],
             data,
+        )
+            .into_response();
+    };
+
+    let total = data.len();
+    match parse_range(&range, total) {
+        Some((start, end)) => (
+            StatusCode::PARTIAL_CONTENT,
+            [
+                ("content-type", "application/octet-stream".to_string()),
+                ("accept-ranges", "bytes".to_string()),
+                ("content-range", format!("bytes {}-{}/{}", start, end, total)),
+            ],
+            data[start..=end].to_vec(),
+        )
+            .into_response(),
+        None => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [("content-range", format!("bytes */{}", total))],
         )
             .into_response(),
-        Err(_) => (StatusCode::NOT_FOUND, "Blob not found").into_response(),
+    }
+}
+
+/// First byte asked for by a `bytes=start-...` header, if it's one we understand.
+fn parse_range_start(header: &str) -> Option<usize> {
+    header
+        .strip_prefix("bytes=")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Turns `bytes=start-` or `bytes=start-end` into inclusive offsets within a blob of `len` bytes.
+/// Multiple ranges and suffix ranges (`bytes=-500`) aren't worth the code—nobody sends them to us.
+fn parse_range(header: &str, len: usize) -> Option<(usize, usize)> {
+    let start = parse_range_start(header)?;
+    let end = match header.split_once('-')?.1.trim() {
+        "" => len.checked_sub(1)?,
+        end => end.parse::<usize>().ok()?.min(len.checked_sub(1)?),
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// Bumps the package, version and daily download counters.
+async fn count_download(state: &AppState, package_id: Uuid, version_id: Uuid, headers: &HeaderMap) {
+    let _ = sqlx::query("UPDATE packages SET download_count = download_count + 1 WHERE id = $1")
+        .bind(package_id)
+        .execute(&state.db)
+        .instrument(db_span("count package download"))
+        .await;
+
+    let _ = sqlx::query("UPDATE package_versions SET download_count = download_count + 1 WHERE id = $1")
+        .bind(version_id)
+        .execute(&state.db)
+        .instrument(db_span("count version download"))
+        .await;
+
+    // Same bump in the daily series, so the admin integrity job can rebuild the counter if it drifts.
+    let _ = sqlx::query(
+        r#"
+        INSERT INTO package_downloads (package_id, day, count)
+        VALUES ($1, $2, 1)
+        ON CONFLICT (package_id, day) DO UPDATE SET count = package_downloads.count + 1
+    "#,
+    )
+    .bind(package_id)
+    .bind(chrono::Utc::now().timestamp() / 86400)
+    .execute(&state.db)
+    .instrument(db_span("count daily download"))
+    .await;
+
+    // Which CLI versions are out there, from clients that opted in (see utils::clients).
+    if let Some(client) = crate::utils::clients::ClientInfo::from_headers(headers) {
+        crate::utils::clients::record(&state.db, &client)
+            .instrument(db_span("count client download"))
+            .await;
     }
 }
 
@@ -647,9 +2388,10 @@ pub async fn deprecate_package(
     Path(name): Path<String>,
     Json(payload): Json<DeprecatePackageRequest>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let package = match sqlx::query_as::<_, Package>(
-        "SELECT id, name, description, author, repository, created_at, updated_at, download_count, deprecated, deprecation_reason FROM packages WHERE name = $1"
-    )
+    let package = match sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM {} WHERE p.name = $1",
+        PACKAGE_COLUMNS, PACKAGE_FROM
+    ))
         .bind(&name)
         .fetch_optional(&state.db)
         .await
@@ -673,7 +2415,7 @@ pub async fn deprecate_package(
         }
     };
 
-    if package.author != user.username {
+    if !user.owns(&package) {
         return (
             StatusCode::FORBIDDEN,
             Json(json!({"error": "Not the owner"})),
@@ -701,6 +2443,157 @@ pub async fn deprecate_package(
     }
 }
 
+/// Stars (and optionally watches) a package for the logged-in user.
+///
+/// PUT is idempotent: starring twice just updates the notify preference.
+/// The body is optional, so `PUT` with nothing stars without notifications.
+pub async fn star_package(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(name): Path<String>,
+    payload: Option<Json<StarRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+    let Json(payload) = payload.unwrap_or_default();
+
+    let pkg_id: Option<Uuid> = match sqlx::query_scalar("SELECT id FROM packages WHERE name = $1")
+        .bind(&name)
+        .fetch_optional(&state.db)
+        .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let Some(pkg_id) = pkg_id else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Package not found"})),
+        );
+    };
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO stars (user_id, package_id, notify, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, package_id) DO UPDATE SET notify = EXCLUDED.notify
+        "#,
+    )
+    .bind(user_id)
+    .bind(pkg_id)
+    .bind(payload.notify)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": format!("Starred {}", name), "notify": payload.notify})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Removes the logged-in user's star from a package.
+/// Unstarring something you never starred is a no-op, not an error.
+pub async fn unstar_package(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(name): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    let result = sqlx::query(
+        "DELETE FROM stars WHERE user_id = $1 AND package_id = (SELECT id FROM packages WHERE name = $2)",
+    )
+    .bind(user_id)
+    .bind(&name)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": format!("Unstarred {}", name)})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Lists every package the logged-in user has starred, newest star first.
+pub async fn list_starred(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    // A star outlives being taken off a private package's team. The package doesn't show.
+    let rows: Vec<(String, String, String, bool, i64)> = match sqlx::query_as(&format!(
+        r#"
+        SELECT p.name, p.description, COALESCE(u.username, p.author), s.notify, s.created_at FROM stars s
+        JOIN packages p ON p.id = s.package_id
+        LEFT JOIN users u ON u.id = p.owner_id
+        WHERE s.user_id = $1 AND {}
+        ORDER BY s.created_at DESC
+        "#,
+        visible_to(1)
+    ))
+    .bind(user_id)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let results: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|(name, description, author, notify, starred_at)| {
+            json!({
+                "name": name,
+                "description": description,
+                "author": author,
+                "notify": notify,
+                "starred_at": starred_at
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(with_rfc3339(json!(results))))
+}
+
 /// Unpublishes a version of a package.
 ///
 /// Policy:
@@ -712,9 +2605,10 @@ pub async fn unpublish_version(
     user: AuthenticatedUser,
     Path((name, version)): Path<(String, String)>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let package = match sqlx::query_as::<_, Package>(
-        "SELECT id, name, description, author, repository, created_at, updated_at, download_count, deprecated, deprecation_reason FROM packages WHERE name = $1"
-    )
+    let package = match sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM {} WHERE p.name = $1",
+        PACKAGE_COLUMNS, PACKAGE_FROM
+    ))
         .bind(&name)
         .fetch_optional(&state.db)
         .await
@@ -738,7 +2632,7 @@ pub async fn unpublish_version(
         }
     };
 
-    if package.author != user.username {
+    if !user.owns(&package) {
         return (
             StatusCode::FORBIDDEN,
             Json(json!({"error": "Not the owner"})),
@@ -779,18 +2673,19 @@ pub async fn unpublish_version(
     if now - target_version.created_at > 24 * 60 * 60 {
         return (
             StatusCode::FORBIDDEN,
-            Json(json!({"error": "Cannot unpublish versions older than 24 hours. Deprecate it instead."})),
+            Json(json!({
+                "error": "Cannot unpublish versions older than 24 hours. Deprecate it instead.",
+                "code": "unpublish_window_closed",
+                "published_at": target_version.created_at
+            })),
         );
     }
 
     // Check 2: Dependents (Left-pad protection)
-    // Checks if ANY package depends on this package name.
-    let dependents: Option<i32> = match sqlx::query_scalar(
-        "SELECT 1 FROM package_versions WHERE dependencies ? $1 LIMIT 1"
-    )
-    .bind(&name)
-    .fetch_optional(&state.db)
-    .await {
+    // Only versions whose range actually accepts this version count. Nobody can depend on a
+    // pre-release through `^1.0.0`, so a broken 2.0.0-beta.1 can go even if half the registry
+    // uses 1.x.
+    let dependents = match crate::utils::dependencies::dependents_of(&state.db, &name, &version).await {
         Ok(d) => d,
         Err(e) => {
             return (
@@ -800,27 +2695,54 @@ pub async fn unpublish_version(
         }
     };
 
-    if dependents.is_some() {
+    if !dependents.is_empty() {
+        let shown: Vec<&str> = dependents.iter().take(5).map(String::as_str).collect();
+        let more = dependents.len().saturating_sub(shown.len());
+        let mut list = shown.join(", ");
+        if more > 0 {
+            list.push_str(&format!(" and {} more", more));
+        }
         return (
             StatusCode::FORBIDDEN,
-            Json(json!({"error": "Cannot unpublish: other packages depend on this package."})),
+            Json(json!({
+                "error": format!("Cannot unpublish: other packages depend on this version: {}", list),
+                "code": "has_dependents",
+                "dependents": dependents
+            })),
         );
     }
 
     // Proceed to delete
-    // 1. Delete blob from R2
-    let hash = target_version.lua_source_url.replace("/packages/blobs/", "");
-    if let Err(e) = state.storage.delete_blob(&hash).await {
-        tracing::error!("Failed to delete blob {} during unpublish: {}", hash, e);
-        // Continue anyway to remove from DB, otherwise we leave a broken record.
-    }
+    // 1. Delete from DB
+    // Drop any dist-tags pointing at this version first so `name@tag` doesn't resolve to a ghost.
+    let _ = sqlx::query("DELETE FROM dist_tags WHERE package_id = $1 AND version = $2")
+        .bind(pkg_id)
+        .bind(&version)
+        .execute(&state.db)
+        .await;
+
+    // Same for cached diffs. If the version number gets reused later, stale diffs would lie.
+    let _ = sqlx::query("DELETE FROM version_diffs WHERE package_id = $1 AND (from_version = $2 OR to_version = $2)")
+        .bind(pkg_id)
+        .bind(&version)
+        .execute(&state.db)
+        .await;
 
-    // 2. Delete from DB
     let delete_res = sqlx::query("DELETE FROM package_versions WHERE id = $1")
         .bind(target_version.id)
         .execute(&state.db)
         .await;
 
+    // 2. Delete the blob from R2, if this was the last version using it. Identical zips share a
+    // blob, and pulling it out from under another version would break every install of that one.
+    // A failure here just leaves an unused object in the bucket, not a broken record.
+    if delete_res.is_ok()
+        && let Some(hash) = target_version.lua_source_url.strip_prefix("/packages/blobs/")
+        && let Err(e) = crate::utils::storage::release_blob(&state.db, &state.storage, hash).await
+    {
+        tracing::error!("Failed to delete blob {} during unpublish: {}", hash, e);
+    }
+
     match delete_res {
         Ok(_) => (
             StatusCode::OK,
@@ -832,7 +2754,3 @@ pub async fn unpublish_version(
         ),
     }
 }
-
-fn text_content(s: &str) -> askalono::TextData {
-    askalono::TextData::from(s)
-}
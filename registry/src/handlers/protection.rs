@@ -0,0 +1,338 @@
+//! Protected publishing: new versions of a protected package wait for the owner to confirm
+//! them before anyone can install them.
+//!
+//! A stolen session token is all it takes to publish, which is a lot of trust to put in one
+//! laptop for a package half the ecosystem depends on. So for protected packages, `upload_blob`
+//! leaves the version 'held' instead of 'published' and sends the owner's webhooks a one-time
+//! confirmation link. The link only works once PROTECTED_PUBLISH_DELAY_MINUTES (60) have passed,
+//! which gives whoever really owns the account time to see the notification and reject the
+//! version. Rejecting works straight away.
+//!
+//! Packages have one owner, so it's that same account that confirms. What a thief with only a
+//! session token doesn't have is the link. Turning protection off is delayed the same way,
+//! otherwise it would be the first thing they did. So is changing where the link goes: while
+//! the account has a protected package, a new webhook only starts getting events after the
+//! delay, a deleted one keeps getting them until then, and the other hooks hear about both
+//! (see handlers::webhook).
+
+use crate::handlers::package::{notify_watchers, point_tag};
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::package::{ApproveVersionRequest, PACKAGE_COLUMNS, PACKAGE_FROM, Package, ProtectionRequest};
+use crate::state::AppState;
+use crate::utils::notify;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How long a held version waits before it can be approved, and how long turning protection
+/// off takes to kick in. PROTECTED_PUBLISH_DELAY_MINUTES, 60 by default.
+pub fn delay_secs() -> i64 {
+    std::env::var("PROTECTED_PUBLISH_DELAY_MINUTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(60)
+        * 60
+}
+
+/// Whether new versions of this package have to be confirmed. Protection that's been turned
+/// off still counts until its `unprotect_at` has passed.
+pub async fn is_protected(db: &PgPool, package_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT protected AND (unprotect_at IS NULL OR unprotect_at > $2) FROM packages WHERE id = $1",
+    )
+    .bind(package_id)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(db)
+    .await
+    .map(|p| p.unwrap_or(false))
+}
+
+/// Whether any of the user's packages is protected. Their webhook changes are delayed then.
+pub async fn guards_account(db: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM packages
+            WHERE owner_id = $1 AND protected AND (unprotect_at IS NULL OR unprotect_at > $2)
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(db)
+    .await
+}
+
+/// Tells the owner a version is waiting, with everything needed to confirm or reject it.
+pub fn notify_held(db: PgPool, owner: String, package: &str, version: &str, token: &str, held_at: i64) {
+    let approve_after = held_at + delay_secs();
+    notify::notify_owner(
+        db,
+        owner,
+        notify::EVENT_PUBLISH_HELD,
+        json!({
+            "package": package,
+            "version": version,
+            "approve_after": approve_after,
+            "approve_url": format!("/packages/{}/versions/{}/approve?token={}", package, version, token),
+            "token": token,
+            "command": format!("mosaic approve {}@{} --token {}", package, version, token),
+            "reject_command": format!("mosaic reject {}@{}", package, version),
+        }),
+    );
+}
+
+pub(crate) async fn owned_package(state: &AppState, user: &AuthenticatedUser, name: &str) -> Result<Package, (StatusCode, Json<serde_json::Value>)> {
+    let package = sqlx::query_as::<_, Package>(&format!(
+        "SELECT {} FROM {} WHERE p.name = $1",
+        PACKAGE_COLUMNS, PACKAGE_FROM
+    ))
+    .bind(name)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()}))))?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, Json(json!({"error": "Package not found"}))))?;
+
+    if !user.owns(&package) {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"error": "Not the owner"}))));
+    }
+    Ok(package)
+}
+
+/// `PUT /packages/{name}/protection`: turns protected publishing on or off.
+///
+/// On is immediate. Off is scheduled `delay_secs` out and the owner's webhooks hear about it;
+/// asking again while it's scheduled doesn't push the date back. Turning it back on cancels it.
+pub async fn set_protection(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(name): Path<String>,
+    Json(payload): Json<ProtectionRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let package = match owned_package(&state, &user, &name).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    let pkg_id = package.id.expect("Package ID should be present");
+    let now = chrono::Utc::now().timestamp();
+
+    let result: Result<(bool, Option<i64>), _> = if payload.protected {
+        sqlx::query_as(
+            "UPDATE packages SET protected = TRUE, unprotect_at = NULL WHERE id = $1 RETURNING protected, unprotect_at",
+        )
+        .bind(pkg_id)
+        .fetch_one(&state.db)
+        .await
+    } else if !package.protected {
+        // Already off (or the scheduled switch-off has passed). Make it stick.
+        sqlx::query_as(
+            "UPDATE packages SET protected = FALSE, unprotect_at = NULL WHERE id = $1 RETURNING protected, unprotect_at",
+        )
+        .bind(pkg_id)
+        .fetch_one(&state.db)
+        .await
+    } else {
+        let result = sqlx::query_as(
+            "UPDATE packages SET unprotect_at = COALESCE(unprotect_at, $2) WHERE id = $1 RETURNING protected, unprotect_at",
+        )
+        .bind(pkg_id)
+        .bind(now + delay_secs())
+        .fetch_one(&state.db)
+        .await;
+        if let Ok((_, Some(at))) = &result {
+            notify::notify_owner(
+                state.db.clone(),
+                package.author.clone(),
+                notify::EVENT_PROTECTION_DISABLED,
+                json!({ "package": package.name, "unprotect_at": at }),
+            );
+        }
+        result
+    };
+
+    match result {
+        Ok((protected, unprotect_at)) => (
+            StatusCode::OK,
+            Json(json!({
+                "name": package.name,
+                "protected": protected,
+                "unprotect_at": unprotect_at,
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// `POST /packages/{name}/versions/{version}/approve`: publishes a held version.
+///
+/// Needs the owner's login *and* the token from the confirmation link (in the body, or as
+/// `?token=` straight from the link), and not before the delay is up. 425 until then, with
+/// `approve_after` saying when.
+pub async fn approve_version(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((name, version)): Path<(String, String)>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+    payload: Option<Json<ApproveVersionRequest>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let package = match owned_package(&state, &user, &name).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    let pkg_id = package.id.expect("Package ID should be present");
+
+    let Some(token) = payload.map(|p| p.0.token).or_else(|| params.get("token").cloned()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Missing token. It's in the confirmation link sent to your webhooks."})),
+        );
+    };
+    let token_hash = format!("{:x}", Sha256::digest(token.as_bytes()));
+
+    let held: Option<(String, Option<String>, Option<i64>)> = match sqlx::query_as(
+        "SELECT status, approval_token_hash, held_at FROM package_versions WHERE package_id = $1 AND version = $2",
+    )
+    .bind(pkg_id)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let held_at = match held {
+        Some((status, Some(stored), held_at)) if status == "held" => {
+            if stored != token_hash {
+                return (
+                    StatusCode::FORBIDDEN,
+                    Json(json!({"error": "That token isn't the one for this version"})),
+                );
+            }
+            held_at.unwrap_or(0)
+        }
+        Some(_) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "This version isn't waiting for approval"})),
+            );
+        }
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Version not found"})),
+            );
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let approve_after = held_at + delay_secs();
+    if now < approve_after {
+        return (
+            StatusCode::TOO_EARLY,
+            Json(json!({
+                "error": format!(
+                    "{}@{} can be approved in {} minutes. The wait is what gives you time to reject a publish you didn't make.",
+                    name,
+                    version,
+                    (approve_after - now + 59) / 60
+                ),
+                "approve_after": approve_after,
+            })),
+        );
+    }
+
+    let result: Result<Option<Option<String>>, _> = sqlx::query_scalar(
+        r#"
+        UPDATE package_versions SET status = 'published', approval_token_hash = NULL
+        WHERE package_id = $1 AND version = $2 AND status = 'held' AND approval_token_hash = $3
+        RETURNING pending_tag
+        "#,
+    )
+    .bind(pkg_id)
+    .bind(&version)
+    .bind(&token_hash)
+    .fetch_optional(&state.db)
+    .await;
+
+    match result {
+        Ok(Some(pending_tag)) => {
+            let tag = pending_tag.unwrap_or_else(|| "latest".to_string());
+            point_tag(&state.db, pkg_id, &package.name, &tag, &version).await;
+            notify_watchers(&state.db, &package.name, &version).await;
+            (
+                StatusCode::OK,
+                Json(json!({"name": package.name, "version": version, "status": "published", "tag": tag})),
+            )
+        }
+        // Someone else approved or rejected it in between.
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "This version isn't waiting for approval"})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// `POST /packages/{name}/versions/{version}/reject`: throws a held version away.
+///
+/// No token and no wait: rejecting can only ever remove something nobody could install yet.
+/// The version number is free again afterwards.
+pub async fn reject_version(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((name, version)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let package = match owned_package(&state, &user, &name).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    let deleted: Result<Option<String>, _> = sqlx::query_scalar(
+        "DELETE FROM package_versions WHERE package_id = $1 AND version = $2 AND status = 'held' RETURNING lua_source_url",
+    )
+    .bind(package.id)
+    .bind(&version)
+    .fetch_optional(&state.db)
+    .await;
+
+    match deleted {
+        Ok(Some(source_url)) => {
+            if let Some(hash) = source_url.strip_prefix("/packages/blobs/")
+                && let Err(e) = crate::utils::storage::release_blob(&state.db, &state.storage, hash).await
+            {
+                tracing::warn!("Couldn't release blob {} of rejected {}@{}: {}", hash, name, version, e);
+            }
+            (
+                StatusCode::OK,
+                Json(json!({"name": package.name, "version": version, "status": "rejected"})),
+            )
+        }
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "This version isn't waiting for approval"})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
@@ -0,0 +1,248 @@
+//! API tokens: long-lived logins for CI, optionally restricted to an allow-list of packages.
+//!
+//! They're JWTs like the ones `login` hands out, with an extra `packages` claim. The middleware
+//! carries it into `AuthenticatedUser`, and `owns()` treats a package that isn't on the list as
+//! someone else's, so a token for `my-ui-lib` can't publish `logger`. Account-wide endpoints
+//! refuse restricted tokens outright (`require_unrestricted`).
+
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::user::{ApiToken, Claims, CreateTokenRequest};
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use jsonwebtoken::{EncodingKey, Header, encode};
+use serde_json::json;
+use std::env;
+use uuid::Uuid;
+
+/// Cap per user. Same reasoning as webhooks: nobody needs more, and it keeps the list readable.
+const MAX_TOKENS_PER_USER: i64 = 20;
+
+const DEFAULT_EXPIRY_DAYS: i64 = 90;
+const MAX_EXPIRY_DAYS: i64 = 365;
+
+/// Creates an API token for the logged-in user.
+///
+/// The token is only ever returned here. Package names are checked for shape, not existence,
+/// so a CI token can be made before its package is first published. It still can't touch a
+/// package somebody else owns: `owns()` checks both.
+pub async fn create_token(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(payload): Json<CreateTokenRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    let name = payload.name.trim().to_string();
+    if name.is_empty() || name.len() > 64 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Token name must be 1-64 characters"})),
+        );
+    }
+
+    let mut packages = payload.packages;
+    if let Some(list) = &mut packages {
+        list.sort();
+        list.dedup();
+        if list.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "List at least one package, or leave `packages` out for an unrestricted token"})),
+            );
+        }
+        for package in list.iter() {
+            if let Err(e) = crate::utils::validation::validate_package_name(package) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": format!("{}: {}", package, e)})),
+                );
+            }
+        }
+    }
+
+    let days = payload.expires_in_days.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    if !(1..=MAX_EXPIRY_DAYS).contains(&days) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Tokens can last 1 to {} days", MAX_EXPIRY_DAYS)})),
+        );
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens WHERE user_id = $1 AND expires_at > $2")
+        .bind(user_id)
+        .bind(now)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+    if count >= MAX_TOKENS_PER_USER {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("You can have at most {} API tokens. Revoke one first.", MAX_TOKENS_PER_USER)})),
+        );
+    }
+
+    let jti = Uuid::new_v4();
+    let expires_at = now + days * 24 * 60 * 60;
+    let claims = Claims {
+        sub: user.user_id.clone(),
+        username: user.username.clone(),
+        exp: expires_at,
+        jti,
+        packages: packages.clone(),
+    };
+    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let Ok(token) = encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref())) else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Token generation error"})),
+        );
+    };
+
+    let inserted = sqlx::query(
+        "INSERT INTO api_tokens (id, user_id, name, packages, created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(jti)
+    .bind(user_id)
+    .bind(&name)
+    .bind(&packages)
+    .bind(now)
+    .bind(expires_at)
+    .execute(&state.db)
+    .await;
+
+    match inserted {
+        Ok(_) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "id": jti,
+                "name": name,
+                "packages": packages,
+                "created_at": now,
+                "expires_at": expires_at,
+                "token": token,
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": format!("Could not create token: {}", e)})),
+        ),
+    }
+}
+
+/// Lists the logged-in user's API tokens that still work. The tokens themselves aren't included.
+pub async fn list_tokens(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        r#"
+        SELECT id, name, packages, created_at, expires_at FROM api_tokens
+        WHERE user_id = $1 AND expires_at > $2
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_all(&state.db)
+    .await;
+
+    match tokens {
+        Ok(tokens) => (StatusCode::OK, Json(json!(tokens))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// Revokes one of the logged-in user's API tokens, the same way logout revokes a login.
+pub async fn revoke_token(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let expires_at: Option<i64> =
+        match sqlx::query_scalar("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2 RETURNING expires_at")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await
+        {
+            Ok(e) => e,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": e.to_string()})),
+                );
+            }
+        };
+    let Some(expires_at) = expires_at else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Token not found"})),
+        );
+    };
+
+    let revoked = sqlx::query("INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await;
+    if let Err(e) = revoked {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        );
+    }
+
+    match tx.commit().await {
+        Ok(_) => (StatusCode::OK, Json(json!({"message": "Token revoked"}))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
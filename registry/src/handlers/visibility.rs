@@ -0,0 +1,386 @@
+//! Private packages: a studio's internal libraries, shared through the same registry and CLI.
+//!
+//! A package is 'public' or 'private'. A private one only exists for its owner and the
+//! accounts on its member list, the team. For everyone else it's a 404 on every endpoint,
+//! metadata and blobs alike, and it's left out of listings and search. That's `guard`, a
+//! middleware on the package routes, plus `visible_to` in the listing queries.
+//!
+//! Members can read and install. Publishing, deprecating and the rest stay with the owner.
+
+use crate::handlers::protection::owned_package;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::package::VisibilityRequest;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{FromRequestParts, MatchedPath, Path, Query, RawPathParams, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Cap per package. A team, not a mailing list.
+const MAX_MEMBERS_PER_PACKAGE: i64 = 100;
+
+/// SQL for "the viewer in `$n` may see package `p`". Bind the viewer as an `Option<Uuid>`
+/// (see `viewer_id`); NULL only matches public packages.
+pub fn visible_to(n: usize) -> String {
+    format!("(p.visibility = 'public' OR {})", on_team(n))
+}
+
+/// SQL for "the viewer in `$n` owns package `p` or is one of its members".
+fn on_team(n: usize) -> String {
+    format!(
+        "(p.owner_id = ${n} OR EXISTS (SELECT 1 FROM package_members m WHERE m.package_id = p.id AND m.user_id = ${n}))"
+    )
+}
+
+/// The id to bind for `visible_to`. None for anonymous requests.
+pub fn viewer_id(viewer: Option<&AuthenticatedUser>) -> Option<Uuid> {
+    viewer.and_then(|v| Uuid::parse_str(&v.user_id).ok())
+}
+
+/// Set by `guard` on requests for a private package the caller may see, with who they are.
+/// Handlers use it to sign download URLs for that caller instead of handing out bucket URLs.
+/// Never set for public packages.
+#[derive(Clone, Copy, Debug)]
+pub struct PrivateAccess {
+    pub user: Uuid,
+}
+
+/// How long a signed download URL for a private package works. Long enough to start the
+/// download right after fetching the metadata, short enough that a leaked link is useless soon.
+pub const DOWNLOAD_URL_TTL_SECS: i64 = 5 * 60;
+
+/// `/packages/{name}/{version}/download` signed for `user`: `?expires=&user=&sig=`, an
+/// HMAC of all of it keyed with JWT_SECRET. It works without a login, for whoever has the
+/// link, until it expires, and only while `user` is still on the package's team.
+pub fn signed_download_url(name: &str, version: &str, user: Uuid) -> String {
+    let expires = chrono::Utc::now().timestamp() + DOWNLOAD_URL_TTL_SECS;
+    let sig = URL_SAFE_NO_PAD.encode(download_mac(name, version, user, expires).finalize().into_bytes());
+    format!(
+        "/packages/{}/{}/download?expires={}&user={}&sig={}",
+        name, version, expires, user, sig
+    )
+}
+
+fn download_mac(name: &str, version: &str, user: Uuid, expires: i64) -> Hmac<Sha256> {
+    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("download\n{}\n{}\n{}\n{}", name, version, user, expires).as_bytes());
+    mac
+}
+
+/// The user a signed download URL was made for, if `uri` is a valid, unexpired one.
+fn signed_user(name: &str, version: &str, uri: &axum::http::Uri) -> Option<Uuid> {
+    let Query(params) = Query::<std::collections::HashMap<String, String>>::try_from_uri(uri).ok()?;
+    let expires: i64 = params.get("expires")?.parse().ok()?;
+    let user = Uuid::parse_str(params.get("user")?).ok()?;
+    let sig = URL_SAFE_NO_PAD.decode(params.get("sig")?).ok()?;
+    if expires < chrono::Utc::now().timestamp() {
+        return None;
+    }
+    download_mac(name, version, user, expires).verify_slice(&sig).ok()?;
+    Some(user)
+}
+
+/// Hides private packages from everyone who can't see them. Runs after routing, so it knows
+/// which package a request is about: `{name}` for most routes, `{hash}` for `/blobs/{hash}`.
+///
+/// Public packages are the fast path: one lookup and through, without even checking the
+/// login. Only for a private one do we work out who's asking, from a signed download URL
+/// (see `signed_download_url`) or the Authorization header, and check they're on its team.
+/// A name with no package behind it goes through untouched; the handler 404s (or creates it).
+/// A restricted API token only sees the private packages it lists, like `owns()`.
+pub async fn guard(
+    State(state): State<AppState>,
+    params: RawPathParams,
+    req: Request,
+    next: Next,
+) -> Response {
+    let mut name = None;
+    let mut version = None;
+    let mut hash = None;
+    for (key, value) in &params {
+        match key {
+            "name" => name = Some(value.to_string()),
+            "version" => version = Some(value.to_string()),
+            "hash" => hash = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    let rows: Result<Vec<(String, bool)>, _> = if let Some(name) = &name {
+        sqlx::query_as("SELECT name, visibility = 'public' FROM packages WHERE name = $1")
+            .bind(name)
+            .fetch_all(&state.db)
+            .await
+    } else if let Some(hash) = &hash {
+        // Identical zips can share a blob. It's served if any package using it is visible.
+        sqlx::query_as(
+            r#"
+            SELECT DISTINCT p.name, p.visibility = 'public' FROM package_versions v
+            JOIN packages p ON p.id = v.package_id
+            WHERE v.lua_source_url = $1
+            "#,
+        )
+        .bind(format!("/packages/blobs/{}", hash))
+        .fetch_all(&state.db)
+        .await
+    } else {
+        Ok(Vec::new())
+    };
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => return internal_error(e),
+    };
+    if rows.is_empty() || rows.iter().any(|(_, public)| *public) {
+        return next.run(req).await;
+    }
+
+    let not_found = || {
+        let error = if hash.is_some() { "Blob not found" } else { "Package not found" };
+        (StatusCode::NOT_FOUND, Json(json!({"error": error}))).into_response()
+    };
+
+    // A signed URL only opens the download it was made for.
+    let is_download = req
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|p| p.as_str().ends_with("/{name}/{version}/download"));
+    let signed = match (&name, &version) {
+        (Some(name), Some(version)) if is_download => signed_user(name, version, req.uri()),
+        _ => None,
+    };
+
+    let (mut parts, body) = req.into_parts();
+    let (user, names): (Uuid, Vec<String>) = match signed {
+        Some(user) => (user, rows.into_iter().map(|(name, _)| name).collect()),
+        None => {
+            let Ok(viewer) = <AuthenticatedUser as FromRequestParts<AppState>>::from_request_parts(&mut parts, &state).await
+            else {
+                return not_found();
+            };
+            let Some(user) = viewer_id(Some(&viewer)) else {
+                return not_found();
+            };
+            let names = rows.into_iter().map(|(name, _)| name).filter(|n| viewer.may_use(n)).collect();
+            (user, names)
+        }
+    };
+
+    let on_team: Result<bool, _> = sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM packages p WHERE p.name = ANY($1) AND {})",
+        on_team(2)
+    ))
+    .bind(&names)
+    .bind(user)
+    .fetch_one(&state.db)
+    .await;
+    match on_team {
+        Ok(true) => {
+            parts.extensions.insert(PrivateAccess { user });
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Ok(false) => not_found(),
+        Err(e) => internal_error(e),
+    }
+}
+
+fn internal_error(e: sqlx::Error) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": e.to_string()})),
+    )
+        .into_response()
+}
+
+/// `PUT /packages/{name}/visibility`: makes a package public or private. Owner only.
+///
+/// Takes effect straight away. Making a package private doesn't reach copies people already
+/// installed; it only stops new installs from anyone off the member list.
+pub async fn set_visibility(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(name): Path<String>,
+    Json(payload): Json<VisibilityRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !matches!(payload.visibility.as_str(), "public" | "private") {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Visibility must be \"public\" or \"private\""})),
+        );
+    }
+    let package = match owned_package(&state, &user, &name).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    let result = sqlx::query("UPDATE packages SET visibility = $2 WHERE id = $1")
+        .bind(package.id)
+        .bind(&payload.visibility)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"name": package.name, "visibility": payload.visibility})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// `GET /packages/{name}/members`: who besides the owner can see a private package.
+///
+/// Anyone who can see the package can see the list, so a member knows who else is on it.
+/// `guard` has already turned everyone else away.
+pub async fn list_members(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let members: Result<Vec<(String, i64)>, _> = sqlx::query_as(
+        r#"
+        SELECT u.username, m.added_at FROM package_members m
+        JOIN packages p ON p.id = m.package_id
+        JOIN users u ON u.id = m.user_id
+        WHERE p.name = $1
+        ORDER BY m.added_at
+        "#,
+    )
+    .bind(&name)
+    .fetch_all(&state.db)
+    .await;
+
+    match members {
+        Ok(members) => {
+            let members: Vec<serde_json::Value> = members
+                .into_iter()
+                .map(|(username, added_at)| json!({"username": username, "added_at": added_at}))
+                .collect();
+            (StatusCode::OK, Json(json!({"name": name, "members": members})))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// `PUT /packages/{name}/members/{username}`: lets someone see a private package. Owner only.
+/// Adding someone who's already on the list is a no-op.
+pub async fn add_member(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((name, username)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let package = match owned_package(&state, &user, &name).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    let member: Option<Uuid> = match sqlx::query_scalar("SELECT id FROM users WHERE username = $1 AND NOT banned")
+        .bind(&username)
+        .fetch_optional(&state.db)
+        .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+    let Some(member) = member else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("No user called {}", username)})),
+        );
+    };
+    if Some(member) == package.owner_id {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "The owner can always see their own package"})),
+        );
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM package_members WHERE package_id = $1")
+        .bind(package.id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+    if count >= MAX_MEMBERS_PER_PACKAGE {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("A package can have at most {} members", MAX_MEMBERS_PER_PACKAGE)})),
+        );
+    }
+
+    let inserted = sqlx::query(
+        "INSERT INTO package_members (package_id, user_id, added_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+    )
+    .bind(package.id)
+    .bind(member)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await;
+
+    match inserted {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"name": package.name, "username": username, "message": "Member added"})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
+
+/// `DELETE /packages/{name}/members/{username}`: takes someone off the list. Owner only.
+pub async fn remove_member(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path((name, username)): Path<(String, String)>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let package = match owned_package(&state, &user, &name).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    let deleted = sqlx::query(
+        r#"
+        DELETE FROM package_members m USING users u
+        WHERE m.user_id = u.id AND m.package_id = $1 AND u.username = $2
+        "#,
+    )
+    .bind(package.id)
+    .bind(&username)
+    .execute(&state.db)
+    .await;
+
+    match deleted {
+        Ok(r) if r.rows_affected() == 0 => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("{} isn't a member of {}", username, package.name)})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"name": package.name, "username": username, "message": "Member removed"})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
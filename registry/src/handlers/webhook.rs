@@ -0,0 +1,277 @@
+use crate::handlers::protection;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::user::CreateWebhookRequest;
+use crate::state::AppState;
+use crate::utils::notify;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Cap per user. Nobody needs more than this, and it bounds the fan-out on every event.
+const MAX_WEBHOOKS_PER_USER: i64 = 5;
+
+/// Drops the user's webhooks whose scheduled deletion has passed.
+async fn purge_deleted(db: &sqlx::PgPool, user_id: Uuid) {
+    let _ = sqlx::query("DELETE FROM webhooks WHERE user_id = $1 AND delete_at <= $2")
+        .bind(user_id)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(db)
+        .await;
+}
+
+/// Registers a webhook for the logged-in user.
+///
+/// The signing secret is only ever returned here. Lose it and you delete + re-register.
+/// Only https URLs are accepted—these payloads are security alerts, not something to send in the clear.
+///
+/// With a protected package on the account, the hook only starts getting events after the
+/// protected-publish delay, and the existing hooks are told it was added. A stolen session
+/// can't have the next approval link sent to itself without the owner hearing about it first.
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    if payload.url.len() > 2048 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Webhook URL is too long"})),
+        );
+    }
+    if let Err(e) = notify::check_url(&payload.url) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    purge_deleted(&state.db, user_id).await;
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM webhooks WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+
+    if count >= MAX_WEBHOOKS_PER_USER {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("You can register at most {} webhooks", MAX_WEBHOOKS_PER_USER)})),
+        );
+    }
+
+    let delayed = match protection::guards_account(&state.db, user_id).await {
+        Ok(d) => d,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    // Two v4 UUIDs back to back is 244 bits of randomness. Plenty for an HMAC key.
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let now = chrono::Utc::now().timestamp();
+    let active_at = if delayed { now + protection::delay_secs() } else { now };
+
+    let id: Result<Uuid, _> = sqlx::query_scalar(
+        "INSERT INTO webhooks (user_id, url, secret, created_at, active_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(&payload.url)
+    .bind(&secret)
+    .bind(now)
+    .bind(active_at)
+    .fetch_one(&state.db)
+    .await;
+
+    match id {
+        Ok(id) => {
+            if delayed {
+                notify::notify_owner(
+                    state.db.clone(),
+                    user.username.clone(),
+                    notify::EVENT_WEBHOOK_ADDED,
+                    json!({ "id": id, "url": payload.url, "active_at": active_at }),
+                );
+            }
+            (
+                StatusCode::CREATED,
+                Json(json!({
+                    "id": id,
+                    "url": payload.url,
+                    "secret": secret,
+                    "created_at": now,
+                    "active_at": active_at
+                })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": format!("Could not create webhook: {}", e)})),
+        ),
+    }
+}
+
+/// Lists the logged-in user's webhooks. Secrets are never included.
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    purge_deleted(&state.db, user_id).await;
+    let rows: Vec<(Uuid, String, i64, i64, Option<i64>)> = match sqlx::query_as(
+        "SELECT id, url, created_at, active_at, delete_at FROM webhooks WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let hooks: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|(id, url, created_at, active_at, delete_at)| {
+            json!({
+                "id": id,
+                "url": url,
+                "created_at": created_at,
+                "active_at": active_at,
+                "delete_at": delete_at
+            })
+        })
+        .collect();
+
+    (StatusCode::OK, Json(json!(hooks)))
+}
+
+/// Deletes one of the logged-in user's webhooks.
+///
+/// With a protected package on the account, a hook that's already getting events is only
+/// scheduled for deletion: it keeps getting them for the protected-publish delay, and every
+/// hook hears about it. One that isn't live yet goes straight away, since it never got anything.
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = user.require_unrestricted() {
+        return e;
+    }
+    let Ok(user_id) = Uuid::parse_str(&user.user_id) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid user in token"})),
+        );
+    };
+
+    // Scoped to user_id so you can't delete someone else's hook by guessing IDs.
+    let hook: Result<Option<(String, i64, Option<i64>)>, _> =
+        sqlx::query_as("SELECT url, active_at, delete_at FROM webhooks WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&state.db)
+            .await;
+    let (url, active_at, delete_at) = match hook {
+        Ok(Some(hook)) => hook,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Webhook not found"})),
+            );
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let delayed = match protection::guards_account(&state.db, user_id).await {
+        Ok(d) => d && active_at <= now,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            );
+        }
+    };
+
+    if !delayed {
+        return match sqlx::query("DELETE FROM webhooks WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&state.db)
+            .await
+        {
+            Ok(_) => (
+                StatusCode::OK,
+                Json(json!({"message": "Webhook deleted"})),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            ),
+        };
+    }
+
+    // Asking again doesn't push the date back.
+    let delete_at = match delete_at {
+        Some(at) => at,
+        None => {
+            let at = now + protection::delay_secs();
+            if let Err(e) = sqlx::query("UPDATE webhooks SET delete_at = $3 WHERE id = $1 AND user_id = $2")
+                .bind(id)
+                .bind(user_id)
+                .bind(at)
+                .execute(&state.db)
+                .await
+            {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": e.to_string()})),
+                );
+            }
+            notify::notify_owner(
+                state.db.clone(),
+                user.username.clone(),
+                notify::EVENT_WEBHOOK_REMOVED,
+                json!({ "id": id, "url": url, "delete_at": at }),
+            );
+            at
+        }
+    };
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "You have a protected package, so the webhook keeps getting events until delete_at",
+            "delete_at": delete_at
+        })),
+    )
+}
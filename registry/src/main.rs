@@ -26,14 +26,24 @@ async fn main() -> anyhow::Result<()> {
     // 2. Initialize logging
     // Uses tracing for structured logs. Respects RUST_LOG env var.
     // Defaults to debug level for the registry and tower_http so you can see what's happening.
+    // With OTEL_EXPORTER_OTLP_ENDPOINT set, the same spans also go out over OTLP (see utils::telemetry).
+    let otel = registry::utils::telemetry::init_tracer()?;
+    let (otel_provider, otel_layer) = match otel {
+        Some((provider, tracer)) => (Some(provider), Some(tracing_opentelemetry::layer().with_tracer(tracer))),
+        None => (None, None),
+    };
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::try_from_default_env()
             .unwrap_or_else(|_| "registry=debug,tower_http=debug".into()))
         .with(tracing_subscriber::fmt::layer())
         .with(sentry_tracing::layer()) // Sentry integration
+        .with(otel_layer)
         .init();
 
     tracing::info!("Starting Mosaic Registry API...");
+    if otel_provider.is_some() {
+        tracing::info!("Exporting traces over OTLP");
+    }
 
     // 3. Connect to database
     // Runs migrations automatically and panics if DATABASE_URL isn't set.
@@ -41,6 +51,42 @@ async fn main() -> anyhow::Result<()> {
     let db = db::connect().await?;
     tracing::info!("Connected to Neon PostgreSQL successfully!");
 
+    // 3.5 Background jobs, every 10 minutes. The first tick fires immediately, which also
+    // cleans up anything left over from before a restart.
+    // - Sweep abandoned publishes: versions that were registered but never got a blob.
+    // - Roll the daily download series up into recent_downloads (for sort=trending).
+    let sweep_db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            match db::expire_pending_versions(&sweep_db).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("Expired {} pending versions", n),
+                Err(e) => tracing::error!("Pending version sweep failed: {}", e),
+            }
+            if let Err(e) = db::refresh_recent_downloads(&sweep_db).await {
+                tracing::error!("Recent downloads rollup failed: {}", e);
+            }
+        }
+    });
+
+    // 3.6 Load the name blocklist/whitelist into memory, then keep it fresh.
+    // Admin edits reload it straight away on the instance that took them; the minute-long
+    // refresh is for every other instance pointed at the same database.
+    registry::utils::validation::reload_name_rules(&db).await?;
+    let rules_db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        interval.tick().await; // Just loaded, skip the immediate tick.
+        loop {
+            interval.tick().await;
+            if let Err(e) = registry::utils::validation::reload_name_rules(&rules_db).await {
+                tracing::error!("Name rules refresh failed: {}", e);
+            }
+        }
+    });
+
     // 4. Initialize R2 storage
     // Reads R2_ACCESS_KEY_ID, R2_SECRET_ACCESS_KEY, R2_ENDPOINT from env.
     // If any of these are missing, it panics. Intentional—storage is non-negotiable.
@@ -60,7 +106,43 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Listening on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // Flush the last batch of spans on the way out.
+    if let Some(provider) = otel_provider {
+        provider.shutdown()?;
+    }
 
     Ok(())
 }
+
+/// Resolves on Ctrl+C, or SIGTERM (what Docker sends on `docker stop`), so in-flight requests
+/// finish and the tracer gets to flush instead of the process just vanishing.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            // Couldn't install the handler. That's not a shutdown request.
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down...");
+}
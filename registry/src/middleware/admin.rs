@@ -0,0 +1,41 @@
+use crate::state::AppState;
+use axum::{
+    extract::FromRequestParts,
+    http::{StatusCode, request::Parts},
+};
+use sha2::{Digest, Sha256};
+use std::env;
+
+/// Marker for requests made with the registry's admin token.
+///
+/// This is deliberately *not* a user account with a flag on it. The admin token is a separate
+/// secret (ADMIN_TOKEN in the env) so a stolen user session can never escalate into moderation powers.
+/// If ADMIN_TOKEN isn't set, every admin route is a 404—self-hosters who don't want it don't get it.
+pub struct AdminUser;
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &AppState) -> Result<Self, Self::Rejection> {
+        let expected = match env::var("ADMIN_TOKEN") {
+            Ok(t) if !t.is_empty() => t,
+            _ => return Err((StatusCode::NOT_FOUND, "Not found")),
+        };
+
+        let token = parts
+            .headers
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or((StatusCode::UNAUTHORIZED, "Missing admin token"))?;
+
+        // Compare hashes rather than the raw strings so the comparison time
+        // doesn't leak how many leading characters were right.
+        if Sha256::digest(token.as_bytes()) != Sha256::digest(expected.as_bytes()) {
+            tracing::warn!("Rejected request with an invalid admin token");
+            return Err((StatusCode::FORBIDDEN, "Invalid admin token"));
+        }
+
+        Ok(AdminUser)
+    }
+}
@@ -1,7 +1,7 @@
 use crate::models::user::Claims;
 use crate::state::AppState;
 use axum::{
-    extract::FromRequestParts,
+    extract::{FromRequestParts, OptionalFromRequestParts},
     http::{StatusCode, request::Parts},
 };
 use jsonwebtoken::{DecodingKey, Validation, decode};
@@ -14,7 +14,8 @@ use uuid::Uuid;
 /// 1. Extract the Authorization header
 /// 2. Verify the JWT signature
 /// 3. Check if the token has been revoked (server-side logout)
-/// 4. Return AuthenticatedUser if valid, or 401 if not
+/// 4. Check the account hasn't been banned by an admin (or renamed since the token was issued)
+/// 5. Return AuthenticatedUser if valid, or 401/403 if not
 ///
 /// Makes authorization super convenient—just add `user: AuthenticatedUser` to your handler.
 pub struct AuthenticatedUser {
@@ -22,6 +23,8 @@ pub struct AuthenticatedUser {
     pub username: String,
     pub jti: Uuid,
     pub exp: i64,
+    /// Set for API tokens restricted to some packages (see handlers::token). None for a login.
+    pub packages: Option<Vec<String>>,
 }
 
 impl FromRequestParts<AppState> for AuthenticatedUser {
@@ -74,11 +77,85 @@ impl FromRequestParts<AppState> for AuthenticatedUser {
             return Err((StatusCode::UNAUTHORIZED, "Token has been revoked"));
         }
 
+        // 4. Check for bans. Tokens issued before the ban would otherwise keep working for a week.
+        // No row at all means the name in the token is gone—usually a rename (see
+        // handlers::auth::rename_username)—so the token goes with it. A DB hiccup lets it through.
+        let banned = sqlx::query_scalar::<_, bool>("SELECT banned FROM users WHERE username = $1")
+            .bind(&token_data.claims.username)
+            .fetch_optional(&state.db)
+            .await;
+
+        match banned {
+            Ok(None) => {
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "Account not found or renamed. Log in again.",
+                ));
+            }
+            Ok(Some(true)) => {
+                return Err((StatusCode::FORBIDDEN, "This account has been suspended"));
+            }
+            _ => {}
+        }
+
         Ok(AuthenticatedUser {
             user_id: token_data.claims.sub,
             username: token_data.claims.username,
             jti: token_data.claims.jti,
             exp: token_data.claims.exp,
+            packages: token_data.claims.packages,
         })
     }
-}
\ No newline at end of file
+}
+
+/// `Option<AuthenticatedUser>`, for endpoints anyone can call that show a logged-in user more
+/// (private packages, see handlers::visibility). A missing, expired or revoked token is just
+/// nobody: a stale login shouldn't stop anyone browsing public packages.
+impl OptionalFromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Option<Self>, Self::Rejection> {
+        if !parts.headers.contains_key("Authorization") {
+            return Ok(None);
+        }
+        Ok(<Self as FromRequestParts<AppState>>::from_request_parts(parts, state).await.ok())
+    }
+}
+
+impl AuthenticatedUser {
+    /// Whether this user owns `package`. Goes by user id, so renames don't matter.
+    /// A package with no owner (unmatched legacy row) belongs to nobody.
+    ///
+    /// A token restricted to some packages only owns the ones it lists, so a CI token for one
+    /// package can't publish (or unpublish, or deprecate) the rest of the account's.
+    pub fn owns(&self, package: &crate::models::package::Package) -> bool {
+        let owner = match (Uuid::parse_str(&self.user_id), package.owner_id) {
+            (Ok(id), Some(owner)) => id == owner,
+            _ => false,
+        };
+        owner && self.may_use(&package.name)
+    }
+
+    /// Whether this token is allowed to act on the package called `name` at all.
+    /// Logins and unrestricted API tokens always are.
+    pub fn may_use(&self, name: &str) -> bool {
+        match &self.packages {
+            Some(packages) => packages.iter().any(|p| p == name),
+            None => true,
+        }
+    }
+
+    /// For account-wide actions (tokens, webhooks, renames, claims): a token restricted to some
+    /// packages gets a 403. Otherwise it could mint itself an unrestricted one.
+    pub fn require_unrestricted(&self) -> Result<(), (StatusCode, axum::Json<serde_json::Value>)> {
+        match &self.packages {
+            Some(_) => Err((
+                StatusCode::FORBIDDEN,
+                axum::Json(serde_json::json!({
+                    "error": "This token is restricted to some packages and can't manage the account. Log in instead."
+                })),
+            )),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Request, State},
+    http::{StatusCode, header},
+    middleware::{Next, from_fn_with_state},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use std::env;
+
+/// Request body limits, per kind of route.
+///
+/// Everything except the zip upload (and the admin import, which streams) is a small JSON body:
+/// a version's dependency map, a claim reason, a webhook URL. None of that gets anywhere near
+/// 256KB, so a 100MB dependencies map is somebody poking at us, not a real publish.
+///
+/// Tunable from the environment, defaults in parentheses:
+/// MAX_JSON_BODY_BYTES (262144), MAX_UPLOAD_BYTES (5242880).
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimits {
+    pub json: usize,
+    pub upload: usize,
+}
+
+impl BodyLimits {
+    pub fn from_env() -> Self {
+        Self {
+            json: env_bytes("MAX_JSON_BODY_BYTES", 256 * 1024),
+            upload: env_bytes("MAX_UPLOAD_BYTES", 5 * 1024 * 1024),
+        }
+    }
+}
+
+fn env_bytes(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default)
+}
+
+/// Caps every route currently in `router` at `max` bytes. Routes added afterwards aren't
+/// covered (that's how `Router::layer` works), which is how the upload route gets its own limit.
+pub fn limit<S>(router: Router<S>, max: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(from_fn_with_state(max, enforce))
+        .layer(DefaultBodyLimit::max(max))
+}
+
+/// Turns an oversized body into a JSON 413 the CLI can show, instead of axum's plain-text one.
+///
+/// 1. If Content-Length already says it's too big, don't read a byte of it
+/// 2. Otherwise the extractor stops reading at the limit (DefaultBodyLimit), and we dress up its 413
+pub async fn enforce(State(max): State<usize>, req: Request, next: Next) -> Response {
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > max as u64) {
+        return too_large(max);
+    }
+
+    let res = next.run(req).await;
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if res.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return too_large(max);
+    }
+    res
+}
+
+/// The 413 body. `code` is stable so clients can match on it instead of the message.
+fn too_large(max: usize) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(json!({
+            "error": format!("Request body is too large. The limit for this endpoint is {}.", human_bytes(max)),
+            "code": "body_too_large",
+            "limit_bytes": max
+        })),
+    )
+        .into_response()
+}
+
+fn human_bytes(n: usize) -> String {
+    if n >= 1024 * 1024 && n.is_multiple_of(1024 * 1024) {
+        format!("{}MB", n / (1024 * 1024))
+    } else if n >= 1024 && n.is_multiple_of(1024) {
+        format!("{}KB", n / 1024)
+    } else {
+        format!("{} bytes", n)
+    }
+}
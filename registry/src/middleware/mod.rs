@@ -1,2 +1,4 @@
+pub mod admin;
 pub mod auth;
-pub mod rate_limit;
\ No newline at end of file
+pub mod body_limit;
+pub mod rate_limit;
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Body for admin actions that take a reason (takedowns, bans).
+/// The reason goes into the audit log and, for takedowns, is shown to users.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AdminActionRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub action: String,
+    pub target: String,
+    pub reason: Option<String>,
+    pub created_at: i64,
+}
+
+/// One row of `name_rules`, with the approved user's name filled in for display.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct NameRule {
+    pub kind: String,
+    pub pattern: String,
+    pub username: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: i64,
+}
+
+/// Body for `POST /admin/names`. `username` is required for (and only used by) `approved`.
+#[derive(Debug, Deserialize)]
+pub struct NameRuleRequest {
+    pub kind: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
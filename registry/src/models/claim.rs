@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Body for `POST /claims`.
+#[derive(Debug, Deserialize)]
+pub struct ClaimRequest {
+    pub name: String,
+    /// Who you are and why the name should be yours. Admins read this, so make it count.
+    pub reason: String,
+}
+
+/// A claim as the claimant and admins see it. `owner` is the package's current owner,
+/// if the name is taken.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Claim {
+    pub id: i64,
+    pub name: String,
+    pub claimant: String,
+    pub owner: Option<String>,
+    pub reason: String,
+    pub status: String,
+    pub resolution: Option<String>,
+    pub created_at: i64,
+    pub resolved_at: Option<i64>,
+}
+
+/// Body for approving or rejecting a claim. `force` skips the abandonment policy on a
+/// transfer—for trademark and legal cases, and it says so in the audit log.
+#[derive(Debug, Deserialize, Default)]
+pub struct ResolveClaimRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub force: bool,
+}
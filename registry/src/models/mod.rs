@@ -1,2 +1,5 @@
+pub mod admin;
+pub mod claim;
 pub mod package;
+pub mod timestamp;
 pub mod user;
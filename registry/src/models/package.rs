@@ -3,20 +3,69 @@ use sqlx::FromRow;
 use std::collections::HashMap;
 use uuid::Uuid;
 
+/// Columns for a `Package` with `author` filled in from the owner's *current* username.
+/// Use with [`PACKAGE_FROM`]: `format!("SELECT {} FROM {} WHERE ...", PACKAGE_COLUMNS, PACKAGE_FROM)`.
+/// `packages.author` is only a fallback now, for rows whose owner couldn't be matched to a user.
+pub const PACKAGE_COLUMNS: &str = "p.id, p.name, p.description, COALESCE(u.username, p.author) AS author, p.owner_id, p.repository, p.created_at, p.updated_at, p.download_count, p.recent_downloads, p.deprecated, p.deprecation_reason, p.featured, COALESCE(u.verified, FALSE) AS verified, (p.protected AND (p.unprotect_at IS NULL OR p.unprotect_at > EXTRACT(EPOCH FROM now())::BIGINT)) AS protected, p.unprotect_at, p.visibility, (SELECT COUNT(*) FROM stars s WHERE s.package_id = p.id) AS stars";
+
+/// The join behind [`PACKAGE_COLUMNS`]. Packages are aliased `p`, so qualify anything ambiguous.
+pub const PACKAGE_FROM: &str = "packages p LEFT JOIN users u ON u.id = p.owner_id";
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Package {
     pub id: Option<Uuid>,
     pub name: String,
     pub description: String,
+    /// The owner's username, for display. Ownership checks go through `owner_id`.
     pub author: String,
+    /// The user who owns this package. NULL only for legacy rows the backfill couldn't match.
+    #[sqlx(default)]
+    #[serde(skip_serializing, default)]
+    pub owner_id: Option<Uuid>,
     pub repository: Option<String>,
     pub created_at: i64,
     pub updated_at: i64,
     #[serde(default)] 
     pub download_count: i64,
+    /// Downloads over the last week, refreshed every few minutes (see db::refresh_recent_downloads).
+    #[serde(default)]
+    #[sqlx(default)]
+    pub recent_downloads: i64,
     #[serde(default)]
     pub deprecated: bool,
     pub deprecation_reason: Option<String>,
+    /// Picked by an admin (POST /admin/packages/{name}/feature).
+    #[serde(default)]
+    #[sqlx(default)]
+    pub featured: bool,
+    /// Whether the owner's account is verified. Comes from the users join, so it's only
+    /// filled in when selecting through [`PACKAGE_COLUMNS`].
+    #[serde(default)]
+    #[sqlx(default)]
+    pub verified: bool,
+    /// New versions wait for the owner's confirmation (see handlers::protection). Through
+    /// [`PACKAGE_COLUMNS`] this already accounts for a pending `unprotect_at`.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub protected: bool,
+    /// When turning protection off takes effect, if the owner asked for that.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub unprotect_at: Option<i64>,
+    /// 'public' or 'private'. Private packages only show up for the owner and its members
+    /// (see handlers::visibility).
+    #[serde(default = "public")]
+    #[sqlx(default)]
+    pub visibility: String,
+    /// How many users starred the package. Counted in the same query, so it's only filled in
+    /// when selecting through [`PACKAGE_COLUMNS`].
+    #[serde(default)]
+    #[sqlx(default)]
+    pub stars: i64,
+}
+
+fn public() -> String {
+    "public".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -26,9 +75,29 @@ pub struct PackageVersion {
     pub version: String,
     pub lua_source_url: String,
     pub readme: Option<String>,
+    pub changelog: Option<String>,
     pub license: Option<String>,
     pub created_at: i64,
     pub dependencies: serde_json::Value,
+    /// "pending" until the blob is uploaded, then "published". Protected packages go through
+    /// "held" in between, until the owner confirms.
+    #[sqlx(default)]
+    pub status: String,
+    /// Where in the zip the readme/changelog/license were found, if anywhere.
+    #[sqlx(default)]
+    pub readme_path: Option<String>,
+    #[sqlx(default)]
+    pub changelog_path: Option<String>,
+    #[sqlx(default)]
+    pub license_path: Option<String>,
+    /// Size of the zip in bytes. NULL for versions uploaded before we started recording it.
+    #[sqlx(default)]
+    #[serde(rename = "size")]
+    pub blob_size: Option<i64>,
+    /// Only sent when asked for (`?include=downloads`), so listings stay small.
+    #[sqlx(default)]
+    #[serde(skip_serializing)]
+    pub download_count: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +106,42 @@ pub struct PublishVersionRequest {
     pub lua_source_url: String,
     #[serde(default = "empty_deps")]
     pub dependencies: HashMap<String, String>,
+    /// Dist-tag to point at this version. Defaults to "latest" when omitted.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// SPDX license expression from mosaic.toml, if the author declared one.
+    #[serde(default)]
+    pub license: Option<String>,
+}
+
+/// Body for `POST /packages/{name}/versions/{version}/upload-session`.
+/// Lets a resumed publish refresh what it registered the first time round.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadSessionRequest {
+    #[serde(default = "empty_deps")]
+    pub dependencies: HashMap<String, String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub license: Option<String>,
+}
+
+/// Body for `PUT /packages/{name}/protection`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtectionRequest {
+    pub protected: bool,
+}
+
+/// Body for `PUT /packages/{name}/visibility`: "public" or "private".
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VisibilityRequest {
+    pub visibility: String,
+}
+
+/// Body for `POST /packages/{name}/versions/{version}/approve`: the token from the confirmation link.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApproveVersionRequest {
+    pub token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,6 +150,20 @@ pub struct DeprecatePackageRequest {
     pub reason: Option<String>,
 }
 
+/// Body for starring a package. Optional—an empty PUT just stars without notifications.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct StarRequest {
+    /// Watch the package: get notified when a new version is published.
+    #[serde(default)]
+    pub notify: bool,
+}
+
+/// Body for `POST /packages/lock-status`: a lockfile's pins, `name -> version`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LockStatusRequest {
+    pub packages: HashMap<String, String>,
+}
+
 fn empty_deps() -> HashMap<String, String> {
     HashMap::new()
 }
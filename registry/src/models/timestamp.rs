@@ -0,0 +1,37 @@
+use serde_json::Value;
+
+/// Epoch seconds as RFC3339 in UTC, e.g. `2025-01-31T12:00:00Z`. `None` if it's out of range.
+pub fn rfc3339(secs: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(secs, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+/// Adds a `<field>_rfc3339` string next to every `*_at` epoch in a response, however deep.
+///
+/// We store and return epochs everywhere (they sort, they're cheap, every language reads them),
+/// but nobody can read `1738324800` at a glance, and half the clients out there were converting
+/// it themselves—some in local time, some not. The epoch stays as-is, so nothing breaks.
+pub fn with_rfc3339(mut value: Value) -> Value {
+    annotate(&mut value);
+    value
+}
+
+fn annotate(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let stamps: Vec<(String, String)> = map
+                .iter()
+                .filter(|(key, _)| key.ends_with("_at"))
+                .filter_map(|(key, v)| Some((format!("{}_rfc3339", key), rfc3339(v.as_i64()?)?)))
+                .collect();
+            for v in map.values_mut() {
+                annotate(v);
+            }
+            for (key, stamp) in stamps {
+                map.insert(key, Value::String(stamp));
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(annotate),
+        _ => {}
+    }
+}
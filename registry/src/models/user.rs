@@ -8,6 +8,12 @@ pub struct User {
     pub username: String,
     pub password_hash: String,
     pub created_at: i64,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub banned: bool,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub verified: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,6 +28,14 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+/// Body for `PUT /auth/username`. The password is asked for again because a stolen token
+/// shouldn't be enough to walk off with someone's name.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameRequest {
+    pub username: String,
+    pub password: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: String,
@@ -34,4 +48,36 @@ pub struct Claims {
     pub username: String,
     pub exp: i64,
     pub jti: uuid::Uuid,
+    /// API tokens only: the packages it may publish and manage. Logins leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packages: Option<Vec<String>>,
+}
+
+/// Body for `POST /auth/me/tokens`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTokenRequest {
+    /// What it's for, e.g. "my-ui-lib CI". Shown in the token list.
+    pub name: String,
+    /// Restricts the token to these packages. Leave it out for a token that can do everything
+    /// your account can.
+    #[serde(default)]
+    pub packages: Option<Vec<String>>,
+    /// 90 by default, at most 365.
+    #[serde(default)]
+    pub expires_in_days: Option<i64>,
+}
+
+/// An API token as `GET /auth/me/tokens` lists it. The token itself is only shown once.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    pub packages: Option<Vec<String>>,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
 }
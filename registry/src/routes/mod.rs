@@ -1,40 +1,111 @@
 use crate::handlers::{
-    auth::{login, logout, signup},
+    admin::{
+        add_name_rule, audit_log, ban_user, client_stats, feature_package, integrity_check,
+        list_name_rules, release_name, remove_name_rule, restore_package, stats,
+        takedown_package, unban_user, unfeature_package, unverify_user, verify_user,
+    },
+    auth::{get_user, login, logout, rename_username, signup},
+    claim::{approve_claim, create_claim, get_policy, list_claims, list_my_claims, reject_claim},
+    export::{export_dump, import_dump},
     health::health_check,
     package::{
-        create_package, create_version, deprecate_package, download_blob, get_package,
-        list_packages, list_versions, search_packages, unpublish_version, upload_blob,
+        create_package, create_upload_session, create_version, deprecate_package, diff_versions, download_blob,
+        download_version,
+        get_changelog, get_dependency_closure, get_integrity, get_lint_report, get_package, get_version, get_version_file, list_packages, list_starred,
+        list_version_files, list_versions, lock_status, search_packages, star_package, unpublish_version,
+        unstar_package, upload_blob,
     },
+    protection::{approve_version, reject_version, set_protection},
+    token::{create_token, list_tokens, revoke_token},
+    visibility::{self, add_member, list_members, remove_member, set_visibility},
+    webhook::{create_webhook, delete_webhook, list_webhooks},
 };
+use crate::middleware::body_limit::{self, BodyLimits};
 use crate::middleware::rate_limit;
 use crate::state::AppState;
 use axum::{
     Router,
     handler::Handler,
-    extract::DefaultBodyLimit,
-    routing::{get, post, delete},
+    extract::{DefaultBodyLimit, MatchedPath, Request},
+    http::{HeaderMap, header},
+    middleware::from_fn_with_state,
+    response::Response,
+    routing::{get, post, put, delete},
 };
 use tower_governor::GovernorLayer;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::trace::TraceLayer;
+
+/// One span per request, named after the route *pattern* (`GET /packages/{name}`), not the
+/// actual path, so traces group by endpoint instead of by package. Everything the handler does—
+/// the db/storage spans—hangs off it.
+///
+/// Body sizes come from Content-Length, so a chunked body or a streamed response shows up
+/// without one. Good enough to spot who's sending (or getting) the big stuff.
+fn request_span(req: &Request) -> tracing::Span {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+    tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        http.route = route,
+        http.request.body.size = content_length(req.headers()),
+        http.response.status_code = tracing::field::Empty,
+        http.response.body.size = tracing::field::Empty,
+    )
+}
+
+fn record_response(res: &Response, latency: std::time::Duration, span: &tracing::Span) {
+    span.record("http.response.status_code", res.status().as_u16());
+    if let Some(size) = content_length(res.headers()) {
+        span.record("http.response.body.size", size);
+    }
+    tracing::debug!(status = res.status().as_u16(), latency = ?latency, "finished processing request");
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
 
 pub fn create_routes(state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        // So the website can page through versions (see list_versions).
+        .expose_headers([axum::http::HeaderName::from_static("x-total-count")]);
 
     // Rate limit configurations
     let publish_conf = rate_limit::create_publish_config();
     let login_conf = rate_limit::create_login_config();
     let search_conf = rate_limit::create_search_config();
 
+    // Body limits. Every group gets the JSON limit; the zip upload and the admin import are
+    // added after it's applied so they can have their own (see body_limit::limit).
+    let limits = BodyLimits::from_env();
+
     let auth_routes = Router::new()
         .route("/signup", post(signup))
         .route(
             "/login", 
-            post(login.layer(GovernorLayer::new(login_conf)))
+            post(login.layer(GovernorLayer::new(login_conf.clone())))
         )
-        .route("/logout", post(logout));
+        .route("/logout", post(logout))
+        .route("/username", put(rename_username.layer(GovernorLayer::new(login_conf))))
+        .route("/users/{username}", get(get_user))
+        .route("/me/starred", get(list_starred))
+        .route("/me/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/me/webhooks/{id}", delete(delete_webhook))
+        .route("/me/tokens", get(list_tokens).post(create_token))
+        .route("/me/tokens/{id}", delete(revoke_token));
+    let auth_routes = body_limit::limit(auth_routes, limits.json);
 
     let package_routes = Router::new()
         .route("/", get(list_packages))
@@ -46,30 +117,95 @@ pub fn create_routes(state: AppState) -> Router {
             "/search", 
             get(search_packages.layer(GovernorLayer::new(search_conf)))
         )
+        .route("/lock-status", post(lock_status))
         .route("/blobs/{hash}", get(download_blob))
         .route("/{name}", get(get_package))
         .route("/{name}/deprecate", post(deprecate_package)) // New route
+        .route("/{name}/protection", put(set_protection))
+        .route("/{name}/visibility", put(set_visibility))
+        .route("/{name}/members", get(list_members))
+        .route("/{name}/members/{username}", put(add_member).delete(remove_member))
+        .route("/{name}/diff", get(diff_versions))
+        .route("/{name}/star", put(star_package).delete(unstar_package))
         .route("/{name}/versions", get(list_versions))
         .route(
             "/{name}/versions", 
             post(create_version.layer(GovernorLayer::new(publish_conf.clone())))
         )
-        .route("/{name}/versions/{version}", delete(unpublish_version))
+        .route("/{name}/{version}/download", get(download_version))
+        .route("/{name}/{version}/integrity", get(get_integrity))
+        .route("/{name}/versions/{version}", get(get_version).delete(unpublish_version))
+        .route("/{name}/versions/{version}/changelog", get(get_changelog))
+        .route("/{name}/versions/{version}/dependencies", get(get_dependency_closure))
+        .route("/{name}/versions/{version}/files", get(list_version_files))
+        .route("/{name}/versions/{version}/lint", get(get_lint_report))
+        .route("/{name}/versions/{version}/files/{*path}", get(get_version_file))
+        .route("/{name}/versions/{version}/approve", post(approve_version))
+        .route("/{name}/versions/{version}/reject", post(reject_version))
+        .route(
+            "/{name}/versions/{version}/upload-session",
+            post(create_upload_session.layer(GovernorLayer::new(publish_conf.clone())))
+        );
+    let package_routes = body_limit::limit(package_routes, limits.json)
         .route(
             "/{name}/versions/{version}/upload", 
-            // 5MB limit. Lua scripts are tiny text files. 
+            // 5MB by default (MAX_UPLOAD_BYTES). Lua scripts are tiny text files.
             // If you're uploading 5MB of text, you're doing something wrong.
             // This stops someone from nuking our R2 bandwidth.
             post(upload_blob
-                .layer(DefaultBodyLimit::max(5 * 1024 * 1024))
+                .layer(DefaultBodyLimit::max(limits.upload))
+                .layer(from_fn_with_state(limits.upload, body_limit::enforce))
                 .layer(GovernorLayer::new(publish_conf.clone()))
             )
-        );
+        )
+        // Private packages are a 404 for anyone off their team (see handlers::visibility).
+        // A route layer, so it runs after routing and can see `{name}` and `{hash}`.
+        .route_layer(from_fn_with_state(state.clone(), visibility::guard));
+
+    // Name claims. Filing one goes through the publish limiter—it pings the package owner,
+    // and nobody needs to file dozens an hour.
+    let claim_routes = Router::new()
+        .route(
+            "/",
+            post(create_claim.layer(GovernorLayer::new(publish_conf.clone())))
+        )
+        .route("/mine", get(list_my_claims))
+        .route("/policy", get(get_policy));
+    let claim_routes = body_limit::limit(claim_routes, limits.json);
+
+    // Everything here requires the ADMIN_TOKEN (see middleware::admin). Not rate limited—
+    // if someone has that token you have bigger problems than request volume.
+    let admin_routes = Router::new()
+        .route("/stats", get(stats))
+        .route("/audit", get(audit_log))
+        .route("/claims", get(list_claims))
+        .route("/clients", get(client_stats))
+        .route("/claims/{id}/approve", post(approve_claim))
+        .route("/claims/{id}/reject", post(reject_claim))
+        .route("/jobs/integrity", post(integrity_check))
+        .route("/export", get(export_dump))
+        .route("/names", get(list_name_rules).post(add_name_rule))
+        .route("/names/{kind}/{pattern}", delete(remove_name_rule))
+        .route("/packages/{name}", delete(release_name))
+        .route("/packages/{name}/feature", post(feature_package).delete(unfeature_package))
+        .route("/packages/{name}/takedown", post(takedown_package).delete(restore_package))
+        .route("/users/{username}/ban", post(ban_user).delete(unban_user))
+        .route("/users/{username}/verify", post(verify_user).delete(unverify_user));
+    let admin_routes = body_limit::limit(admin_routes, limits.json)
+        // Dumps with blobs are big. The import streams line by line, so no body limit here.
+        .route("/import", post(import_dump.layer(DefaultBodyLimit::disable())));
 
     Router::new()
         .route("/health", get(health_check))
+        .nest("/admin", admin_routes)
         .nest("/auth", auth_routes)
+        .nest("/claims", claim_routes)
         .nest("/packages", package_routes)
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(record_response),
+        )
         .layer(cors)
         .with_state(state)
 }
@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+
+/// The most we'll inflate from a single entry of an uploaded zip. Uploads are capped at 5MB
+/// compressed, but deflate can turn that into gigabytes, so decompressed sizes get their own cap.
+pub const MAX_ENTRY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The most we'll inflate from a whole uploaded zip.
+pub const MAX_UNPACKED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// An archive entry that inflates past the caller's limit.
+#[derive(Debug)]
+pub struct TooLarge;
+
+impl std::fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("archive entry is too large")
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// One file inside a package zip, as shown by the file listing endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Walks a package zip and returns every file with its size and SHA256.
+///
+/// Directories are skipped—the tree is implied by the paths.
+/// Sorted by path so the output is stable no matter how the zip was built.
+/// Entries are hashed as they stream out, held to the same caps as [`read_all`]: an archive
+/// that declares or actually inflates to more than that is a [`TooLarge`] error.
+pub fn list_files(bytes: &[u8]) -> anyhow::Result<Vec<FileEntry>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    if archive
+        .decompressed_size()
+        .is_some_and(|size| size > u128::from(MAX_UNPACKED_SIZE))
+    {
+        return Err(TooLarge.into());
+    }
+
+    let mut entries = Vec::with_capacity(archive.len());
+    let mut total = 0;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        let limit = MAX_ENTRY_SIZE.min(MAX_UNPACKED_SIZE - total);
+        if file.size() > limit {
+            return Err(TooLarge.into());
+        }
+
+        let path = file.name().to_string();
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut file.by_ref().take(limit + 1), &mut hasher)?;
+        if size > limit {
+            return Err(TooLarge.into());
+        }
+        total += size;
+
+        entries.push(FileEntry {
+            path,
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        });
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Where a package's docs live inside its zip. Each is the path of the file we picked, if any.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DocFiles {
+    pub readme: Option<String>,
+    pub changelog: Option<String>,
+    pub license: Option<String>,
+}
+
+/// Finds the README, CHANGELOG and LICENSE in a package zip.
+///
+/// People put these in all sorts of places, so we look at the root, then `docs/`, `doc/` and
+/// `.github/`, and accept the usual name variants (`README`, `readme.markdown`, `LICENCE`,
+/// `LICENSE-MIT`, `COPYING`, `HISTORY.md`...). Root beats subfolders; the canonical name beats
+/// variants; ties go to the alphabetically first path so the pick is stable.
+pub fn find_docs(bytes: &[u8]) -> anyhow::Result<DocFiles> {
+    let archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut best: [Option<(u8, String)>; 3] = [None, None, None];
+
+    for path in archive.file_names() {
+        let Some((kind, rank)) = doc_rank(path) else {
+            continue;
+        };
+        let slot = &mut best[kind];
+        let better = match slot {
+            None => true,
+            Some((r, p)) => (rank, path) < (*r, p.as_str()),
+        };
+        if better {
+            *slot = Some((rank, path.to_string()));
+        }
+    }
+
+    let [readme, changelog, license] = best.map(|b| b.map(|(_, path)| path));
+    Ok(DocFiles {
+        readme,
+        changelog,
+        license,
+    })
+}
+
+/// Classifies a path as README (0), CHANGELOG (1) or LICENSE (2), with a rank where lower is better.
+fn doc_rank(path: &str) -> Option<(usize, u8)> {
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    let dir_rank = match dir.to_ascii_lowercase().as_str() {
+        "" => 0,
+        "docs" | "doc" | ".github" => 10,
+        _ => return None,
+    };
+
+    // Unknown extensions stay part of the stem: "readme.rst" won't match, "license-apache-2.0" will.
+    let file = file.to_ascii_lowercase();
+    let (stem, ext_rank) = match file.rsplit_once('.') {
+        Some((stem, "md")) => (stem, 0),
+        Some((stem, "markdown")) => (stem, 1),
+        Some((stem, "txt")) => (stem, 2),
+        _ => (file.as_str(), 2),
+    };
+
+    let (kind, name_rank) = match stem {
+        "readme" => (0, 0),
+        "changelog" => (1, 0),
+        "changes" | "history" => (1, 3),
+        "license" => (2, 0),
+        "licence" => (2, 3),
+        "copying" => (2, 4),
+        // LICENSE-MIT, LICENSE_APACHE, license-apache-2.0...
+        s if s.starts_with("license-") || s.starts_with("license_") => (2, 5),
+        s if s.starts_with("licence-") || s.starts_with("licence_") => (2, 6),
+        _ => return None,
+    };
+
+    // Licenses are often extensionless plain text, so don't penalize that for them.
+    let ext_rank = if kind == 2 && ext_rank == 2 { 0 } else { ext_rank };
+    Some((kind, dir_rank + name_rank + ext_rank))
+}
+
+/// Reads every file in a package zip into memory, keyed by path.
+/// The 5MB upload cap only bounds the compressed bytes, so each entry is held to MAX_ENTRY_SIZE
+/// and the whole archive to MAX_UNPACKED_SIZE; past either we bail with [`TooLarge`].
+pub fn read_all(bytes: &[u8]) -> anyhow::Result<BTreeMap<String, Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut files = BTreeMap::new();
+    let mut total = 0;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        let limit = MAX_ENTRY_SIZE.min(MAX_UNPACKED_SIZE - total);
+        let contents = read_capped(&mut file, limit)?;
+        total += contents.len() as u64;
+        files.insert(file.name().to_string(), contents);
+    }
+
+    Ok(files)
+}
+
+/// Reads a single file out of a package zip by its exact path.
+/// Returns Ok(None) if the path isn't in the archive, and a [`TooLarge`] error if it's over `limit`.
+pub fn read_file(bytes: &[u8], path: &str, limit: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut file = match archive.by_name(path) {
+        Ok(f) => f,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    if file.is_dir() {
+        return Ok(None);
+    }
+
+    read_capped(&mut file, limit).map(Some)
+}
+
+/// Inflates one entry, giving up as soon as it passes `limit`.
+/// The declared size is checked first, but it's whatever the uploader wrote, so the read is capped too.
+fn read_capped(file: &mut zip::read::ZipFile<'_, impl Read>, limit: u64) -> anyhow::Result<Vec<u8>> {
+    if file.size() > limit {
+        return Err(TooLarge.into());
+    }
+
+    let mut contents = Vec::with_capacity(file.size() as usize);
+    file.by_ref().take(limit + 1).read_to_end(&mut contents)?;
+    if contents.len() as u64 > limit {
+        return Err(TooLarge.into());
+    }
+    Ok(contents)
+}
+
+/// Picks a content type for viewing a package file in the browser.
+/// Everything is served as text; the caller rejects non-UTF-8 files before we get here.
+pub fn content_type_for(path: &str) -> &'static str {
+    let ext = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "lua" | "luau" => "text/x-lua; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "json" => "application/json; charset=utf-8",
+        "toml" => "application/toml; charset=utf-8",
+        "xml" | "poly" => "application/xml; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    }
+}
@@ -0,0 +1,53 @@
+use axum::http::HeaderMap;
+use sqlx::PgPool;
+
+/// The opt-in header CLIs send with downloads: `mosaic/0.4.2 (windows)`. See `mosaic telemetry`.
+pub const CLIENT_HEADER: &str = "x-mosaic-client";
+
+/// OSes we count by name. Anything else lands in "other"—the header is client-controlled, and
+/// a free-text column is an invitation to fill the table with garbage.
+const KNOWN_OS: [&str; 3] = ["windows", "macos", "linux"];
+
+/// A parsed client header: a real semver version and a bucketed OS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub version: String,
+    pub os: String,
+}
+
+impl ClientInfo {
+    /// `None` for requests without the header (telemetry off, or some other tool) or with
+    /// one we can't make sense of. Those just don't get counted here.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(CLIENT_HEADER)?.to_str().ok()?;
+        let (product, rest) = value.trim().split_once(' ')?;
+        let version = product.strip_prefix("mosaic/")?;
+        // Real versions are short. A 2KB prerelease tag is somebody being funny.
+        if version.len() > 32 {
+            return None;
+        }
+        let version = semver::Version::parse(version).ok()?.to_string();
+        let os = rest.trim().strip_prefix('(')?.strip_suffix(')')?.to_lowercase();
+        let os = if KNOWN_OS.contains(&os.as_str()) { os } else { "other".to_string() };
+        Some(Self { version, os })
+    }
+}
+
+/// Bumps today's count for this client. Daily totals only: no user, no IP, no package.
+pub async fn record(db: &PgPool, client: &ClientInfo) {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO client_downloads (day, cli_version, os, count)
+        VALUES ($1, $2, $3, 1)
+        ON CONFLICT (day, cli_version, os) DO UPDATE SET count = client_downloads.count + 1
+    "#,
+    )
+    .bind(chrono::Utc::now().timestamp() / 86400)
+    .bind(&client.version)
+    .bind(&client.os)
+    .execute(db)
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Couldn't record client download: {}", e);
+    }
+}
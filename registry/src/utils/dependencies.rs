@@ -0,0 +1,355 @@
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Whether a dependency's range has to match at least one published version.
+/// On unless REQUIRE_SATISFIABLE_DEPENDENCIES is "false"/"0"—turn it off if you're importing
+/// a registry in an order where dependents can land before what they depend on.
+pub fn require_satisfiable() -> bool {
+    !matches!(
+        std::env::var("REQUIRE_SATISFIABLE_DEPENDENCIES").ok().as_deref(),
+        Some("false") | Some("0")
+    )
+}
+
+/// Parses a dependency requirement the way the CLI resolves it: a bare version is an exact
+/// pin (`1.2.0` means `=1.2.0`, not `^1.2.0`), anything else has to be a semver range.
+/// Dist-tags aren't allowed—`beta` today is a different version tomorrow, and a published
+/// version's dependencies shouldn't move under people.
+pub fn parse_requirement(requirement: &str) -> Result<VersionReq, String> {
+    let requirement = requirement.trim();
+    if let Ok(exact) = Version::parse(requirement) {
+        return Ok(VersionReq::parse(&format!("={}", exact)).expect("an exact version is a valid range"));
+    }
+    VersionReq::parse(requirement).map_err(|_| {
+        format!(
+            "'{}' isn't a version or semver range (dist-tags can't be used in published dependencies)",
+            requirement
+        )
+    })
+}
+
+/// Checks a version's dependencies before it's registered. Returns one message per bad entry,
+/// keyed by dependency name, so the CLI can show all of them at once. Empty means fine.
+///
+/// 1. The name has to be a valid package name, and not the package itself
+/// 2. The requirement has to parse (see `parse_requirement`)
+/// 3. The package has to exist (and not be taken down)
+/// 4. Unless turned off, some published version has to satisfy the range
+/// 5. Following the resolved versions mustn't lead back to the package (see `resolve`)
+#[tracing::instrument(name = "dependencies.check", skip_all, fields(package = %package, count = dependencies.len()))]
+pub async fn check(
+    db: &PgPool,
+    package: &str,
+    dependencies: &HashMap<String, String>,
+) -> Result<BTreeMap<String, String>, sqlx::Error> {
+    let mut errors = BTreeMap::new();
+    let mut ranges = BTreeMap::new();
+
+    for (name, requirement) in dependencies {
+        if name == package {
+            errors.insert(name.clone(), "a package can't depend on itself".to_string());
+            continue;
+        }
+        if let Err(e) = crate::utils::validation::validate_package_name(name) {
+            errors.insert(name.clone(), e);
+            continue;
+        }
+        match parse_requirement(requirement) {
+            Ok(req) => {
+                ranges.insert(name.clone(), req);
+            }
+            Err(e) => {
+                errors.insert(name.clone(), e);
+            }
+        }
+    }
+
+    if ranges.is_empty() {
+        return Ok(errors);
+    }
+
+    // One query for every referenced package's published versions. A package with no published
+    // versions still comes back (with a NULL version) so "exists" and "satisfiable" stay separate.
+    let names: Vec<String> = ranges.keys().cloned().collect();
+    let rows: Vec<(String, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT p.name, v.version FROM packages p
+        LEFT JOIN package_versions v ON v.package_id = p.id AND v.status = 'published'
+        WHERE p.name = ANY($1) AND NOT p.taken_down
+        "#,
+    )
+    .bind(&names)
+    .fetch_all(db)
+    .await?;
+
+    let mut published: HashMap<&str, Vec<Version>> = HashMap::new();
+    for (name, version) in &rows {
+        let versions = published.entry(name.as_str()).or_default();
+        if let Some(v) = version.as_deref().and_then(|v| Version::parse(v).ok()) {
+            versions.push(v);
+        }
+    }
+
+    let satisfiable = require_satisfiable();
+    for (name, req) in &ranges {
+        match published.get(name.as_str()) {
+            None => {
+                errors.insert(name.clone(), "no such package on this registry".to_string());
+            }
+            Some(versions) if satisfiable && !versions.iter().any(|v| req.matches(v)) => {
+                errors.insert(
+                    name.clone(),
+                    format!("no published version matches {}", dependencies[name].trim()),
+                );
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Only worth walking the graph if everything it starts from is sound.
+    if errors.is_empty() {
+        let closure = resolve(db, package, dependencies).await?;
+        for cycle in closure.cycles.iter().filter(|c| c.first().map(String::as_str) == Some(package)) {
+            // Blame the direct dependency the loop goes out through.
+            if let Some(via) = cycle.get(1) {
+                errors
+                    .entry(via.clone())
+                    .or_insert_with(|| format!("introduces a dependency cycle: {}", cycle.join(" -> ")));
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
+/// One package in a transitive closure, resolved the way the CLI would install it:
+/// the highest published version that satisfies the first range that asked for it.
+#[derive(Debug, Serialize)]
+pub struct Resolved {
+    pub name: String,
+    pub version: String,
+    pub requirement: String,
+    pub required_by: String,
+    #[serde(skip)]
+    dependencies: Vec<String>,
+}
+
+/// A dependency the walk couldn't pin down, and why.
+#[derive(Debug, Serialize)]
+pub struct Unresolved {
+    pub name: String,
+    pub requirement: String,
+    pub required_by: String,
+    pub reason: String,
+}
+
+/// Everything a package pulls in, plus whatever loops turned up on the way.
+/// Each cycle starts and ends with the same name: `["a", "b", "a"]`.
+#[derive(Debug, Serialize)]
+pub struct Closure {
+    pub dependencies: Vec<Resolved>,
+    pub unresolved: Vec<Unresolved>,
+    pub cycles: Vec<Vec<String>>,
+}
+
+/// Package name -> its published versions, each with its dependency map.
+type Published = HashMap<String, Vec<(Version, HashMap<String, String>)>>;
+
+/// Published versions of the given packages, with their dependency maps.
+async fn load_published(db: &PgPool, names: &[String]) -> Result<Published, sqlx::Error> {
+    let rows: Vec<(String, String, Option<serde_json::Value>)> = sqlx::query_as(
+        r#"
+        SELECT p.name, v.version, v.dependencies FROM package_versions v
+        JOIN packages p ON p.id = v.package_id
+        WHERE p.name = ANY($1) AND v.status = 'published' AND NOT p.taken_down
+        "#,
+    )
+    .bind(names)
+    .fetch_all(db)
+    .await?;
+
+    let mut published = Published::new();
+    for (name, version, deps) in rows {
+        let Ok(version) = Version::parse(&version) else {
+            continue;
+        };
+        let deps = deps
+            .and_then(|d| serde_json::from_value(d).ok())
+            .unwrap_or_default();
+        published.entry(name).or_default().push((version, deps));
+    }
+    Ok(published)
+}
+
+/// Walks the registry's dependency graph from `root` (with the given direct dependencies).
+///
+/// Goes breadth-first so each level is one query, no matter how wide it is. Like the CLI,
+/// every name resolves once—whoever asks first picks the version—so the graph is keyed by name.
+#[tracing::instrument(name = "dependencies.resolve", skip_all, fields(root = %root))]
+pub async fn resolve(
+    db: &PgPool,
+    root: &str,
+    dependencies: &HashMap<String, String>,
+) -> Result<Closure, sqlx::Error> {
+    let mut graph: BTreeMap<String, Resolved> = BTreeMap::new();
+    let mut unresolved = Vec::new();
+
+    let mut root_edges: Vec<String> = dependencies.keys().cloned().collect();
+    root_edges.sort();
+    let mut frontier: Vec<(String, String, String)> = root_edges
+        .iter()
+        .map(|name| (root.to_string(), name.clone(), dependencies[name].clone()))
+        .collect();
+
+    while !frontier.is_empty() {
+        let mut names: Vec<String> = frontier
+            .iter()
+            .map(|(_, name, _)| name.clone())
+            .filter(|name| name != root && !graph.contains_key(name))
+            .collect();
+        names.sort();
+        names.dedup();
+        let published = load_published(db, &names).await?;
+
+        let mut next = Vec::new();
+        for (parent, name, requirement) in frontier {
+            // The root is already "resolved"; the edge back to it is what the cycle check is for.
+            if name == root || graph.contains_key(&name) {
+                continue;
+            }
+            let reason = match parse_requirement(&requirement) {
+                Err(e) => Some(e),
+                Ok(req) => match published.get(&name) {
+                    None => Some("no such package on this registry".to_string()),
+                    Some(versions) => match versions
+                        .iter()
+                        .filter(|(v, _)| req.matches(v))
+                        .max_by(|a, b| a.0.cmp(&b.0))
+                    {
+                        None => Some(format!("no published version matches {}", requirement.trim())),
+                        Some((version, deps)) => {
+                            let mut edges: Vec<String> = deps.keys().cloned().collect();
+                            edges.sort();
+                            next.extend(
+                                edges.iter().map(|d| (name.clone(), d.clone(), deps[d].clone())),
+                            );
+                            graph.insert(
+                                name.clone(),
+                                Resolved {
+                                    name: name.clone(),
+                                    version: version.to_string(),
+                                    requirement: requirement.clone(),
+                                    required_by: parent.clone(),
+                                    dependencies: edges,
+                                },
+                            );
+                            None
+                        }
+                    },
+                },
+            };
+            if let Some(reason) = reason {
+                unresolved.push(Unresolved {
+                    name,
+                    requirement,
+                    required_by: parent,
+                    reason,
+                });
+            }
+        }
+        frontier = next;
+    }
+
+    let mut cycles = Vec::new();
+    let mut stack = vec![root.to_string()];
+    let mut done = HashSet::new();
+    find_cycles(&root_edges, &graph, &mut stack, &mut done, &mut cycles);
+
+    Ok(Closure {
+        dependencies: graph.into_values().collect(),
+        unresolved,
+        cycles,
+    })
+}
+
+/// Depth-first search for back edges. `stack` is the current path; an edge to
+/// something already on it closes a loop, and that slice of the path is the cycle.
+fn find_cycles(
+    edges: &[String],
+    graph: &BTreeMap<String, Resolved>,
+    stack: &mut Vec<String>,
+    done: &mut HashSet<String>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    for next in edges {
+        if let Some(start) = stack.iter().position(|n| n == next) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(next.clone());
+            cycles.push(cycle);
+            continue;
+        }
+        if done.contains(next) {
+            continue;
+        }
+        let Some(node) = graph.get(next) else {
+            continue;
+        };
+        stack.push(next.clone());
+        find_cycles(&node.dependencies, graph, stack, done, cycles);
+        stack.pop();
+        done.insert(next.clone());
+    }
+}
+
+/// Versions of other packages whose requirement on `name` would accept `version`, as
+/// `dependent@version (range)`. Used by unpublish, so it leans cautious: a requirement we can't
+/// parse (an old dist-tag dependency, say) could point anywhere, so it counts.
+pub async fn dependents_of(
+    db: &PgPool,
+    name: &str,
+    version: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    // The edge table the dependents trigger keeps (migration 41), not a scan of every version.
+    let rows: Vec<(String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT p.name, v.version, e.requirement FROM package_dependents e
+        JOIN package_versions v ON v.id = e.version_id
+        JOIN packages p ON p.id = e.package_id
+        WHERE e.dependency = $1 AND p.name <> $1
+        ORDER BY p.name, v.created_at
+        "#,
+    )
+    .bind(name)
+    .fetch_all(db)
+    .await?;
+
+    // A version that doesn't parse can't be matched against anything, so anyone who mentions
+    // the name blocks—same as before ranges were checked.
+    let target = Version::parse(version).ok();
+
+    Ok(rows
+        .into_iter()
+        .filter(|(_, _, requirement)| match (&target, parse_requirement(requirement)) {
+            (Some(target), Ok(req)) => req.matches(target),
+            _ => true,
+        })
+        .map(|(dependent, dependent_version, requirement)| {
+            format!("{}@{} ({})", dependent, dependent_version, requirement)
+        })
+        .collect())
+}
+
+/// The 400 body for a failed check: one readable summary plus the per-entry details.
+pub fn error_body(errors: &BTreeMap<String, String>) -> serde_json::Value {
+    let summary: Vec<String> = errors
+        .iter()
+        .map(|(name, e)| format!("{}: {}", name, e))
+        .collect();
+    json!({
+        "error": format!("Invalid dependencies: {}", summary.join("; ")),
+        "dependency_errors": errors
+    })
+}
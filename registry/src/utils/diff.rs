@@ -0,0 +1,96 @@
+use crate::utils::archive;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+/// How a file changed between two versions.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One unified-diff hunk. Lines keep their leading ' ', '+' or '-' so clients can print them as-is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hunk {
+    pub header: String,
+    pub lines: Vec<String>,
+}
+
+/// File-level diff entry. Binary files get `binary: true` and no hunks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub status: FileStatus,
+    pub binary: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Diffs two package zips file by file.
+///
+/// Unchanged files are left out entirely. Added/removed text files are diffed against
+/// an empty file so the hunks still show their full content.
+pub fn diff_archives(old: &[u8], new: &[u8]) -> anyhow::Result<Vec<FileDiff>> {
+    let old_files = archive::read_all(old)?;
+    let new_files = archive::read_all(new)?;
+
+    let mut paths: Vec<&String> = old_files.keys().chain(new_files.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut diffs = Vec::new();
+    for path in paths {
+        let (status, before, after) = match (old_files.get(path), new_files.get(path)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(a), Some(b)) => (FileStatus::Modified, a.as_slice(), b.as_slice()),
+            (None, Some(b)) => (FileStatus::Added, &[][..], b.as_slice()),
+            (Some(a), None) => (FileStatus::Removed, a.as_slice(), &[][..]),
+            (None, None) => continue,
+        };
+
+        let (Ok(before), Ok(after)) = (std::str::from_utf8(before), std::str::from_utf8(after)) else {
+            diffs.push(FileDiff {
+                path: path.clone(),
+                status,
+                binary: true,
+                hunks: Vec::new(),
+            });
+            continue;
+        };
+
+        diffs.push(FileDiff {
+            path: path.clone(),
+            status,
+            binary: false,
+            hunks: text_hunks(before, after),
+        });
+    }
+
+    Ok(diffs)
+}
+
+/// Line diff with 3 lines of context, same as `diff -u`.
+fn text_hunks(before: &str, after: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(before, after);
+    let mut unified = diff.unified_diff();
+    unified.context_radius(3);
+
+    unified
+        .iter_hunks()
+        .map(|hunk| Hunk {
+            header: hunk.header().to_string().trim_end().to_string(),
+            lines: hunk
+                .iter_changes()
+                .map(|change| {
+                    let sign = match change.tag() {
+                        ChangeTag::Delete => '-',
+                        ChangeTag::Insert => '+',
+                        ChangeTag::Equal => ' ',
+                    };
+                    format!("{}{}", sign, change.value().trim_end_matches(['\r', '\n']))
+                })
+                .collect(),
+        })
+        .collect()
+}
@@ -0,0 +1,80 @@
+use askalono::{Store, TextData};
+use std::sync::OnceLock;
+
+/// The askalono store, built from the embedded SPDX cache once and kept around.
+/// It used to get rebuilt on every upload, which was wasteful but nobody was publishing fast enough to notice.
+fn store() -> Option<&'static Store> {
+    static STORE: OnceLock<Option<Store>> = OnceLock::new();
+    STORE
+        .get_or_init(|| {
+            // It's small (~300KB compressed).
+            let cache_data = include_bytes!("license_cache.bin.zstd");
+            Store::from_cache(&cache_data[..]).ok()
+        })
+        .as_ref()
+}
+
+/// Identifies the license in a LICENSE file. "Custom" if nothing matches well enough
+/// (or, in theory, if the cache didn't load).
+pub fn detect(text: &str) -> String {
+    let Some(store) = store() else {
+        return "Custom".to_string();
+    };
+
+    let analysis = store.analyze(&TextData::from(text));
+    if analysis.score > 0.8 {
+        analysis.name.to_string()
+    } else {
+        "Custom".to_string()
+    }
+}
+
+/// Validates a declared license against the SPDX list.
+///
+/// Accepts a single identifier (`MIT`) or a simple expression (`MIT OR Apache-2.0`,
+/// `GPL-2.0-only WITH Classpath-exception-2.0`). Every identifier in it has to be known.
+/// `LicenseRef-*` is allowed for anything bespoke, as SPDX intends.
+pub fn validate_spdx(expression: &str) -> Result<(), String> {
+    let ids = identifiers(expression);
+    if ids.is_empty() {
+        return Err("License can't be empty".to_string());
+    }
+
+    let Some(store) = store() else {
+        // Can't check, so don't block the publish over it.
+        return Ok(());
+    };
+
+    for id in ids {
+        if id.starts_with("LicenseRef-") {
+            continue;
+        }
+        // Exceptions (after WITH) aren't licenses, so the store doesn't know them. Shape check only.
+        if id.ends_with("-exception") || id.contains("-exception-") {
+            continue;
+        }
+        if !store.licenses().any(|known| known == id) {
+            return Err(format!(
+                "'{}' isn't an SPDX license identifier. See https://spdx.org/licenses/",
+                id
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a detected license is one of the licenses named in a declared expression.
+pub fn matches(declared: &str, detected: &str) -> bool {
+    identifiers(declared).into_iter().any(|id| id == detected)
+}
+
+/// The identifiers in an SPDX expression, with operators and parentheses dropped.
+fn identifiers(expression: &str) -> Vec<&str> {
+    expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|t| !t.is_empty())
+        .filter(|t| !matches!(*t, "AND" | "OR" | "WITH"))
+        .map(|t| t.trim_end_matches('+'))
+        .collect()
+}
@@ -0,0 +1,245 @@
+use full_moon::ast::{
+    Assignment, Block, FunctionBody, FunctionCall, FunctionDeclaration, GenericFor, Index,
+    LocalAssignment, LocalFunction, NumericFor, Parameter, Prefix, Suffix, Var,
+};
+use full_moon::tokenizer::TokenReference;
+use full_moon::visitors::Visitor;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+
+/// Bump this when the rules change, so old reports can be told apart from new ones.
+pub const LINT_VERSION: u32 = 1;
+
+/// Reports keep at most this many findings. The summary counts everything.
+const MAX_FINDINGS: usize = 200;
+
+/// Calls that still work but shouldn't be in new code, with what to use instead.
+/// Lua stdlib leftovers for now; deprecated Polytoria APIs go in the same list as they're retired.
+const DEPRECATED: &[(&str, &str)] = &[
+    ("setfenv", "pass an environment table explicitly"),
+    ("getfenv", "pass an environment table explicitly"),
+    ("loadstring", "load"),
+    ("unpack", "table.unpack"),
+    ("table.getn", "the # operator"),
+    ("table.foreach", "a for ... in pairs loop"),
+    ("table.foreachi", "a for ... in ipairs loop"),
+    ("math.pow", "the ^ operator"),
+    ("math.mod", "math.fmod or %"),
+];
+
+/// Whether linting runs on upload. On unless LINT_UPLOADS is "false"/"0"—it's a quality
+/// signal, not a gate, so there's no reason to turn it off unless the box is struggling.
+pub fn enabled() -> bool {
+    !matches!(
+        std::env::var("LINT_UPLOADS").ok().as_deref(),
+        Some("false") | Some("0")
+    )
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub file: String,
+    pub line: usize,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+/// Lints every `.lua`/`.luau` file in a package zip and builds the stored report.
+///
+/// Nothing here blocks a publish—the CLI already refuses code that doesn't parse, so a parse
+/// error here just means someone used an old CLI or `--allow-syntax-errors`. It's counted and moved past.
+pub fn lint_package(bytes: &[u8]) -> anyhow::Result<serde_json::Value> {
+    let files = crate::utils::archive::read_all(bytes)?;
+    let mut findings = Vec::new();
+    let mut files_checked = 0;
+    let mut parse_errors = 0;
+
+    for (path, contents) in &files {
+        let lower = path.to_ascii_lowercase();
+        if !(lower.ends_with(".lua") || lower.ends_with(".luau")) {
+            continue;
+        }
+        files_checked += 1;
+
+        let source = String::from_utf8_lossy(contents);
+        match full_moon::parse(&source) {
+            Ok(ast) => {
+                let mut linter = Linter::new(path);
+                linter.visit_ast(&ast);
+                findings.extend(linter.findings);
+            }
+            Err(_) => parse_errors += 1,
+        }
+    }
+
+    let mut summary: BTreeMap<&str, usize> = BTreeMap::new();
+    for f in &findings {
+        *summary.entry(f.rule).or_default() += 1;
+    }
+    let total = findings.len();
+    findings.truncate(MAX_FINDINGS);
+
+    Ok(json!({
+        "lint_version": LINT_VERSION,
+        "files_checked": files_checked,
+        "parse_errors": parse_errors,
+        "total": total,
+        "summary": summary,
+        "findings": findings,
+        "truncated": total > MAX_FINDINGS
+    }))
+}
+
+/// Walks one file with a stack of scopes, one per block, holding the locals declared in it.
+struct Linter<'a> {
+    file: &'a str,
+    scopes: Vec<HashSet<String>>,
+    /// Names that belong to the *next* block: function parameters, for-loop variables.
+    pending: Vec<String>,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(file: &'a str) -> Self {
+        Self {
+            file,
+            scopes: Vec::new(),
+            pending: Vec::new(),
+            findings: Vec::new(),
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|s| s.contains(name))
+    }
+
+    /// Declares a local in the current scope, flagging it if it hides an outer one.
+    fn declare(&mut self, token: &TokenReference) {
+        let name = token.token().to_string();
+        // `_` is the conventional throwaway; shadowing it is the whole point.
+        if name != "_" && self.is_local(&name) {
+            self.report(
+                token,
+                "shadowing",
+                format!("local '{}' shadows an existing local with the same name", name),
+            );
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name);
+        }
+    }
+
+    fn report(&mut self, token: &TokenReference, rule: &'static str, message: String) {
+        self.findings.push(Finding {
+            file: self.file.to_string(),
+            line: token.token().start_position().line(),
+            rule,
+            message,
+        });
+    }
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_block(&mut self, _: &Block) {
+        self.scopes.push(self.pending.drain(..).collect());
+    }
+
+    fn visit_block_end(&mut self, _: &Block) {
+        self.scopes.pop();
+    }
+
+    fn visit_local_assignment(&mut self, node: &LocalAssignment) {
+        for name in node.names() {
+            self.declare(name);
+        }
+    }
+
+    fn visit_local_function(&mut self, node: &LocalFunction) {
+        self.declare(node.name());
+    }
+
+    fn visit_function_body(&mut self, node: &FunctionBody) {
+        for param in node.parameters() {
+            if let Parameter::Name(name) = param {
+                self.pending.push(name.token().to_string());
+            }
+        }
+    }
+
+    fn visit_numeric_for(&mut self, node: &NumericFor) {
+        self.pending.push(node.index_variable().token().to_string());
+    }
+
+    fn visit_generic_for(&mut self, node: &GenericFor) {
+        for name in node.names() {
+            self.pending.push(name.token().to_string());
+        }
+    }
+
+    // `x = 1` where x was never declared local writes a global. Every script that requires
+    // the package then shares (and can clobber) it.
+    fn visit_assignment(&mut self, node: &Assignment) {
+        for var in node.variables() {
+            if let Var::Name(token) = var {
+                let name = token.token().to_string();
+                if !self.is_local(&name) {
+                    self.report(
+                        token,
+                        "global-leak",
+                        format!("assignment to undeclared '{}' creates a global", name),
+                    );
+                }
+            }
+        }
+    }
+
+    // `function foo()` is a global too. `function M.foo()` is fine—that's a table field.
+    fn visit_function_declaration(&mut self, node: &FunctionDeclaration) {
+        let name = node.name();
+        if name.method_name().is_some() || name.names().len() != 1 {
+            return;
+        }
+        if let Some(token) = name.names().iter().next() {
+            let ident = token.token().to_string();
+            if !self.is_local(&ident) {
+                self.report(
+                    token,
+                    "global-leak",
+                    format!("function '{}' is declared global; use 'local function'", ident),
+                );
+            }
+        }
+    }
+
+    fn visit_function_call(&mut self, node: &FunctionCall) {
+        let Prefix::Name(base) = node.prefix() else {
+            return;
+        };
+        let base_name = base.token().to_string();
+        // A local called `unpack` is somebody's own function, not the deprecated global.
+        if self.is_local(&base_name) {
+            return;
+        }
+
+        // Build `table.getn` from `table` + `.getn`, stopping at the first call or non-dot index.
+        let mut path = base_name;
+        for suffix in node.suffixes() {
+            match suffix {
+                Suffix::Index(Index::Dot { name, .. }) => {
+                    path.push('.');
+                    path.push_str(&name.token().to_string());
+                }
+                _ => break,
+            }
+        }
+
+        if let Some((_, instead)) = DEPRECATED.iter().find(|(api, _)| *api == path) {
+            self.report(
+                base,
+                "deprecated-api",
+                format!("'{}' is deprecated; use {}", path, instead),
+            );
+        }
+    }
+}
@@ -1,3 +1,13 @@
+pub mod archive;
 pub mod auth;
+pub mod clients;
+pub mod dependencies;
+pub mod diff;
+pub mod license;
+pub mod lint;
+pub mod notify;
+pub mod quota;
+pub mod sanitize;
 pub mod storage;
+pub mod telemetry;
 pub mod validation;
\ No newline at end of file
@@ -0,0 +1,224 @@
+use crate::db::DB;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A version was published from a different session than the previous one.
+pub const EVENT_NEW_SESSION_PUBLISH: &str = "publish.new_session";
+/// A package changed hands. Approving a name claim is the only way that happens.
+pub const EVENT_OWNERSHIP_CHANGED: &str = "package.ownership_changed";
+/// Someone asked for one of the owner's packages through a name claim.
+pub const EVENT_NAME_CLAIMED: &str = "package.name_claimed";
+/// An admin approved or rejected one of the user's name claims.
+pub const EVENT_CLAIM_RESOLVED: &str = "claim.resolved";
+/// A new version of a protected package is waiting for the owner to approve it.
+pub const EVENT_PUBLISH_HELD: &str = "publish.held";
+/// The owner asked to turn protection off. It takes effect at `unprotect_at`.
+pub const EVENT_PROTECTION_DISABLED: &str = "package.protection_disabled";
+/// A new version of a package the user watches (starred with `notify`) is out.
+pub const EVENT_VERSION_PUBLISHED: &str = "package.version_published";
+/// A webhook was added to an account with protected packages. It gets events from `active_at`.
+pub const EVENT_WEBHOOK_ADDED: &str = "webhook.added";
+/// A webhook of an account with protected packages is going away at `delete_at`.
+pub const EVENT_WEBHOOK_REMOVED: &str = "webhook.removed";
+
+/// Fires an event at every webhook the user has registered.
+///
+/// Runs in the background: a slow or dead receiver should never hold up a publish.
+/// Delivery is best-effort—failures are logged and dropped, no retries (yet).
+pub fn notify_owner(db: DB, username: String, event: &'static str, data: serde_json::Value) {
+    tokio::spawn(async move {
+        if let Err(e) = deliver(&db, &username, event, data).await {
+            tracing::warn!("Failed to notify {} about {}: {}", username, event, e);
+        }
+    });
+}
+
+/// The user's webhooks that get events right now, as `(id, url, secret)`: not one that's still
+/// waiting out its delay, and not one whose scheduled deletion has passed.
+pub async fn recipients(db: &DB, username: &str) -> Result<Vec<(Uuid, String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT w.id, w.url, w.secret FROM webhooks w
+        JOIN users u ON u.id = w.user_id
+        WHERE u.username = $1 AND w.active_at <= $2 AND (w.delete_at IS NULL OR w.delete_at > $2)
+        ORDER BY w.created_at
+        "#,
+    )
+    .bind(username)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_all(db)
+    .await
+}
+
+/// Usernames watching a package: they starred it with `notify`. Someone who starred a private
+/// package and has since left its team isn't told about it anymore.
+pub async fn watchers(db: &DB, package: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT u.username FROM stars s
+        JOIN users u ON u.id = s.user_id
+        JOIN packages p ON p.id = s.package_id
+        WHERE p.name = $1 AND s.notify AND (
+            p.visibility = 'public' OR p.owner_id = s.user_id
+            OR EXISTS (SELECT 1 FROM package_members m WHERE m.package_id = p.id AND m.user_id = s.user_id)
+        )
+        ORDER BY s.created_at
+        "#,
+    )
+    .bind(package)
+    .fetch_all(db)
+    .await
+}
+
+/// Checks a webhook URL before it's saved: https, a host, and not an address on the registry's
+/// own network. The registry makes these requests from inside its network, so a hook pointed at
+/// `https://169.254.169.254/` or `https://localhost:5432/` is a way in. Hostnames are looked up
+/// again at delivery (see `resolve`), since DNS can change after this.
+pub fn check_url(raw: &str) -> Result<reqwest::Url, &'static str> {
+    let url = reqwest::Url::parse(raw).map_err(|_| "Webhook URL isn't a valid URL")?;
+    if url.scheme() != "https" {
+        return Err("Webhook URL must be an https:// URL");
+    }
+    let blocked = match (url.host_str(), host_ip(&url)) {
+        (None, _) => return Err("Webhook URL needs a host"),
+        (_, Some(ip)) => !is_public(ip),
+        (Some(name), None) => {
+            let name = name.trim_end_matches('.').to_ascii_lowercase();
+            name == "localhost" || name.ends_with(".localhost")
+        }
+    };
+    if blocked {
+        return Err("Webhook URL can't point at a loopback, private or link-local address");
+    }
+    Ok(url)
+}
+
+/// The host as an IP, when it's written as one. IPv6 hosts come with their brackets.
+fn host_ip(url: &reqwest::Url) -> Option<IpAddr> {
+    url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Whether `ip` is somewhere on the internet rather than on this machine or its network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Carrier-grade NAT, which cloud providers use for internal addresses too.
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // unique local
+                || (first & 0xffc0) == 0xfe80) // link-local
+        }
+    }
+}
+
+/// Looks up a webhook's host and returns its addresses, or an error if any of them is one
+/// `check_url` would have refused. The caller connects to exactly these, so the name can't
+/// resolve somewhere else between the check and the request.
+async fn resolve(url: &reqwest::Url) -> anyhow::Result<(String, Vec<SocketAddr>)> {
+    let url = check_url(url.as_str()).map_err(|e| anyhow::anyhow!(e))?;
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = match host_ip(&url) {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => tokio::net::lookup_host((host.as_str(), port)).await?.collect(),
+    };
+    if addrs.is_empty() {
+        anyhow::bail!("{} didn't resolve", host);
+    }
+    if let Some(addr) = addrs.iter().find(|a| !is_public(a.ip())) {
+        anyhow::bail!("{} resolves to {}, which isn't a public address", host, addr.ip());
+    }
+    Ok((host, addrs))
+}
+
+async fn deliver(
+    db: &DB,
+    username: &str,
+    event: &str,
+    data: serde_json::Value,
+) -> anyhow::Result<()> {
+    let hooks = recipients(db, username).await?;
+
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let body = serde_json::to_vec(&json!({
+        "event": event,
+        "timestamp": chrono::Utc::now().timestamp(),
+        "data": data
+    }))?;
+
+    for (id, url, secret) in hooks {
+        // Pinned to the addresses we just checked, and no redirects: a 302 to an internal
+        // address would undo the check.
+        let pinned = async {
+            let url = reqwest::Url::parse(&url)?;
+            let (host, addrs) = resolve(&url).await?;
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .redirect(reqwest::redirect::Policy::none())
+                .resolve_to_addrs(&host, &addrs)
+                .build()?;
+            anyhow::Ok((client, url))
+        };
+        let (client, url) = match pinned.await {
+            Ok(pinned) => pinned,
+            Err(e) => {
+                tracing::warn!("Webhook {} not delivered: {}", id, e);
+                continue;
+            }
+        };
+
+        let res = client
+            .post(url)
+            .header("content-type", "application/json")
+            .header("x-mosaic-event", event)
+            .header("x-mosaic-signature", format!("sha256={}", sign(&secret, &body)))
+            .body(body.clone())
+            .send()
+            .await;
+
+        match res {
+            Ok(r) if r.status().is_success() => {}
+            Ok(r) => tracing::warn!("Webhook {} returned {}", id, r.status()),
+            Err(e) => tracing::warn!("Webhook {} delivery failed: {}", id, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// HMAC-SHA256 of the raw body, hex encoded. Same scheme GitHub uses, so receivers
+/// can reuse whatever verification code they already have.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,105 @@
+use serde_json::json;
+use sqlx::PgPool;
+use std::env;
+use uuid::Uuid;
+
+/// How many versions a single package may register per window.
+///
+/// The publish governor is per *user*, so someone with twenty packages can still pour ten
+/// versions an hour into each one. This caps each package on its own. Pending versions count
+/// too—they take up rows and feed slots just the same.
+#[derive(Debug, Clone, Copy)]
+pub struct PublishQuota {
+    pub per_hour: i64,
+    pub per_day: i64,
+}
+
+impl PublishQuota {
+    /// The quota for an account. Verified accounts get a bigger one so release tooling
+    /// (monorepos, CI pushing canaries) doesn't trip over it.
+    ///
+    /// Tunable from the environment, defaults in parentheses:
+    /// PUBLISH_QUOTA_HOURLY (5), PUBLISH_QUOTA_DAILY (20),
+    /// PUBLISH_QUOTA_VERIFIED_HOURLY (30), PUBLISH_QUOTA_VERIFIED_DAILY (100).
+    pub fn for_account(verified: bool) -> Self {
+        if verified {
+            Self {
+                per_hour: env_limit("PUBLISH_QUOTA_VERIFIED_HOURLY", 30),
+                per_day: env_limit("PUBLISH_QUOTA_VERIFIED_DAILY", 100),
+            }
+        } else {
+            Self {
+                per_hour: env_limit("PUBLISH_QUOTA_HOURLY", 5),
+                per_day: env_limit("PUBLISH_QUOTA_DAILY", 20),
+            }
+        }
+    }
+}
+
+/// Which window ran out, and when there'll be room again.
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    pub window: &'static str,
+    pub limit: i64,
+    pub retry_after: i64,
+}
+
+impl QuotaExceeded {
+    /// The 429 body. `code` is stable so clients can match on it instead of the message.
+    pub fn to_json(&self, package: &str) -> serde_json::Value {
+        json!({
+            "error": format!(
+                "{} has hit its limit of {} versions per {}. Try again in {} minutes.",
+                package,
+                self.limit,
+                self.window,
+                (self.retry_after + 59) / 60
+            ),
+            "code": "package_publish_quota",
+            "window": self.window,
+            "limit": self.limit,
+            "retry_after": self.retry_after
+        })
+    }
+}
+
+/// Checks whether `package_id` has room for one more version under `quota`.
+/// Hour first, so the error names the window that frees up soonest.
+#[tracing::instrument(name = "quota.check", skip_all)]
+pub async fn check(
+    db: &PgPool,
+    package_id: Uuid,
+    quota: PublishQuota,
+) -> Result<Option<QuotaExceeded>, sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+
+    for (window, secs, limit) in [("hour", 3600, quota.per_hour), ("day", 86400, quota.per_day)] {
+        let (count, oldest): (i64, Option<i64>) = sqlx::query_as(
+            "SELECT COUNT(*), MIN(created_at) FROM package_versions WHERE package_id = $1 AND created_at > $2",
+        )
+        .bind(package_id)
+        .bind(now - secs)
+        .fetch_one(db)
+        .await?;
+
+        if count >= limit {
+            // Room opens up when the oldest version in the window ages out of it.
+            let retry_after = oldest.map(|t| t + secs - now).unwrap_or(secs).max(1);
+            return Ok(Some(QuotaExceeded {
+                window,
+                limit,
+                retry_after,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+fn env_limit(key: &str, default: i64) -> i64 {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
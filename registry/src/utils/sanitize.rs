@@ -0,0 +1,198 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Package descriptions show up in listings and search results. A paragraph, not an essay.
+pub const MAX_DESCRIPTION_CHARS: usize = 500;
+
+/// README and CHANGELOG cap. 256KB of markdown is a small book; anything bigger is
+/// almost always a bundled minified file that got named README by accident.
+pub const MAX_DOC_BYTES: usize = 256 * 1024;
+
+/// Text that's been cleaned up, plus what we had to do to it so the publisher can be told.
+pub struct Sanitized {
+    pub text: String,
+    pub warnings: Vec<String>,
+}
+
+/// Cleans up a package description.
+///
+/// Descriptions are plain text, so any HTML goes entirely. Newlines and runs of whitespace
+/// collapse to single spaces. Too long is an error rather than a silent chop—it's one field,
+/// the publisher can fix it.
+pub fn description(raw: &str) -> Result<String, String> {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let tag = TAG.get_or_init(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+
+    let stripped = tag.replace_all(raw, "");
+    let cleaned = stripped
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if cleaned.chars().count() > MAX_DESCRIPTION_CHARS {
+        return Err(format!(
+            "Description must be at most {} characters long",
+            MAX_DESCRIPTION_CHARS
+        ));
+    }
+
+    Ok(cleaned)
+}
+
+/// Decodes and cleans a markdown file from a package zip (README, CHANGELOG).
+///
+/// We don't render HTML today, but the website will eventually, and scrubbing at ingest
+/// means every old version is already safe when that day comes. So:
+/// 1. Decode: UTF-8 (BOM stripped), UTF-16 if there's a BOM for it, lossy UTF-8 otherwise.
+/// 2. Normalize line endings and drop control characters.
+/// 3. Strip script-ish HTML and neutralize `javascript:`-style links.
+/// 4. Truncate to MAX_DOC_BYTES.
+///
+/// Plain markdown passes through untouched—this isn't a full HTML sanitizer, just a filter
+/// for the constructs that can run code.
+pub fn document(file_name: &str, bytes: &[u8]) -> Sanitized {
+    let mut warnings = Vec::new();
+
+    // 1. Decode
+    let decoded = if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(rest).into_owned()
+    } else if let Some(text) = decode_utf16(bytes) {
+        warnings.push(format!("{} was UTF-16; converted to UTF-8", file_name));
+        text
+    } else {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => {
+                warnings.push(format!(
+                    "{} isn't valid UTF-8; invalid bytes were replaced",
+                    file_name
+                ));
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+        }
+    };
+
+    // 2. Line endings and control characters. Tabs and newlines are the only ones that belong in markdown.
+    let mut text: String = decoded
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect();
+
+    // 3. Dangerous constructs
+    let (cleaned, removed) = strip_active_content(&text);
+    if removed > 0 {
+        warnings.push(format!(
+            "Removed {} script, embed or event-handler construct{} from {}",
+            removed,
+            if removed == 1 { "" } else { "s" },
+            file_name
+        ));
+    }
+    text = cleaned;
+
+    // 4. Size cap. Cut on a char boundary so we never store half a code point.
+    if text.len() > MAX_DOC_BYTES {
+        let mut cut = MAX_DOC_BYTES;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        text.truncate(cut);
+        warnings.push(format!(
+            "{} was truncated to {}KB",
+            file_name,
+            MAX_DOC_BYTES / 1024
+        ));
+    }
+
+    Sanitized { text, warnings }
+}
+
+/// Removes the bits of HTML that can execute things. Returns the cleaned text and how many
+/// things were removed.
+fn strip_active_content(text: &str) -> (String, usize) {
+    static BLOCKS: OnceLock<Regex> = OnceLock::new();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    static HANDLERS: OnceLock<Regex> = OnceLock::new();
+    static URLS: OnceLock<Regex> = OnceLock::new();
+
+    // Whole elements, contents and all. Nothing inside a <script> is worth keeping.
+    let blocks = BLOCKS.get_or_init(|| {
+        Regex::new(r"(?is)<(script|style|iframe|object|embed|form|frame|frameset)\b[^>]*>.*?</\s*(script|style|iframe|object|embed|form|frame|frameset)\s*>").unwrap()
+    });
+    // Leftover open/self-closing tags of the same kinds (unclosed, or void like <embed>), plus <meta>/<base>/<link>.
+    let tags = TAGS.get_or_init(|| {
+        Regex::new(r"(?i)</?\s*(script|style|iframe|object|embed|form|frame|frameset|meta|base|link)\b[^>]*>").unwrap()
+    });
+    // onclick="...", onerror='...', onload=foo
+    let handlers = HANDLERS.get_or_init(|| {
+        Regex::new(r#"(?i)\s+on[a-z]+\s*=\s*("[^"]*"|'[^']*'|[^\s>]+)"#).unwrap()
+    });
+    // javascript:, vbscript:, and non-image data: URLs, whether in markdown links or attributes.
+    let urls = URLS.get_or_init(|| {
+        Regex::new(r"(?i)\b(javascript|vbscript|data)\s*:[^\s)>'\x22]*").unwrap()
+    });
+
+    let mut removed = 0;
+
+    let text = blocks.replace_all(text, |_: &regex::Captures| {
+        removed += 1;
+        ""
+    });
+    let text = tags.replace_all(&text, |_: &regex::Captures| {
+        removed += 1;
+        ""
+    });
+    // Only touch handlers inside tags, or "see onboarding = easy" in prose would get eaten.
+    let text = strip_in_tags(&text, handlers, &mut removed);
+    let text = urls.replace_all(&text, |caps: &regex::Captures| {
+        let url = &caps[0];
+        if url.to_ascii_lowercase().starts_with("data:image/") {
+            return url.to_string();
+        }
+        removed += 1;
+        "#".to_string()
+    });
+
+    (text.into_owned(), removed)
+}
+
+/// Applies `pattern` (removal) only inside `<...>` tags.
+fn strip_in_tags(text: &str, pattern: &Regex, removed: &mut usize) -> String {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let tag = TAG.get_or_init(|| Regex::new(r"<[a-zA-Z][^>]*>").unwrap());
+
+    tag.replace_all(text, |caps: &regex::Captures| {
+        let original = &caps[0];
+        let count = pattern.find_iter(original).count();
+        if count == 0 {
+            return original.to_string();
+        }
+        *removed += count;
+        pattern.replace_all(original, "").into_owned()
+    })
+    .into_owned()
+}
+
+/// UTF-16 with a byte order mark. Without a BOM we don't guess—too many false positives.
+fn decode_utf16(bytes: &[u8]) -> Option<String> {
+    let (rest, little_endian) = match bytes {
+        [0xFF, 0xFE, rest @ ..] => (rest, true),
+        [0xFE, 0xFF, rest @ ..] => (rest, false),
+        _ => return None,
+    };
+
+    let units: Vec<u16> = rest
+        .chunks_exact(2)
+        .map(|pair| {
+            if little_endian {
+                u16::from_le_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_be_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+
+    Some(String::from_utf16_lossy(&units))
+}
@@ -3,16 +3,62 @@ use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_s3::Client;
 use aws_sdk_s3::config::Region;
 use aws_sdk_s3::primitives::ByteStream;
+use crate::utils::telemetry::db_span;
+use sqlx::PgPool;
 use std::env;
+use std::path::PathBuf;
+use tracing::Instrument;
 
 /// Wrapper around AWS S3/R2 for storing package blobs.
 ///
 /// Cloudflare R2 is S3-compatible, so we use the AWS SDK directly.
 /// All package zips get uploaded here by hash, then we store the R2 URL in the database.
+///
+/// With `BLOB_DIR` set, blobs go in a plain folder instead. That's for tests and single-box
+/// setups that don't want an object store; the keys (`blobs/<hash>`) are the same either way.
 #[derive(Clone)]
 pub struct StorageService {
-    client: Client,
-    bucket: String,
+    backend: Backend,
+}
+
+#[derive(Clone)]
+enum Backend {
+    R2 { client: Client, bucket: String },
+    Filesystem { root: PathBuf },
+}
+
+/// Public URL for a blob, if the bucket is exposed directly (R2 public bucket or custom domain).
+///
+/// Set `BLOB_PUBLIC_URL` to the base that serves the bucket's keys, e.g. `https://pub-xxxx.r2.dev`.
+/// Clients fall back to it when the API is down. Blobs are content-addressed, so handing out
+/// a URL that skips our server costs nothing security-wise—the CLI checks the hash either way.
+pub fn public_blob_url(hash: &str) -> Option<String> {
+    let base = env::var("BLOB_PUBLIC_URL").ok()?;
+    let base = base.trim().trim_end_matches('/');
+    if base.is_empty() {
+        return None;
+    }
+    Some(format!("{}/blobs/{}", base, hash))
+}
+
+/// How many versions point at a blob. Blobs are content-addressed, so identical zips
+/// (a re-tag, the same bytes under a new version number) share one object in the bucket.
+pub async fn blob_references(db: &PgPool, hash: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM package_versions WHERE lua_source_url = $1")
+        .bind(format!("/packages/blobs/{}", hash))
+        .fetch_one(db)
+        .instrument(db_span("count blob references"))
+        .await
+}
+
+/// Deletes a blob, but only once no version references it anymore. Call it after the
+/// version rows are gone. Returns whether the blob was actually deleted.
+pub async fn release_blob(db: &PgPool, storage: &StorageService, hash: &str) -> anyhow::Result<bool> {
+    if blob_references(db, hash).await? > 0 {
+        return Ok(false);
+    }
+    storage.delete_blob(hash).await?;
+    Ok(true)
 }
 
 impl StorageService {
@@ -24,7 +70,12 @@ impl StorageService {
     /// - R2_BUCKET_NAME (defaults to "mosaic-packages" if not set)
     ///
     /// R2 uses "auto" region and custom endpoint URL instead of traditional AWS regions.
+    /// `BLOB_DIR` skips all of that and stores blobs on disk (see [`StorageService::filesystem`]).
     pub async fn new() -> Self {
+        if let Some(dir) = env::var_os("BLOB_DIR").filter(|d| !d.is_empty()) {
+            return Self::filesystem(dir);
+        }
+
         let access_key = env::var("R2_ACCESS_KEY_ID").expect("R2_ACCESS_KEY_ID must be set");
         let secret_key =
             env::var("R2_SECRET_ACCESS_KEY").expect("R2_SECRET_ACCESS_KEY must be set");
@@ -48,31 +99,65 @@ impl StorageService {
 
         let client = Client::new(&config);
 
-        Self { client, bucket }
+        Self {
+            backend: Backend::R2 { client, bucket },
+        }
+    }
+
+    /// Blobs as files under `root`, at the same keys they'd have in the bucket.
+    pub fn filesystem(root: impl Into<PathBuf>) -> Self {
+        Self {
+            backend: Backend::Filesystem { root: root.into() },
+        }
+    }
+
+    /// Blob keys are hex SHA256s. Anything else never came from us, and on disk a `..` in
+    /// one would be a way out of the folder.
+    fn blob_path(root: &std::path::Path, hash: &str) -> anyhow::Result<PathBuf> {
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("Invalid blob hash: {}", hash);
+        }
+        Ok(root.join("blobs").join(hash))
     }
 
     /// Uploads a package blob to R2.
     ///
     /// Uses the content hash as the S3 key so we never store duplicates.
     /// If the same blob is uploaded twice, it just overwrites (which is fine).
+    #[tracing::instrument(name = "storage.put", skip_all, fields(hash = %hash, bytes = data.len()))]
     pub async fn upload_blob(&self, hash: &str, data: Vec<u8>) -> anyhow::Result<()> {
-        self.client
-            .put_object()
-            .bucket(&self.bucket)
-            .key(format!("blobs/{}", hash))
-            .body(ByteStream::from(data))
-            .content_type("application/octet-stream")
-            .send()
-            .await?;
+        match &self.backend {
+            Backend::R2 { client, bucket } => {
+                client
+                    .put_object()
+                    .bucket(bucket)
+                    .key(format!("blobs/{}", hash))
+                    .body(ByteStream::from(data))
+                    .content_type("application/octet-stream")
+                    .send()
+                    .await?;
+            }
+            Backend::Filesystem { root } => {
+                let path = Self::blob_path(root, hash)?;
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(path, data).await?;
+            }
+        }
         Ok(())
     }
 
     /// Downloads a package blob from R2 by hash.
+    #[tracing::instrument(name = "storage.get", skip_all, fields(hash = %hash))]
     pub async fn get_blob(&self, hash: &str) -> anyhow::Result<Vec<u8>> {
-        let output = self
-            .client
+        let (client, bucket) = match &self.backend {
+            Backend::R2 { client, bucket } => (client, bucket),
+            Backend::Filesystem { root } => return Ok(tokio::fs::read(Self::blob_path(root, hash)?).await?),
+        };
+        let output = client
             .get_object()
-            .bucket(&self.bucket)
+            .bucket(bucket)
             .key(format!("blobs/{}", hash))
             .send()
             .await?;
@@ -82,15 +167,47 @@ impl StorageService {
         Ok(data.to_vec())
     }
 
-    /// Deletes a package blob from R2.
-    /// Used for rolling back failed uploads.
-    pub async fn delete_blob(&self, hash: &str) -> anyhow::Result<()> {
-        self.client
-            .delete_object()
-            .bucket(&self.bucket)
+    /// Checks whether a blob exists without downloading it.
+    /// Any error other than "not found" is passed up—we don't want a network blip
+    /// to make the integrity job think half the registry is missing.
+    #[tracing::instrument(name = "storage.head", skip_all, fields(hash = %hash))]
+    pub async fn blob_exists(&self, hash: &str) -> anyhow::Result<bool> {
+        let (client, bucket) = match &self.backend {
+            Backend::R2 { client, bucket } => (client, bucket),
+            Backend::Filesystem { root } => return Ok(tokio::fs::try_exists(Self::blob_path(root, hash)?).await?),
+        };
+        match client
+            .head_object()
+            .bucket(bucket)
             .key(format!("blobs/{}", hash))
             .send()
-            .await?;
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|se| se.is_not_found()) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Deletes a package blob from R2, no questions asked.
+    /// Other versions may share it—go through `release_blob` unless you know they don't.
+    #[tracing::instrument(name = "storage.delete", skip_all, fields(hash = %hash))]
+    pub async fn delete_blob(&self, hash: &str) -> anyhow::Result<()> {
+        match &self.backend {
+            Backend::R2 { client, bucket } => {
+                client
+                    .delete_object()
+                    .bucket(bucket)
+                    .key(format!("blobs/{}", hash))
+                    .send()
+                    .await?;
+            }
+            // S3 deletes of a missing key succeed, so this does too.
+            Backend::Filesystem { root } => match tokio::fs::remove_file(Self::blob_path(root, hash)?).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
         Ok(())
     }
 }
@@ -0,0 +1,44 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+
+/// Sets up OTLP trace export, if `OTEL_EXPORTER_OTLP_ENDPOINT` is set. `None` means off.
+///
+/// This is on top of Sentry, not instead of it: Sentry is for errors, this is for people running
+/// Tempo/Jaeger/whatever who want to see where a slow publish actually spent its time.
+/// Speaks OTLP over HTTP (port 4318, usually). The exporter reads the standard `OTEL_*` variables
+/// itself, so headers, timeouts etc. work the way their docs say.
+///
+/// Hand the tracer to `tracing_opentelemetry::layer()`, and keep the provider around so
+/// `shutdown()` can flush whatever's still batched when the server stops.
+pub fn init_tracer() -> anyhow::Result<Option<(SdkTracerProvider, Tracer)>> {
+    let configured = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .or_else(|_| std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"))
+        .is_ok_and(|v| !v.trim().is_empty());
+    if !configured {
+        return Ok(None);
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "mosaic-registry".to_string());
+
+    // Batched so a slow collector never sits in front of a request.
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build();
+    let tracer = provider.tracer("registry");
+
+    Ok(Some((provider, tracer)))
+}
+
+/// A span for one database round trip. sqlx doesn't make spans of its own, so wrap the queries
+/// worth seeing on a trace: `query.fetch_one(db).instrument(db_span("find package")).await`.
+/// `otel.name` is what Tempo/Jaeger show as the span name.
+pub fn db_span(operation: &'static str) -> tracing::Span {
+    tracing::info_span!("db", otel.name = operation, db.system = "postgresql")
+}
@@ -1,5 +1,7 @@
 use regex::Regex;
-use std::sync::OnceLock;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
 
 /// Validates a package name against strict rules.
 ///
@@ -46,36 +48,155 @@ pub fn validate_package_name(name: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Validates a username for `PUT /auth/username`.
+///
+/// Looser than package names—mixed case and underscores are fine—but still no spaces,
+/// slashes or anything else that gets awkward in a URL or a log line. Same blocklist,
+/// so nobody renames themselves to "mosaic-staff".
+pub fn validate_username(name: &str) -> Result<(), String> {
+    if name.len() < 2 || name.len() > 39 {
+        return Err("Username must be between 2 and 39 characters long".to_string());
+    }
+
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"^[A-Za-z0-9][A-Za-z0-9_-]*$").unwrap());
+    if !re.is_match(name) {
+        return Err("Username can only contain letters, digits, '_' and '-', and must start with a letter or digit".to_string());
+    }
+
+    if is_blocked(&name.to_ascii_lowercase()) {
+        return Err("Username contains reserved or inappropriate words".to_string());
+    }
+
+    Ok(())
+}
+
+/// What `name_rules` gets seeded with the first time the registry starts. After that the
+/// table is the source of truth—edit it with `mosaic admin names`, not here.
+pub const DEFAULT_BLOCKLIST: [&str; 48] = [
+    // System reserved
+    // We reserve these so nobody pretends to be us.
+    "admin", "root", "system", "mosaic", "registry", "official", "mod", "moderator",
+    "polytoria", "staff", "security", "test", "example", "demo", "null", "undefined",
+    "api", "dev", "beta", "stable", "latest", "internal",
+
+    // Offensive / Inappropriate
+    // This list is unfortunately necessary. It's not exhaustive, but it catches the
+    // low-effort edgelords.
+    "fuck", "shit", "nigger", "faggot", "cunt", "bitch", "whore", "slut", "dick",
+    "pussy", "asshole", "bastard", "sex", "porn", "xxx", "kill", "suicide", "death",
+    "hate", "nazi", "hitler", "kkk", "terrorist", "bomb", "murder", "rape",
+];
+
+/// The "Scunthorpe problem" whitelist.
+/// Words that look bad to a robot but are fine for humans.
+pub const DEFAULT_ALLOWLIST: [&str; 21] = [
+    "analytics", "analysis", "assassin", "assembly", "assets", "assistant",
+    "association", "assume", "class", "classic", "classify", "pass", "password",
+    "shell", "shithzu", "button", "push", "pull", "hello", "scraper", "grass",
+];
+
+/// Prefixes that look official. Claiming one needs an admin to approve the exact name first.
+pub const DEFAULT_RESERVED_PREFIXES: [&str; 1] = ["polytoria-"];
+
+/// In-memory copy of the `name_rules` table. Validation runs on every publish and signup,
+/// so it reads this instead of hitting the database.
+#[derive(Default)]
+struct NameRules {
+    blocked: Vec<String>,
+    allowed: Vec<String>,
+    reserved: Vec<String>,
+    reserved_prefixes: Vec<String>,
+    /// Exact name -> the user id allowed to claim it.
+    approved: HashMap<String, String>,
+}
+
+static NAME_RULES: RwLock<Option<Arc<NameRules>>> = RwLock::new(None);
+
+fn name_rules() -> Arc<NameRules> {
+    NAME_RULES
+        .read()
+        .ok()
+        .and_then(|rules| rules.clone())
+        .unwrap_or_default()
+}
+
+/// Reloads the cached rules from the database. Runs at startup, after every admin change,
+/// and once a minute so other instances behind the same database catch up.
+pub async fn reload_name_rules(db: &PgPool) -> Result<(), sqlx::Error> {
+    let rows: Vec<(String, String, Option<uuid::Uuid>)> =
+        sqlx::query_as("SELECT kind, pattern, user_id FROM name_rules")
+            .fetch_all(db)
+            .await?;
+
+    let mut rules = NameRules::default();
+    for (kind, pattern, user_id) in rows {
+        match kind.as_str() {
+            "block" => rules.blocked.push(pattern),
+            "allow" => rules.allowed.push(pattern),
+            "reserved" => rules.reserved.push(pattern),
+            "reserved_prefix" => rules.reserved_prefixes.push(pattern),
+            "approved" => {
+                if let Some(id) = user_id {
+                    rules.approved.insert(pattern, id.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Ok(mut cached) = NAME_RULES.write() {
+        *cached = Some(Arc::new(rules));
+    }
+    Ok(())
+}
+
+/// Why `name` can't be claimed by `user_id`, if it can't: it's reserved outright, or it starts
+/// with a reserved prefix. An admin approving that exact name for that user lifts either.
+/// Kept out of `validate_package_name` because it depends on who's asking: depending on
+/// `polytoria-ui` is fine, claiming it isn't.
+pub fn reservation(name: &str, user_id: &str) -> Option<String> {
+    let rules = name_rules();
+    if rules.approved.get(name).is_some_and(|id| id == user_id) {
+        return None;
+    }
+    reason_reserved(&rules, name)
+}
+
+/// Whether `name` is reserved for anyone at all. Approvals don't count—a reserved name
+/// stays reserved, it just has someone allowed to use it.
+pub fn is_reserved(name: &str) -> bool {
+    reason_reserved(&name_rules(), name).is_some()
+}
+
+fn reason_reserved(rules: &NameRules, name: &str) -> Option<String> {
+    if rules.reserved.iter().any(|r| r == name) {
+        return Some(format!("'{}' is reserved", name));
+    }
+    rules
+        .reserved_prefixes
+        .iter()
+        .find(|p| name.starts_with(p.as_str()))
+        .map(|p| format!("Names starting with '{}' are reserved", p))
+}
+
 /// Checks if a name contains blocked terms.
 fn is_blocked(name: &str) -> bool {
-    let blocklist = [
-        // System reserved
-        // We reserve these so nobody pretends to be us.
-        "admin", "root", "system", "mosaic", "registry", "official", "mod", "moderator",
-        "polytoria", "staff", "security", "test", "example", "demo", "null", "undefined",
-        "api", "dev", "beta", "stable", "latest", "internal",
-        
-        // Offensive / Inappropriate
-        // This list is unfortunately necessary. It's not exhaustive, but it catches the
-        // low-effort edgelords.
-        "fuck", "shit", "nigger", "faggot", "cunt", "bitch", "whore", "slut", "dick",
-        "pussy", "asshole", "bastard", "sex", "porn", "xxx", "kill", "suicide", "death",
-        "hate", "nazi", "hitler", "kkk", "terrorist", "bomb", "murder", "rape",
-    ];
-
-    for term in blocklist {
+    let rules = name_rules();
+
+    for term in &rules.blocked {
         // Exact match is always blocked.
         // "root" is bad, but "beetroot" is a delicious vegetable (usually).
         if name == term {
             return true;
         }
-        
+
         // Substring match for offensive terms.
         // We only check if the term is long enough to avoid the "ass" in "class" problem.
-        if term.len() > 3 && name.contains(term) {
+        if term.len() > 3 && name.contains(term.as_str()) {
             // Check whitelist before flagging.
             // We don't want to ban "analytics" just because it has "anal" in it.
-            if !is_whitelisted(name) {
+            if !is_whitelisted(&rules, name) {
                 return true;
             }
         }
@@ -85,19 +206,29 @@ fn is_blocked(name: &str) -> bool {
 }
 
 /// Returns true if the name contains a whitelisted term that might trigger a false positive.
-fn is_whitelisted(name: &str) -> bool {
-    // The "Scunthorpe problem" whitelist.
-    // Words that look bad to a robot but are fine for humans.
-    let whitelist = [
-        "analytics", "analysis", "assassin", "assembly", "assets", "assistant",
-        "association", "assume", "class", "classic", "classify", "pass", "password",
-        "shell", "shithzu", "button", "push", "pull", "hello", "scraper", "grass",
-    ];
-
-    for safe in whitelist {
-        if name.contains(safe) {
-            return true;
-        }
+fn is_whitelisted(rules: &NameRules, name: &str) -> bool {
+    rules.allowed.iter().any(|safe| name.contains(safe.as_str()))
+}
+
+/// Validates a dist-tag name (e.g. "latest", "beta", "next").
+///
+/// Tags share the URL space with versions in install specs (`name@beta` vs `name@1.0.0`),
+/// so anything that parses as semver is rejected to keep that unambiguous.
+pub fn validate_tag_name(tag: &str) -> Result<(), String> {
+    if tag.is_empty() || tag.len() > 32 {
+        return Err("Tag must be between 1 and 32 characters long".to_string());
     }
-    false
-}
\ No newline at end of file
+
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"^[a-z][a-z0-9-]*$").unwrap());
+
+    if !re.is_match(tag) {
+        return Err("Tag must start with a lowercase letter and contain only lowercase letters, digits and hyphens".to_string());
+    }
+
+    if semver::Version::parse(tag).is_ok() {
+        return Err("Tag cannot be a valid semantic version".to_string());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,313 @@
+//! A real registry and a sandboxed CLI, for integration tests that exercise both halves.
+//!
+//! [`TestRegistry`] serves the actual axum app on a random local port, against a fresh
+//! Postgres database and blobs in a temp folder. [`Cli`] runs the `mosaic` binary with its
+//! config, cache and credentials in a temp folder too, so tests never touch your real login.
+//!
+//! Postgres comes from `MOSAIC_TEST_DATABASE_URL` (any database on the server; each test
+//! creates and drops its own). Without it, [`TestRegistry::start`] returns `None` and tests
+//! skip, so `cargo test` still works on a machine with no Postgres around.
+//!
+//! [`mock::MockRegistry`] is the offline half: recorded responses played back by wiremock.
+
+use anyhow::{Context, Result, anyhow};
+use registry::state::AppState;
+use registry::utils::storage::StorageService;
+use sqlx::PgPool;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Once;
+use tempfile::TempDir;
+use tokio::task::JoinHandle;
+
+pub mod mock;
+
+/// Where the tests find Postgres.
+pub const DATABASE_ENV: &str = "MOSAIC_TEST_DATABASE_URL";
+
+/// Password every [`TestRegistry::signup`] account gets.
+pub const PASSWORD: &str = "correct-horse-battery-staple";
+
+/// The registry's admin token, for `/admin` routes.
+pub const ADMIN_TOKEN: &str = "mosaic-integration-admin";
+
+/// A running registry. Call [`TestRegistry::stop`] at the end of the test to drop its
+/// database; a test that panics first leaves a `mosaic_test_*` database behind.
+pub struct TestRegistry {
+    /// Base URL, no trailing slash. What you'd pass to `--registry`.
+    pub url: String,
+    /// The registry's own pool, for poking at rows directly.
+    pub db: PgPool,
+    admin_url: String,
+    db_name: String,
+    server: JoinHandle<()>,
+    _blobs: TempDir,
+}
+
+impl TestRegistry {
+    /// Creates a database, runs the migrations and starts serving. `None` when
+    /// [`DATABASE_ENV`] isn't set.
+    pub async fn start() -> Result<Option<Self>> {
+        let Ok(admin_url) = std::env::var(DATABASE_ENV) else {
+            eprintln!("{} not set, skipping registry integration test", DATABASE_ENV);
+            return Ok(None);
+        };
+
+        // The registry reads these straight from the environment.
+        static ENV: Once = Once::new();
+        ENV.call_once(|| {
+            // SAFETY: set once, before any registry code runs, and never changed afterwards.
+            unsafe {
+                std::env::set_var("JWT_SECRET", "mosaic-integration-tests");
+                std::env::set_var("LINT_UPLOADS", "false");
+                std::env::set_var("ADMIN_TOKEN", ADMIN_TOKEN);
+            }
+            let _ = rustls::crypto::ring::default_provider().install_default();
+        });
+
+        let db_name = format!("mosaic_test_{}", uuid::Uuid::new_v4().simple());
+        let admin = PgPool::connect(&admin_url)
+            .await
+            .with_context(|| format!("Couldn't connect to {}", DATABASE_ENV))?;
+        sqlx::query(&format!("CREATE DATABASE {}", db_name))
+            .execute(&admin)
+            .await?;
+        admin.close().await;
+
+        let mut url = reqwest::Url::parse(&admin_url)?;
+        url.set_path(&format!("/{}", db_name));
+        let db = registry::db::connect_to(url.as_str()).await?;
+        registry::utils::validation::reload_name_rules(&db).await?;
+
+        let blobs = TempDir::new()?;
+        let state = AppState {
+            db: db.clone(),
+            storage: StorageService::filesystem(blobs.path()),
+        };
+        let app = registry::routes::create_routes(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Some(Self {
+            url: format!("http://{}", addr),
+            db,
+            admin_url,
+            db_name,
+            server,
+            _blobs: blobs,
+        }))
+    }
+
+    /// Signs up `username` with [`PASSWORD`] and returns the token.
+    pub async fn signup(&self, username: &str) -> Result<String> {
+        let res = reqwest::Client::new()
+            .post(format!("{}/auth/signup", self.url))
+            .json(&serde_json::json!({ "username": username, "password": PASSWORD }))
+            .send()
+            .await?;
+        let status = res.status();
+        let body: serde_json::Value = res.json().await?;
+        if !status.is_success() {
+            return Err(anyhow!("Signup failed ({}): {}", status, body));
+        }
+        body["token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Signup response had no token: {}", body))
+    }
+
+    /// Logs `username` in again with [`PASSWORD`]: a second session, same account.
+    pub async fn login(&self, username: &str) -> Result<String> {
+        let res = reqwest::Client::new()
+            .post(format!("{}/auth/login", self.url))
+            .json(&serde_json::json!({ "username": username, "password": PASSWORD }))
+            .send()
+            .await?;
+        let status = res.status();
+        let body: serde_json::Value = res.json().await?;
+        if !status.is_success() {
+            return Err(anyhow!("Login failed ({}): {}", status, body));
+        }
+        body["token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Login response had no token: {}", body))
+    }
+
+    /// The URLs of `username`'s webhooks that an event would go to right now.
+    pub async fn webhook_recipients(&self, username: &str) -> Result<Vec<String>> {
+        let hooks = registry::utils::notify::recipients(&self.db, username).await?;
+        Ok(hooks.into_iter().map(|(_, url, _)| url).collect())
+    }
+
+    /// Who'd get a `package.version_published` event for `package` right now.
+    pub async fn watchers(&self, package: &str) -> Result<Vec<String>> {
+        Ok(registry::utils::notify::watchers(&self.db, package).await?)
+    }
+
+    /// Stands in for the confirmation link of a held version: swaps its approval token for a
+    /// known one and backdates it past the protected-publish delay. Returns the token.
+    pub async fn approval_token(&self, package: &str, version: &str) -> Result<String> {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let held = sqlx::query(
+            r#"
+            UPDATE package_versions SET approval_token_hash = encode(sha256($3::bytea), 'hex'), held_at = 0
+            WHERE version = $2 AND status = 'held'
+              AND package_id = (SELECT id FROM packages WHERE name = $1)
+            "#,
+        )
+        .bind(package)
+        .bind(version)
+        .bind(token.as_bytes())
+        .execute(&self.db)
+        .await?;
+        if held.rows_affected() == 0 {
+            return Err(anyhow!("{}@{} isn't held", package, version));
+        }
+        Ok(token)
+    }
+
+    /// Moves a version's upload `secs` into the past, as if it had been sitting there that long.
+    pub async fn backdate(&self, package: &str, version: &str, secs: i64) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE package_versions SET created_at = created_at - $3, held_at = held_at - $3
+            WHERE version = $2 AND package_id = (SELECT id FROM packages WHERE name = $1)
+            "#,
+        )
+        .bind(package)
+        .bind(version)
+        .bind(secs)
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Stops serving and drops the database.
+    pub async fn stop(self) -> Result<()> {
+        self.server.abort();
+        self.db.close().await;
+        let admin = PgPool::connect(&self.admin_url).await?;
+        sqlx::query(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.db_name))
+            .execute(&admin)
+            .await?;
+        admin.close().await;
+        Ok(())
+    }
+}
+
+/// The `mosaic` binary, sandboxed: its own MOSAIC_HOME, pointed at one registry (real or
+/// mock), no colors and no prompts.
+pub struct Cli {
+    bin: PathBuf,
+    registry: String,
+    home: TempDir,
+    work: TempDir,
+}
+
+/// What a command printed and how it exited.
+#[derive(Debug)]
+pub struct Run {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Run {
+    /// Panics with everything the command printed unless it succeeded.
+    #[track_caller]
+    pub fn ok(self) -> Self {
+        assert!(
+            self.status.success(),
+            "command failed ({})\n--- stdout\n{}\n--- stderr\n{}",
+            self.status,
+            self.stdout,
+            self.stderr
+        );
+        self
+    }
+
+    /// Panics unless the command failed.
+    #[track_caller]
+    pub fn err(self) -> Self {
+        assert!(
+            !self.status.success(),
+            "command should have failed\n--- stdout\n{}\n--- stderr\n{}",
+            self.stdout,
+            self.stderr
+        );
+        self
+    }
+}
+
+impl Cli {
+    /// `bin` is the CLI binary, i.e. `env!("CARGO_BIN_EXE_mosaic")` from the CLI's own tests.
+    /// `registry` is a base URL, e.g. [`TestRegistry::url`] or [`mock::MockRegistry::url`].
+    pub fn new(bin: impl Into<PathBuf>, registry: &str) -> Result<Self> {
+        Ok(Self {
+            bin: bin.into(),
+            registry: registry.to_string(),
+            home: TempDir::new()?,
+            work: TempDir::new()?,
+        })
+    }
+
+    /// Stores a token the way `mosaic login` would in portable mode.
+    pub fn login(&self, username: &str, token: &str) -> Result<()> {
+        let config = self.home.path().join("config");
+        std::fs::create_dir_all(&config)?;
+        std::fs::write(
+            config.join("auth.toml"),
+            format!("username = {:?}\nregistry_url = {:?}\n", username, self.registry),
+        )?;
+        std::fs::write(config.join("credentials.toml"), format!("token = {:?}\n", token))?;
+        Ok(())
+    }
+
+    /// A fresh folder called `name` to run commands in. The name matters: `mosaic init`
+    /// uses it as the package name.
+    pub fn project(&self, name: &str) -> Result<PathBuf> {
+        let dir = self.work.path().join(name);
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Runs `mosaic <args> --registry <url>` in `dir`.
+    pub async fn run(&self, dir: &Path, args: &[&str]) -> Result<Run> {
+        self.run_with_env(dir, args, &[]).await
+    }
+
+    /// [`Cli::run`] with extra environment variables, e.g. `MOSAIC_TOKEN`.
+    pub async fn run_with_env(&self, dir: &Path, args: &[&str], env: &[(&str, &str)]) -> Result<Run> {
+        let output = tokio::process::Command::new(&self.bin)
+            .args(args)
+            .arg("--registry")
+            .arg(&self.registry)
+            .current_dir(dir)
+            .env("MOSAIC_HOME", self.home.path())
+            .env("NO_COLOR", "1")
+            .env("CI", "true")
+            .env_remove("MOSAIC_REGISTRY_URL")
+            .env_remove("MOSAIC_FALLBACK_REGISTRIES")
+            .env_remove("MOSAIC_TOKEN")
+            .envs(env.iter().copied())
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        Ok(Run {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+/// An empty place file with a ScriptService for `mosaic install` to inject into.
+pub const EMPTY_POLY: &str = r#"<game>
+  <Item class="ScriptService">
+  </Item>
+</game>
+"#;
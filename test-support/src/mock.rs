@@ -0,0 +1,264 @@
+//! A fake registry that plays back recorded responses, so the CLI can be tested with no
+//! Postgres and no network.
+//!
+//! Fixtures live in `test-support/fixtures/`, one response per file. They're recorded from a
+//! real [`TestRegistry`](crate::TestRegistry) by the CLI's ignored `record_fixtures` test, so
+//! when the registry's responses change, re-record instead of editing them by hand:
+//!
+//! ```text
+//! MOSAIC_TEST_DATABASE_URL=postgres://... cargo test --test contract -- --ignored record_fixtures
+//! ```
+//!
+//! Error cases the registry won't produce on demand (a 500, a body that isn't JSON) are built
+//! in the test with [`Fixture::new`].
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use wiremock::{Match, Mock, MockServer, Request, ResponseTemplate};
+
+/// Where the recorded responses live.
+pub const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+/// Response headers worth keeping. Everything else (dates, content length) changes on every
+/// recording and nothing in the CLI reads it.
+const KEPT_HEADERS: &[&str] = &["content-type", "etag", "retry-after", "x-ratelimit-after"];
+
+/// One recorded request and what the registry answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub method: String,
+    /// Path and query, exactly as requested.
+    pub path: String,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Body,
+}
+
+/// What came back. Zips are kept next to the fixture rather than inside it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+    Json(serde_json::Value),
+    Text(String),
+    /// A file in [`FIXTURES`], by relative path.
+    File(String),
+}
+
+impl Fixture {
+    /// A response that was never recorded, e.g. `Fixture::new("GET", "/packages/x", 500, Body::Text(..))`.
+    pub fn new(method: &str, path: &str, status: u16, body: Body) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+            headers: BTreeMap::new(),
+            body,
+        }
+    }
+
+    /// Loads `fixtures/<name>.json`.
+    pub fn load(name: &str) -> Result<Self> {
+        let path = PathBuf::from(FIXTURES).join(format!("{}.json", name));
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("No fixture at {}", path.display()))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Writes `fixtures/<name>.json`.
+    pub fn save(&self, name: &str) -> Result<()> {
+        std::fs::create_dir_all(FIXTURES)?;
+        let path = PathBuf::from(FIXTURES).join(format!("{}.json", name));
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// Sends `method path` to a real registry at `base` and keeps the answer. `build` adds
+    /// headers and a body; binary answers are written to `fixtures/<file>`.
+    pub async fn record(
+        base: &str,
+        method: &str,
+        path: &str,
+        build: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+        file: Option<&str>,
+    ) -> Result<Self> {
+        let request = reqwest::Client::new().request(method.parse()?, format!("{}{}", base, path));
+        let res = build(request).send().await?;
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .iter()
+            .filter(|(k, _)| KEPT_HEADERS.contains(&k.as_str()))
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let bytes = res.bytes().await?;
+        let body = match file {
+            Some(file) => {
+                let dest = PathBuf::from(FIXTURES).join(file);
+                std::fs::create_dir_all(dest.parent().unwrap_or(&dest))?;
+                std::fs::write(dest, &bytes)?;
+                Body::File(file.to_string())
+            }
+            None => match serde_json::from_slice(&bytes) {
+                Ok(json) => Body::Json(json),
+                Err(_) => Body::Text(String::from_utf8_lossy(&bytes).into_owned()),
+            },
+        };
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// The same response, served for a different request.
+    pub fn at(mut self, method: &str, path: &str) -> Self {
+        self.method = method.to_string();
+        self.path = path.to_string();
+        self
+    }
+
+    /// The JSON body. Panics for anything else.
+    pub fn json(&self) -> &serde_json::Value {
+        match &self.body {
+            Body::Json(json) => json,
+            other => panic!("{} {} has no JSON body: {:?}", self.method, self.path, other),
+        }
+    }
+
+    /// The body as it goes over the wire.
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        Ok(match &self.body {
+            Body::Json(json) => serde_json::to_vec(json)?,
+            Body::Text(text) => text.clone().into_bytes(),
+            Body::File(file) => std::fs::read(PathBuf::from(FIXTURES).join(file))?,
+        })
+    }
+
+    fn template(&self, status: u16) -> ResponseTemplate {
+        let mut res = ResponseTemplate::new(status);
+        for (k, v) in &self.headers {
+            res = res.insert_header(k.as_str(), v.as_str());
+        }
+        res
+    }
+
+    fn response(&self) -> Result<ResponseTemplate> {
+        let res = self.template(self.status);
+        Ok(match &self.body {
+            Body::Json(json) => res.set_body_json(json),
+            _ => res.set_body_bytes(self.bytes()?),
+        })
+    }
+}
+
+/// Matches the path and query exactly. wiremock's own `path` ignores the query, which would
+/// mix up `/packages/x` and `/packages/x?include_prereleases=false`.
+struct PathAndQuery(String);
+
+impl Match for PathAndQuery {
+    fn matches(&self, request: &Request) -> bool {
+        let url = &request.url;
+        match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query) == self.0,
+            None => url.path() == self.0,
+        }
+    }
+}
+
+/// A registry made of fixtures. Anything not mounted is a 404, same as a package that
+/// doesn't exist.
+pub struct MockRegistry {
+    server: MockServer,
+}
+
+impl MockRegistry {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// Base URL, no trailing slash. What you'd pass to `--registry`.
+    pub fn url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Serves the recorded fixtures called `names`.
+    pub async fn serve(&self, names: &[&str]) -> Result<()> {
+        for name in names {
+            self.mount(Fixture::load(name)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Serves one fixture.
+    pub async fn mount(&self, fixture: Fixture) -> Result<()> {
+        Mock::given(wiremock::matchers::method(fixture.method.as_str()))
+            .and(PathAndQuery(fixture.path.clone()))
+            .respond_with(fixture.response()?)
+            .mount(&self.server)
+            .await;
+        Ok(())
+    }
+
+    /// Serves one fixture in place of whatever was already mounted at its path.
+    pub async fn mount_over(&self, fixture: Fixture) -> Result<()> {
+        Mock::given(wiremock::matchers::method(fixture.method.as_str()))
+            .and(PathAndQuery(fixture.path.clone()))
+            .respond_with(fixture.response()?)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+        Ok(())
+    }
+
+    /// Serves `fixture` as if the connection dropped after `keep` bytes, and answers
+    /// `Range: bytes=<keep>-` with the rest. For downloads that have to resume.
+    pub async fn mount_interrupted(&self, fixture: Fixture, keep: usize) -> Result<()> {
+        let bytes = fixture.bytes()?;
+        let keep = keep.min(bytes.len());
+        Mock::given(wiremock::matchers::method(fixture.method.as_str()))
+            .and(PathAndQuery(fixture.path.clone()))
+            .and(wiremock::matchers::header("range", format!("bytes={}-", keep).as_str()))
+            .respond_with(fixture.template(206).set_body_bytes(bytes[keep..].to_vec()))
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+        Mock::given(wiremock::matchers::method(fixture.method.as_str()))
+            .and(PathAndQuery(fixture.path.clone()))
+            .respond_with(fixture.template(fixture.status).set_body_bytes(bytes[..keep].to_vec()))
+            .mount(&self.server)
+            .await;
+        Ok(())
+    }
+
+    /// Every request the CLI made, as `METHOD /path?query`, in order.
+    pub async fn requests(&self) -> Vec<String> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(|r| match r.url.query() {
+                Some(q) => format!("{} {}?{}", r.method, r.url.path(), q),
+                None => format!("{} {}", r.method, r.url.path()),
+            })
+            .collect()
+    }
+
+    /// The JSON body of the first `METHOD /path` request the CLI made, if it made one.
+    pub async fn body(&self, request: &str) -> Option<serde_json::Value> {
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .find(|r| format!("{} {}", r.method, r.url.path()) == request)
+            .and_then(|r| serde_json::from_slice(&r.body).ok())
+    }
+}
@@ -1,7 +1,7 @@
 import { getPackage, getVersions } from "@/lib/registry";
 import { notFound } from "next/navigation";
 import Link from "next/link";
-import { ArrowLeft, Github, Package, TriangleAlert, Box, History, Calendar } from "lucide-react";
+import { ArrowLeft, Github, Package, TriangleAlert, Box, History, Calendar, BadgeCheck, Star } from "lucide-react";
 import ReactMarkdown from "react-markdown";
 import { format } from "date-fns";
 
@@ -62,6 +62,12 @@ export default async function PackagePage({ params }: PackagePageProps) {
             <div className="flex items-center gap-3 mb-3">
               <Package className="h-6 w-6 text-primary" />
               <h1 className="text-3xl font-bold text-foreground font-mono">{pkg.name}</h1>
+              {pkg.featured && (
+                <span className="inline-flex items-center gap-1 rounded-full bg-primary/10 px-2.5 py-0.5 text-sm font-medium text-primary">
+                  <Star className="h-3.5 w-3.5" />
+                  Featured
+                </span>
+              )}
             </div>
             <p className="text-lg text-muted-foreground leading-relaxed max-w-2xl">
               {pkg.description}
@@ -178,6 +184,9 @@ export default async function PackagePage({ params }: PackagePageProps) {
                         <div className="flex items-center gap-1.5 text-xs text-muted-foreground/60 mt-0.5">
                           <Calendar className="h-3 w-3" />
                           {v.created_at ? format(new Date(v.created_at * 1000), "MMM d, yyyy") : "Unknown"}
+                          {v.downloads !== undefined && (
+                            <span>· {v.downloads.toLocaleString()} downloads</span>
+                          )}
                         </div>
                       </div>
                       {/* Only show 'latest' badge for the top one? Or check logic. */}
@@ -196,7 +205,12 @@ export default async function PackagePage({ params }: PackagePageProps) {
             <div className="grid grid-cols-1 gap-4">
               <div className="bg-card border border-border rounded-lg p-5">
                 <h3 className="text-sm text-muted-foreground/60 mb-2">Author</h3>
-                <p className="text-base text-foreground font-medium">{pkg.author}</p>
+                <p className="flex items-center gap-1 text-base text-foreground font-medium">
+                  {pkg.author}
+                  {pkg.verified && (
+                    <BadgeCheck className="h-4 w-4 text-primary" aria-label="Verified publisher" />
+                  )}
+                </p>
               </div>
               <div className="bg-card border border-border rounded-lg p-5">
                 <h3 className="text-sm text-muted-foreground/60 mb-2">License</h3>
@@ -218,6 +232,11 @@ export default async function PackagePage({ params }: PackagePageProps) {
               <div className="bg-card border border-border rounded-lg p-5">
                 <h3 className="text-sm text-muted-foreground/60 mb-2">Downloads</h3>
                 <p className="text-base text-foreground font-medium">{(pkg.download_count || 0).toLocaleString()}</p>
+                {latestVersionData?.downloads !== undefined && (
+                  <p className="text-xs text-muted-foreground/60 mt-1">
+                    {latestVersionData.downloads.toLocaleString()} for v{pkg.version}
+                  </p>
+                )}
               </div>
             </div>
           </div>
@@ -1,7 +1,7 @@
 "use client";
 
 import { useEffect, useState, Suspense } from "react";
-import { Search, Loader2, ArrowRight } from "lucide-react";
+import { Search, Loader2, ArrowRight, BadgeCheck, Star } from "lucide-react";
 import Link from "next/link";
 import { useSearchParams } from "next/navigation";
 import { searchPackages, type RegistryPackage } from "@/lib/registry";
@@ -75,6 +75,18 @@ function PackagesContent() {
                     <span className="text-base text-muted-foreground/60 font-mono">
                       {pkg.version}
                     </span>
+                    {pkg.featured && (
+                      <span className="inline-flex items-center gap-1 self-center rounded-full bg-primary/10 px-2 py-0.5 text-xs font-medium text-primary">
+                        <Star className="h-3 w-3" />
+                        Featured
+                      </span>
+                    )}
+                  </div>
+                  <div className="flex items-center gap-1 mb-1 text-sm text-muted-foreground/60">
+                    <span>by {pkg.author}</span>
+                    {pkg.verified && (
+                      <BadgeCheck className="h-4 w-4 text-primary" aria-label="Verified publisher" />
+                    )}
                   </div>
                   <p className="text-base text-muted-foreground">
                     {pkg.description || "No description"}
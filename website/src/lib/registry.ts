@@ -10,19 +10,21 @@ export interface RegistryPackage {
   license?: string;
   deprecated?: boolean;
   deprecation_reason?: string;
+  featured?: boolean; // Picked by a registry admin
+  verified?: boolean; // The author's account is verified
 }
 
 export interface RegistryVersion {
   version: string;
   created_at: number;
   dependencies: Record<string, string>;
-  download_count?: number; // Not per version in DB yet, but maybe future proof
+  downloads?: number; // Only present when fetched with ?include=downloads
 }
 
 const REGISTRY_URL = process.env.NEXT_PUBLIC_REGISTRY_URL || "https://api.getmosaic.run";
 
 interface SearchOptions {
-  sort?: "updated" | "downloads" | "newest";
+  sort?: "updated" | "downloads" | "trending" | "newest";
   limit?: number;
 }
 
@@ -86,7 +88,7 @@ export async function getPackage(name: string): Promise<RegistryPackage | null>
 /// Fetches the version history for a package.
 export async function getVersions(name: string): Promise<RegistryVersion[]> {
   try {
-    const res = await fetch(`${REGISTRY_URL}/packages/${encodeURIComponent(name)}/versions`, {
+    const res = await fetch(`${REGISTRY_URL}/packages/${encodeURIComponent(name)}/versions?include=downloads`, {
       next: { revalidate: 3600 },
       signal: AbortSignal.timeout(3000),
     });